@@ -0,0 +1,295 @@
+// Export of batches as Arrow record batches through the Arrow C Data Interface
+// (https://arrow.apache.org/docs/format/CDataInterface.html), so pyarrow and everything built on
+// it can import them. The arrays are copied once into buffers in the Arrow layout (the matrices
+// in row-major order), which the consumer then takes over without another copy. Only the few
+// layouts needed for batches are supported: primitive integer columns and fixed size lists of
+// them for the 2D arrays.
+// The `arrow` crate isn't used for this: it would pull in a large dependency tree to build a
+// handful of flat columns, and its pyarrow support pins a pyo3 version of its own.
+use std::ffi::{c_void, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use ndarray::{Array1, Array2};
+
+use crate::PreprocessingError;
+
+pub enum Values {
+    UInt32(Vec<u32>),
+    UInt64(Vec<u64>),
+    Int64(Vec<i64>),
+}
+
+impl Values {
+    fn len(&self) -> usize {
+        match self {
+            Values::UInt32(values) => values.len(),
+            Values::UInt64(values) => values.len(),
+            Values::Int64(values) => values.len(),
+        }
+    }
+    fn format(&self) -> &'static str {
+        match self {
+            Values::UInt32(_) => "I",
+            Values::UInt64(_) => "L",
+            Values::Int64(_) => "l",
+        }
+    }
+    fn as_ptr(&self) -> *const c_void {
+        match self {
+            Values::UInt32(values) => values.as_ptr() as *const c_void,
+            Values::UInt64(values) => values.as_ptr() as *const c_void,
+            Values::Int64(values) => values.as_ptr() as *const c_void,
+        }
+    }
+}
+
+// A column of one value per row, or of `width` values per row stored as a fixed size list.
+pub struct Column {
+    pub(crate) name: String,
+    pub(crate) values: Values,
+    pub(crate) width: Option<usize>,
+}
+
+impl Column {
+    pub fn from_array1(name: &str, values: Values) -> Column {
+        Column {
+            name: name.to_string(),
+            values,
+            width: None,
+        }
+    }
+    pub fn from_array2<T: Clone>(
+        name: &str,
+        array: &Array2<T>,
+        values: fn(Vec<T>) -> Values,
+    ) -> Column {
+        Column {
+            name: name.to_string(),
+            values: values(array.as_standard_layout().into_owned().into_raw_vec()),
+            width: Some(array.ncols()),
+        }
+    }
+    fn num_rows(&self) -> usize {
+        match self.width {
+            Some(width) => self.values.len() / width,
+            None => self.values.len(),
+        }
+    }
+}
+
+pub struct RecordBatch {
+    pub(crate) num_rows: usize,
+    pub(crate) columns: Vec<Column>,
+}
+
+impl RecordBatch {
+    pub fn new(num_rows: usize, columns: Vec<Column>) -> Result<RecordBatch, PreprocessingError> {
+        for column in &columns {
+            // A fixed size list of width 0 doesn't tell the number of rows.
+            let ragged = column
+                .width
+                .is_some_and(|width| width > 0 && column.values.len() % width != 0);
+            if column.width != Some(0) && (ragged || column.num_rows() != num_rows) {
+                return Err(PreprocessingError::InvalidArgument(format!(
+                    "Column {} has {} rows instead of {}",
+                    column.name,
+                    column.num_rows(),
+                    num_rows
+                )));
+            }
+            if column.name.contains('\0') {
+                return Err(PreprocessingError::InvalidArgument(format!(
+                    "Column name {:?} contains a null character",
+                    column.name
+                )));
+            }
+        }
+        Ok(RecordBatch { num_rows, columns })
+    }
+    pub(crate) fn export(self) -> ExportedRecordBatch {
+        let mut fields = Vec::new();
+        let mut arrays = Vec::new();
+        for column in self.columns {
+            let format = column.values.format();
+            // No validity bitmap since there are no nulls.
+            let values = FfiArray::new(
+                column.values.len(),
+                vec![ptr::null(), column.values.as_ptr()],
+                Vec::new(),
+                Some(column.values),
+            );
+            match column.width {
+                None => {
+                    fields.push(FfiSchema::new(format, &column.name, Vec::new()));
+                    arrays.push(values);
+                }
+                Some(width) => {
+                    let item = FfiSchema::new(format, "item", Vec::new());
+                    let list_format = format!("+w:{}", width);
+                    fields.push(FfiSchema::new(&list_format, &column.name, vec![item]));
+                    let list = FfiArray::new(self.num_rows, vec![ptr::null()], vec![values], None);
+                    arrays.push(list);
+                }
+            }
+        }
+        ExportedRecordBatch {
+            schema: FfiSchema::new("+s", "", fields),
+            array: FfiArray::new(self.num_rows, vec![ptr::null()], arrays, None),
+        }
+    }
+}
+
+// `struct ArrowSchema` of the C Data Interface.
+#[repr(C)]
+pub(crate) struct FfiSchema {
+    pub(crate) format: *const c_char,
+    pub(crate) name: *const c_char,
+    pub(crate) metadata: *const c_char,
+    pub(crate) flags: i64,
+    pub(crate) n_children: i64,
+    pub(crate) children: *mut *mut FfiSchema,
+    pub(crate) dictionary: *mut FfiSchema,
+    pub(crate) release: Option<unsafe extern "C" fn(*mut FfiSchema)>,
+    pub(crate) private_data: *mut c_void,
+}
+
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut FfiSchema>,
+}
+
+impl FfiSchema {
+    // The children are boxed since the parent only keeps pointers to them.
+    #[allow(clippy::vec_box)]
+    fn new(format: &str, name: &str, children: Vec<Box<FfiSchema>>) -> Box<FfiSchema> {
+        let mut private = Box::new(SchemaPrivate {
+            format: CString::new(format).unwrap(),
+            name: CString::new(name).expect("Checked by `RecordBatch::new`"),
+            children: children.into_iter().map(Box::into_raw).collect(),
+        });
+        Box::new(FfiSchema {
+            format: private.format.as_ptr(),
+            name: private.name.as_ptr(),
+            metadata: ptr::null(),
+            flags: 0,
+            n_children: private.children.len() as i64,
+            children: private.children.as_mut_ptr(),
+            dictionary: ptr::null_mut(),
+            release: Some(release_schema),
+            private_data: Box::into_raw(private) as *mut c_void,
+        })
+    }
+}
+
+impl Drop for FfiSchema {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) };
+        }
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut FfiSchema) {
+    let schema = &mut *schema;
+    let private = Box::from_raw(schema.private_data as *mut SchemaPrivate);
+    for child in private.children {
+        drop(Box::from_raw(child));
+    }
+    schema.release = None;
+}
+
+// `struct ArrowArray` of the C Data Interface.
+#[repr(C)]
+pub(crate) struct FfiArray {
+    pub(crate) length: i64,
+    pub(crate) null_count: i64,
+    pub(crate) offset: i64,
+    pub(crate) n_buffers: i64,
+    pub(crate) n_children: i64,
+    pub(crate) buffers: *mut *const c_void,
+    pub(crate) children: *mut *mut FfiArray,
+    pub(crate) dictionary: *mut FfiArray,
+    pub(crate) release: Option<unsafe extern "C" fn(*mut FfiArray)>,
+    pub(crate) private_data: *mut c_void,
+}
+
+struct ArrayPrivate {
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut FfiArray>,
+    // Owner of the data buffer, the pointers in `buffers` point into it.
+    _values: Option<Values>,
+}
+
+impl FfiArray {
+    #[allow(clippy::vec_box)]
+    fn new(
+        length: usize,
+        buffers: Vec<*const c_void>,
+        children: Vec<Box<FfiArray>>,
+        values: Option<Values>,
+    ) -> Box<FfiArray> {
+        let mut private = Box::new(ArrayPrivate {
+            buffers,
+            children: children.into_iter().map(Box::into_raw).collect(),
+            _values: values,
+        });
+        Box::new(FfiArray {
+            length: length as i64,
+            null_count: 0,
+            offset: 0,
+            n_buffers: private.buffers.len() as i64,
+            n_children: private.children.len() as i64,
+            buffers: private.buffers.as_mut_ptr(),
+            children: private.children.as_mut_ptr(),
+            dictionary: ptr::null_mut(),
+            release: Some(release_array),
+            private_data: Box::into_raw(private) as *mut c_void,
+        })
+    }
+}
+
+impl Drop for FfiArray {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) };
+        }
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut FfiArray) {
+    let array = &mut *array;
+    let private = Box::from_raw(array.private_data as *mut ArrayPrivate);
+    for child in private.children {
+        drop(Box::from_raw(child));
+    }
+    array.release = None;
+}
+
+// Record batch exported as a struct array. A consumer imports it from the addresses of `array`
+// and `schema` and marks both as released, whatever it didn't take over is released on drop.
+pub(crate) struct ExportedRecordBatch {
+    pub(crate) array: Box<FfiArray>,
+    pub(crate) schema: Box<FfiSchema>,
+}
+
+impl ExportedRecordBatch {
+    pub(crate) fn array_address(&mut self) -> usize {
+        &mut *self.array as *mut FfiArray as usize
+    }
+    pub(crate) fn schema_address(&mut self) -> usize {
+        &mut *self.schema as *mut FfiSchema as usize
+    }
+}
+
+pub(crate) fn uint64_column(name: &str, array: &Array1<usize>) -> Column {
+    Column::from_array1(
+        name,
+        Values::UInt64(array.iter().map(|&value| value as u64).collect()),
+    )
+}
+
+pub(crate) fn int64_column(name: &str, array: &Array1<i64>) -> Column {
+    Column::from_array1(name, Values::Int64(array.to_vec()))
+}
@@ -0,0 +1,40 @@
+// Backing storage of batch matrices that consumers handed back after copying them out, so a
+// batcher only allocates when no buffer of the requested shape is left (e.g. for a short last
+// batch). The Python bindings hand back the matrices of a `BatchEncoding` once the Python object
+// is dropped, every getter copies the arrays into numpy before that and DLPack tensors keep the
+// object alive.
+use std::sync::{Arc, Mutex};
+
+use ndarray::Array2;
+
+// Enough for the matrices of a few batches in flight, more buffers are dropped.
+const MAX_BUFFERS: usize = 32;
+
+#[derive(Clone, Default)]
+pub(crate) struct BufferPool(Arc<Mutex<Vec<Array2<u32>>>>);
+
+impl BufferPool {
+    // A `shape` matrix filled with `value`, reusing a pooled buffer of that shape if there is one.
+    pub(crate) fn take(&self, shape: (usize, usize), value: u32) -> Array2<u32> {
+        let mut buffers = self.0.lock().unwrap();
+        match buffers.iter().position(|buffer| buffer.dim() == shape) {
+            Some(index) => {
+                let mut buffer = buffers.swap_remove(index);
+                drop(buffers);
+                buffer.fill(value);
+                buffer
+            }
+            None => Array2::from_elem(shape, value),
+        }
+    }
+    pub(crate) fn put(&self, buffer: Array2<u32>) {
+        let mut buffers = self.0.lock().unwrap();
+        if buffers.len() < MAX_BUFFERS && !buffer.is_empty() {
+            buffers.push(buffer);
+        }
+    }
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
@@ -0,0 +1,413 @@
+// Minimal reader for the Arrow IPC format as written by HuggingFace `datasets`. Only
+// uncompressed record batches are supported and only string and integer columns can be decoded,
+// other columns are skipped.
+use std::io;
+
+pub enum Column {
+    Utf8(Vec<Option<String>>),
+    Int(Vec<Option<i64>>),
+}
+
+impl Column {
+    fn extend(&mut self, other: Column) -> io::Result<()> {
+        match (self, other) {
+            (Column::Utf8(values), Column::Utf8(other)) => values.extend(other),
+            (Column::Int(values), Column::Int(other)) => values.extend(other),
+            _ => return Err(invalid("Column changes its type between record batches")),
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Offsets and lengths come straight from the file, so all arithmetic on them is checked.
+fn add(a: usize, b: usize) -> io::Result<usize> {
+    a.checked_add(b)
+        .ok_or_else(|| invalid("Offset out of range in Arrow data"))
+}
+
+fn mul(a: usize, b: usize) -> io::Result<usize> {
+    a.checked_mul(b)
+        .ok_or_else(|| invalid("Offset out of range in Arrow data"))
+}
+
+fn to_usize(value: i64) -> io::Result<usize> {
+    usize::try_from(value).map_err(|_| invalid("Negative offset or length in Arrow data"))
+}
+
+fn slice(buf: &[u8], pos: usize, len: usize) -> io::Result<&[u8]> {
+    buf.get(pos..add(pos, len)?)
+        .ok_or_else(|| invalid("Unexpected end of Arrow data"))
+}
+
+fn read<const N: usize>(buf: &[u8], pos: usize) -> io::Result<[u8; N]> {
+    Ok(slice(buf, pos, N)?
+        .try_into()
+        .expect("The slice has N bytes"))
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(read(buf, pos)?))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read(buf, pos)?))
+}
+
+fn read_i32(buf: &[u8], pos: usize) -> io::Result<i32> {
+    Ok(i32::from_le_bytes(read(buf, pos)?))
+}
+
+fn read_i64(buf: &[u8], pos: usize) -> io::Result<i64> {
+    Ok(i64::from_le_bytes(read(buf, pos)?))
+}
+
+// Flatbuffers table, `pos` is the start of the table in `buf`.
+#[derive(Clone, Copy)]
+struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    fn root(buf: &'a [u8]) -> io::Result<Table<'a>> {
+        let pos = read_u32(buf, 0)? as usize;
+        Ok(Table { buf, pos })
+    }
+    // Absolute position of field `index`, `None` if it isn't set.
+    fn field(&self, index: usize) -> io::Result<Option<usize>> {
+        let vtable = to_usize(self.pos as i64 - read_i32(self.buf, self.pos)? as i64)?;
+        let vtable_len = read_u16(self.buf, vtable)? as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_len {
+            return Ok(None);
+        }
+        match read_u16(self.buf, add(vtable, entry)?)? {
+            0 => Ok(None),
+            offset => Ok(Some(add(self.pos, offset as usize)?)),
+        }
+    }
+    fn u8(&self, index: usize) -> io::Result<u8> {
+        match self.field(index)? {
+            Some(pos) => Ok(read::<1>(self.buf, pos)?[0]),
+            None => Ok(0),
+        }
+    }
+    fn i32(&self, index: usize) -> io::Result<i32> {
+        self.field(index)?
+            .map_or(Ok(0), |pos| read_i32(self.buf, pos))
+    }
+    fn i64(&self, index: usize) -> io::Result<i64> {
+        self.field(index)?
+            .map_or(Ok(0), |pos| read_i64(self.buf, pos))
+    }
+    // Follows the offset stored in field `index`.
+    fn indirect(&self, index: usize) -> io::Result<Option<usize>> {
+        match self.field(index)? {
+            Some(pos) => Ok(Some(add(pos, read_u32(self.buf, pos)? as usize)?)),
+            None => Ok(None),
+        }
+    }
+    fn table(&self, index: usize) -> io::Result<Option<Table<'a>>> {
+        Ok(self
+            .indirect(index)?
+            .map(|pos| Table { buf: self.buf, pos }))
+    }
+    fn string(&self, index: usize) -> io::Result<String> {
+        match self.vector(index)? {
+            Some((pos, len)) => String::from_utf8(slice(self.buf, pos, len)?.to_vec())
+                .map_err(|_| invalid("Invalid field name")),
+            None => Ok(String::new()),
+        }
+    }
+    // Start and length of a vector.
+    fn vector(&self, index: usize) -> io::Result<Option<(usize, usize)>> {
+        match self.indirect(index)? {
+            Some(pos) => Ok(Some((add(pos, 4)?, read_u32(self.buf, pos)? as usize))),
+            None => Ok(None),
+        }
+    }
+    fn tables(&self, index: usize) -> io::Result<Vec<Table<'a>>> {
+        let (start, len) = self.vector(index)?.unwrap_or((0, 0));
+        slice(self.buf, start, mul(len, 4)?)?;
+        (0..len)
+            .map(|i| {
+                let pos = start + 4 * i;
+                Ok(Table {
+                    buf: self.buf,
+                    pos: add(pos, read_u32(self.buf, pos)? as usize)?,
+                })
+            })
+            .collect()
+    }
+    // Vector of structs made of two longs, like `FieldNode` and `Buffer`.
+    fn long_pairs(&self, index: usize) -> io::Result<Vec<(i64, i64)>> {
+        let (start, len) = self.vector(index)?.unwrap_or((0, 0));
+        slice(self.buf, start, mul(len, 16)?)?;
+        (0..len)
+            .map(|i| {
+                let pos = start + 16 * i;
+                Ok((read_i64(self.buf, pos)?, read_i64(self.buf, pos + 8)?))
+            })
+            .collect()
+    }
+}
+
+// `Type` union of Schema.fbs.
+const TYPE_INT: u8 = 2;
+const TYPE_UTF8: u8 = 5;
+const TYPE_LARGE_UTF8: u8 = 20;
+
+struct Field {
+    name: String,
+    type_type: u8,
+    bit_width: i32,
+    // Number of field nodes and buffers the field occupies in a record batch.
+    n_nodes: usize,
+    n_buffers: usize,
+}
+
+// Nested types deeper than this are rejected, a malformed schema can make a field its own child.
+const MAX_DEPTH: usize = 64;
+
+impl Field {
+    // Every field table takes at least 4 bytes, so `fields_left` starts at a quarter of the
+    // metadata length and stops malformed schemas that share children between fields.
+    fn from_table(table: Table, depth: usize, fields_left: &mut usize) -> io::Result<Field> {
+        if depth > MAX_DEPTH || *fields_left == 0 {
+            return Err(invalid("Malformed nested Arrow type"));
+        }
+        *fields_left -= 1;
+        let type_type = table.u8(2)?;
+        let bit_width = match table.table(3)? {
+            Some(int) if type_type == TYPE_INT => int.i32(0)?,
+            _ => 0,
+        };
+        let own_buffers = match type_type {
+            // Null
+            1 => 0,
+            // Int, FloatingPoint, Bool, Decimal, Date, Time, Timestamp, Interval,
+            // FixedSizeBinary, Duration
+            2 | 3 | 6 | 7 | 8 | 9 | 10 | 11 | 15 | 18 => 2,
+            // Binary, Utf8, LargeBinary, LargeUtf8
+            4 | 5 | 19 | 20 => 3,
+            // List, Map, LargeList
+            12 | 17 | 21 => 2,
+            // Struct, FixedSizeList
+            13 | 16 => 1,
+            _ => return Err(invalid("Unsupported Arrow type")),
+        };
+        let mut field = Field {
+            name: table.string(0)?,
+            type_type,
+            bit_width,
+            n_nodes: 1,
+            n_buffers: own_buffers,
+        };
+        for child in table.tables(5)? {
+            let child = Field::from_table(child, depth + 1, fields_left)?;
+            field.n_nodes += child.n_nodes;
+            field.n_buffers += child.n_buffers;
+        }
+        Ok(field)
+    }
+}
+
+// `length` has to be checked against the data buffers before, so it doesn't allocate more than
+// the file holds.
+fn validity(bitmap: &[u8], length: usize) -> Vec<bool> {
+    if bitmap.is_empty() {
+        return vec![true; length];
+    }
+    (0..length)
+        .map(|i| {
+            bitmap
+                .get(i / 8)
+                .is_some_and(|byte| byte & (1 << (i % 8)) != 0)
+        })
+        .collect()
+}
+
+fn buffer<'a>(buffers: &[&'a [u8]], index: usize) -> io::Result<&'a [u8]> {
+    buffers
+        .get(index)
+        .copied()
+        .ok_or_else(|| invalid("Missing buffers"))
+}
+
+// Checks that the data buffer holds `length` values of `width` bytes (plus `extra` ones).
+fn check_length(data: &[u8], length: usize, extra: usize, width: usize) -> io::Result<()> {
+    if mul(add(length, extra)?, width)? > data.len() {
+        return Err(invalid("Column is longer than its buffers"));
+    }
+    Ok(())
+}
+
+fn decode(field: &Field, length: usize, buffers: &[&[u8]]) -> io::Result<Column> {
+    let bitmap = buffer(buffers, 0)?;
+    match field.type_type {
+        TYPE_UTF8 | TYPE_LARGE_UTF8 => {
+            let (offsets, data) = (buffer(buffers, 1)?, buffer(buffers, 2)?);
+            let width = if field.type_type == TYPE_UTF8 { 4 } else { 8 };
+            if length > 0 {
+                check_length(offsets, length, 1, width)?;
+            }
+            let offset = |i: usize| -> io::Result<usize> {
+                match width {
+                    4 => to_usize(read_i32(offsets, i * 4)? as i64),
+                    _ => to_usize(read_i64(offsets, i * 8)?),
+                }
+            };
+            let valid = validity(bitmap, length);
+            let mut values = Vec::with_capacity(length);
+            for (i, &valid) in valid.iter().enumerate() {
+                if !valid {
+                    values.push(None);
+                    continue;
+                }
+                let bytes = data
+                    .get(offset(i)?..offset(i + 1)?)
+                    .ok_or_else(|| invalid("String offsets out of bounds"))?;
+                let value = String::from_utf8(bytes.to_vec())
+                    .map_err(|_| invalid("Invalid UTF-8 in string column"))?;
+                values.push(Some(value));
+            }
+            Ok(Column::Utf8(values))
+        }
+        TYPE_INT => {
+            let data = buffer(buffers, 1)?;
+            let width = match field.bit_width {
+                8 | 16 | 32 | 64 => field.bit_width as usize / 8,
+                _ => return Err(invalid("Unsupported integer width")),
+            };
+            check_length(data, length, 0, width)?;
+            let valid = validity(bitmap, length);
+            let mut values = Vec::with_capacity(length);
+            for (i, &valid) in valid.iter().enumerate() {
+                if !valid {
+                    values.push(None);
+                    continue;
+                }
+                let pos = i * width;
+                let value = match width {
+                    1 => read::<1>(data, pos)?[0] as i8 as i64,
+                    2 => i16::from_le_bytes(read(data, pos)?) as i64,
+                    4 => read_i32(data, pos)? as i64,
+                    _ => read_i64(data, pos)?,
+                };
+                values.push(Some(value));
+            }
+            Ok(Column::Int(values))
+        }
+        _ => Err(invalid(&format!(
+            "Column {:?} is neither a string nor an integer column",
+            field.name
+        ))),
+    }
+}
+
+// `MessageHeader` union of Message.fbs.
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_DICTIONARY_BATCH: u8 = 2;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+// Reads the columns selected by `wanted` from a stream (or file), in schema order.
+pub fn read_columns<F: Fn(&str) -> bool>(
+    bytes: &[u8],
+    wanted: F,
+) -> io::Result<Vec<(String, Column)>> {
+    // The file format is the stream format between a magic header and a footer.
+    let mut pos = if bytes.starts_with(b"ARROW1") { 8 } else { 0 };
+    let mut fields: Option<Vec<Field>> = None;
+    let mut columns: Vec<(String, Column)> = Vec::new();
+    while pos + 4 <= bytes.len() {
+        let mut metadata_len = read_i32(bytes, pos)?;
+        pos += 4;
+        // Continuation marker of the current format, older streams start with the length.
+        if metadata_len == -1 {
+            metadata_len = read_i32(bytes, pos)?;
+            pos += 4;
+        }
+        if metadata_len <= 0 {
+            break;
+        }
+        let metadata = slice(bytes, pos, metadata_len as usize)?;
+        pos += metadata_len as usize;
+        let message = Table::root(metadata)?;
+        let body_len = to_usize(message.i64(3)?)?;
+        let body = slice(bytes, pos, body_len)?;
+        pos += body_len;
+        let header = match message.table(2)? {
+            Some(header) => header,
+            None => continue,
+        };
+        match message.u8(1)? {
+            HEADER_SCHEMA => {
+                let mut fields_left = metadata.len() / 4;
+                let schema = header
+                    .tables(1)?
+                    .into_iter()
+                    .map(|field| Field::from_table(field, 0, &mut fields_left))
+                    .collect::<io::Result<Vec<Field>>>()?;
+                fields = Some(schema);
+            }
+            HEADER_DICTIONARY_BATCH => {
+                return Err(invalid("Dictionary encoded columns are not supported"))
+            }
+            HEADER_RECORD_BATCH => {
+                let fields = fields
+                    .as_ref()
+                    .ok_or_else(|| invalid("Record batch before the schema"))?;
+                if header.field(3)?.is_some() {
+                    return Err(invalid("Compressed record batches are not supported"));
+                }
+                let length = to_usize(header.i64(0)?)?;
+                let nodes = header.long_pairs(1)?;
+                let buffers = header
+                    .long_pairs(2)?
+                    .into_iter()
+                    .map(|(offset, len)| slice(body, to_usize(offset)?, to_usize(len)?))
+                    .collect::<io::Result<Vec<&[u8]>>>()?;
+                let (mut node, mut buffer) = (0, 0);
+                let mut batch_columns = Vec::new();
+                for field in fields.iter() {
+                    if wanted(&field.name) {
+                        let field_length = match nodes.get(node) {
+                            Some(&(field_length, _)) => to_usize(field_length)?,
+                            None => length,
+                        };
+                        let field_buffers = buffers
+                            .get(buffer..add(buffer, field.n_buffers)?)
+                            .ok_or_else(|| invalid("Missing buffers"))?;
+                        batch_columns.push((
+                            field.name.clone(),
+                            decode(field, field_length, field_buffers)?,
+                        ));
+                    }
+                    node += field.n_nodes;
+                    buffer += field.n_buffers;
+                }
+                if columns.is_empty() {
+                    columns = batch_columns;
+                } else {
+                    for ((_, column), (_, batch_column)) in columns.iter_mut().zip(batch_columns) {
+                        column.extend(batch_column)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if columns.is_empty() {
+        if let Some(fields) = fields {
+            // Schema without record batches, i.e. an empty split.
+            for field in fields.iter().filter(|field| wanted(&field.name)) {
+                columns.push((field.name.clone(), decode(field, 0, &[&[], &[], &[]])?));
+            }
+        }
+    }
+    Ok(columns)
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use super::arrow::{self, Column};
+use crate::NodeOutput;
+use crate::{ClassLabel, NoLabel, Node, OptionalLabel, Text, TextPair};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+type SplitColumns = (Vec<Vec<String>>, Option<Vec<Option<u32>>>);
+
+// Class ids of string labels. Without a fixed mapping, ids are assigned in the order the labels
+// are first seen, as by `LabelEncoder`. With one, unknown labels are an error.
+pub(super) struct LabelIds {
+    pub(super) mapping: HashMap<String, u32>,
+    pub(super) fixed: bool,
+}
+
+impl LabelIds {
+    pub(super) fn id(&mut self, label: String) -> io::Result<u32> {
+        if let Some(&id) = self.mapping.get(&label) {
+            return Ok(id);
+        }
+        if self.fixed {
+            return Err(invalid(format!("Unknown label {:?}", label)));
+        }
+        let id = self.mapping.len() as u32;
+        self.mapping.insert(label, id);
+        Ok(id)
+    }
+}
+
+// Dataset prepared by HuggingFace `datasets`, i.e. a directory with a `dataset_info.json` and
+// one or more `<builder>-<split>[-<shard>-of-<shards>].arrow` files per split.
+pub struct HfDataset {
+    dir: PathBuf,
+    // String columns and the `ClassLabel` column declared in the features.
+    text_columns: Vec<String>,
+    label_column: Option<String>,
+    // Whether the label column holds label names rather than class ids.
+    string_labels: bool,
+    // `dtype`s of all `Value` features.
+    dtypes: HashMap<String, String>,
+}
+
+impl HfDataset {
+    // `path` is either the prepared directory itself or a directory above it, like
+    // `~/.cache/huggingface/datasets/<name>`. In the latter case the most recently prepared
+    // config/version is used.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<HfDataset> {
+        let dir = find_prepared_dir(path.as_ref())?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No dataset_info.json found under {:?}", path.as_ref()),
+            )
+        })?;
+        let info: Value = serde_json::from_reader(fs::File::open(dir.join("dataset_info.json"))?)?;
+        let features = info
+            .get("features")
+            .and_then(Value::as_object)
+            .ok_or_else(|| invalid("dataset_info.json has no features".to_string()))?;
+        let mut text_columns = Vec::new();
+        let mut label_column = None;
+        let mut dtypes = HashMap::new();
+        for (name, feature) in features {
+            let feature_type = feature.get("_type").and_then(Value::as_str);
+            let dtype = feature.get("dtype").and_then(Value::as_str);
+            if let (Some("Value"), Some(dtype)) = (feature_type, dtype) {
+                dtypes.insert(name.clone(), dtype.to_string());
+            }
+            match (feature_type, dtype) {
+                (Some("Value"), Some("string" | "large_string")) => text_columns.push(name.clone()),
+                (Some("ClassLabel"), _) if label_column.is_some() => {
+                    return Err(invalid("More than one ClassLabel feature".to_string()));
+                }
+                (Some("ClassLabel"), _) => label_column = Some(name.clone()),
+                _ => {}
+            }
+        }
+        let dataset = HfDataset {
+            dir,
+            text_columns,
+            label_column,
+            string_labels: false,
+            dtypes,
+        };
+        dataset.check_text_columns()?;
+        Ok(dataset)
+    }
+    // Takes the labels from the `Value` feature `name` instead of a `ClassLabel` feature. Whether
+    // they are class ids or label names is detected from the feature's dtype, names are converted
+    // to ids by the loader (see `HfCacheLoader::with_mapping`).
+    pub fn with_label_column(mut self, name: &str) -> io::Result<HfDataset> {
+        let dtype = self
+            .dtypes
+            .get(name)
+            .ok_or_else(|| invalid(format!("No Value feature {:?}", name)))?;
+        self.string_labels = match dtype.as_str() {
+            "string" | "large_string" => true,
+            "int8" | "int16" | "int32" | "int64" => false,
+            _ => {
+                return Err(invalid(format!(
+                    "Feature {:?} of dtype {} can't be used as labels",
+                    name, dtype
+                )))
+            }
+        };
+        self.text_columns.retain(|column| column != name);
+        self.label_column = Some(name.to_string());
+        self.check_text_columns()?;
+        Ok(self)
+    }
+    fn check_text_columns(&self) -> io::Result<()> {
+        if self.text_columns.is_empty() || self.text_columns.len() > 2 {
+            return Err(invalid(format!(
+                "Expected one or two string features, found {}",
+                self.text_columns.len()
+            )));
+        }
+        Ok(())
+    }
+    pub fn n_texts(&self) -> usize {
+        self.text_columns.len()
+    }
+    pub fn has_label(&self) -> bool {
+        self.label_column.is_some()
+    }
+    pub fn has_string_labels(&self) -> bool {
+        self.string_labels
+    }
+    fn split_files(&self, split: &str) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let stem = match name.strip_suffix(".arrow") {
+                Some(stem) => stem,
+                None => continue,
+            };
+            if stem.ends_with(&format!("-{}", split)) || is_shard_of(stem, split) {
+                files.push(path);
+            }
+        }
+        if files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No arrow files for split {:?} in {:?}", split, self.dir),
+            ));
+        }
+        files.sort();
+        Ok(files)
+    }
+    // Text columns in schema order and the labels of all rows of a split. Class id -1 is how
+    // `datasets` marks unlabelled rows (e.g. of a test split), they have no label.
+    fn read_split(&self, split: &str, label_ids: &mut LabelIds) -> io::Result<SplitColumns> {
+        let mut texts: Vec<Vec<String>> = Vec::new();
+        let mut labels: Option<Vec<Option<u32>>> = self.label_column.as_ref().map(|_| Vec::new());
+        for file in self.split_files(split)? {
+            let bytes = fs::read(&file)?;
+            let columns = arrow::read_columns(&bytes, |name| {
+                self.text_columns.iter().any(|column| column == name)
+                    || self.label_column.as_deref() == Some(name)
+            })?;
+            let mut file_texts = Vec::new();
+            for (name, column) in columns {
+                match column {
+                    Column::Utf8(values) if self.label_column.as_ref() == Some(&name) => {
+                        for value in values {
+                            let label = value
+                                .ok_or_else(|| invalid(format!("Missing label in {:?}", name)))?;
+                            labels.as_mut().unwrap().push(Some(label_ids.id(label)?));
+                        }
+                    }
+                    Column::Utf8(values) => {
+                        file_texts.push(values.into_iter().map(Option::unwrap_or_default).collect())
+                    }
+                    Column::Int(values) => {
+                        for value in values {
+                            let label = match value {
+                                Some(-1) => None,
+                                Some(id) => Some(u32::try_from(id).map_err(|_| {
+                                    invalid(format!("Invalid label {} in {:?}", id, name))
+                                })?),
+                                None => {
+                                    return Err(invalid(format!("Missing label in {:?}", name)))
+                                }
+                            };
+                            labels.as_mut().unwrap().push(label);
+                        }
+                    }
+                }
+            }
+            if file_texts.len() != self.text_columns.len() {
+                return Err(invalid(format!(
+                    "{:?} doesn't contain all string columns",
+                    file
+                )));
+            }
+            if texts.is_empty() {
+                texts = file_texts;
+            } else {
+                for (column, file_column) in texts.iter_mut().zip(file_texts) {
+                    column.extend(file_column);
+                }
+            }
+        }
+        Ok((texts, labels))
+    }
+}
+
+// Matches `<builder>-<split>-00000-of-00002`.
+fn is_shard_of(stem: &str, split: &str) -> bool {
+    let parts: Vec<&str> = stem.rsplitn(4, '-').collect();
+    matches!(parts.as_slice(), [total, "of", index, rest]
+        if rest.ends_with(&format!("-{}", split))
+            && [total, index].iter().all(|part| part.chars().all(|c| c.is_ascii_digit())))
+}
+
+fn find_prepared_dir(path: &Path) -> io::Result<Option<PathBuf>> {
+    if path.join("dataset_info.json").is_file() {
+        return Ok(Some(path.to_path_buf()));
+    }
+    let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(dir) = find_prepared_dir(&entry.path())? {
+            let modified = fs::metadata(dir.join("dataset_info.json"))?.modified()?;
+            if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                newest = Some((modified, dir));
+            }
+        }
+    }
+    Ok(newest.map(|(_, dir)| dir))
+}
+
+// Samples that can be built from the string columns and the label of a row.
+pub trait HfSample: Clone + NodeOutput {
+    const N_TEXTS: usize;
+    const LABELLED: bool;
+    // Whether rows without a label are samples too, for `ListLoader`.
+    const OPTIONAL_LABEL: bool = false;
+    fn from_row(texts: Vec<String>, label: Option<u32>) -> Self;
+}
+
+impl HfSample for Text<NoLabel> {
+    const N_TEXTS: usize = 1;
+    const LABELLED: bool = false;
+    fn from_row(mut texts: Vec<String>, _label: Option<u32>) -> Self {
+        Text {
+            text: texts.remove(0),
+            label: NoLabel(),
+            metadata: None,
+        }
+    }
+}
+
+impl HfSample for Text<ClassLabel> {
+    const N_TEXTS: usize = 1;
+    const LABELLED: bool = true;
+    fn from_row(mut texts: Vec<String>, label: Option<u32>) -> Self {
+        Text {
+            text: texts.remove(0),
+            label: ClassLabel(label.unwrap()),
+            metadata: None,
+        }
+    }
+}
+
+impl HfSample for TextPair<NoLabel> {
+    const N_TEXTS: usize = 2;
+    const LABELLED: bool = false;
+    fn from_row(mut texts: Vec<String>, _label: Option<u32>) -> Self {
+        let second = texts.pop().unwrap();
+        TextPair {
+            text: (texts.pop().unwrap(), second),
+            label: NoLabel(),
+            metadata: None,
+        }
+    }
+}
+
+impl HfSample for TextPair<ClassLabel> {
+    const N_TEXTS: usize = 2;
+    const LABELLED: bool = true;
+    fn from_row(mut texts: Vec<String>, label: Option<u32>) -> Self {
+        let second = texts.pop().unwrap();
+        TextPair {
+            text: (texts.pop().unwrap(), second),
+            label: ClassLabel(label.unwrap()),
+            metadata: None,
+        }
+    }
+}
+
+impl HfSample for Text<OptionalLabel<ClassLabel>> {
+    const N_TEXTS: usize = 1;
+    const LABELLED: bool = true;
+    const OPTIONAL_LABEL: bool = true;
+    fn from_row(mut texts: Vec<String>, label: Option<u32>) -> Self {
+        Text {
+            text: texts.remove(0),
+            label: OptionalLabel(label.map(ClassLabel)),
+            metadata: None,
+        }
+    }
+}
+
+impl HfSample for TextPair<OptionalLabel<ClassLabel>> {
+    const N_TEXTS: usize = 2;
+    const LABELLED: bool = true;
+    const OPTIONAL_LABEL: bool = true;
+    fn from_row(mut texts: Vec<String>, label: Option<u32>) -> Self {
+        let second = texts.pop().unwrap();
+        TextPair {
+            text: (texts.pop().unwrap(), second),
+            label: OptionalLabel(label.map(ClassLabel)),
+            metadata: None,
+        }
+    }
+}
+
+pub struct HfCacheLoader<T> {
+    samples: Vec<T>,
+    current_index: usize,
+    // Ids of the label names, for datasets with string labels.
+    mapping: Option<HashMap<String, u32>>,
+}
+
+impl<T: HfSample> HfCacheLoader<T> {
+    pub fn new(dataset: &HfDataset, split: &str) -> io::Result<HfCacheLoader<T>> {
+        HfCacheLoader::load(dataset, split, HashMap::new(), false)
+    }
+    // Converts string labels with the `label_mapping` of the loader of another split, so both
+    // splits use the same ids. Labels that aren't in the mapping are an error.
+    pub fn with_mapping(
+        dataset: &HfDataset,
+        split: &str,
+        mapping: HashMap<String, u32>,
+    ) -> io::Result<HfCacheLoader<T>> {
+        if !dataset.has_string_labels() {
+            return Err(invalid(
+                "A label mapping requires a label column of strings".to_string(),
+            ));
+        }
+        HfCacheLoader::load(dataset, split, mapping, true)
+    }
+    fn load(
+        dataset: &HfDataset,
+        split: &str,
+        mapping: HashMap<String, u32>,
+        fixed: bool,
+    ) -> io::Result<HfCacheLoader<T>> {
+        if dataset.n_texts() != T::N_TEXTS || dataset.has_label() != T::LABELLED {
+            return Err(invalid(
+                "The dataset's features don't match the sample type".to_string(),
+            ));
+        }
+        let mut label_ids = LabelIds { mapping, fixed };
+        let (texts, labels) = dataset.read_split(split, &mut label_ids)?;
+        let len = texts[0].len();
+        let lengths_match = texts.iter().all(|column| column.len() == len)
+            && labels.as_ref().is_none_or(|labels| labels.len() == len);
+        if !lengths_match {
+            return Err(invalid("Columns of different lengths".to_string()));
+        }
+        let mut columns: Vec<_> = texts.into_iter().map(Vec::into_iter).collect();
+        let samples = (0..len)
+            .map(|i| {
+                let row = columns
+                    .iter_mut()
+                    .map(|column| column.next().unwrap())
+                    .collect();
+                let label = match labels.as_ref().map(|labels| labels[i]) {
+                    Some(None) if !T::OPTIONAL_LABEL => {
+                        return Err(invalid(format!(
+                            "Row {} is unlabelled, which needs a sample type with an OptionalLabel",
+                            i
+                        )))
+                    }
+                    label => label.flatten(),
+                };
+                Ok(T::from_row(row, label))
+            })
+            .collect::<io::Result<Vec<T>>>()?;
+        Ok(HfCacheLoader {
+            samples,
+            current_index: 0,
+            mapping: dataset.has_string_labels().then_some(label_ids.mapping),
+        })
+    }
+}
+
+impl<T: HfSample> Node for HfCacheLoader<T> {
+    type Output = T;
+    fn get(&self, index: usize) -> Option<T> {
+        self.samples.get(index).cloned()
+    }
+    fn len(&self) -> Option<usize> {
+        Some(self.samples.len())
+    }
+    fn next(&mut self) -> Option<T> {
+        let sample = self.samples.get(self.current_index)?;
+        self.current_index += 1;
+        Some(sample.clone())
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.mapping.clone()
+    }
+}
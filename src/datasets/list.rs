@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::datasets::hf_cache::LabelIds;
+use crate::datasets::HfSample;
+use crate::{Node, PreprocessingError};
+
+// Label of a row of a `ListLoader`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowLabel {
+    Id(u32),
+    Name(String),
+}
+
+// Samples held in memory, e.g. converted from a list of Python dicts. Every row has the texts of
+// a sample and, if the sample type is labelled, its label (optional for `OptionalLabel`s). Label
+// names get ids in the order they are first seen, or the ids of `mapping` (the `label_mapping` of
+// another loader). Names and ids can't be mixed.
+pub struct ListLoader<T> {
+    samples: Vec<T>,
+    current_index: usize,
+    // Ids of the label names, for rows with named labels.
+    mapping: Option<HashMap<String, u32>>,
+}
+
+impl<T: HfSample> ListLoader<T> {
+    pub fn new(
+        rows: Vec<(Vec<String>, Option<RowLabel>)>,
+        mapping: Option<HashMap<String, u32>>,
+    ) -> Result<ListLoader<T>, PreprocessingError> {
+        let named = rows
+            .iter()
+            .any(|(_, label)| matches!(label, Some(RowLabel::Name(_))));
+        let mut label_ids = LabelIds {
+            fixed: mapping.is_some(),
+            mapping: mapping.unwrap_or_default(),
+        };
+        let invalid = |row: usize, message: &str| {
+            PreprocessingError::InvalidArgument(format!("Row {}: {}", row, message))
+        };
+        let samples = rows
+            .into_iter()
+            .enumerate()
+            .map(|(i, (texts, label))| {
+                if texts.len() != T::N_TEXTS {
+                    return Err(invalid(i, &format!("expected {} texts", T::N_TEXTS)));
+                }
+                let label = match (label, T::LABELLED) {
+                    (None, false) => None,
+                    (Some(RowLabel::Id(id)), true) if !named => Some(id),
+                    (Some(RowLabel::Name(name)), true) => Some(
+                        label_ids
+                            .id(name)
+                            .map_err(|err| invalid(i, &err.to_string()))?,
+                    ),
+                    (Some(RowLabel::Id(_)), true) => {
+                        return Err(invalid(i, "label names and ids can't be mixed"))
+                    }
+                    (None, true) if T::OPTIONAL_LABEL => None,
+                    (None, true) => return Err(invalid(i, "missing label")),
+                    (Some(_), false) => return Err(invalid(i, "unexpected label")),
+                };
+                Ok(T::from_row(texts, label))
+            })
+            .collect::<Result<Vec<T>, PreprocessingError>>()?;
+        Ok(ListLoader {
+            samples,
+            current_index: 0,
+            mapping: named.then_some(label_ids.mapping),
+        })
+    }
+}
+
+impl<T: HfSample> Node for ListLoader<T> {
+    type Output = T;
+    fn get(&self, index: usize) -> Option<T> {
+        self.samples.get(index).cloned()
+    }
+    fn len(&self) -> Option<usize> {
+        Some(self.samples.len())
+    }
+    fn next(&mut self) -> Option<T> {
+        let sample = self.samples.get(self.current_index)?;
+        self.current_index += 1;
+        Some(sample.clone())
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.mapping.clone()
+    }
+}
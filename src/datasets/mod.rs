@@ -1,21 +1,283 @@
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+use std::collections::HashMap;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use tokenizers::normalizers::StripAccents;
+use tokenizers::tokenizer::normalizer::Range;
+use tokenizers::tokenizer::{NormalizedString, Normalizer};
 
-use crate::{NoLabel, Node, Span, Text, TextPair};
+use crate::{
+    error, warnings, ClassLabel, Ids, MultipleChoice, NoLabel, Node, NodeOutput,
+    PreprocessingError, Retrieval, Span, Spans, StringLabel, Text, TextPair, TokenSpan,
+};
+
+pub(crate) mod arrow;
+mod hf_cache;
+mod list;
+mod source;
+
+pub use hf_cache::{HfCacheLoader, HfDataset, HfSample};
+pub use list::{ListLoader, RowLabel};
+use source::Source;
+
+// What to do with lines that aren't valid UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidUtf8 {
+    // Replace invalid bytes with U+FFFD.
+    Lossy,
+    Skip,
+    Error,
+}
+
+impl FromStr for InvalidUtf8 {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lossy" => Ok(InvalidUtf8::Lossy),
+            "skip" => Ok(InvalidUtf8::Skip),
+            "error" => Ok(InvalidUtf8::Error),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"lossy\", \"skip\" or \"error\"",
+                s
+            ))),
+        }
+    }
+}
+
+// What to do with malformed lines, like a line of a `LabelledTxtLoader` without the separator.
+// `Skip` warns about every skipped line (see `warnings`), `Error` panics on the first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MalformedPolicy {
+    Skip,
+    Error,
+}
+
+impl FromStr for MalformedPolicy {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MalformedPolicy::Skip),
+            "error" => Ok(MalformedPolicy::Error),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"skip\" or \"error\"",
+                s
+            ))),
+        }
+    }
+}
+
+impl MalformedPolicy {
+    // `skipped` names what is skipped in the warning.
+    pub(crate) fn handle(self, message: &str, skipped: &str) {
+        match self {
+            MalformedPolicy::Skip => warnings::warn(&format!("{}, skipping {}", message, skipped)),
+            MalformedPolicy::Error => error::raise(PreprocessingError::Parse(message.to_string())),
+        }
+    }
+}
+
+// How much whitespace `TxtLoader` strips from every line. `LineEnding` only removes the `\r`s
+// that end the lines of Windows (`\r\n`) and old Mac (`\r`) files, `None` keeps them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineTrim {
+    None,
+    LineEnding,
+    // Trailing whitespace.
+    End,
+    // Leading and trailing whitespace.
+    Both,
+}
+
+impl FromStr for LineTrim {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(LineTrim::None),
+            "line_ending" => Ok(LineTrim::LineEnding),
+            "end" => Ok(LineTrim::End),
+            "both" => Ok(LineTrim::Both),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"none\", \"line_ending\", \"end\" or \"both\"",
+                s
+            ))),
+        }
+    }
+}
+
+impl LineTrim {
+    fn apply(self, mut line: String) -> String {
+        let end = match self {
+            LineTrim::None => return line,
+            LineTrim::LineEnding => line.trim_end_matches('\r').len(),
+            LineTrim::End | LineTrim::Both => line.trim_end().len(),
+        };
+        line.truncate(end);
+        if self == LineTrim::Both {
+            let start = line.len() - line.trim_start().len();
+            line.drain(..start);
+        }
+        line
+    }
+}
+
+// Capacity of the read buffer of files. Lines are cut out of the buffer directly, so a larger
+// buffer means fewer reads and fewer lines that span two chunks.
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+// Reads raw bytes up to each newline, so invalid UTF-8 can be handled per line.
+struct Lines<R = Source> {
+    reader: io::BufReader<R>,
+    on_invalid_utf8: InvalidUtf8,
+    trim: LineTrim,
+    line_number: usize,
+    estimated_lines: usize,
+}
+
+impl Lines {
+    fn open<P: AsRef<Path>>(file: P, on_invalid_utf8: InvalidUtf8) -> io::Result<Lines> {
+        Lines::with_buffer_size(file, on_invalid_utf8, DEFAULT_BUFFER_SIZE)
+    }
+    fn with_buffer_size<P: AsRef<Path>>(
+        file: P,
+        on_invalid_utf8: InvalidUtf8,
+        buffer_size: usize,
+    ) -> io::Result<Lines> {
+        let source = Source::open(file)?;
+        let file_size = source.len()?;
+        let mut reader = io::BufReader::with_capacity(buffer_size.max(1), source);
+        let estimated_lines = match file_size {
+            Some(file_size) => estimate_lines(reader.fill_buf()?, file_size as usize),
+            None => 0,
+        };
+        Ok(Lines {
+            reader,
+            on_invalid_utf8,
+            trim: LineTrim::LineEnding,
+            line_number: 0,
+            estimated_lines,
+        })
+    }
+}
+
+impl<R: io::Read> Lines<R> {
+    // Without a file size nothing can be estimated, so the estimate only counts the lines read.
+    fn from_reader(reader: R, on_invalid_utf8: InvalidUtf8) -> Lines<R> {
+        Lines {
+            reader: io::BufReader::new(reader),
+            on_invalid_utf8,
+            trim: LineTrim::LineEnding,
+            line_number: 0,
+            estimated_lines: 0,
+        }
+    }
+    // Bytes of the next line without the newline. A line that lies within the buffer is copied out
+    // of it with a single allocation of the right size, only lines spanning several chunks
+    // are assembled piecewise.
+    fn read_line(&mut self) -> Option<Vec<u8>> {
+        let mut line: Option<Vec<u8>> = None;
+        loop {
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => error::raise(err.into()),
+            };
+            if buf.is_empty() {
+                return line;
+            }
+            match buf.iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    let bytes = match line {
+                        Some(mut line) => {
+                            line.extend_from_slice(&buf[..end]);
+                            line
+                        }
+                        None => buf[..end].to_vec(),
+                    };
+                    self.reader.consume(end + 1);
+                    return Some(bytes);
+                }
+                None => {
+                    let len = buf.len();
+                    line.get_or_insert_with(Vec::new).extend_from_slice(buf);
+                    self.reader.consume(len);
+                }
+            }
+        }
+    }
+    // The estimate, or the number of lines read so far once that's larger.
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.estimated_lines.max(self.line_number))
+    }
+}
+
+// Extrapolates the average line length of the start of the file (the first buffer of the reader,
+// i.e. a few KB) to the whole file.
+fn estimate_lines(start: &[u8], file_size: usize) -> usize {
+    if start.is_empty() {
+        return 0;
+    }
+    let newlines = start.iter().filter(|&&byte| byte == b'\n').count();
+    if start.len() == file_size {
+        // The whole file, only a missing final newline needs to be accounted for.
+        return newlines + !start.ends_with(b"\n") as usize;
+    }
+    if newlines == 0 {
+        return 1;
+    }
+    file_size * newlines / start.len()
+}
+
+impl<R: io::Read> Iterator for Lines<R> {
+    type Item = String;
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let bytes = self.read_line()?;
+            self.line_number += 1;
+            match String::from_utf8(bytes) {
+                Ok(line) => return Some(self.trim.apply(line)),
+                Err(error) => match self.on_invalid_utf8 {
+                    InvalidUtf8::Lossy => {
+                        let line = String::from_utf8_lossy(error.as_bytes()).into_owned();
+                        return Some(self.trim.apply(line));
+                    }
+                    InvalidUtf8::Skip => {}
+                    InvalidUtf8::Error => error::raise(PreprocessingError::Parse(format!(
+                        "Invalid UTF-8 in line {}: {}",
+                        self.line_number, error
+                    ))),
+                },
+            }
+        }
+    }
+}
 
 pub struct TxtLoader {
-    lines: io::Lines<io::BufReader<File>>,
+    lines: Lines,
 }
 
 impl TxtLoader {
-    pub fn new<P: AsRef<Path>>(file: P) -> io::Result<TxtLoader> {
-        let file = File::open(file)?;
+    pub fn new<P: AsRef<Path>>(file: P, on_invalid_utf8: InvalidUtf8) -> io::Result<TxtLoader> {
+        Ok(TxtLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+        })
+    }
+    // Reads the file in chunks of `buffer_size` bytes instead of the default 64 KiB.
+    pub fn with_buffer_size<P: AsRef<Path>>(
+        file: P,
+        on_invalid_utf8: InvalidUtf8,
+        buffer_size: usize,
+    ) -> io::Result<TxtLoader> {
         Ok(TxtLoader {
-            lines: io::BufReader::new(file).lines(),
+            lines: Lines::with_buffer_size(file, on_invalid_utf8, buffer_size)?,
         })
     }
+    // Strips whitespace from the lines as given by `trim` instead of only the `\r` of line endings.
+    pub fn with_trim(mut self, trim: LineTrim) -> TxtLoader {
+        self.lines.trim = trim;
+        self
+    }
 }
 
 impl Node for TxtLoader {
@@ -28,11 +290,610 @@ impl Node for TxtLoader {
         None
     }
     fn next(&mut self) -> Option<Self::Output> {
-        self.lines.next().map(|line| Text {
-            text: line.expect("Failed to read line"),
+        self.lines.next().map(|text| Text {
+            text,
             label: NoLabel(),
+            metadata: None,
         })
     }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+// Loads lines of whitespace separated token ids, e.g. a corpus that was tokenized beforehand, to
+// be fed to a `PreTokenized` node.
+pub struct IdsTxtLoader {
+    lines: Lines,
+}
+
+impl IdsTxtLoader {
+    pub fn new<P: AsRef<Path>>(file: P) -> io::Result<IdsTxtLoader> {
+        Ok(IdsTxtLoader {
+            lines: Lines::open(file, InvalidUtf8::Error)?,
+        })
+    }
+}
+
+impl Node for IdsTxtLoader {
+    type Output = Ids;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.lines.next().map(|line| {
+            let ids = line
+                .split_whitespace()
+                .map(|id| {
+                    id.parse().unwrap_or_else(|_| {
+                        error::raise(PreprocessingError::Parse(format!(
+                            "Invalid token id {:?} in line {:?}",
+                            id, line
+                        )))
+                    })
+                })
+                .collect();
+            Ids(ids)
+        })
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+// Reads lines from the standard input, e.g. for `cat corpus.txt | python preprocess.py`. The
+// input is buffered by the loader itself rather than through a `StdinLock`, which can't be sent
+// to other threads.
+pub struct StdinLoader {
+    lines: Lines<io::Stdin>,
+}
+
+impl StdinLoader {
+    pub fn new(on_invalid_utf8: InvalidUtf8) -> io::Result<StdinLoader> {
+        Ok(StdinLoader {
+            lines: Lines::from_reader(io::stdin(), on_invalid_utf8),
+        })
+    }
+}
+
+impl Node for StdinLoader {
+    type Output = Text<NoLabel>;
+    // Not supported, the input can only be read once.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.lines.next().map(|text| Text {
+            text,
+            label: NoLabel(),
+            metadata: None,
+        })
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+// Loads lines of the form `first<separator>second` as sequence pairs.
+pub struct PairTxtLoader {
+    lines: Lines,
+    separator: String,
+}
+
+impl PairTxtLoader {
+    pub fn new<P: AsRef<Path>>(
+        file: P,
+        separator: String,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<PairTxtLoader> {
+        if separator.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The separator must not be empty",
+            ));
+        }
+        Ok(PairTxtLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+            separator,
+        })
+    }
+}
+
+impl Node for PairTxtLoader {
+    type Output = TextPair<NoLabel>;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.lines.next().map(|line| {
+            let mut parts = line.split(self.separator.as_str());
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(first), Some(second), None) => TextPair {
+                    text: (first.to_string(), second.to_string()),
+                    label: NoLabel(),
+                    metadata: None,
+                },
+                _ => error::raise(PreprocessingError::Parse(format!(
+                    "Expected exactly one {:?} in line {:?}",
+                    self.separator, line
+                ))),
+            }
+        })
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+// Loads lines of the form `start<separator>end<separator>text` with answers given as token
+// indices, see `TokenSpan`. Lines with empty `start` and `end` have no answer.
+pub struct TokenSpanTxtLoader {
+    lines: Lines,
+    separator: String,
+}
+
+impl TokenSpanTxtLoader {
+    pub fn new<P: AsRef<Path>>(
+        file: P,
+        separator: String,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<TokenSpanTxtLoader> {
+        if separator.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The separator must not be empty",
+            ));
+        }
+        Ok(TokenSpanTxtLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+            separator,
+        })
+    }
+}
+
+impl Node for TokenSpanTxtLoader {
+    type Output = Text<TokenSpan>;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.lines.next().map(|line| {
+            let mut parts = line.splitn(3, self.separator.as_str());
+            let (span, text) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(""), Some(""), Some(text)) => (None, text),
+                (Some(start), Some(end), Some(text)) => match (start.parse(), end.parse()) {
+                    (Ok(start), Ok(end)) => (Some((start, end)), text),
+                    _ => error::raise(PreprocessingError::Parse(format!(
+                        "Invalid token indices in line {:?}",
+                        line
+                    ))),
+                },
+                _ => error::raise(PreprocessingError::Parse(format!(
+                    "Expected two {:?} in line {:?}",
+                    self.separator, line
+                ))),
+            };
+            Text {
+                text: text.to_string(),
+                label: TokenSpan(span),
+                metadata: None,
+            }
+        })
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+// Loads lines of the form `label<separator>text` with string labels, which can be turned into
+// class ids with a `LabelEncoder`. Lines without the separator are handled by `on_malformed`.
+pub struct LabelledTxtLoader {
+    lines: Lines,
+    separator: String,
+    on_malformed: MalformedPolicy,
+}
+
+impl LabelledTxtLoader {
+    pub fn new<P: AsRef<Path>>(
+        file: P,
+        separator: String,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<LabelledTxtLoader> {
+        if separator.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The separator must not be empty",
+            ));
+        }
+        Ok(LabelledTxtLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+            separator,
+            on_malformed: MalformedPolicy::Error,
+        })
+    }
+    pub fn with_on_malformed(mut self, on_malformed: MalformedPolicy) -> LabelledTxtLoader {
+        self.on_malformed = on_malformed;
+        self
+    }
+}
+
+impl Node for LabelledTxtLoader {
+    type Output = Text<StringLabel>;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let line = self.lines.next()?;
+            match line.split_once(self.separator.as_str()) {
+                Some((label, text)) => {
+                    return Some(Text {
+                        text: text.to_string(),
+                        label: StringLabel(label.to_string()),
+                        metadata: None,
+                    })
+                }
+                None => self.on_malformed.handle(
+                    &format!(
+                        "Expected a {:?} in line {} {:?}",
+                        self.separator, self.lines.line_number, line
+                    ),
+                    "it",
+                ),
+            }
+        }
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+// Pairs line `i` of a text file with line `i` of a label file. The labels are class ids, or
+// label names looked up in `mapping` if one is given. The files are read in lockstep, so
+// different line counts are only noticed once the shorter file ends. `on_malformed` handles
+// invalid labels and the extra lines of the longer file, which are skipped as a whole.
+pub struct PairedFilesLoader {
+    texts: Lines,
+    labels: Lines,
+    mapping: Option<HashMap<String, u32>>,
+    on_malformed: MalformedPolicy,
+}
+
+impl PairedFilesLoader {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        text_file: P,
+        label_file: Q,
+        mapping: Option<HashMap<String, u32>>,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<PairedFilesLoader> {
+        Ok(PairedFilesLoader {
+            texts: Lines::open(text_file, on_invalid_utf8)?,
+            labels: Lines::open(label_file, on_invalid_utf8)?,
+            mapping,
+            on_malformed: MalformedPolicy::Error,
+        })
+    }
+    pub fn with_on_malformed(mut self, on_malformed: MalformedPolicy) -> PairedFilesLoader {
+        self.on_malformed = on_malformed;
+        self
+    }
+    fn parse_label(&self, label: &str) -> Option<ClassLabel> {
+        let label = label.trim();
+        let id = match &self.mapping {
+            Some(mapping) => mapping.get(label).copied(),
+            None => label.parse().ok(),
+        };
+        if id.is_none() {
+            self.on_malformed.handle(
+                &format!(
+                    "Invalid label {:?} in line {}",
+                    label, self.labels.line_number
+                ),
+                "the line of both files",
+            );
+        }
+        id.map(ClassLabel)
+    }
+}
+
+impl Node for PairedFilesLoader {
+    type Output = Text<ClassLabel>;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            match (self.texts.next(), self.labels.next()) {
+                (Some(text), Some(label)) => {
+                    if let Some(label) = self.parse_label(&label) {
+                        return Some(Text {
+                            text,
+                            label,
+                            metadata: None,
+                        });
+                    }
+                }
+                (None, None) => return None,
+                (texts, _) => {
+                    self.on_malformed.handle(
+                        &format!(
+                            "The {} file has more lines than the {} file",
+                            if texts.is_some() { "text" } else { "label" },
+                            if texts.is_some() { "label" } else { "text" },
+                        ),
+                        "the rest",
+                    );
+                    return None;
+                }
+            }
+        }
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.mapping.clone()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.texts.estimated_len()
+    }
+}
+
+#[derive(Deserialize)]
+struct RetrievalPassage {
+    text: String,
+    label: u32,
+}
+#[derive(Deserialize)]
+struct RetrievalLine {
+    query: String,
+    passages: Vec<RetrievalPassage>,
+}
+
+// Loads JSON lines of a query with its candidate passages and their relevance labels (0 for
+// negatives), like
+// `{"query": "who wrote it", "passages": [{"text": "bob wrote it", "label": 1}, {"text": "it was in paris", "label": 0}]}`.
+// Queries without passages are skipped.
+pub struct RetrievalLoader {
+    lines: Lines,
+}
+
+impl RetrievalLoader {
+    pub fn new<P: AsRef<Path>>(
+        file: P,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<RetrievalLoader> {
+        Ok(RetrievalLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+        })
+    }
+}
+
+impl Node for RetrievalLoader {
+    type Output = Retrieval;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let line = self.lines.next()?;
+            let line: RetrievalLine = match serde_json::from_str(&line) {
+                Ok(line) => line,
+                Err(err) => error::raise(PreprocessingError::Parse(format!(
+                    "Invalid retrieval sample in line {}: {}",
+                    self.lines.line_number, err
+                ))),
+            };
+            if line.passages.is_empty() {
+                continue;
+            }
+            let passages = line
+                .passages
+                .into_iter()
+                .map(|passage| (passage.text, passage.label))
+                .collect();
+            return Some(Retrieval::new(line.query, passages));
+        }
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+#[derive(Deserialize)]
+struct MultipleChoiceLine {
+    context: String,
+    options: Vec<String>,
+    label: u32,
+}
+
+// Loads JSON lines of a context with its candidate answers and the index of the right one, like
+// `{"context": "who wrote it?", "options": ["bob", "paris"], "label": 0}`.
+pub struct MultipleChoiceLoader {
+    lines: Lines,
+}
+
+impl MultipleChoiceLoader {
+    pub fn new<P: AsRef<Path>>(
+        file: P,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<MultipleChoiceLoader> {
+        Ok(MultipleChoiceLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+        })
+    }
+}
+
+impl Node for MultipleChoiceLoader {
+    type Output = MultipleChoice;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let line = self.lines.next()?;
+        let line: MultipleChoiceLine = match serde_json::from_str(&line) {
+            Ok(line) => line,
+            Err(err) => error::raise(PreprocessingError::Parse(format!(
+                "Invalid multiple-choice sample in line {}: {}",
+                self.lines.line_number, err
+            ))),
+        };
+        if line.label as usize >= line.options.len() {
+            error::raise(PreprocessingError::Parse(format!(
+                "The label {} of line {} is out of range for {} options",
+                line.label,
+                self.lines.line_number,
+                line.options.len()
+            )));
+        }
+        Some(MultipleChoice::new(line.context, line.options, line.label))
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
+}
+
+#[derive(Deserialize)]
+struct MultiSpanLine {
+    question: Option<String>,
+    context: String,
+    spans: Vec<(usize, usize)>,
+}
+
+// Samples of a `MultiSpanLoader`: the context as a `Text`, or the question and the context as a
+// `TextPair`.
+pub trait MultiSpanSample: NodeOutput + Sized {
+    const PAIR: bool;
+    fn from_line(question: Option<String>, context: String, spans: Spans) -> Self;
+}
+
+impl MultiSpanSample for Text<Spans> {
+    const PAIR: bool = false;
+    fn from_line(_question: Option<String>, context: String, spans: Spans) -> Self {
+        Text {
+            text: context,
+            label: spans,
+            metadata: None,
+        }
+    }
+}
+
+impl MultiSpanSample for TextPair<Spans> {
+    const PAIR: bool = true;
+    fn from_line(question: Option<String>, context: String, spans: Spans) -> Self {
+        TextPair {
+            text: (question.expect("Checked by the loader"), context),
+            label: spans,
+            metadata: None,
+        }
+    }
+}
+
+// Loads JSON lines of answers made of several spans of the context (see `Spans`), like
+// `{"question": "who wrote it", "context": "bob and alice wrote it", "spans": [[0, 2], [8, 12]]}`
+// for MultiSpanQA. The spans are character offsets, both ends inclusive, and an empty list means
+// there is no answer. Text pairs need the question, texts ignore it. Lines that aren't of this
+// form or with spans outside of the context are handled by `on_malformed`.
+pub struct MultiSpanLoader<T> {
+    lines: Lines,
+    on_malformed: MalformedPolicy,
+    sample: std::marker::PhantomData<T>,
+}
+
+impl<T: MultiSpanSample> MultiSpanLoader<T> {
+    pub fn new<P: AsRef<Path>>(
+        file: P,
+        on_invalid_utf8: InvalidUtf8,
+    ) -> io::Result<MultiSpanLoader<T>> {
+        Ok(MultiSpanLoader {
+            lines: Lines::open(file, on_invalid_utf8)?,
+            on_malformed: MalformedPolicy::Error,
+            sample: std::marker::PhantomData,
+        })
+    }
+    pub fn with_on_malformed(mut self, on_malformed: MalformedPolicy) -> MultiSpanLoader<T> {
+        self.on_malformed = on_malformed;
+        self
+    }
+    fn parse(line: &str) -> Result<T, String> {
+        let line: MultiSpanLine = serde_json::from_str(line).map_err(|err| err.to_string())?;
+        if T::PAIR && line.question.is_none() {
+            return Err("missing field `question`".to_string());
+        }
+        let len = line.context.chars().count();
+        if let Some(&(start, end)) = line
+            .spans
+            .iter()
+            .find(|&&(start, end)| start > end || end >= len)
+        {
+            return Err(format!(
+                "the span ({}, {}) is outside of the {} characters of the context",
+                start, end, len
+            ));
+        }
+        Ok(T::from_line(
+            line.question,
+            line.context,
+            Spans::new(line.spans),
+        ))
+    }
+}
+
+impl<T: MultiSpanSample> Node for MultiSpanLoader<T> {
+    type Output = T;
+    // Not implemented for performance reasons
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let line = self.lines.next()?;
+            match Self::parse(&line) {
+                Ok(sample) => return Some(sample),
+                Err(err) => self.on_malformed.handle(
+                    &format!(
+                        "Invalid multi-span sample in line {}: {}",
+                        self.lines.line_number, err
+                    ),
+                    "it",
+                ),
+            }
+        }
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.lines.estimated_len()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -62,62 +923,493 @@ struct SQuAD {
     data: Vec<SQuADTopic>,
 }
 
+// Which of the answers of a question becomes its `Span` label. Only the answers found in the
+// context count, ties go to the earlier answer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnswerPolicy {
+    First,
+    Last,
+    // The answer with the fewest characters.
+    Shortest,
+    Longest,
+}
+
+impl FromStr for AnswerPolicy {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(AnswerPolicy::First),
+            "last" => Ok(AnswerPolicy::Last),
+            "shortest" => Ok(AnswerPolicy::Shortest),
+            "longest" => Ok(AnswerPolicy::Longest),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"first\", \"last\", \"shortest\" or \"longest\"",
+                s
+            ))),
+        }
+    }
+}
+
+impl AnswerPolicy {
+    // The selected one of the located answers, given as their byte ranges and texts.
+    fn select(self, answers: Vec<((usize, usize), &str)>) -> Option<((usize, usize), &str)> {
+        let length = |(_, text): &((usize, usize), &str)| text.chars().count();
+        match self {
+            AnswerPolicy::First => answers.into_iter().next(),
+            AnswerPolicy::Last => answers.into_iter().last(),
+            AnswerPolicy::Shortest => answers.into_iter().min_by_key(length),
+            // `max_by_key` returns the last of equal answers.
+            AnswerPolicy::Longest => answers.into_iter().rev().max_by_key(length),
+        }
+    }
+}
+
+// A question, its context is stored once per paragraph in `SQuADData::contexts`.
+struct SQuADSample {
+    question: String,
+    context: usize,
+    label: Span,
+}
+
+struct SQuADData {
+    contexts: Vec<String>,
+    samples: Vec<SQuADSample>,
+}
+
+impl SQuADData {
+    fn get(&self, index: usize) -> Option<TextPair<Span>> {
+        let sample = self.samples.get(index)?;
+        Some(TextPair {
+            text: (
+                sample.question.clone(),
+                self.contexts[sample.context].clone(),
+            ),
+            label: sample.label.clone(),
+            metadata: None,
+        })
+    }
+}
+
+// The options applied while the parsed files are turned into samples, see the `with_*` builders
+// of `SQuADLoader`.
+#[derive(Clone, Copy)]
+struct SQuADOptions {
+    end_inclusive: bool,
+    normalize_answers: bool,
+    max_questions_per_context: Option<usize>,
+    split_context: Option<usize>,
+    keep_answers: bool,
+    trim_answers: bool,
+    answer_policy: AnswerPolicy,
+}
+
+impl Default for SQuADOptions {
+    fn default() -> Self {
+        SQuADOptions {
+            end_inclusive: true,
+            normalize_answers: false,
+            max_questions_per_context: None,
+            split_context: None,
+            keep_answers: false,
+            trim_answers: true,
+            answer_policy: AnswerPolicy::First,
+        }
+    }
+}
+
+// Keeps every context once rather than in each of its samples, they are only paired with the
+// questions when a sample is returned. The data is shared by the clones returned by `try_clone`.
+// The files are parsed by the constructor, but only turned into samples when the first sample or
+// the length is requested, so the options of the builders apply to every sample. Setting them
+// afterwards has no effect.
 pub struct SQuADLoader {
-    texts: Vec<TextPair<Span>>,
+    // Taken by the first call of `data`.
+    parsed: Mutex<Option<Vec<SQuAD>>>,
+    data: OnceLock<Arc<SQuADData>>,
+    options: SQuADOptions,
     current_index: usize,
+    snap_window: usize,
+    impossible_to_cls: bool,
+    char_spans: bool,
 }
 
-impl SQuADLoader {
-    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = File::open(path)?;
-        let reader = io::BufReader::new(file);
-        let squad: SQuAD = serde_json::from_reader(reader)?;
-        let mut texts = Vec::new();
-        for topic in squad.data {
-            for paragraph in topic.paragraphs {
-                let SQuADParagraph { context, qas } = paragraph;
-                for qa in qas {
-                    let SQuADQuestion {
-                        question,
-                        answers,
-                        is_impossible,
-                        ..
-                    } = qa;
-                    let mut span = None;
-                    for answer in answers {
-                        let answer_start = answer.answer_start;
-                        let text = answer.text;
-                        if !is_impossible {
-                            span = Some((answer_start, answer_start + text.len() - 1));
+fn normalize_for_matching(text: &str) -> NormalizedString {
+    let mut normalized = NormalizedString::from(text);
+    normalized.nfd();
+    StripAccents
+        .normalize(&mut normalized)
+        .expect("Stripping accents can't fail");
+    normalized.lowercase();
+    normalized
+}
+
+// Byte range of the answer in the context, verified to contain the answer text. `answer_start` is
+// taken as a character offset, as which SQuAD defines it, and only as a byte offset if the answer
+// isn't there (both only differ after non-ASCII characters, where a byte offset may point at
+// another occurrence of the answer). Otherwise the occurrence of the answer closest to
+// `answer_start` is taken, which fixes dataset offset errors, and if there's none and
+// `normalize_answers` is set, the context is searched ignoring case and accents. The
+// normalization is only used for matching, the returned range points into the original context.
+// `None` if the answer isn't found at all.
+fn locate_answer(
+    context: &str,
+    answer_start: usize,
+    answer: &str,
+    normalize_answers: bool,
+) -> Option<(usize, usize)> {
+    let matches_at = |start: usize| context.get(start..start + answer.len()) == Some(answer);
+    let char_start = context
+        .char_indices()
+        .nth(answer_start)
+        .map(|(start, _)| start);
+    if let Some(start) = char_start.filter(|&start| matches_at(start)) {
+        return Some((start, start + answer.len()));
+    }
+    if matches_at(answer_start) {
+        return Some((answer_start, answer_start + answer.len()));
+    }
+    let target = char_start.unwrap_or(answer_start);
+    let closest = context
+        .match_indices(answer)
+        .map(|(start, _)| start)
+        .min_by_key(|start| start.abs_diff(target));
+    if let Some(start) = closest {
+        return Some((start, start + answer.len()));
+    }
+    if !normalize_answers {
+        return None;
+    }
+    let normalized_context = normalize_for_matching(context);
+    let normalized_answer = normalize_for_matching(answer);
+    let needle = normalized_answer.get();
+    if needle.is_empty() {
+        return None;
+    }
+    normalized_context
+        .get()
+        .match_indices(needle)
+        .filter_map(|(start, _)| {
+            normalized_context.convert_offsets(Range::Normalized(start..start + needle.len()))
+        })
+        .min_by_key(|range| range.start.abs_diff(target))
+        .map(|range| (range.start, range.end))
+}
+
+// The answer without surrounding whitespace, with `answer_start` moved past the removed
+// characters.
+fn trim_answer(answer_start: usize, answer: &str) -> (usize, &str) {
+    let trimmed = answer.trim_start();
+    let leading = answer[..answer.len() - trimmed.len()].chars().count();
+    (answer_start + leading, trimmed.trim_end())
+}
+
+// Character span of the byte span `(start, last)` of `text`, both ends inclusive. `last` may be any
+// byte of the last character.
+fn char_span(text: &str, (start, last): (usize, usize)) -> (usize, usize) {
+    let char_index = |byte: usize| {
+        text.char_indices()
+            .take_while(|&(offset, _)| offset <= byte)
+            .count()
+            - 1
+    };
+    (char_index(start), char_index(last))
+}
+
+// Quotes and brackets that may follow the end of a sentence.
+const CLOSING_CHARS: &[char] = &['"', '\'', ')', ']', '”', '’'];
+
+// Byte ranges of the chunks of at most `max_chars` characters a context is split into. A chunk
+// ends at the last paragraph break (newline) within the limit, or else at the last sentence end
+// or whitespace, but never within one of the `answers` (byte ranges, both ends inclusive). Without
+// such a boundary the chunk is cut at the limit, or after the answer the limit falls into.
+// Whitespace around the chunks isn't part of them.
+fn split_context_chunks(
+    context: &str,
+    max_chars: usize,
+    answers: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let splits_answer = |end: usize| {
+        answers
+            .iter()
+            .any(|&(start, last)| start < end && end <= last)
+    };
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        start = context.len() - context[start..].trim_start().len();
+        if start == context.len() {
+            return chunks;
+        }
+        let rest = &context[start..];
+        let limit = match rest.char_indices().nth(max_chars) {
+            Some((offset, _)) => start + offset,
+            None => {
+                chunks.push((start, start + rest.trim_end().len()));
+                return chunks;
+            }
+        };
+        let (mut paragraph, mut sentence, mut word) = (None, None, None);
+        for (offset, c) in rest.char_indices() {
+            let end = start + offset;
+            if end > limit {
+                break;
+            }
+            if !c.is_whitespace() || splits_answer(end) {
+                continue;
+            }
+            let before = context[start..end].trim_end_matches(CLOSING_CHARS);
+            if c == '\n' {
+                paragraph = Some(end);
+            } else if before.ends_with(&['.', '!', '?'][..]) {
+                sentence = Some(end);
+            } else {
+                word = Some(end);
+            }
+        }
+        let end = paragraph.or(sentence).or(word).unwrap_or_else(|| {
+            answers
+                .iter()
+                .filter(|&&(answer_start, last)| answer_start < limit && limit <= last)
+                .map(|&(_, last)| last + 1)
+                .max()
+                .unwrap_or(limit)
+        });
+        chunks.push((start, start + context[start..end].trim_end().len()));
+        start = end;
+    }
+}
+
+fn parse_squad<P: AsRef<Path>>(path: P) -> Result<SQuAD, PreprocessingError> {
+    let reader = io::BufReader::new(Source::open_decompressed(path)?);
+    serde_json::from_reader(reader)
+        .map_err(|err| PreprocessingError::Parse(format!("Invalid SQuAD file: {}", err)))
+}
+
+// The samples of the parsed files, in the order of the files.
+fn squad_data(parsed: Vec<SQuAD>, options: &SQuADOptions) -> SQuADData {
+    let mut contexts = Vec::new();
+    let mut samples = Vec::new();
+    for topic in parsed.into_iter().flat_map(|squad| squad.data) {
+        for paragraph in topic.paragraphs {
+            let SQuADParagraph { context, qas } = paragraph;
+            let mut questions = Vec::new();
+            // Only the first questions of a paragraph are kept.
+            let max_questions = options.max_questions_per_context.unwrap_or(usize::MAX);
+            for qa in qas.into_iter().take(max_questions) {
+                let SQuADQuestion {
+                    question,
+                    answers,
+                    is_impossible,
+                    ..
+                } = qa;
+                let mut located = Vec::new();
+                for answer in &answers {
+                    let (answer_start, text) = match options.trim_answers {
+                        true => trim_answer(answer.answer_start, &answer.text),
+                        false => (answer.answer_start, answer.text.as_str()),
+                    };
+                    // Answers of only whitespace leave the question impossible.
+                    if !is_impossible && !text.is_empty() {
+                        let normalize = options.normalize_answers;
+                        match locate_answer(&context, answer_start, text, normalize) {
+                            Some((start, end)) => located.push(((start, end - 1), text)),
+                            None => warnings::warn(&format!(
+                                "Answer {:?} of question {:?} not found in its context, ignoring it",
+                                answer.text, question
+                            )),
                         }
                     }
-                    let text = TextPair {
-                        text: (question, context.clone()),
-                        label: Span(span),
-                    };
-                    texts.push(text);
                 }
+                let (span, answer_text) = match options.answer_policy.select(located) {
+                    Some((span, text)) => (Some(span), Some(text.to_string())),
+                    None => (None, None),
+                };
+                questions.push((question, span, answer_text.filter(|_| options.keep_answers)));
             }
+            let max_chars = match options.split_context {
+                Some(max_chars) if context.chars().count() > max_chars => max_chars,
+                _ => {
+                    for (question, span, answer) in questions {
+                        // The answers are located as byte ranges, spans count characters.
+                        let span = span.map(|span| char_span(&context, span));
+                        samples.push(SQuADSample {
+                            question,
+                            context: contexts.len(),
+                            label: Span::new(span)
+                                .with_end_inclusive(options.end_inclusive)
+                                .with_answer(answer),
+                        });
+                    }
+                    contexts.push(context);
+                    continue;
+                }
+            };
+            let answers: Vec<_> = questions.iter().filter_map(|(_, span, _)| *span).collect();
+            let chunks = split_context_chunks(&context, max_chars, &answers);
+            let first_chunk = contexts.len();
+            for (question, span, answer) in questions {
+                for (i, &(chunk_start, chunk_end)) in chunks.iter().enumerate() {
+                    let span = span
+                        .filter(|&(start, end)| chunk_start <= start && end < chunk_end)
+                        .map(|(start, end)| (start - chunk_start, end - chunk_start))
+                        .map(|span| char_span(&context[chunk_start..chunk_end], span));
+                    // Like the span, the answer text only goes with the chunk containing it.
+                    let answer = answer.clone().filter(|_| span.is_some());
+                    samples.push(SQuADSample {
+                        question: question.clone(),
+                        context: first_chunk + i,
+                        label: Span::new(span)
+                            .with_end_inclusive(options.end_inclusive)
+                            .with_answer(answer),
+                    });
+                }
+            }
+            contexts.extend(
+                chunks
+                    .into_iter()
+                    .map(|(start, end)| context[start..end].to_string()),
+            );
         }
-        Ok(SQuADLoader {
-            texts,
+    }
+    SQuADData { contexts, samples }
+}
+
+impl SQuADLoader {
+    // Files ending in `.gz` or `.zst` are decompressed while they are parsed.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, PreprocessingError> {
+        Ok(SQuADLoader::from_parsed(vec![parse_squad(path)?]))
+    }
+    // The examples of several SQuAD files in one loader, in the order of the files, e.g. to train
+    // on SQuAD 1.1 and 2.0 together. Every file is loaded like by `new`.
+    pub fn from_paths<P: AsRef<Path>>(paths: Vec<P>) -> Result<Self, PreprocessingError> {
+        if paths.is_empty() {
+            return Err(PreprocessingError::InvalidArgument(
+                "At least one SQuAD file is needed".to_string(),
+            ));
+        }
+        let parsed = paths
+            .into_iter()
+            .map(parse_squad)
+            .collect::<Result<_, _>>()?;
+        Ok(SQuADLoader::from_parsed(parsed))
+    }
+    fn from_parsed(parsed: Vec<SQuAD>) -> Self {
+        SQuADLoader {
+            parsed: Mutex::new(Some(parsed)),
+            data: OnceLock::new(),
+            options: SQuADOptions::default(),
             current_index: 0,
+            snap_window: 0,
+            impossible_to_cls: false,
+            char_spans: false,
+        }
+    }
+    fn data(&self) -> &Arc<SQuADData> {
+        self.data.get_or_init(|| {
+            let parsed = self
+                .parsed
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+                .unwrap_or_default();
+            Arc::new(squad_data(parsed, &self.options))
         })
     }
+    // Whether the span ends point at the last answer token or one past it, see
+    // `Span::with_end_inclusive`. Inclusive by default.
+    pub fn with_end_inclusive(mut self, end_inclusive: bool) -> Self {
+        self.options.end_inclusive = end_inclusive;
+        self
+    }
+    // Answers not found at `answer_start` or elsewhere in the context are searched ignoring case
+    // and accents.
+    pub fn with_normalize_answers(mut self, normalize_answers: bool) -> Self {
+        self.options.normalize_answers = normalize_answers;
+        self
+    }
+    // Only the first `max_questions` questions of every context are kept.
+    pub fn with_max_questions_per_context(mut self, max_questions: usize) -> Self {
+        self.options.max_questions_per_context = Some(max_questions);
+        self
+    }
+    // Contexts longer than `max_chars` characters are split into chunks (see
+    // `split_context_chunks`) and every question is paired with every chunk of its context. The
+    // answer is attached to the chunk that contains it, the other chunks are impossible.
+    pub fn with_split_context(mut self, max_chars: usize) -> Result<Self, PreprocessingError> {
+        if max_chars == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The maximum context length has to be positive".to_string(),
+            ));
+        }
+        self.options.split_context = Some(max_chars);
+        Ok(self)
+    }
+    // The spans also keep the answer text, which ends up in `BatchSpan::answers`.
+    pub fn with_keep_answers(mut self, keep_answers: bool) -> Self {
+        self.options.keep_answers = keep_answers;
+        self
+    }
+    // Whitespace around the answer texts is removed before they are located, so the spans only
+    // cover the answer words. On by default.
+    pub fn with_trim_answers(mut self, trim_answers: bool) -> Self {
+        self.options.trim_answers = trim_answers;
+        self
+    }
+    // Selects the answer of questions with several, the first one by default.
+    pub fn with_answer_policy(mut self, answer_policy: AnswerPolicy) -> Self {
+        self.options.answer_policy = answer_policy;
+        self
+    }
+    // Answers whose ends don't map to tokens are snapped to tokens within `snap_window`
+    // characters, see `Span::with_snap_window`.
+    pub fn with_snap_window(mut self, snap_window: usize) -> Self {
+        self.snap_window = snap_window;
+        self
+    }
+    // Impossible questions point at the `[CLS]` token, see `Span::with_impossible_to_cls`.
+    pub fn with_impossible_to_cls(mut self, impossible_to_cls: bool) -> Self {
+        self.impossible_to_cls = impossible_to_cls;
+        self
+    }
+    // The batches carry the character spans of the answers, see `Span::with_char_span`.
+    pub fn with_char_spans(mut self, char_spans: bool) -> Self {
+        self.char_spans = char_spans;
+        self
+    }
+    fn configure_span(&self, mut sample: TextPair<Span>) -> TextPair<Span> {
+        sample.label = sample
+            .label
+            .with_snap_window(self.snap_window)
+            .with_impossible_to_cls(self.impossible_to_cls)
+            .with_char_span(self.char_spans);
+        sample
+    }
 }
 
 impl Node for SQuADLoader {
     type Output = TextPair<Span>;
     fn get(&self, index: usize) -> Option<Self::Output> {
-        let text = self.texts.get(index)?;
-        Some(text.clone())
+        self.data()
+            .get(index)
+            .map(|sample| self.configure_span(sample))
     }
     fn len(&self) -> Option<usize> {
-        Some(self.texts.len())
+        Some(self.data().samples.len())
     }
     fn next(&mut self) -> Option<Self::Output> {
-        let text = self.texts.get(self.current_index)?;
+        let text = self.data().get(self.current_index)?;
         self.current_index += 1;
-        Some(text.clone())
+        Some(self.configure_span(text))
+    }
+    fn try_clone(&self) -> Option<Box<dyn Node<Output = Self::Output>>> {
+        Some(Box::new(SQuADLoader {
+            parsed: Mutex::new(None),
+            data: OnceLock::from(Arc::clone(self.data())),
+            options: self.options,
+            current_index: 0,
+            snap_window: self.snap_window,
+            impossible_to_cls: self.impossible_to_cls,
+            char_spans: self.char_spans,
+        }))
     }
 }
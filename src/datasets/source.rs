@@ -0,0 +1,88 @@
+// Input of the file based loaders: a local file or, with the `http` feature, the body of an
+// `http://` or `https://` URL. The body is streamed, nothing is downloaded beforehand.
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub(crate) enum Source {
+    File(File),
+    #[cfg(feature = "http")]
+    Http(Box<reqwest::blocking::Response>),
+}
+
+fn url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+impl Source {
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> io::Result<Source> {
+        match url(path.as_ref()) {
+            Some(url) => Source::get(url),
+            None => Ok(Source::File(File::open(path)?)),
+        }
+    }
+    #[cfg(feature = "http")]
+    fn get(url: &str) -> io::Result<Source> {
+        // The error already names the URL.
+        let to_io = |err: reqwest::Error| {
+            let kind = match err.status() {
+                Some(reqwest::StatusCode::NOT_FOUND) => io::ErrorKind::NotFound,
+                Some(reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::UNAUTHORIZED) => {
+                    io::ErrorKind::PermissionDenied
+                }
+                _ => io::ErrorKind::Other,
+            };
+            io::Error::new(kind, err)
+        };
+        // Without a timeout, since the body is read while the pipeline runs.
+        let client = reqwest::blocking::Client::builder()
+            .timeout(None)
+            .build()
+            .map_err(to_io)?;
+        let response = client
+            .get(url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(to_io)?;
+        Ok(Source::Http(Box::new(response)))
+    }
+    #[cfg(not(feature = "http"))]
+    fn get(url: &str) -> io::Result<Source> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Loading {} requires the http feature", url),
+        ))
+    }
+    // The source decompressed according to the extension of the path: gzip for `.gz`, Zstandard
+    // for `.zst`, anything else is read as it is. Decompression is streamed like the source.
+    pub(crate) fn open_decompressed<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read + Send>> {
+        let path = path.as_ref();
+        let source = Source::open(path)?;
+        Ok(
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("gz") => Box::new(flate2::read::MultiGzDecoder::new(source)),
+                Some("zst") => Box::new(zstd::stream::read::Decoder::new(source)?),
+                _ => Box::new(source),
+            },
+        )
+    }
+    // Size in bytes, if known (servers may not send a `Content-Length`).
+    pub(crate) fn len(&self) -> io::Result<Option<u64>> {
+        match self {
+            Source::File(file) => Ok(Some(file.metadata()?.len())),
+            #[cfg(feature = "http")]
+            Source::Http(response) => Ok(response.content_length()),
+        }
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::File(file) => file.read(buf),
+            #[cfg(feature = "http")]
+            Source::Http(response) => response.read(buf),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Export of the batch matrices as DLPack tensors (https://dmlc.github.io/dlpack/latest/), so
+// `torch.from_dlpack` and the other consumers share the memory of a matrix instead of copying it.
+// Only what the matrices need is supported: 2D CPU tensors of uint32.
+use std::any::Any;
+use std::ffi::c_void;
+
+use ndarray::Array2;
+
+// `kDLCPU` and `kDLUInt` of `dlpack.h`.
+const DEVICE_CPU: i32 = 1;
+const TYPE_UINT: u8 = 1;
+
+// `struct DLDevice`.
+#[repr(C)]
+pub(crate) struct DlDevice {
+    pub(crate) device_type: i32,
+    pub(crate) device_id: i32,
+}
+
+// `struct DLDataType`.
+#[repr(C)]
+pub(crate) struct DlDataType {
+    pub(crate) code: u8,
+    pub(crate) bits: u8,
+    pub(crate) lanes: u16,
+}
+
+// `struct DLTensor`.
+#[repr(C)]
+pub(crate) struct DlTensor {
+    pub(crate) data: *mut c_void,
+    pub(crate) device: DlDevice,
+    pub(crate) ndim: i32,
+    pub(crate) dtype: DlDataType,
+    pub(crate) shape: *mut i64,
+    pub(crate) strides: *mut i64,
+    pub(crate) byte_offset: u64,
+}
+
+// `struct DLManagedTensor`. The consumer calls `deleter` once it's done with the tensor, which
+// frees it together with the buffers owned by `manager_ctx`.
+#[repr(C)]
+pub(crate) struct DlManagedTensor {
+    pub(crate) dl_tensor: DlTensor,
+    pub(crate) manager_ctx: *mut c_void,
+    pub(crate) deleter: Option<unsafe extern "C" fn(*mut DlManagedTensor)>,
+}
+
+struct TensorPrivate {
+    // Dropped by `deleter`, see `from_array2`.
+    _owner: Box<dyn Any + Send>,
+    shape: [i64; 2],
+    strides: [i64; 2],
+}
+
+impl DlManagedTensor {
+    // A tensor sharing the memory of `array`. The tensor is leaked until `deleter` is called,
+    // e.g. by the consumer or by the destructor of a capsule that was never consumed.
+    //
+    // Safety: `owner` has to keep `array` alive and unchanged until then.
+    pub(crate) unsafe fn from_array2(
+        array: &Array2<u32>,
+        owner: Box<dyn Any + Send>,
+    ) -> *mut DlManagedTensor {
+        let (rows, cols) = array.dim();
+        let strides = array.strides();
+        let mut private = Box::new(TensorPrivate {
+            _owner: owner,
+            shape: [rows as i64, cols as i64],
+            // In elements, like ndarray's.
+            strides: [strides[0] as i64, strides[1] as i64],
+        });
+        let tensor = DlTensor {
+            data: array.as_ptr() as *mut c_void,
+            device: DlDevice {
+                device_type: DEVICE_CPU,
+                device_id: 0,
+            },
+            ndim: 2,
+            dtype: DlDataType {
+                code: TYPE_UINT,
+                bits: 32,
+                lanes: 1,
+            },
+            shape: private.shape.as_mut_ptr(),
+            strides: private.strides.as_mut_ptr(),
+            byte_offset: 0,
+        };
+        Box::into_raw(Box::new(DlManagedTensor {
+            dl_tensor: tensor,
+            manager_ctx: Box::into_raw(private) as *mut c_void,
+            deleter: Some(delete_tensor),
+        }))
+    }
+}
+
+unsafe extern "C" fn delete_tensor(tensor: *mut DlManagedTensor) {
+    let tensor = Box::from_raw(tensor);
+    drop(Box::from_raw(tensor.manager_ctx as *mut TensorPrivate));
+}
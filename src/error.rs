@@ -0,0 +1,31 @@
+use std::io;
+
+use thiserror::Error;
+
+// Errors raised while building a pipeline. In Python every variant becomes a matching built-in
+// exception, see `python.rs`.
+#[derive(Debug, Error)]
+pub enum PreprocessingError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Tokenizer(#[from] tokenizers::Error),
+    // The upstream node doesn't provide what the node needs, e.g. random access.
+    #[error("{0}")]
+    IncompatibleNode(String),
+    #[error("This node is already in use by another node.")]
+    NodeConsumed,
+    // Malformed input data or option strings.
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    InvalidArgument(String),
+}
+
+// Errors that `Node::next` and `Node::get` can't return, like a file that fails to read half way
+// through, are raised as a panic with the error as its payload. It reaches the caller through the
+// worker threads of `DataLoader` and `Pipelined` like any panic, and the Python bindings raise the
+// exception of the error instead of a `PanicException`.
+pub(crate) fn raise(err: PreprocessingError) -> ! {
+    std::panic::panic_any(err)
+}
@@ -1,51 +1,459 @@
 //use ndarray::prelude::*;
-#![feature(associated_type_bounds)]
-use numpy::ndarray::prelude::*;
+use ndarray::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::iter;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::Duration;
 use std::usize;
-use tokenizers::tokenizer;
+use tokenizers::normalizers::Sequence;
+use tokenizers::processors::template::TemplateProcessing;
+use tokenizers::tokenizer::{self, TruncationDirection, TruncationParams, TruncationStrategy};
 
-mod datasets;
-mod python;
+#[cfg(feature = "python")]
+mod arrow;
+mod buffer_pool;
+pub mod datasets;
+#[cfg(feature = "dlpack")]
+mod dlpack;
+mod error;
+pub mod nodes;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod retry;
+pub mod seed;
 mod test;
+pub mod warnings;
 
-use python::ToPyObjectConsume;
+use buffer_pool::BufferPool;
+pub use error::PreprocessingError;
+use nodes::{AnswerRetention, BatchStats, TruncationStats};
+use retry::Retry;
 
-pub trait Node: Send {
-    type Output: ToPyObjectConsume;
+// Bound of everything a node can output. The Python bindings need to convert it to Python
+// objects, without them it only has to be shareable between threads.
+#[cfg(feature = "python")]
+pub trait NodeOutput: python::ToPyObjectConsume {}
+#[cfg(feature = "python")]
+impl<T: python::ToPyObjectConsume> NodeOutput for T {}
+#[cfg(not(feature = "python"))]
+pub trait NodeOutput: Send + Sync {}
+#[cfg(not(feature = "python"))]
+impl<T: Send + Sync> NodeOutput for T {}
+
+// `Sync` so indexable pipelines can be read from several threads at once.
+pub trait Node: Send + Sync {
+    type Output: NodeOutput;
     fn get(&self, index: usize) -> Option<Self::Output>;
     fn len(&self) -> Option<usize>;
     fn next(&mut self) -> Option<Self::Output>;
+    // Outputs `start..start + count`, fewer if the node ends before. Empty for nodes that aren't
+    // indexable.
+    fn get_many(&self, start: usize, count: usize) -> Vec<Self::Output> {
+        (start..start.saturating_add(count))
+            .map_while(|index| self.get(index))
+            .collect()
+    }
+    // Statistics of the produced batches if this node (or a node it wraps) is a `Stats` node.
+    fn stats(&self) -> Option<BatchStats> {
+        None
+    }
+    // Truncation counters if this node is a `StaticBatcher` (or wraps one).
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        None
+    }
+    // How many answers survived tokenization if this node is a `Tokenizer` (or wraps one).
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        None
+    }
+    // Non-padding tokens produced so far if this node is a `Tokenizer` or `StaticBatcher` (or
+    // wraps one), counted by the last of them in the pipeline. Samples built more than once, e.g.
+    // by `get` and `next`, count every time. Nodes that change the number of tokens, like `Pack`
+    // or `RandomCrop`, return `None` if there's no batcher after them.
+    fn total_tokens(&self) -> Option<u64> {
+        None
+    }
+    // Longest sample in tokens that this node produces if it's a tokenizer truncating its samples
+    // (or wraps one), so that batchers can check their sequence length against it.
+    fn max_length(&self) -> Option<usize> {
+        None
+    }
+    // The Hugging Face tokenizer if this node is a `Tokenizer` (or wraps one), e.g. to look up
+    // the vocabulary size and special tokens a model is built with.
+    fn tokenizer(&self) -> Option<Arc<tokenizer::Tokenizer>> {
+        None
+    }
+    // Token count of every sample if this node is an indexable tokenizer (or wraps one).
+    fn lengths(&self) -> Option<Array1<i64>> {
+        None
+    }
+    // Mean and standard deviation of every feature if this node is a `Standardize` node (or wraps
+    // one).
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        None
+    }
+    // Text of the tokens `start_token..=end_token` in overflowing window `window` of an example.
+    // `None` if no indexable tokenizer is part of the pipeline, `Some(None)` if the tokens don't
+    // map to a single sequence of the example.
+    fn reconstruct(
+        &self,
+        _example_index: usize,
+        _window: usize,
+        _start_token: usize,
+        _end_token: usize,
+    ) -> Option<Option<String>> {
+        None
+    }
+    // Context tokens (`start..end`, counted from the first token of the context) that each
+    // overflowing window of an example covers, to debug how the windows overlap. The context is the
+    // last sequence of a sample. `None` if no indexable tokenizer is part of the pipeline.
+    fn window_ranges(&self, _example_index: usize) -> Option<Vec<(usize, usize)>> {
+        None
+    }
+    // Label to id mapping if this node is a `LabelEncoder` (or wraps one).
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        None
+    }
+    // Reseeds stochastic nodes for a new epoch, see `seed::epoch_seed`. Nodes with an upstream node
+    // pass it on, so it can be called on the last node of a pipeline.
+    fn set_epoch(&self, _epoch: u64) {}
+    // Flushes the buffering nodes of the pipeline (like `BufferedShuffle` or `Pack`): they stop
+    // reading from upstream, so `next` emits what they hold back and then returns `None`. A
+    // buffering node that has another one upstream keeps reading from it until that one is
+    // drained. Returns whether a buffering node was finalized. Without calling it, buffering
+    // nodes emit their remaining contents once upstream is exhausted.
+    fn finalize(&mut self) -> bool {
+        false
+    }
+    // Makes the tokenizer of the pipeline keep the text of every sample in its encodings, which
+    // `WithText` then emits along with them. Returns whether there is such a tokenizer. Samples
+    // that were already encoded (like a peeked one) don't get their text.
+    fn keep_texts(&mut self) -> bool {
+        false
+    }
+    // The timer of a `Timed` node. Unlike the other methods it isn't passed on by wrapping nodes,
+    // the Python bindings collect the timers of a pipeline while it's built.
+    fn timer(&self) -> Option<nodes::Timer> {
+        None
+    }
+    // A copy that reads the same data from the start, for nodes whose data can be shared cheaply
+    // (like the in-memory samples of a `SQuADLoader`). `None` for streaming nodes.
+    fn try_clone(&self) -> Option<Box<dyn Node<Output = Self::Output>>> {
+        None
+    }
+    // Bounds on the total number of outputs, like `Iterator::size_hint`. Exact for indexable nodes
+    // by default.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.len() {
+            Some(len) => (len, Some(len)),
+            None => (0, None),
+        }
+    }
+    // Estimated total number of outputs for progress bars, e.g. extrapolated from the start of
+    // the file by the streaming loaders. Unlike `size_hint` it can be too large or too small.
+    // Defaults to the exact count if `size_hint` knows it.
+    fn estimated_len(&self) -> Option<usize> {
+        match self.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(lower),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct NoLabel();
+// Character span of an answer, both ends inclusive. The indices count characters (not bytes) from
+// the start of the sequence, loaders of datasets with byte offsets convert them.
+#[derive(Clone)]
+pub struct Span {
+    span: Option<(usize, usize)>,
+    // Index of the sequence the answer lives in. `None` means the last one.
+    sequence_index: Option<usize>,
+    // Whether the tokenized end is the last answer token (default) or the one after it.
+    end_inclusive: bool,
+    // Gold answer text, carried through to `BatchSpan::answers` for evaluation.
+    answer: Option<String>,
+    // How many characters around an end that doesn't map to a token are searched for one that
+    // does, see `with_snap_window`.
+    snap_window: usize,
+    // Whether an impossible question points at the `[CLS]` token, see `with_impossible_to_cls`.
+    impossible_to_cls: bool,
+    // Whether the characters the answer tokens cover are carried to the batch, see
+    // `with_char_span`.
+    char_span: bool,
+}
+
+impl Span {
+    pub fn new(span: Option<(usize, usize)>) -> Span {
+        Span {
+            span,
+            sequence_index: None,
+            end_inclusive: true,
+            answer: None,
+            snap_window: 0,
+            impossible_to_cls: false,
+            char_span: false,
+        }
+    }
+    pub fn in_sequence(span: Option<(usize, usize)>, sequence_index: usize) -> Span {
+        Span {
+            span,
+            sequence_index: Some(sequence_index),
+            end_inclusive: true,
+            answer: None,
+            snap_window: 0,
+            impossible_to_cls: false,
+            char_span: false,
+        }
+    }
+    pub fn with_end_inclusive(mut self, end_inclusive: bool) -> Span {
+        self.end_inclusive = end_inclusive;
+        self
+    }
+    pub fn with_answer(mut self, answer: Option<String>) -> Span {
+        self.answer = answer;
+        self
+    }
+    // Ends of the span often fall on characters without a token, like whitespace the normalizer
+    // removed. Instead of dropping the answer, the nearest character within `snap_window` that
+    // maps to a token is used, searching inside the span before outside of it. 0 drops such
+    // answers. Next to the edges of overflowing windows this can also recover answers that are
+    // partly cut off.
+    pub fn with_snap_window(mut self, snap_window: usize) -> Span {
+        self.snap_window = snap_window;
+        self
+    }
+    // Points the span of impossible questions at the `[CLS]` token like the classic SQuAD 2.0
+    // training recipe, i.e. `(0, 0)` (`(0, 1)` without `end_inclusive`) instead of no span. This
+    // needs a tokenizer that puts a special token at index 0 (e.g. through
+    // `Tokenizer::with_pair_template`), impossible questions stay without a span otherwise. The
+    // span then counts as an answer, so it replaces the answerability flags of `BatchSpan` and
+    // `SpanToAnswerable` (which are 1 for these questions) rather than complementing them.
+    // Answers that aren't part of an overflowing window keep having no span.
+    pub fn with_impossible_to_cls(mut self, impossible_to_cls: bool) -> Span {
+        self.impossible_to_cls = impossible_to_cls;
+        self
+    }
+    // Carries the characters of the sequence covered by the answer tokens to
+    // `BatchSpan::answer_char_start` and `answer_char_end`, so predictions can be evaluated on the
+    // text without looking up the offsets again.
+    pub fn with_char_span(mut self, char_span: bool) -> Span {
+        self.char_span = char_span;
+        self
+    }
+}
+
+// Answer made of several disjoint character spans, e.g. in MultiSpanQA. Like `Span`, both ends
+// are inclusive and the spans refer to the last sequence.
+#[derive(Clone)]
+pub struct Spans(Vec<(usize, usize)>);
+
+impl Spans {
+    pub fn new(spans: Vec<(usize, usize)>) -> Spans {
+        Spans(spans)
+    }
+}
+
+// Answer given directly as token indices of the encoding, both ends inclusive, for data that is
+// already aligned to the tokenizer. The indices refer to the whole encoding (including special
+// tokens), so they aren't meant for overflowing windows.
+#[derive(Clone)]
+pub struct TokenSpan(Option<(usize, usize)>);
+
+impl TokenSpan {
+    pub fn new(span: Option<(usize, usize)>) -> TokenSpan {
+        TokenSpan(span)
+    }
+}
+
+#[derive(Clone)]
+pub struct ClassLabel(u32);
+
+// The class ids of a sample with any number of classes, for multi-label classification.
+#[derive(Clone)]
+pub struct MultiLabel(Vec<u32>);
+
+impl MultiLabel {
+    pub fn new(ids: Vec<u32>) -> MultiLabel {
+        MultiLabel(ids)
+    }
+}
+
+// Raw class name, converted into a `ClassLabel` by a `LabelEncoder`.
+#[derive(Clone)]
+pub struct StringLabel(String);
+
+// Two labels of one sample for multitask training, e.g. the answer span of a question and
+// whether it's answerable, for separate heads. Tokenizing and batching handle both labels on their
+// own and pair the results, so the tokenized and batched labels are `Both` as well.
+#[derive(Clone)]
+pub struct Both<A, B>(A, B);
+
+impl<A, B> Both<A, B> {
+    pub fn new(first: A, second: B) -> Both<A, B> {
+        Both(first, second)
+    }
+}
+
+// A label that only some samples have, e.g. for semi-supervised training on a mix of labelled and
+// unlabelled texts. Unlabelled samples are batched like the rows of a padded batch (their labels
+// are the ignore index), and the batch has a mask of the labelled rows.
 #[derive(Clone)]
-pub struct Span(Option<(usize, usize)>);
+pub struct OptionalLabel<T>(Option<T>);
+
+impl<T> OptionalLabel<T> {
+    pub fn new(label: Option<T>) -> OptionalLabel<T> {
+        OptionalLabel(label)
+    }
+}
 
-//pub trait Label: ToPyObjectConsume {
-pub trait Label: Clone + ToPyObjectConsume {
+pub trait Label: Clone + NodeOutput {
     type Tokenized: TokenizedLabel;
     fn tokenize(self, encoding: &tokenizer::Encoding, starting_index: usize) -> Self::Tokenized;
+    // Whether the label points at an answer in the text, for `AnswerRetention`.
+    fn has_answer(&self) -> bool {
+        false
+    }
+    // Whether the label refers to positions in the sequences, which fixes their order (see
+    // `Tokenizer::with_longer_second`).
+    const POSITIONAL: bool = false;
+    // Whether the label is given in characters, which are mapped to tokens with the offsets of the
+    // encoding (see `check_char_offsets`).
+    const CHAR_OFFSETS: bool = false;
 }
 
 impl Label for Span {
     type Tokenized = TokenizedSpan;
+    const POSITIONAL: bool = true;
+    const CHAR_OFFSETS: bool = true;
     fn tokenize(self, encoding: &tokenizer::Encoding, starting_index: usize) -> TokenizedSpan {
-        if let Some((mut start, mut end)) = self.0 {
-            if start < starting_index {
-                return TokenizedSpan(None);
+        let span = match self.span {
+            None if self.impossible_to_cls => {
+                let cls = encoding.get_special_tokens_mask().first() == Some(&1);
+                cls.then_some((0, !self.end_inclusive as usize))
             }
-            start -= starting_index;
-            end -= starting_index;
-            let sequence_index = encoding.n_sequences() - 1;
-            if let (Some(start), Some(end)) = (
-                encoding.char_to_token(start, sequence_index),
-                encoding.char_to_token(end, sequence_index),
-            ) {
-                return TokenizedSpan(Some((start, end)));
+            _ => self.token_span(encoding, starting_index),
+        };
+        // The end of the span in characters is exclusive either way.
+        let char_span = match (self.char_span, self.span, span) {
+            (true, Some(_), Some((start, end))) => {
+                let last = end - !self.end_inclusive as usize;
+                let offsets = encoding.get_offsets();
+                Some((
+                    offsets[start].0 + starting_index,
+                    offsets[last].1 + starting_index,
+                ))
             }
+            _ => None,
+        };
+        TokenizedSpan(span, self.answer, char_span)
+    }
+    fn has_answer(&self) -> bool {
+        self.span.is_some()
+    }
+}
+
+impl Span {
+    fn token_span(
+        &self,
+        encoding: &tokenizer::Encoding,
+        starting_index: usize,
+    ) -> Option<(usize, usize)> {
+        let (start, end) = self.span?;
+        if start < starting_index {
+            return None;
+        }
+        let sequence_index = self
+            .sequence_index
+            .unwrap_or_else(|| encoding.n_sequences() - 1);
+        if sequence_index >= encoding.n_sequences() {
+            return None;
         }
-        TokenizedSpan(None)
+        let to_token = |index: usize| {
+            encoding.char_to_token(index.checked_sub(starting_index)?, sequence_index)
+        };
+        // A character can be split into several tokens (e.g. by byte-level tokenizers), the span
+        // ends with the last of them.
+        let to_last_token = |index: usize| {
+            let token = to_token(index)?;
+            let position = index - starting_index;
+            let covers = |&token: &usize| {
+                let (start, end) = encoding.get_offsets()[token];
+                encoding.token_to_sequence(token) == Some(sequence_index)
+                    && start <= position
+                    && position < end
+            };
+            (token..encoding.len()).take_while(covers).last()
+        };
+        let start_token = (0..=self.snap_window)
+            .map(|distance| start + distance)
+            .filter(|&index| index <= end)
+            .chain((1..=self.snap_window).filter_map(|distance| start.checked_sub(distance)))
+            .find_map(to_token)?;
+        let end_token = (0..=self.snap_window)
+            .filter_map(|distance| end.checked_sub(distance))
+            .filter(|&index| index >= start)
+            .chain((1..=self.snap_window).map(|distance| end + distance))
+            .find_map(to_last_token)?;
+        Some((
+            start_token,
+            if self.end_inclusive {
+                end_token
+            } else {
+                end_token + 1
+            },
+        ))
+    }
+}
+
+// Every span is tokenized like a `Span`, spans that don't map to tokens of the encoding are
+// dropped. The token spans are sorted and overlapping ones are merged, so they stay disjoint.
+impl Label for Spans {
+    type Tokenized = TokenizedSpans;
+    const POSITIONAL: bool = true;
+    const CHAR_OFFSETS: bool = true;
+    fn tokenize(self, encoding: &tokenizer::Encoding, starting_index: usize) -> TokenizedSpans {
+        let mut spans: Vec<(usize, usize)> = self
+            .0
+            .into_iter()
+            .filter_map(|span| Span::new(Some(span)).tokenize(encoding, starting_index).0)
+            .collect();
+        spans.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in spans {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        TokenizedSpans(merged)
+    }
+    fn has_answer(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+// The indices are kept as they are, spans that don't fit into the encoding (e.g. because it was
+// truncated) become `None`.
+impl Label for TokenSpan {
+    type Tokenized = TokenizedSpan;
+    const POSITIONAL: bool = true;
+    fn tokenize(self, encoding: &tokenizer::Encoding, _starting_index: usize) -> TokenizedSpan {
+        TokenizedSpan(
+            self.0
+                .filter(|&(start, end)| start <= end && end < encoding.len()),
+            None,
+            None,
+        )
+    }
+    fn has_answer(&self) -> bool {
+        self.0.is_some()
     }
 }
 
@@ -56,112 +464,747 @@ impl Label for NoLabel {
     }
 }
 
+// String labels have to be encoded before tokenization, tokenizing drops them.
+impl Label for StringLabel {
+    type Tokenized = NoTokenizedLabel;
+    fn tokenize(self, _encoding: &tokenizer::Encoding, _starting_index: usize) -> NoTokenizedLabel {
+        NoTokenizedLabel
+    }
+}
+
+// Class ids don't depend on the tokenization, so they are their own tokenized label.
+impl Label for ClassLabel {
+    type Tokenized = ClassLabel;
+    fn tokenize(self, _encoding: &tokenizer::Encoding, _starting_index: usize) -> ClassLabel {
+        self
+    }
+}
+
+impl Label for MultiLabel {
+    type Tokenized = MultiLabel;
+    fn tokenize(self, _encoding: &tokenizer::Encoding, _starting_index: usize) -> MultiLabel {
+        self
+    }
+}
+
+// Has an answer if either label has one.
+impl<A: Label, B: Label> Label for Both<A, B> {
+    type Tokenized = Both<A::Tokenized, B::Tokenized>;
+    const POSITIONAL: bool = A::POSITIONAL || B::POSITIONAL;
+    const CHAR_OFFSETS: bool = A::CHAR_OFFSETS || B::CHAR_OFFSETS;
+    fn tokenize(self, encoding: &tokenizer::Encoding, starting_index: usize) -> Self::Tokenized {
+        Both(
+            self.0.tokenize(encoding, starting_index),
+            self.1.tokenize(encoding, starting_index),
+        )
+    }
+    fn has_answer(&self) -> bool {
+        self.0.has_answer() || self.1.has_answer()
+    }
+}
+
+impl<T: Label> Label for OptionalLabel<T> {
+    type Tokenized = OptionalLabel<T::Tokenized>;
+    const POSITIONAL: bool = T::POSITIONAL;
+    const CHAR_OFFSETS: bool = T::CHAR_OFFSETS;
+    fn tokenize(self, encoding: &tokenizer::Encoding, starting_index: usize) -> Self::Tokenized {
+        OptionalLabel(self.0.map(|label| label.tokenize(encoding, starting_index)))
+    }
+    fn has_answer(&self) -> bool {
+        self.0.as_ref().is_some_and(Label::has_answer)
+    }
+}
+
+#[derive(Clone)]
 pub struct NoTokenizedLabel;
-pub struct TokenizedSpan(Option<(usize, usize)>);
+#[derive(Clone)]
+// Token span of an answer and the answer text if the `Span` has one. The end follows the
+// `end_inclusive` setting of the `Span`, answers that aren't in the encoding are `None` and become
+// `(0, 0)` in a `BatchSpan`, i.e. point at the first token like unanswerable questions in
+// `transformers`. If no row of a batch has an answer they become the ignore index instead, see
+// `BatchSpan::answerable`. The last field is the character span the answer tokens cover, with an
+// exclusive end, if the `Span` keeps it (see `Span::with_char_span`).
+pub struct TokenizedSpan(
+    Option<(usize, usize)>,
+    Option<String>,
+    Option<(usize, usize)>,
+);
 
-pub trait TokenizedLabel: Sized + ToPyObjectConsume {
+// Disjoint token spans of a `Spans` answer, both ends inclusive.
+#[derive(Clone)]
+pub struct TokenizedSpans(Vec<(usize, usize)>);
+
+// Label value of batch positions that shouldn't contribute to the loss, the default
+// `ignore_index` of PyTorch's cross entropy loss.
+pub const IGNORE_INDEX: i64 = -100;
+
+pub trait TokenizedLabel: Clone + Sized + NodeOutput {
     type Batch: BatchLabel;
-    fn to_batch(selfs: Vec<Self>) -> Self::Batch;
+    // Rows that are `None` (like the repeated rows of a padded batch) and padding within rows are
+    // set to `ignore_index`.
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> Self::Batch;
+    // Whether an answer of the text is part of the encoding, for `AnswerRetention`.
+    fn has_answer(&self) -> bool {
+        false
+    }
 }
 impl TokenizedLabel for NoTokenizedLabel {
     type Batch = NoBatchLabel;
-    fn to_batch(_selfs: Vec<Self>) -> NoBatchLabel {
+    fn to_batch(_selfs: Vec<Option<Self>>, _ignore_index: i64) -> NoBatchLabel {
         NoBatchLabel
     }
 }
 
 impl TokenizedLabel for TokenizedSpan {
     type Batch = BatchSpan;
-    fn to_batch(selfs: Vec<Self>) -> Self::Batch {
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> Self::Batch {
         let mut start = Vec::new();
         let mut end = Vec::new();
+        let mut answers = Vec::new();
+        let mut char_spans = Vec::new();
+        let answerable: Array1<i64> = selfs
+            .iter()
+            .map(|span| matches!(span, Some(TokenizedSpan(Some(_), ..))) as i64)
+            .collect();
+        // A batch of only `(0, 0)` (e.g. after truncating every answer away) can't be told apart
+        // from answers at the first token, which without special tokens is a word of the text.
+        let no_answer = match answerable.sum() {
+            0 => ignore_index,
+            _ => 0,
+        };
         for span in selfs.into_iter() {
-            match span.0 {
-                Some((start_index, end_index)) => {
-                    start.push(start_index);
-                    end.push(end_index);
-                }
-                None => {
-                    start.push(0);
-                    end.push(0);
+            let (start_index, end_index) = match &span {
+                Some(TokenizedSpan(Some((start_index, end_index)), ..)) => {
+                    (*start_index as i64, *end_index as i64)
                 }
+                Some(TokenizedSpan(None, ..)) => (no_answer, no_answer),
+                None => (ignore_index, ignore_index),
             };
+            start.push(start_index);
+            end.push(end_index);
+            char_spans.push(span.as_ref().and_then(|span| span.2));
+            answers.push(span.and_then(|span| span.1));
         }
         let start = Array1::from_vec(start);
         let end = Array1::from_vec(end);
-        BatchSpan { start, end }
+        // Only kept if any sample carries its answer text, rows without one get an empty string.
+        let answers = answers
+            .iter()
+            .any(Option::is_some)
+            .then(|| answers.into_iter().map(Option::unwrap_or_default).collect());
+        // Likewise for the character spans, rows without one get the ignore index.
+        let has_char_spans = char_spans.iter().any(Option::is_some);
+        let char_column = |position: fn((usize, usize)) -> usize| {
+            let column = char_spans
+                .iter()
+                .map(|span| span.map_or(ignore_index, |span| position(span) as i64));
+            Array1::from_iter(column)
+        };
+        BatchSpan {
+            start,
+            end,
+            answers,
+            answerable,
+            answer_char_start: has_char_spans.then(|| char_column(|(start, _)| start)),
+            answer_char_end: has_char_spans.then(|| char_column(|(_, end)| end)),
+        }
+    }
+    fn has_answer(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+// Rows with fewer spans than the row with the most are padded with `ignore_index`.
+impl TokenizedLabel for TokenizedSpans {
+    type Batch = BatchSpans;
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> BatchSpans {
+        let n_spans = selfs
+            .iter()
+            .flatten()
+            .map(|spans| spans.0.len())
+            .max()
+            .unwrap_or(0);
+        let mut start = Array2::from_elem((selfs.len(), n_spans), ignore_index);
+        let mut end = Array2::from_elem((selfs.len(), n_spans), ignore_index);
+        for (i, spans) in selfs.into_iter().enumerate() {
+            for (j, (start_index, end_index)) in
+                spans.into_iter().flat_map(|spans| spans.0).enumerate()
+            {
+                start[[i, j]] = start_index as i64;
+                end[[i, j]] = end_index as i64;
+            }
+        }
+        BatchSpans { start, end }
+    }
+    fn has_answer(&self) -> bool {
+        !self.0.is_empty()
     }
 }
 
+impl TokenizedLabel for ClassLabel {
+    type Batch = BatchClassLabel;
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> BatchClassLabel {
+        let labels = selfs
+            .into_iter()
+            .map(|label| label.map_or(ignore_index, |label| label.0 as i64))
+            .collect();
+        BatchClassLabel(Array1::from_vec(labels))
+    }
+}
+
+// Every row has as many columns as the row with the most classes, the rest is `ignore_index`.
+impl TokenizedLabel for MultiLabel {
+    type Batch = BatchMultiLabel;
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> BatchMultiLabel {
+        let width = selfs
+            .iter()
+            .flatten()
+            .map(|label| label.0.len())
+            .max()
+            .unwrap_or(0);
+        let mut labels = Array2::from_elem((selfs.len(), width), ignore_index);
+        for (mut row, label) in labels.rows_mut().into_iter().zip(selfs) {
+            for (entry, &id) in row.iter_mut().zip(label.iter().flat_map(|label| &label.0)) {
+                *entry = id as i64;
+            }
+        }
+        BatchMultiLabel(labels)
+    }
+}
+
+impl<A: TokenizedLabel, B: TokenizedLabel> TokenizedLabel for Both<A, B> {
+    type Batch = Both<A::Batch, B::Batch>;
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> Self::Batch {
+        let (first, second): (Vec<Option<A>>, Vec<Option<B>>) = selfs
+            .into_iter()
+            .map(|label| match label {
+                Some(Both(first, second)) => (Some(first), Some(second)),
+                None => (None, None),
+            })
+            .unzip();
+        Both(
+            A::to_batch(first, ignore_index),
+            B::to_batch(second, ignore_index),
+        )
+    }
+    fn has_answer(&self) -> bool {
+        self.0.has_answer() || self.1.has_answer()
+    }
+}
+
+impl<T: TokenizedLabel> TokenizedLabel for OptionalLabel<T> {
+    type Batch = BatchOptionalLabel<T::Batch>;
+    fn to_batch(selfs: Vec<Option<Self>>, ignore_index: i64) -> Self::Batch {
+        let (labels, present): (Vec<Option<T>>, Vec<i64>) = selfs
+            .into_iter()
+            .map(|label| {
+                let label = label.and_then(|label| label.0);
+                let present = label.is_some() as i64;
+                (label, present)
+            })
+            .unzip();
+        BatchOptionalLabel {
+            labels: T::to_batch(labels, ignore_index),
+            present: Array1::from_vec(present),
+        }
+    }
+    fn has_answer(&self) -> bool {
+        self.0.as_ref().is_some_and(TokenizedLabel::has_answer)
+    }
+}
+
+#[derive(Clone)]
 pub struct NoBatchLabel;
+#[derive(Clone)]
 pub struct BatchSpan {
-    start: Array1<usize>,
-    end: Array1<usize>,
+    start: Array1<i64>,
+    end: Array1<i64>,
+    answers: Option<Vec<String>>,
+    // 1 for the rows whose answer is in the encoding, 0 for unanswerable rows and the repeated
+    // rows of a padded batch. Not one of the named arrays, since QA models don't take it.
+    answerable: Array1<i64>,
+    // Character span of every answer in its sequence, with an exclusive end, if any of the spans
+    // keeps it (see `Span::with_char_span`). Rows without an answer are the ignore index.
+    answer_char_start: Option<Array1<i64>>,
+    answer_char_end: Option<Array1<i64>>,
+}
+impl BatchSpan {
+    // The start and end positions as the columns of one `(batch, 2)` array.
+    pub fn positions(&self) -> Array2<i64> {
+        ndarray::stack![Axis(1), self.start, self.end]
+    }
+}
+#[derive(Clone)]
+pub struct BatchSpans {
+    start: Array2<i64>,
+    end: Array2<i64>,
+}
+#[derive(Clone)]
+pub struct BatchClassLabel(Array1<i64>);
+// Language modelling targets, the input ids with the ignore index at padding, see
+// `CausalLMBatcher`.
+#[derive(Clone)]
+pub struct BatchLMLabels(Array2<i64>);
+#[derive(Clone)]
+pub struct BatchMultiLabel(Array2<i64>);
+// The batched labels of an `OptionalLabel` and whether each row has a label (1) or not (0). Rows
+// of a padded batch count as unlabelled.
+#[derive(Clone)]
+pub struct BatchOptionalLabel<T> {
+    labels: T,
+    present: Array1<i64>,
 }
 
-impl BatchLabel for NoBatchLabel {}
-impl BatchLabel for BatchSpan {}
+impl BatchLabel for NoBatchLabel {
+    fn into_named_arrays(self) -> NamedArrays {
+        Vec::new()
+    }
+}
+impl BatchLabel for BatchSpan {
+    fn into_named_arrays(self) -> NamedArrays {
+        vec![
+            ("start_positions", self.start.into_dyn()),
+            ("end_positions", self.end.into_dyn()),
+        ]
+    }
+}
+impl BatchLabel for BatchSpans {
+    fn into_named_arrays(self) -> NamedArrays {
+        vec![
+            ("start_positions", self.start.into_dyn()),
+            ("end_positions", self.end.into_dyn()),
+        ]
+    }
+}
+impl BatchLabel for BatchClassLabel {
+    fn into_named_arrays(self) -> NamedArrays {
+        vec![("labels", self.0.into_dyn())]
+    }
+}
+impl BatchLabel for BatchLMLabels {
+    fn into_named_arrays(self) -> NamedArrays {
+        vec![("labels", self.0.into_dyn())]
+    }
+}
+impl BatchLabel for BatchMultiLabel {
+    fn into_named_arrays(self) -> NamedArrays {
+        vec![("labels", self.0.into_dyn())]
+    }
+}
+// The arrays of the first label followed by those of the second, e.g. `start_positions`,
+// `end_positions` and `labels` for a span and a class.
+impl<A: BatchLabel, B: BatchLabel> BatchLabel for Both<A, B> {
+    fn into_named_arrays(self) -> NamedArrays {
+        let mut arrays = self.0.into_named_arrays();
+        arrays.extend(self.1.into_named_arrays());
+        arrays
+    }
+}
+// The arrays of the labels followed by the mask as `label_mask`.
+impl<T: BatchLabel> BatchLabel for BatchOptionalLabel<T> {
+    fn into_named_arrays(self) -> NamedArrays {
+        let mut arrays = self.labels.into_named_arrays();
+        arrays.push(("label_mask", self.present.into_dyn()));
+        arrays
+    }
+}
+
+// Arbitrary values carried along with a sample, like its id or source file, see `WithMetadata`.
+pub type Metadata = HashMap<String, String>;
 
 #[derive(Clone)]
 pub struct Text<T: Label> {
     text: String,
     label: T,
+    metadata: Option<Metadata>,
 }
 
 #[derive(Clone)]
 pub struct TextPair<T: Label> {
     text: (String, String),
     label: T,
+    metadata: Option<Metadata>,
+}
+
+// Character ranges (`start..end`) of the whitespace-delimited words of `text`, e.g. to relate the
+// word ids of a tokenizer's encoding back to the words and from there to positions in the text.
+pub fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.chars().enumerate() {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                spans.push((word_start, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    if let Some(word_start) = start {
+        spans.push((word_start, text.chars().count()));
+    }
+    spans
+}
+
+// A query with candidate passages, labelled with their relevance to it (0 for negatives), for
+// training retrievers and rerankers. Loaded by a `RetrievalLoader`, batched by a
+// `RetrievalBatcher`.
+#[derive(Clone)]
+pub struct Retrieval {
+    query: String,
+    passages: Vec<Text<ClassLabel>>,
+}
+
+impl Retrieval {
+    pub fn new(query: String, passages: Vec<(String, u32)>) -> Retrieval {
+        Retrieval {
+            query,
+            passages: passages
+                .into_iter()
+                .map(|(text, label)| Text {
+                    text,
+                    label: ClassLabel(label),
+                    metadata: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+// A context (or question) with candidate answers, `label` being the index of the right one, for
+// multiple-choice tasks like SWAG or RACE. Loaded by a `MultipleChoiceLoader`, batched by a
+// `MultipleChoiceBatcher`.
+#[derive(Clone)]
+pub struct MultipleChoice {
+    context: String,
+    options: Vec<String>,
+    label: u32,
+}
+
+impl MultipleChoice {
+    pub fn new(context: String, options: Vec<String>, label: u32) -> MultipleChoice {
+        MultipleChoice {
+            context,
+            options,
+            label,
+        }
+    }
+}
+
+// Token ids of a text that was tokenized beforehand, turned into a `TokenizedText` by a
+// `PreTokenized` node without running a tokenizer.
+#[derive(Clone)]
+pub struct Ids(Vec<u32>);
+
+impl Ids {
+    pub fn new(ids: Vec<u32>) -> Ids {
+        Ids(ids)
+    }
 }
 
 pub trait Sample {
     type Label: Label;
+    // Which sequence gets cut when the tokenizer truncates the sample.
+    const TRUNCATION_STRATEGY: TruncationStrategy;
     fn tokenize(
         self,
         tokenizer: &tokenizer::Tokenizer,
     ) -> TokenizedText<<<Self as Sample>::Label as Label>::Tokenized>;
+    // Like `tokenize`, but keeps every overflowing window as a separate sample.
+    fn tokenize_overflowing(
+        self,
+        tokenizer: &tokenizer::Tokenizer,
+    ) -> Vec<TokenizedText<<<Self as Sample>::Label as Label>::Tokenized>>;
+    // The sequences passed to the tokenizer.
+    fn input(&self) -> tokenizer::EncodeInput<'_>;
+    // Special tokens are only added by `Tokenizer::with_pair_template`. The offsets of the
+    // encoding are characters, like the indices of spans.
+    fn encode(
+        &self,
+        tokenizer: &tokenizer::Tokenizer,
+        add_special_tokens: bool,
+    ) -> tokenizer::Encoding {
+        tokenizer
+            .encode_char_offsets(self.input(), add_special_tokens)
+            .expect("Failed to tokenize")
+    }
+    fn sequence(&self, index: usize) -> Option<&str>;
+    // The `word_spans` of sequence `index`, counted from the start of the sequence.
+    fn word_spans(&self, index: usize) -> Option<Vec<(usize, usize)>> {
+        self.sequence(index).map(word_spans)
+    }
+    fn into_label(self) -> Self::Label;
+    // The metadata tokenizers move into the `Encoding`, see `WithMetadata`.
+    fn take_metadata(&mut self) -> Option<Metadata>;
+    fn set_metadata(&mut self, metadata: Metadata);
+    // The sample with the longer of its sequences second, see `Tokenizer::with_longer_second`.
+    fn longer_second(self) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
 }
 
 impl<T: Label> Sample for Text<T> {
     type Label = T;
+    const TRUNCATION_STRATEGY: TruncationStrategy = TruncationStrategy::LongestFirst;
     fn tokenize(self, tokenizer: &tokenizer::Tokenizer) -> TokenizedText<T::Tokenized> {
-        let tokens = tokenizer
-            .encode(self.text, false)
-            .expect("Failed to tokenize");
-        let label = self.label.tokenize(&tokens, 0);
-        TokenizedText {
-            encoding: Encoding::from_tokenizer_encoding(
-                tokens,
-                tokenizer.get_padding().map_or(0, |pad| pad.pad_id),
-            ),
-            label: label,
+        let tokens = self.encode(tokenizer, false);
+        let mut sample =
+            TokenizedText::from_tokenizer_encoding(tokens, self.label, pad_token(tokenizer));
+        sample.encoding.metadata = self.metadata;
+        sample
+    }
+    fn tokenize_overflowing(
+        self,
+        tokenizer: &tokenizer::Tokenizer,
+    ) -> Vec<TokenizedText<T::Tokenized>> {
+        let tokens = self.encode(tokenizer, false);
+        let mut samples =
+            TokenizedText::from_overflowing_encoding(tokens, self.label, pad_token(tokenizer));
+        for sample in &mut samples {
+            sample.encoding.metadata = self.metadata.clone();
         }
+        samples
+    }
+    fn input(&self) -> tokenizer::EncodeInput<'_> {
+        self.text.as_str().into()
+    }
+    fn sequence(&self, index: usize) -> Option<&str> {
+        match index {
+            0 => Some(&self.text),
+            _ => None,
+        }
+    }
+    fn into_label(self) -> T {
+        self.label
+    }
+    fn take_metadata(&mut self) -> Option<Metadata> {
+        self.metadata.take()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = Some(metadata);
     }
 }
 
+// Only the second sequence (the context for QA) is windowed, the first is repeated.
 impl<T: Label> Sample for TextPair<T> {
     type Label = T;
+    const TRUNCATION_STRATEGY: TruncationStrategy = TruncationStrategy::OnlySecond;
     fn tokenize(self, tokenizer: &tokenizer::Tokenizer) -> TokenizedText<T::Tokenized> {
-        let tokens = tokenizer
-            .encode(self.text, false)
-            .expect("Failed to tokenize");
-        let label = self.label.tokenize(&tokens, 0);
-        TokenizedText {
-            encoding: Encoding::from_tokenizer_encoding(
-                tokens,
-                tokenizer.get_padding().map_or(0, |pad| pad.pad_id),
-            ),
-            label: label,
+        let tokens = self.encode(tokenizer, false);
+        let mut sample =
+            TokenizedText::from_tokenizer_encoding(tokens, self.label, pad_token(tokenizer));
+        sample.encoding.metadata = self.metadata;
+        sample
+    }
+    fn tokenize_overflowing(
+        self,
+        tokenizer: &tokenizer::Tokenizer,
+    ) -> Vec<TokenizedText<T::Tokenized>> {
+        let tokens = self.encode(tokenizer, false);
+        let mut samples =
+            TokenizedText::from_overflowing_encoding(tokens, self.label, pad_token(tokenizer));
+        for sample in &mut samples {
+            sample.encoding.metadata = self.metadata.clone();
+        }
+        samples
+    }
+    fn input(&self) -> tokenizer::EncodeInput<'_> {
+        (self.text.0.as_str(), self.text.1.as_str()).into()
+    }
+    fn sequence(&self, index: usize) -> Option<&str> {
+        match index {
+            0 => Some(&self.text.0),
+            1 => Some(&self.text.1),
+            _ => None,
+        }
+    }
+    fn into_label(self) -> T {
+        self.label
+    }
+    fn take_metadata(&mut self) -> Option<Metadata> {
+        self.metadata.take()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = Some(metadata);
+    }
+    // Sequences are compared by their number of characters. Pairs with positional labels keep
+    // their order.
+    fn longer_second(mut self) -> Self {
+        if !T::POSITIONAL && self.text.0.chars().count() > self.text.1.chars().count() {
+            let (first, second) = self.text;
+            self.text = (second, first);
+        }
+        self
+    }
+}
+
+// The encoding followed by its overflowing windows.
+fn windows(mut encoding: tokenizer::Encoding) -> Vec<tokenizer::Encoding> {
+    let overflowing = encoding.take_overflowing();
+    let n_sequences = encoding.n_sequences();
+    let mut windows = vec![encoding];
+    for window in overflowing {
+        if window.n_sequences() < n_sequences {
+            windows.push(restore_sequence_ranges(window));
+        } else {
+            windows.push(window);
+        }
+    }
+    windows
+}
+
+// The characters `start..end` of `text`, `None` if they are out of bounds.
+fn char_slice(text: &str, start: usize, end: usize) -> Option<&str> {
+    let byte = |index: usize| {
+        text.char_indices()
+            .map(|(offset, _)| offset)
+            .chain(iter::once(text.len()))
+            .nth(index)
+    };
+    text.get(byte(start)?..byte(end)?)
+}
+
+// Pad id the tokenizer is configured with, 0 if it has none. `Tokenizer` replaces it with the
+// result of `resolve_pad_token`.
+fn pad_token(tokenizer: &tokenizer::Tokenizer) -> u32 {
+    tokenizer.get_padding().map_or(0, |pad| pad.pad_id)
+}
+
+// Ids of the special tokens of a tokenizer, looked up under their BERT and RoBERTa names. The pad
+// token is the one of the padding configuration if there is one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpecialTokenIds {
+    pub pad: Option<u32>,
+    pub cls: Option<u32>,
+    pub sep: Option<u32>,
+    pub mask: Option<u32>,
+}
+
+impl SpecialTokenIds {
+    pub fn of(tokenizer: &tokenizer::Tokenizer) -> SpecialTokenIds {
+        let find = |names: [&str; 2]| names.iter().find_map(|name| tokenizer.token_to_id(name));
+        SpecialTokenIds {
+            pad: tokenizer
+                .get_padding()
+                .map(|padding| padding.pad_id)
+                .or_else(|| find(["[PAD]", "<pad>"])),
+            cls: find(["[CLS]", "<s>"]),
+            sep: find(["[SEP]", "</s>"]),
+            mask: find(["[MASK]", "<mask>"]),
         }
     }
 }
 
-#[derive(Debug)]
+// The pad token fills the padded region of the batches. An explicit `pad_token` takes
+// precedence over the configured padding. Without either, a `[PAD]` or `<pad>` token of the
+// vocabulary is used. Tokenizers without one (like GPT-2's) need an explicit id, since falling
+// back to 0 would pad with a real token.
+fn resolve_pad_token(
+    tokenizer: &tokenizer::Tokenizer,
+    pad_token: Option<u32>,
+) -> Result<u32, PreprocessingError> {
+    let pad_id = match (pad_token, tokenizer.get_padding()) {
+        (Some(pad_id), _) => pad_id,
+        (None, Some(padding)) => return Ok(padding.pad_id),
+        (None, None) => SpecialTokenIds::of(tokenizer).pad.ok_or_else(|| {
+            PreprocessingError::InvalidArgument(
+                "The tokenizer has no pad token, it has to be given explicitly".to_string(),
+            )
+        })?,
+    };
+    if tokenizer.id_to_token(pad_id).is_none() {
+        return Err(PreprocessingError::InvalidArgument(format!(
+            "The pad token {} isn't in the vocabulary",
+            pad_id
+        )));
+    }
+    Ok(pad_id)
+}
+
+// Labels in characters are mapped to tokens with the offsets of the encoding, so with a tokenizer
+// that doesn't provide them (every token covers an empty range) all of them would silently be
+// dropped. Checked on the encoding of a probe text, an empty encoding passes.
+fn check_char_offsets(probe: &tokenizer::Encoding) -> Result<(), PreprocessingError> {
+    let offsets = probe.get_offsets();
+    if offsets.is_empty() || offsets.iter().any(|&(start, end)| start < end) {
+        return Ok(());
+    }
+    Err(PreprocessingError::IncompatibleNode(
+        "The tokenizer doesn't provide character offsets, which span labels need to be mapped to tokens"
+            .to_string(),
+    ))
+}
+
+// Overflowing windows of a pair lose their sequence ranges when no post-processor adds special
+// tokens, so they are rebuilt from the type ids of the non-special tokens.
+fn restore_sequence_ranges(encoding: tokenizer::Encoding) -> tokenizer::Encoding {
+    let mut sequence_ranges: HashMap<usize, Range<usize>> = HashMap::new();
+    let tokens = encoding
+        .get_type_ids()
+        .iter()
+        .zip(encoding.get_special_tokens_mask());
+    for (i, (&type_id, &special)) in tokens.enumerate() {
+        if special == 0 {
+            sequence_ranges
+                .entry(type_id as usize)
+                .or_insert(i..i + 1)
+                .end = i + 1;
+        }
+    }
+    tokenizer::Encoding::new(
+        encoding.get_ids().to_vec(),
+        encoding.get_type_ids().to_vec(),
+        encoding.get_tokens().to_vec(),
+        encoding.get_word_ids().to_vec(),
+        encoding.get_offsets().to_vec(),
+        encoding.get_special_tokens_mask().to_vec(),
+        encoding.get_attention_mask().to_vec(),
+        Vec::new(),
+        sequence_ranges,
+    )
+}
+
+#[derive(Clone, Debug)]
 pub struct Encoding {
     input_ids: Array1<u32>,
+    attention_mask: Array1<u32>,
+    token_type_ids: Array1<u32>,
     pad_token: u32,
+    // Index of the example this encoding was cut from, set when overflowing windows are kept.
+    example_index: Option<usize>,
+    // 1 for the tokens of the last (windowed) sequence that weren't already part of the previous
+    // overlapping window, 0 for everything else. Lets postprocessing pick every answer from one
+    // window only.
+    owned_tokens: Array1<u32>,
+    // 1 for a leading special token (like `[CLS]`) and the tokens of the first sequence of a pair,
+    // i.e. the question in QA. Stacked into the `global_attention_mask` of Longformer-style models.
+    global_attention: Array1<u32>,
+    // 1 for the first token of every word, 0 for the following subwords of a word and special
+    // tokens. Stacked into the `word_start_mask` for first-subword pooling, e.g. in NER.
+    word_starts: Array1<u8>,
+    // 1 for the non-special tokens of the last sequence, i.e. the context of a QA pair, 0 for the
+    // question and special tokens. Unlike `owned_tokens`, it includes the overlap with the previous
+    // window. Stacked into the `context_mask`.
+    context: Array1<u8>,
+    // 1-based index of the packed sequence every token belongs to, only set by `Pack`.
+    sequence_ids: Option<Array1<u32>>,
+    // Number of special tokens (like `[CLS]`) in front of the first sequence.
+    prefix_special_tokens: usize,
+    // Index of the first token of the last sequence, i.e. where the context of a QA pair starts.
+    context_start: usize,
+    // Text the encoding was built from, only kept for `WithText` and `HashId`.
+    text: Option<String>,
+    // Hash of the text, only set by `HashId`.
+    hash_id: Option<u64>,
+    // Metadata of the sample the encoding was built from, see `WithMetadata`.
+    metadata: Option<Metadata>,
 }
 
+#[derive(Clone)]
 pub struct TokenizedText<T: TokenizedLabel> {
     encoding: Encoding,
     label: T,
@@ -173,52 +1216,1019 @@ impl Encoding {
         //clone :(
         let input_ids = encoding.get_ids().to_vec();
         let input_ids = Array::from_vec(input_ids);
+        let (prefix_special_tokens, context_start) = sequence_layout(&encoding);
         Encoding {
             input_ids: input_ids,
+            attention_mask: Array::from_vec(encoding.get_attention_mask().to_vec()),
+            token_type_ids: Array::from_vec(encoding.get_type_ids().to_vec()),
+            pad_token,
+            example_index: None,
+            owned_tokens: owned_tokens(&encoding, 0),
+            global_attention: global_attention(&encoding),
+            word_starts: word_starts(&encoding),
+            context: owned_tokens(&encoding, 0).mapv(|token| token as u8),
+            sequence_ids: None,
+            prefix_special_tokens,
+            context_start,
+            text: None,
+            hash_id: None,
+            metadata: None,
+        }
+    }
+    // Encoding of a single sequence given as ids, all of which are attended to and owned.
+    pub fn from_ids(ids: Vec<u32>, pad_token: u32) -> Encoding {
+        let len = ids.len();
+        Encoding {
+            input_ids: Array::from_vec(ids),
+            attention_mask: Array::ones(len),
+            token_type_ids: Array::zeros(len),
             pad_token,
+            example_index: None,
+            owned_tokens: Array::ones(len),
+            global_attention: Array::zeros(len),
+            word_starts: Array::ones(len),
+            context: Array::ones(len),
+            sequence_ids: None,
+            prefix_special_tokens: 0,
+            context_start: 0,
+            text: None,
+            hash_id: None,
+            metadata: None,
+        }
+    }
+    // Index of the first subword of every word, see `word_starts`. Gathering the logits of a token
+    // classification model at these positions gives one prediction per word.
+    pub fn first_subword_indices(&self) -> Array1<i64> {
+        first_subword_indices(self.word_starts.view())
+    }
+}
+
+fn first_subword_indices(word_starts: ArrayView1<u8>) -> Array1<i64> {
+    word_starts
+        .iter()
+        .enumerate()
+        .filter(|(_, &start)| start == 1)
+        .map(|(token, _)| token as i64)
+        .collect()
+}
+
+// Non-special tokens of the last sequence starting at or after the character `owned_from`.
+fn owned_tokens(encoding: &tokenizer::Encoding, owned_from: usize) -> Array1<u32> {
+    let sequence = encoding.n_sequences() - 1;
+    (0..encoding.len())
+        .map(|token| {
+            let owned = encoding.get_special_tokens_mask()[token] == 0
+                && encoding.token_to_sequence(token) == Some(sequence)
+                && encoding.get_offsets()[token].0 >= owned_from;
+            owned as u32
+        })
+        .collect()
+}
+
+// The number of special tokens in front of the first sequence and the index of the first token of
+// the last sequence. An empty last sequence starts at the end.
+fn sequence_layout(encoding: &tokenizer::Encoding) -> (usize, usize) {
+    let special_tokens = encoding.get_special_tokens_mask();
+    let prefix = special_tokens
+        .iter()
+        .take_while(|&&special| special == 1)
+        .count();
+    let sequence = encoding.n_sequences() - 1;
+    let context_start = (0..encoding.len())
+        .find(|&token| {
+            special_tokens[token] == 0 && encoding.token_to_sequence(token) == Some(sequence)
+        })
+        .unwrap_or(encoding.len());
+    (prefix, context_start)
+}
+
+fn global_attention(encoding: &tokenizer::Encoding) -> Array1<u32> {
+    let special_tokens = encoding.get_special_tokens_mask();
+    (0..encoding.len())
+        .map(|token| {
+            let global = if special_tokens[token] == 1 {
+                token == 0
+            } else {
+                encoding.n_sequences() == 2 && encoding.token_to_sequence(token) == Some(0)
+            };
+            global as u32
+        })
+        .collect()
+}
+
+// The word ids restart with every sequence, so a token also starts a word if it's the first of
+// its sequence.
+fn word_starts(encoding: &tokenizer::Encoding) -> Array1<u8> {
+    let word_ids = encoding.get_word_ids();
+    let type_ids = encoding.get_type_ids();
+    (0..encoding.len())
+        .map(|token| {
+            let start = word_ids[token].is_some()
+                && (token == 0
+                    || word_ids[token - 1] != word_ids[token]
+                    || type_ids[token - 1] != type_ids[token]);
+            start as u8
+        })
+        .collect()
+}
+
+// End of the last character of the last sequence covered by the encoding.
+fn covered_end(encoding: &tokenizer::Encoding) -> usize {
+    let sequence = encoding.n_sequences() - 1;
+    (0..encoding.len())
+        .filter(|&token| {
+            encoding.get_special_tokens_mask()[token] == 0
+                && encoding.token_to_sequence(token) == Some(sequence)
+        })
+        .map(|token| encoding.get_offsets()[token].1)
+        .max()
+        .unwrap_or(0)
+}
+
+impl<T: TokenizedLabel> TokenizedText<T> {
+    fn from_tokenizer_encoding<L: Label<Tokenized = T>>(
+        encoding: tokenizer::Encoding,
+        label: L,
+        pad_token: u32,
+    ) -> TokenizedText<T> {
+        let label = label.tokenize(&encoding, 0);
+        TokenizedText {
+            encoding: Encoding::from_tokenizer_encoding(encoding, pad_token),
+            label,
         }
     }
+    fn from_overflowing_encoding<L: Label<Tokenized = T>>(
+        encoding: tokenizer::Encoding,
+        label: L,
+        pad_token: u32,
+    ) -> Vec<TokenizedText<T>> {
+        let mut owned_from = 0;
+        windows(encoding)
+            .into_iter()
+            .map(|window| {
+                let owned = owned_tokens(&window, owned_from);
+                owned_from = owned_from.max(covered_end(&window));
+                let mut sample =
+                    TokenizedText::from_tokenizer_encoding(window, label.clone(), pad_token);
+                sample.encoding.owned_tokens = owned;
+                sample
+            })
+            .collect()
+    }
 }
 
 pub struct BatchAnswer {}
 
-pub trait BatchLabel: ToPyObjectConsume {}
+// Arrays under the names of the keyword arguments of `transformers` models, so a batch can be
+// passed as `model(**batch)` and the model computes the loss itself.
+pub type NamedArrays = Vec<(&'static str, ArrayD<i64>)>;
 
+pub trait BatchLabel: Clone + NodeOutput {
+    fn into_named_arrays(self) -> NamedArrays;
+}
+
+#[derive(Clone)]
 pub struct BatchEncoding {
     input_ids: Array2<u32>,
+    attention_mask: Array2<u32>,
+    token_type_ids: Array2<u32>,
     pad_token: u32,
+    // Number of tokens of every row before truncation to the sequence length.
+    lengths: Array1<usize>,
+    // 1 for the rows that were cut off at the sequence length, 0 otherwise.
+    truncated: Array1<u8>,
+    // Example index of every row, only present when overflowing windows are kept.
+    overflow_to_sample_mapping: Option<Array1<i64>>,
+    owned_tokens: Array2<u32>,
+    // 0 for rows repeated to fill up a short batch, 1 otherwise. Only present with `pad_batch`.
+    sample_mask: Option<Array1<i64>>,
+    // Packed sequence of every token, 0 for padding. Only present if every row was packed.
+    sequence_ids: Option<Array2<i64>>,
+    // `Encoding::prefix_special_tokens` and `Encoding::context_start` of every row.
+    prefix_special_tokens: Array1<i64>,
+    context_start: Array1<i64>,
+    // Tokens attended to globally, only present with `StaticBatcher::with_global_attention` or
+    // `StaticBatcher::with_num_global_tokens`.
+    global_attention_mask: Option<Array2<u32>>,
+    // `Encoding::word_starts` of every row, 0 for padding.
+    word_start_mask: Array2<u8>,
+    // `Encoding::context` of every row, 0 for padding. Masks the question out of the start and end
+    // logits of extractive QA.
+    context_mask: Array2<u8>,
+    // `Encoding::hash_id` of every row, only present if every row has one.
+    hash_ids: Option<Array1<u64>>,
+    // `Encoding::metadata` of every row, only present if every row has some.
+    metadata: Option<Vec<Metadata>>,
+    // Pool of the batcher the matrices were taken from, they're handed back by `recycle`.
+    pool: Option<BufferPool>,
+}
+
+impl BatchEncoding {
+    // Hands the matrices back to the batcher for the next batches, leaving empty ones behind.
+    pub fn recycle(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            for matrix in [
+                &mut self.input_ids,
+                &mut self.attention_mask,
+                &mut self.token_type_ids,
+                &mut self.owned_tokens,
+            ] {
+                pool.put(std::mem::take(matrix));
+            }
+            if let Some(matrix) = self.global_attention_mask.take() {
+                pool.put(matrix);
+            }
+        }
+    }
+    // Number of rows, including the repeated rows of a padded batch.
+    pub fn batch_size(&self) -> usize {
+        self.input_ids.nrows()
+    }
+    // Width of the rows, the batcher's sequence length (or the longest row of this batch for
+    // batchers that pad every batch to its own length).
+    pub fn seq_length(&self) -> usize {
+        self.input_ids.ncols()
+    }
+    pub fn pad_token(&self) -> u32 {
+        self.pad_token
+    }
+    // Built on access so the batch doesn't hold a second copy of the mask.
+    pub fn bool_attention_mask(&self) -> Array2<bool> {
+        self.attention_mask.mapv(|mask| mask != 0)
+    }
+    // `Encoding::first_subword_indices` of every row, padded with -1 to the row with the most
+    // words. Built from the `word_start_mask`, so words cut off by truncation are left out.
+    pub fn first_subword_indices(&self) -> Array2<i64> {
+        let rows: Vec<Array1<i64>> = self
+            .word_start_mask
+            .outer_iter()
+            .map(first_subword_indices)
+            .collect();
+        let width = rows.iter().map(Array1::len).max().unwrap_or(0);
+        let mut indices = Array2::from_elem((rows.len(), width), -1);
+        for (i, row) in rows.iter().enumerate() {
+            assign_row(&mut indices, i, row);
+        }
+        indices
+    }
+    // One row per sample. The 2D arrays become `fixed_size_list<uint32>[seq_length]` columns,
+    // `lengths` becomes `uint64` and the optional `overflow_to_sample_mapping` and `sample_mask`
+    // become `int64` columns if present, `sequence_ids` a `fixed_size_list<int64>` column,
+    // `global_attention_mask` a further `fixed_size_list<uint32>` column and `hash_ids` a `uint64`
+    // column.
+    // `pad_token` isn't an array and is left out.
+    #[cfg(feature = "python")]
+    pub fn to_arrow(&self) -> Result<arrow::RecordBatch, PreprocessingError> {
+        let mut columns = vec![
+            arrow::Column::from_array2("input_ids", &self.input_ids, arrow::Values::UInt32),
+            arrow::Column::from_array2(
+                "attention_mask",
+                &self.attention_mask,
+                arrow::Values::UInt32,
+            ),
+            arrow::Column::from_array2(
+                "token_type_ids",
+                &self.token_type_ids,
+                arrow::Values::UInt32,
+            ),
+            arrow::Column::from_array2("owned_tokens", &self.owned_tokens, arrow::Values::UInt32),
+            arrow::uint64_column("lengths", &self.lengths),
+        ];
+        if let Some(mapping) = &self.overflow_to_sample_mapping {
+            columns.push(arrow::int64_column("overflow_to_sample_mapping", mapping));
+        }
+        if let Some(mask) = &self.sample_mask {
+            columns.push(arrow::int64_column("sample_mask", mask));
+        }
+        if let Some(sequence_ids) = &self.sequence_ids {
+            columns.push(arrow::Column::from_array2(
+                "sequence_ids",
+                sequence_ids,
+                arrow::Values::Int64,
+            ));
+        }
+        if let Some(mask) = &self.global_attention_mask {
+            columns.push(arrow::Column::from_array2(
+                "global_attention_mask",
+                mask,
+                arrow::Values::UInt32,
+            ));
+        }
+        if let Some(hash_ids) = &self.hash_ids {
+            columns.push(arrow::Column::from_array1(
+                "hash_ids",
+                arrow::Values::UInt64(hash_ids.to_vec()),
+            ));
+        }
+        arrow::RecordBatch::new(self.input_ids.nrows(), columns)
+    }
 }
+
+#[derive(Clone)]
 pub struct Batch<T: BatchLabel> {
     encoding: BatchEncoding,
     labels: T,
 }
 
-pub struct Tokenizer<T: Node<Output: Sample>> {
+impl<T: BatchLabel> Batch<T> {
+    // The model inputs followed by the labels, `labels` for classes and `start_positions` and
+    // `end_positions` for spans. Arrays that aren't model inputs (like `owned_tokens`) are left
+    // out, since models reject unknown arguments.
+    pub fn into_named_arrays(self) -> NamedArrays {
+        let input = |array: Array2<u32>| array.mapv(i64::from).into_dyn();
+        let mut arrays = vec![
+            ("input_ids", input(self.encoding.input_ids)),
+            ("attention_mask", input(self.encoding.attention_mask)),
+            ("token_type_ids", input(self.encoding.token_type_ids)),
+        ];
+        if let Some(mask) = self.encoding.global_attention_mask {
+            arrays.push(("global_attention_mask", input(mask)));
+        }
+        arrays.extend(self.labels.into_named_arrays());
+        arrays
+    }
+}
+
+type TokenizedSample<T> = TokenizedText<<<T as Sample>::Label as Label>::Tokenized>;
+
+// What `Tokenizer::with_timeout` does with a sample that takes too long to encode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeoutPolicy {
+    // Drops the sample and goes on with the next one.
+    Skip,
+    // Raises an `Io` error of kind `TimedOut` (see `error::raise`), a `TimeoutError` in Python.
+    Raise,
+}
+
+// Timed out encoding threads that are left running at most. A further timeout first waits for
+// the oldest of them, so pathological inputs can't pile up busy threads.
+const MAX_ABANDONED_ENCODERS: usize = 4;
+
+// Thread encoding the samples of a `Tokenizer` with a timeout, one at a time. The sample goes to
+// the thread and comes back with its encoding.
+struct EncodeWorker<S> {
+    samples: mpsc::Sender<S>,
+    encodings: mpsc::Receiver<thread::Result<(S, tokenizer::Encoding)>>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl<S: Sample + Send + 'static> EncodeWorker<S> {
+    fn spawn(tokenizer: Arc<tokenizer::Tokenizer>, add_special_tokens: bool) -> EncodeWorker<S> {
+        let (samples, requests) = mpsc::channel::<S>();
+        let (results, encodings) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            for sample in requests {
+                let encoded = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let encoding = sample.encode(&tokenizer, add_special_tokens);
+                    (sample, encoding)
+                }));
+                // Nobody is waiting anymore if the sample timed out.
+                if results.send(encoded).is_err() {
+                    break;
+                }
+            }
+        });
+        EncodeWorker {
+            samples,
+            encodings,
+            thread,
+        }
+    }
+}
+
+// The worker is kept for all samples, a new one only takes over after a timeout since a thread
+// can't be stopped. The abandoned ones run until their sample is encoded.
+struct TimeoutEncoder<S> {
+    worker: Option<EncodeWorker<S>>,
+    abandoned: VecDeque<thread::JoinHandle<()>>,
+}
+
+impl<S> TimeoutEncoder<S> {
+    fn abandon(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.abandoned.retain(|thread| !thread.is_finished());
+            if self.abandoned.len() >= MAX_ABANDONED_ENCODERS {
+                let _ = self.abandoned.pop_front().map(thread::JoinHandle::join);
+            }
+            // Dropping the channels ends the thread once its sample is encoded.
+            self.abandoned.push_back(worker.thread);
+        }
+    }
+}
+
+impl FromStr for TimeoutPolicy {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(TimeoutPolicy::Skip),
+            "raise" => Ok(TimeoutPolicy::Raise),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"skip\" or \"raise\"",
+                s
+            ))),
+        }
+    }
+}
+
+// Which end of an overlong sample `Tokenizer::with_truncation` cuts off. `Left` keeps the end of
+// the sample, e.g. the most recent turns of a dialogue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TruncationSide {
+    Left,
+    Right,
+}
+
+impl TruncationSide {
+    fn direction(self) -> TruncationDirection {
+        match self {
+            TruncationSide::Left => TruncationDirection::Left,
+            TruncationSide::Right => TruncationDirection::Right,
+        }
+    }
+}
+
+impl FromStr for TruncationSide {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(TruncationSide::Left),
+            "right" => Ok(TruncationSide::Right),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"left\" or \"right\"",
+                s
+            ))),
+        }
+    }
+}
+
+pub struct Tokenizer<T: Node>
+where
+    T::Output: Sample,
+{
     loader: T,
-    tokenizer: tokenizer::Tokenizer,
+    // Shared with the threads encoding samples under a timeout.
+    tokenizer: Arc<tokenizer::Tokenizer>,
+    overflow: bool,
+    example_index: usize,
+    features: VecDeque<TokenizedSample<T::Output>>,
+    lengths: OnceLock<Array1<i64>>,
+    pad_token: u32,
+    encode_batch_size: usize,
+    truncation_side: TruncationSide,
+    add_special_tokens: bool,
+    // `get` only has shared access, so the counters need interior mutability.
+    answers: Mutex<AnswerRetention>,
+    tokens: AtomicU64,
+    timeout: Option<(Duration, TimeoutPolicy)>,
+    encoder: Mutex<TimeoutEncoder<T::Output>>,
+    keep_texts: bool,
+    longer_second: bool,
 }
 
-impl<T: Node<Output: Sample>> Tokenizer<T> {
-    fn new<S: AsRef<str>>(loader: T, tokenizer: S) -> Result<Tokenizer<T>, tokenizer::Error> {
-        let tokenizer = tokenizer::Tokenizer::from_pretrained(tokenizer, None)?;
-        Ok(Tokenizer { loader, tokenizer })
+impl<T: Node> Tokenizer<T>
+where
+    T::Output: Sample + 'static,
+{
+    // Downloads the tokenizer from the Hugging Face Hub (or reads it from the local cache), retrying
+    // failed downloads as configured by `retry`. Pinning a `revision` (a commit of the tokenizer's
+    // repository) keeps the tokenization the same wherever the pipeline runs.
+    pub fn new<S: AsRef<str>>(
+        loader: T,
+        tokenizer: S,
+        revision: Option<&str>,
+        pad_token: Option<u32>,
+        retry: &Retry,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        let tokenizer = retry::from_pretrained(tokenizer.as_ref(), revision, retry)?;
+        Tokenizer::from_tokenizer(loader, tokenizer, pad_token)
+    }
+    // Builds the tokenizer from its serialized form, e.g. `tokenizer.to_str()` in Python.
+    pub fn from_json(
+        loader: T,
+        json: &str,
+        pad_token: Option<u32>,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        let tokenizer: tokenizer::Tokenizer = json.parse()?;
+        Tokenizer::from_tokenizer(loader, tokenizer, pad_token)
+    }
+    // Fails if the pad token can't be determined, see `resolve_pad_token`.
+    pub fn from_tokenizer(
+        loader: T,
+        tokenizer: tokenizer::Tokenizer,
+        pad_token: Option<u32>,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        let pad_token = resolve_pad_token(&tokenizer, pad_token)?;
+        if <<T::Output as Sample>::Label as Label>::CHAR_OFFSETS {
+            let probe = tokenizer.encode_char_offsets("offset probe", false)?;
+            check_char_offsets(&probe)?;
+        }
+        Ok(Tokenizer {
+            loader,
+            tokenizer: Arc::new(tokenizer),
+            pad_token,
+            overflow: false,
+            example_index: 0,
+            features: VecDeque::new(),
+            lengths: OnceLock::new(),
+            encode_batch_size: 1,
+            truncation_side: TruncationSide::Right,
+            add_special_tokens: false,
+            answers: Mutex::new(AnswerRetention::default()),
+            tokens: AtomicU64::new(0),
+            timeout: None,
+            encoder: Mutex::new(TimeoutEncoder {
+                worker: None,
+                abandoned: VecDeque::new(),
+            }),
+            keep_texts: false,
+            longer_second: false,
+        })
+    }
+    // The sample with its encoding, `None` if it timed out and is skipped.
+    fn encode(&self, sample: T::Output) -> Option<(T::Output, tokenizer::Encoding)> {
+        let (timeout, policy) = match self.timeout {
+            Some(timeout) => timeout,
+            None => {
+                let encoding = sample.encode(&self.tokenizer, self.add_special_tokens);
+                return Some((sample, encoding));
+            }
+        };
+        let preview: String = sample
+            .sequence(0)
+            .unwrap_or_default()
+            .chars()
+            .take(50)
+            .collect();
+        let mut encoder = self.encoder.lock().unwrap_or_else(PoisonError::into_inner);
+        let worker = encoder.worker.get_or_insert_with(|| {
+            EncodeWorker::spawn(Arc::clone(&self.tokenizer), self.add_special_tokens)
+        });
+        worker
+            .samples
+            .send(sample)
+            .expect("The encoding thread only stops when it's abandoned");
+        match worker.encodings.recv_timeout(timeout) {
+            Ok(Ok(encoded)) => Some(encoded),
+            Ok(Err(payload)) => {
+                drop(encoder);
+                panic::resume_unwind(payload)
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                encoder.abandon();
+                drop(encoder);
+                match policy {
+                    TimeoutPolicy::Skip => None,
+                    TimeoutPolicy::Raise => error::raise(PreprocessingError::Io(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "Tokenizing the sample {:?} took longer than {:?}",
+                            preview, timeout
+                        ),
+                    ))),
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                unreachable!("The encoding thread only stops when it's abandoned")
+            }
+        }
+    }
+    fn reorder(&self, sample: T::Output) -> T::Output {
+        if self.longer_second {
+            sample.longer_second()
+        } else {
+            sample
+        }
+    }
+    fn tokenize(&self, sample: T::Output) -> Option<TokenizedSample<T::Output>> {
+        let (mut sample, encoding) = self.encode(self.reorder(sample))?;
+        let text = self.source_text(&sample);
+        let metadata = sample.take_metadata();
+        let label = sample.into_label();
+        let answerable = label.has_answer();
+        let mut feature = TokenizedText::from_tokenizer_encoding(encoding, label, self.pad_token);
+        feature.encoding.text = text;
+        feature.encoding.metadata = metadata;
+        self.record_answer(answerable, || feature.label.has_answer());
+        Some(feature)
+    }
+    // The sequences of the sample joined by newlines, if texts are kept for `WithText`.
+    fn source_text(&self, sample: &T::Output) -> Option<String> {
+        if !self.keep_texts {
+            return None;
+        }
+        let sequences: Vec<&str> = (0..).map_while(|index| sample.sequence(index)).collect();
+        Some(sequences.join("\n"))
+    }
+    // The builders run before anything is encoded, so no encoding thread shares the tokenizer yet.
+    fn tokenizer_mut(&mut self) -> &mut tokenizer::Tokenizer {
+        Arc::get_mut(&mut self.tokenizer).expect("The tokenizer is shared with an encoding thread")
+    }
+    fn record_answer(&self, answerable: bool, retained: impl FnOnce() -> bool) {
+        if answerable {
+            self.answers.lock().unwrap().add(retained());
+        }
+    }
+    fn count_tokens(&self, feature: &TokenizedSample<T::Output>) {
+        let tokens = feature.encoding.attention_mask.sum() as u64;
+        self.tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+    // Replaces the post-processor with a template that puts `cls_token` in front of the
+    // sequences, `separator` between the two sequences of a pair and `eos_token` at the end, e.g.
+    // "[SEP]" with "[CLS]" and "[SEP]" for BERT. The separator may consist of several tokens
+    // separated by whitespace, like "</s> </s>" for RoBERTa. Every token has to be in the
+    // vocabulary. Truncation leaves room for the added tokens and spans are still mapped to the
+    // tokens of their sequence.
+    pub fn with_pair_template(
+        mut self,
+        separator: &str,
+        cls_token: Option<&str>,
+        eos_token: Option<&str>,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        let cls: Vec<&str> = cls_token.into_iter().collect();
+        let separator: Vec<&str> = separator.split_whitespace().collect();
+        let eos: Vec<&str> = eos_token.into_iter().collect();
+        let mut special_tokens = Vec::new();
+        for &token in cls.iter().chain(&separator).chain(&eos) {
+            let id = self.tokenizer.token_to_id(token).ok_or_else(|| {
+                PreprocessingError::InvalidArgument(format!(
+                    "The special token {} isn't in the vocabulary",
+                    token
+                ))
+            })?;
+            special_tokens.push((token, id));
+        }
+        let piece = |token: &&str, type_id: u32| format!("{}:{}", token, type_id);
+        let single: Vec<String> = cls
+            .iter()
+            .map(|token| piece(token, 0))
+            .chain(["$A:0".to_string()])
+            .chain(eos.iter().map(|token| piece(token, 0)))
+            .collect();
+        let pair: Vec<String> = cls
+            .iter()
+            .map(|token| piece(token, 0))
+            .chain(["$A:0".to_string()])
+            .chain(separator.iter().map(|token| piece(token, 0)))
+            .chain(["$B:1".to_string()])
+            .chain(eos.iter().map(|token| piece(token, 1)))
+            .collect();
+        let invalid = |err: String| PreprocessingError::InvalidArgument(err);
+        let template = TemplateProcessing::builder()
+            .try_single(single)
+            .map_err(invalid)?
+            .try_pair(pair)
+            .map_err(invalid)?
+            .special_tokens(special_tokens)
+            .build()
+            .map_err(|err| invalid(err.to_string()))?;
+        self.tokenizer_mut().with_post_processor(template);
+        self.add_special_tokens = true;
+        Ok(self)
+    }
+    // Histogram of the token lengths, see `nodes::length_histogram`. Tokenizes the whole (indexable)
+    // loader once, like `lengths`, without consuming it. Lengths are counted after truncation, so
+    // this is meant for tokenizers without `max_length`.
+    pub fn length_histogram(
+        &self,
+        buckets: usize,
+    ) -> Result<(Array1<i64>, Array1<i64>), PreprocessingError> {
+        let lengths = self.lengths().ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "Length histograms need an indexable loader".to_string(),
+            )
+        })?;
+        nodes::length_histogram(&lengths, buckets)
+    }
+    // Makes `next` read `encode_batch_size` samples at once and encode them with a single
+    // `encode_batch` call, which tokenizes them in parallel. `get` still encodes one sample.
+    pub fn with_encode_batch_size(
+        mut self,
+        encode_batch_size: usize,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        if encode_batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The encode batch size has to be positive".to_string(),
+            ));
+        }
+        self.encode_batch_size = encode_batch_size;
+        Ok(self)
+    }
+    // Gives up on samples that take longer than `timeout` to encode, e.g. pathological inputs that
+    // would stall the pipeline, and skips them or raises an error depending on `policy`. The
+    // samples are encoded one at a time on a separate thread, which disables the batched encoding
+    // of `with_encode_batch_size` and serializes `get` from several threads. A timed out thread
+    // can't be stopped, it keeps running in the background until the tokenizer returns and a new
+    // thread takes over, see `MAX_ABANDONED_ENCODERS`. Since skipped samples can't be indexed
+    // around, `get` and `len` aren't supported with `Skip`.
+    pub fn with_timeout(mut self, timeout: Duration, policy: TimeoutPolicy) -> Tokenizer<T> {
+        self.timeout = Some((timeout, policy));
+        self
+    }
+    // Swaps the sequences of pairs whose first sequence is longer, so the longer one is second
+    // and truncation (which only cuts the second sequence of a pair) trims it. Meant for
+    // cross-encoders, where the order of the sequences doesn't matter. Samples with spans or other
+    // labels pointing into the sequences keep their order, setting this only warns for them.
+    pub fn with_longer_second(mut self, longer_second: bool) -> Tokenizer<T> {
+        let positional = <<T::Output as Sample>::Label as Label>::POSITIONAL;
+        if longer_second && positional {
+            warnings::warn("The labels point into the sequences, so they aren't reordered");
+        }
+        self.longer_second = longer_second && !positional;
+        self
+    }
+    // Without normalization the text goes to the pre-tokenizer as is, which saves the time of the
+    // normalizer (e.g. lowercasing and stripping accents for BERT) on corpora that were cleaned
+    // beforehand. Only meant for text the normalizer wouldn't change anyway: otherwise the token
+    // ids change, e.g. uppercase words become unknown tokens to an uncased vocabulary.
+    pub fn with_normalization(mut self, normalize: bool) -> Tokenizer<T> {
+        if !normalize {
+            // `tokenizers` can't unset the normalizer, an empty sequence leaves the text as is.
+            self.tokenizer_mut()
+                .with_normalizer(Sequence::new(Vec::new()));
+        }
+        self
+    }
+    fn skips_samples(&self) -> bool {
+        matches!(self.timeout, Some((_, TimeoutPolicy::Skip)))
+    }
+    // Encodes the next `encode_batch_size` samples into `features`. `false` once the loader is
+    // exhausted.
+    fn encode_next(&mut self) -> bool {
+        let samples: Vec<T::Output> = (0..self.encode_batch_size)
+            .map_while(|_| self.loader.next())
+            .collect();
+        let samples: Vec<T::Output> = samples
+            .into_iter()
+            .map(|sample| self.reorder(sample))
+            .collect();
+        let encoded: Vec<Option<(T::Output, tokenizer::Encoding)>> = match samples.len() {
+            0 => return false,
+            len if len == 1 || self.timeout.is_some() => samples
+                .into_iter()
+                .map(|sample| self.encode(sample))
+                .collect(),
+            _ => {
+                let encodings = self
+                    .tokenizer
+                    .encode_batch_char_offsets(
+                        samples.iter().map(Sample::input).collect(),
+                        self.add_special_tokens,
+                    )
+                    .expect("Failed to tokenize");
+                samples.into_iter().zip(encodings).map(Some).collect()
+            }
+        };
+        for encoded in encoded {
+            let (mut sample, encoding) = match encoded {
+                Some(encoded) => encoded,
+                // Skipped samples keep their example index, so it still matches the loader.
+                None => {
+                    self.example_index += self.overflow as usize;
+                    continue;
+                }
+            };
+            let text = self.source_text(&sample);
+            let metadata = sample.take_metadata();
+            let label = sample.into_label();
+            let answerable = label.has_answer();
+            if !self.overflow {
+                let mut feature =
+                    TokenizedText::from_tokenizer_encoding(encoding, label, self.pad_token);
+                feature.encoding.text = text;
+                feature.encoding.metadata = metadata;
+                self.record_answer(answerable, || feature.label.has_answer());
+                self.count_tokens(&feature);
+                self.features.push_back(feature);
+                continue;
+            }
+            let features =
+                TokenizedText::from_overflowing_encoding(encoding, label, self.pad_token);
+            self.record_answer(answerable, || {
+                features.iter().any(|feature| feature.label.has_answer())
+            });
+            for mut feature in features {
+                feature.encoding.example_index = Some(self.example_index);
+                feature.encoding.text = text.clone();
+                feature.encoding.metadata = metadata.clone();
+                self.count_tokens(&feature);
+                self.features.push_back(feature);
+            }
+            self.example_index += 1;
+        }
+        true
+    }
+    // Truncates samples to `max_length` tokens. With `overflow`, the cut off tokens are emitted
+    // as further samples in windows overlapping by `stride` tokens. Since the number of windows
+    // is only known after tokenizing, `get` and `len` aren't supported in that case. Only the
+    // second sequence of a pair (the context in QA) is windowed, the first one (the question) and
+    // the special tokens are repeated in every window, so they have to leave more than `stride`
+    // tokens of every window to the context.
+    pub fn with_truncation(
+        mut self,
+        max_length: usize,
+        stride: usize,
+        overflow: bool,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        if stride >= max_length {
+            return Err(PreprocessingError::InvalidArgument(
+                "The stride has to be smaller than max_length".to_string(),
+            ));
+        }
+        let direction = self.truncation_side.direction();
+        self.tokenizer_mut().with_truncation(Some(TruncationParams {
+            max_length,
+            stride,
+            strategy: <T::Output as Sample>::TRUNCATION_STRATEGY,
+            direction,
+        }));
+        self.overflow = overflow;
+        self.check_truncation_side()?;
+        Ok(self)
+    }
+    // Can be set before or after `with_truncation`. Overflowing windows are only supported when
+    // truncating on the right, since they are assumed to advance through the sample.
+    pub fn with_truncation_side(
+        mut self,
+        truncation_side: TruncationSide,
+    ) -> Result<Tokenizer<T>, PreprocessingError> {
+        self.truncation_side = truncation_side;
+        self.check_truncation_side()?;
+        if let Some(params) = self.tokenizer.get_truncation().cloned() {
+            self.tokenizer_mut().with_truncation(Some(TruncationParams {
+                direction: truncation_side.direction(),
+                ..params
+            }));
+        }
+        Ok(self)
+    }
+    fn check_truncation_side(&self) -> Result<(), PreprocessingError> {
+        if self.overflow && self.truncation_side == TruncationSide::Left {
+            return Err(PreprocessingError::InvalidArgument(
+                "Overflowing windows require truncation on the right".to_string(),
+            ));
+        }
+        Ok(())
     }
 }
 
-impl<T: Node<Output: Sample>> Node for Tokenizer<T> {
-    type Output = TokenizedText<<<<T as Node>::Output as Sample>::Label as Label>::Tokenized>;
+impl<T: Node> Node for Tokenizer<T>
+where
+    T::Output: Sample + 'static,
+{
+    type Output = TokenizedSample<T::Output>;
     fn get(&self, index: usize) -> Option<Self::Output> {
-        self.loader
-            .get(index)
-            .map(|sample| sample.tokenize(&self.tokenizer))
+        if self.overflow || self.skips_samples() {
+            return None;
+        }
+        let feature = self.tokenize(self.loader.get(index)?)?;
+        self.count_tokens(&feature);
+        Some(feature)
     }
     fn len(&self) -> Option<usize> {
+        if self.overflow || self.skips_samples() {
+            return None;
+        }
         self.loader.len()
     }
     fn next(&mut self) -> Option<Self::Output> {
-        self.loader
-            .next()
-            .map(|sample| sample.tokenize(&self.tokenizer))
+        while self.features.is_empty() && self.encode_next() {}
+        self.features.pop_front()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        Some(*self.answers.lock().unwrap())
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        Some(self.tokens.load(Ordering::Relaxed))
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.tokenizer
+            .get_truncation()
+            .map(|truncation| truncation.max_length)
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizer::Tokenizer>> {
+        Some(self.tokenizer.clone())
+    }
+    // Computed once by tokenizing every sample, later calls reuse the result. The samples
+    // tokenized for it aren't counted in `total_tokens`.
+    fn lengths(&self) -> Option<Array1<i64>> {
+        if let Some(lengths) = self.lengths.get() {
+            return Some(lengths.clone());
+        }
+        let lengths = (0..self.len()?)
+            .map(|index| {
+                let sample = self.tokenize(self.loader.get(index)?)?;
+                Some(sample.encoding.input_ids.len() as i64)
+            })
+            .collect::<Option<Vec<i64>>>()?;
+        Some(
+            self.lengths
+                .get_or_init(|| Array1::from_vec(lengths))
+                .clone(),
+        )
+    }
+    // Slices the sample's text with the offsets of the tokens, so it only needs an indexable
+    // loader and also works with overflowing windows.
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        let (sample, encoding) = match self.encode(self.reorder(self.loader.get(example_index)?)) {
+            Some((sample, encoding)) => (sample, windows(encoding).into_iter().nth(window)),
+            None => return Some(None),
+        };
+        Some(encoding.and_then(|encoding| {
+            let (sequence, (start, _)) = encoding.token_to_chars(start_token)?;
+            let (end_sequence, (_, end)) = encoding.token_to_chars(end_token)?;
+            if sequence != end_sequence {
+                return None;
+            }
+            let text = char_slice(sample.sequence(sequence)?, start, end)?;
+            Some(text.to_string())
+        }))
+    }
+    // Consecutive windows overlap by the stride, so every window starts `stride` tokens before
+    // the end of the previous one. Without `overflow` only the first window is emitted. Samples
+    // skipped after a timeout have no windows.
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        let sample = self.reorder(self.loader.get(example_index)?);
+        let stride = self
+            .tokenizer
+            .get_truncation()
+            .map_or(0, |truncation| truncation.stride);
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let windows = self
+            .encode(sample)
+            .map(|(_, encoding)| windows(encoding))
+            .unwrap_or_default();
+        for window in windows
+            .into_iter()
+            .take(if self.overflow { usize::MAX } else { 1 })
+        {
+            let sequence = window.n_sequences() - 1;
+            let special_tokens = window.get_special_tokens_mask();
+            let len = (0..window.len())
+                .filter(|&token| {
+                    special_tokens[token] == 0 && window.token_to_sequence(token) == Some(sequence)
+                })
+                .count();
+            let start = ranges
+                .last()
+                .map_or(0, |&(_, end)| end.saturating_sub(stride));
+            ranges.push((start, start + len));
+        }
+        Some(ranges)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.loader.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.loader.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.keep_texts = true;
+        true
+    }
+    // Every example yields at least one window, but there's no upper bound with `overflow`.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.loader.size_hint();
+        (lower, upper.filter(|_| !self.overflow))
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.loader.estimated_len()
+    }
+}
+
+// What `StaticBatcher::get` returns for the last batch if the number of samples isn't a multiple
+// of the batch size. `next` always returns the short batch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartialBatch {
+    // The remaining samples. `len` only counts the full batches.
+    Short,
+    // `None`, i.e. only full batches are available. `len` counts the full batches.
+    Drop,
+    // The remaining samples followed by the first samples of the dataset. `len` counts the
+    // short batch as well.
+    Wrap,
+}
+
+impl FromStr for PartialBatch {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "short" => Ok(PartialBatch::Short),
+            "drop" => Ok(PartialBatch::Drop),
+            "wrap" => Ok(PartialBatch::Wrap),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"short\", \"drop\" or \"wrap\"",
+                s
+            ))),
+        }
     }
 }
 
@@ -226,6 +2236,18 @@ pub struct StaticBatcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>>
     tokenizer: T,
     batch_size: usize,
     seq_length: usize,
+    pad_batch: bool,
+    partial_batch: PartialBatch,
+    ignore_index: i64,
+    global_attention: bool,
+    num_global_tokens: usize,
+    dedup_within_batch: bool,
+    // Only counted by `next`, so samples that are also built by `get` or repeated to fill a batch
+    // count once.
+    truncation: TruncationStats,
+    // `get` only has shared access, so the counter needs interior mutability.
+    tokens: AtomicU64,
+    buffers: BufferPool,
 }
 
 impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> StaticBatcher<S, T> {
@@ -233,61 +2255,268 @@ impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> StaticBatcher<S, T>
         tokenizer: T,
         batch_size: usize,
         seq_length: usize,
-    ) -> Result<StaticBatcher<S, T>, String> {
+    ) -> Result<StaticBatcher<S, T>, PreprocessingError> {
+        if let Some(message) = Self::check_seq_length(&tokenizer, seq_length) {
+            warnings::warn(&message);
+        }
+        Self::build(tokenizer, batch_size, seq_length)
+    }
+    // Like `new`, but fails instead of warning if `seq_length` exceeds the maximum length of the
+    // upstream tokenizer.
+    pub fn new_strict(
+        tokenizer: T,
+        batch_size: usize,
+        seq_length: usize,
+    ) -> Result<StaticBatcher<S, T>, PreprocessingError> {
+        if let Some(message) = Self::check_seq_length(&tokenizer, seq_length) {
+            return Err(PreprocessingError::InvalidArgument(message));
+        }
+        Self::build(tokenizer, batch_size, seq_length)
+    }
+    // Batches longer than the tokenizer's maximum length would usually exceed the positions the
+    // model was trained on, which only fails once the batch reaches the model.
+    fn check_seq_length(tokenizer: &T, seq_length: usize) -> Option<String> {
+        let max_length = tokenizer.max_length()?;
+        (seq_length > max_length).then(|| {
+            format!(
+                "seq_length {} exceeds the maximum length {} of the tokenizer",
+                seq_length, max_length
+            )
+        })
+    }
+    fn build(
+        tokenizer: T,
+        batch_size: usize,
+        seq_length: usize,
+    ) -> Result<StaticBatcher<S, T>, PreprocessingError> {
         Ok(StaticBatcher {
             tokenizer,
             batch_size,
             seq_length,
+            pad_batch: false,
+            partial_batch: PartialBatch::Short,
+            ignore_index: IGNORE_INDEX,
+            global_attention: false,
+            num_global_tokens: 0,
+            dedup_within_batch: false,
+            truncation: TruncationStats::default(),
+            tokens: AtomicU64::new(0),
+            buffers: BufferPool::default(),
         })
     }
-    pub fn create_batch(&self, samples: Vec<TokenizedText<S>>) -> Batch<S::Batch> {
-        let mut inputs: Vec<Array2<u32>> = Vec::new();
-        let mut labels: Vec<S> = Vec::new();
-        let mut pad_token = 0;
-        let len = samples.len();
-        for (i, sample) in samples.into_iter().enumerate() {
-            let TokenizedText { encoding, label } = sample;
-            labels.push(label);
-            let Encoding {
-                input_ids,
-                pad_token: current_pad_token,
-            } = encoding;
-            let arrays = vec![input_ids];
-            pad_token = current_pad_token;
-            for (j, array) in arrays.iter().enumerate() {
-                match inputs.get_mut(j) {
-                    Some(matrix) => {
-                        let mut len = array.len();
-                        if len > self.seq_length {
-                            len = self.seq_length;
-                        }
-                        matrix
-                            .slice_mut(s![i, 0..len])
-                            .assign(&array.slice(s![..len]));
-                    }
-                    None => {
-                        let mut matrix = Array2::zeros((len, self.seq_length));
-                        matrix.fill(pad_token);
-                        let mut len = array.len();
-                        if len > self.seq_length {
-                            len = self.seq_length;
-                        }
-                        matrix
-                            .slice_mut(s![i, 0..len])
-                            .assign(&array.slice(s![..len]));
-                        inputs.push(matrix);
-                    }
-                }
+    // Pads every batch to the longest sample of the dataset instead of a fixed `seq_length`. The
+    // upstream node has to be indexable, its samples are tokenized once to find the maximum
+    // (reusing the cached `lengths` of a tokenizer).
+    pub fn with_global_max(
+        tokenizer: T,
+        batch_size: usize,
+    ) -> Result<StaticBatcher<S, T>, PreprocessingError> {
+        let len = tokenizer.len().ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "GlobalMaxBatcher requires an indexable upstream node".to_string(),
+            )
+        })?;
+        let seq_length = match tokenizer.lengths() {
+            Some(lengths) => lengths.iter().copied().max().unwrap_or(0) as usize,
+            None => (0..len)
+                .filter_map(|index| tokenizer.get(index))
+                .map(|sample| sample.encoding.input_ids.len())
+                .max()
+                .unwrap_or(0),
+        };
+        StaticBatcher::new(tokenizer, batch_size, seq_length)
+    }
+    // Fills up a short final batch to `batch_size` rows by repeating its last sample, so every
+    // batch has the same shape. The repeated rows are marked in the batch's `sample_mask` and
+    // their labels are set to the ignore index.
+    pub fn with_pad_batch(mut self, pad_batch: bool) -> StaticBatcher<S, T> {
+        self.pad_batch = pad_batch;
+        self
+    }
+    pub fn with_partial_batch(mut self, partial_batch: PartialBatch) -> StaticBatcher<S, T> {
+        self.partial_batch = partial_batch;
+        self
+    }
+    // Label value of positions that shouldn't contribute to the loss, `IGNORE_INDEX` by default.
+    pub fn with_ignore_index(mut self, ignore_index: i64) -> StaticBatcher<S, T> {
+        self.ignore_index = ignore_index;
+        self
+    }
+    // Adds a `global_attention_mask` for Longformer-style models to the batches, marking `[CLS]`
+    // and the question (the first text of a `TextPair`) as attending globally. Requires a
+    // tokenizer that adds special tokens for `[CLS]` to be marked, e.g. through
+    // `Tokenizer::with_pair_template`.
+    pub fn with_global_attention(mut self, global_attention: bool) -> StaticBatcher<S, T> {
+        self.global_attention = global_attention;
+        self
+    }
+    // Also marks the first `num_global_tokens` tokens of every row (not counting padding) as
+    // attending globally, regardless of the sequence they belong to, for models with a fixed global
+    // prefix. Adds a `global_attention_mask` even without `with_global_attention`, with which it
+    // can be combined.
+    pub fn with_num_global_tokens(mut self, num_global_tokens: usize) -> StaticBatcher<S, T> {
+        self.num_global_tokens = num_global_tokens;
+        self
+    }
+    // Skips samples whose row (the input ids cut to `seq_length`) is already in the current batch
+    // and fills the batch with the next samples instead, e.g. for contrastive objectives where
+    // duplicates would be false negatives of each other. Which samples are skipped depends on
+    // the ones read before, so `get` and `len` aren't supported in that case.
+    pub fn with_dedup_within_batch(mut self, dedup_within_batch: bool) -> StaticBatcher<S, T> {
+        self.dedup_within_batch = dedup_within_batch;
+        self
+    }
+    pub fn create_batch(&self, mut samples: Vec<TokenizedText<S>>) -> Batch<S::Batch> {
+        let mut sample_mask = None;
+        if self.pad_batch {
+            let n_samples = samples.len();
+            if let Some(last) = samples.last().cloned() {
+                samples.resize(n_samples.max(self.batch_size), last);
             }
+            let mask = (0..samples.len()).map(|i| (i < n_samples) as i64).collect();
+            sample_mask = Some(Array1::from_vec(mask));
         }
-        let input_ids = inputs.pop().unwrap();
-        Batch {
-            encoding: BatchEncoding {
-                input_ids,
-                pad_token,
-            },
-            labels: S::to_batch(labels),
-        }
+        let global_attention = self.global_attention || self.num_global_tokens > 0;
+        let global_attention_mask = global_attention.then(|| {
+            let mut mask = self.buffers.take((samples.len(), self.seq_length), 0);
+            for (i, sample) in samples.iter().enumerate() {
+                if self.global_attention {
+                    assign_row(&mut mask, i, &sample.encoding.global_attention);
+                }
+                let attended = sample.encoding.attention_mask.iter().take(self.seq_length);
+                let prefix = attended
+                    .enumerate()
+                    .filter(|(_, &attended)| attended == 1)
+                    .take(self.num_global_tokens);
+                for (j, _) in prefix {
+                    mask[[i, j]] = 1;
+                }
+            }
+            mask
+        });
+        let mut batch = stack_samples(
+            samples,
+            self.seq_length,
+            sample_mask,
+            self.ignore_index,
+            Some(&self.buffers),
+        );
+        batch.encoding.global_attention_mask = global_attention_mask;
+        // Rows repeated by `pad_batch` aren't counted.
+        let sample_mask = &batch.encoding.sample_mask;
+        let tokens: u32 = batch
+            .encoding
+            .attention_mask
+            .outer_iter()
+            .enumerate()
+            .filter(|(i, _)| sample_mask.as_ref().is_none_or(|mask| mask[*i] == 1))
+            .map(|(_, row)| row.sum())
+            .sum();
+        self.tokens.fetch_add(tokens as u64, Ordering::Relaxed);
+        batch
+    }
+}
+
+// Copies `row` into row `i` of `matrix`, cut off at the width of the matrix.
+fn assign_row<A: Clone>(matrix: &mut Array2<A>, i: usize, row: &Array1<A>) {
+    let len = row.len().min(matrix.ncols());
+    matrix
+        .slice_mut(s![i, 0..len])
+        .assign(&row.slice(s![..len]));
+}
+
+// Pads or truncates the samples to `seq_length` and stacks them into a batch. The labels of rows
+// that are 0 in the `sample_mask` are set to `ignore_index`. The matrices are taken from `pool`
+// if one is given.
+pub(crate) fn stack_samples<S: TokenizedLabel>(
+    samples: Vec<TokenizedText<S>>,
+    seq_length: usize,
+    sample_mask: Option<Array1<i64>>,
+    ignore_index: i64,
+    pool: Option<&BufferPool>,
+) -> Batch<S::Batch> {
+    let len = samples.len();
+    let pad_token = samples
+        .first()
+        .map_or(0, |sample| sample.encoding.pad_token);
+    let matrix = |pad_value| match pool {
+        Some(pool) => pool.take((len, seq_length), pad_value),
+        None => Array2::from_elem((len, seq_length), pad_value),
+    };
+    let mut input_ids = matrix(pad_token);
+    let mut attention_mask = matrix(0);
+    let mut token_type_ids = matrix(0);
+    let mut owned_tokens = matrix(0);
+    let mut word_start_mask = Array2::zeros((len, seq_length));
+    let mut context_mask = Array2::zeros((len, seq_length));
+    let mut labels: Vec<Option<S>> = Vec::new();
+    let mut lengths: Vec<usize> = Vec::new();
+    let mut example_indices: Vec<Option<i64>> = Vec::new();
+    let mut hash_ids: Vec<Option<u64>> = Vec::new();
+    let mut metadata: Vec<Option<Metadata>> = Vec::new();
+    let mut sequence_ids: Vec<Option<Array1<u32>>> = Vec::new();
+    let mut prefix_special_tokens: Vec<i64> = Vec::new();
+    let mut context_start: Vec<i64> = Vec::new();
+    for (i, sample) in samples.into_iter().enumerate() {
+        let TokenizedText { encoding, label } = sample;
+        let ignored = sample_mask.as_ref().is_some_and(|mask| mask[i] == 0);
+        labels.push(if ignored { None } else { Some(label) });
+        lengths.push(encoding.input_ids.len());
+        example_indices.push(encoding.example_index.map(|index| index as i64));
+        hash_ids.push(encoding.hash_id);
+        prefix_special_tokens.push(encoding.prefix_special_tokens as i64);
+        context_start.push(encoding.context_start as i64);
+        assign_row(&mut input_ids, i, &encoding.input_ids);
+        assign_row(&mut attention_mask, i, &encoding.attention_mask);
+        assign_row(&mut token_type_ids, i, &encoding.token_type_ids);
+        assign_row(&mut owned_tokens, i, &encoding.owned_tokens);
+        assign_row(&mut word_start_mask, i, &encoding.word_starts);
+        assign_row(&mut context_mask, i, &encoding.context);
+        sequence_ids.push(encoding.sequence_ids);
+        metadata.push(encoding.metadata);
+    }
+    let overflow_to_sample_mapping = example_indices
+        .into_iter()
+        .collect::<Option<Vec<i64>>>()
+        .map(Array1::from_vec);
+    let sequence_ids = sequence_ids
+        .into_iter()
+        .collect::<Option<Vec<Array1<u32>>>>()
+        .map(|rows| {
+            let mut matrix = Array2::zeros((len, seq_length));
+            for (i, row) in rows.iter().enumerate() {
+                assign_row(&mut matrix, i, &row.mapv(i64::from));
+            }
+            matrix
+        });
+    Batch {
+        encoding: BatchEncoding {
+            input_ids,
+            attention_mask,
+            token_type_ids,
+            owned_tokens,
+            pad_token,
+            truncated: lengths
+                .iter()
+                .map(|&len| (len > seq_length) as u8)
+                .collect(),
+            lengths: Array1::from_vec(lengths),
+            overflow_to_sample_mapping,
+            sample_mask,
+            sequence_ids,
+            prefix_special_tokens: Array1::from_vec(prefix_special_tokens),
+            context_start: Array1::from_vec(context_start),
+            global_attention_mask: None,
+            word_start_mask,
+            context_mask,
+            hash_ids: hash_ids
+                .into_iter()
+                .collect::<Option<Vec<u64>>>()
+                .map(Array1::from_vec),
+            metadata: metadata.into_iter().collect(),
+            pool: pool.cloned(),
+        },
+        labels: S::to_batch(labels, ignore_index),
     }
 }
 
@@ -295,12 +2524,24 @@ impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for StaticBatch
     type Output = Batch<S::Batch>;
     fn next(&mut self) -> Option<Batch<S::Batch>> {
         let mut samples: Vec<TokenizedText<S>> = Vec::new();
-        for _ in 0..self.batch_size {
+        let mut rows: HashSet<Vec<u32>> = HashSet::new();
+        while samples.len() < self.batch_size {
             match self.tokenizer.next() {
+                Some(sample) if self.dedup_within_batch => {
+                    let ids = &sample.encoding.input_ids;
+                    let row = ids.slice(s![..ids.len().min(self.seq_length)]).to_vec();
+                    if rows.insert(row) {
+                        samples.push(sample);
+                    }
+                }
                 Some(sample) => samples.push(sample),
                 None => break,
             }
         }
+        for sample in &samples {
+            self.truncation
+                .add(sample.encoding.input_ids.len(), self.seq_length);
+        }
         if samples.is_empty() {
             None
         } else {
@@ -308,6 +2549,9 @@ impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for StaticBatch
         }
     }
     fn get(&self, index: usize) -> Option<Batch<S::Batch>> {
+        if self.dedup_within_batch {
+            return None;
+        }
         let index = index * self.batch_size;
         let mut samples: Vec<TokenizedText<S>> = Vec::new();
         for i in index..index + self.batch_size {
@@ -316,6 +2560,19 @@ impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for StaticBatch
                 None => break,
             }
         }
+        if !samples.is_empty() && samples.len() < self.batch_size {
+            match self.partial_batch {
+                PartialBatch::Short => {}
+                PartialBatch::Drop => return None,
+                PartialBatch::Wrap => {
+                    let missing = self.batch_size - samples.len();
+                    let n_samples = self.tokenizer.len()?;
+                    for i in 0..missing {
+                        samples.push(self.tokenizer.get(i % n_samples)?);
+                    }
+                }
+            }
+        }
         if samples.is_empty() {
             None
         } else {
@@ -323,9 +2580,61 @@ impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for StaticBatch
         }
     }
     fn len(&self) -> Option<usize> {
-        match self.tokenizer.len() {
-            Some(len) => Some(len / self.batch_size),
-            None => None,
+        if self.dedup_within_batch {
+            return None;
+        }
+        match (self.tokenizer.len(), self.partial_batch) {
+            (Some(len), PartialBatch::Wrap) => Some(len.div_ceil(self.batch_size)),
+            (Some(len), _) => Some(len / self.batch_size),
+            (None, _) => None,
+        }
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        Some(self.truncation)
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.tokenizer.answer_retention()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        Some(self.tokens.load(Ordering::Relaxed))
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.tokenizer
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.tokenizer.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.tokenizer.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.tokenizer.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.tokenizer.finalize()
+    }
+    // Counts the batches returned by `next`, i.e. including a short last batch. With
+    // `dedup_within_batch` every sample might repeat the first, so only one batch is certain.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.tokenizer.size_hint();
+        let lower = match self.dedup_within_batch {
+            true => lower.min(1),
+            false => lower.div_ceil(self.batch_size),
+        };
+        (lower, upper.map(|upper| upper.div_ceil(self.batch_size)))
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        if self.dedup_within_batch {
+            return None;
         }
+        let estimate = self.tokenizer.estimated_len()?;
+        Some(estimate.div_ceil(self.batch_size))
     }
 }
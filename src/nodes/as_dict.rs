@@ -0,0 +1,42 @@
+use crate::{Batch, BatchLabel, NamedArrays, Node, PreprocessingError};
+
+// Batch as named arrays, see `Batch::into_named_arrays`. Becomes a dict in Python.
+#[derive(Clone)]
+pub struct NamedBatch(pub(crate) NamedArrays);
+
+// Turns batches into `NamedBatch`es, so Python gets dicts that can be passed to `transformers`
+// models as `model(**batch)`.
+pub struct AsDict<S: BatchLabel, T: Node<Output = Batch<S>>> {
+    node: T,
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> AsDict<S, T> {
+    pub fn new(node: T) -> Result<AsDict<S, T>, PreprocessingError> {
+        Ok(AsDict { node })
+    }
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Node for AsDict<S, T> {
+    type Output = NamedBatch;
+    fn get(&self, index: usize) -> Option<NamedBatch> {
+        let batch = self.node.get(index)?;
+        Some(NamedBatch(batch.into_named_arrays()))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<NamedBatch> {
+        let batch = self.node.next()?;
+        Some(NamedBatch(batch.into_named_arrays()))
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed::{self, derive_seed, epoch_seed};
+use crate::{NoLabel, Node, PreprocessingError, Text};
+
+pub trait Augmentation: Send + Sync {
+    fn augment(&self, text: &str, rng: &mut ChaCha8Rng) -> String;
+}
+
+// Drops every whitespace separated word with probability `p`. At least one word is kept so the
+// variant isn't empty.
+pub struct WordDropout {
+    p: f64,
+}
+
+impl WordDropout {
+    pub fn new(p: f64) -> Result<WordDropout, PreprocessingError> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Dropout probability {} is not in [0, 1]",
+                p
+            )));
+        }
+        Ok(WordDropout { p })
+    }
+}
+
+impl Augmentation for WordDropout {
+    fn augment(&self, text: &str, rng: &mut ChaCha8Rng) -> String {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut kept: Vec<&str> = words
+            .iter()
+            .copied()
+            .filter(|_| !rng.gen_bool(self.p))
+            .collect();
+        if kept.is_empty() && !words.is_empty() {
+            kept.push(words[rng.gen_range(0..words.len())]);
+        }
+        kept.join(" ")
+    }
+}
+
+// Emits `k` augmented variants of every upstream sample. Variant `j` of sample `i` is always
+// generated from the same seed in an epoch, so `get` and `next` agree. `get` and `len` scale the upstream
+// indices by `k` and are therefore only available if the upstream node is indexable.
+pub struct Augment<T: Node, A> {
+    node: T,
+    augmentation: A,
+    k: usize,
+    seed: u64,
+    epoch: AtomicU64,
+    // Current upstream sample and the number of its variants emitted so far.
+    current: Option<Text<NoLabel>>,
+    variant: usize,
+    sample_index: usize,
+}
+
+impl<T: Node<Output = Text<NoLabel>>, A: Augmentation> Augment<T, A> {
+    pub fn new(
+        node: T,
+        augmentation: A,
+        k: usize,
+        seed: Option<u64>,
+    ) -> Result<Augment<T, A>, PreprocessingError> {
+        if k == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "k has to be positive".to_string(),
+            ));
+        }
+        Ok(Augment {
+            node,
+            augmentation,
+            k,
+            seed: seed::node_rng(seed).gen(),
+            epoch: AtomicU64::new(0),
+            current: None,
+            variant: 0,
+            sample_index: 0,
+        })
+    }
+    fn augmented(&self, sample: &Text<NoLabel>, index: usize) -> Text<NoLabel> {
+        let seed = epoch_seed(self.seed, self.epoch.load(Ordering::Relaxed));
+        let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(seed, index as u64));
+        Text {
+            text: self.augmentation.augment(&sample.text, &mut rng),
+            label: NoLabel(),
+            metadata: sample.metadata.clone(),
+        }
+    }
+}
+
+impl<T: Node<Output = Text<NoLabel>>, A: Augmentation> Node for Augment<T, A> {
+    type Output = Text<NoLabel>;
+    fn get(&self, index: usize) -> Option<Text<NoLabel>> {
+        let sample = self.node.get(index / self.k)?;
+        Some(self.augmented(&sample, index))
+    }
+    fn len(&self) -> Option<usize> {
+        Some(self.node.len()? * self.k)
+    }
+    fn next(&mut self) -> Option<Text<NoLabel>> {
+        if self.variant == self.k {
+            self.current = None;
+            self.variant = 0;
+            self.sample_index += 1;
+        }
+        if self.current.is_none() {
+            self.current = Some(self.node.next()?);
+        }
+        let index = self.sample_index * self.k + self.variant;
+        self.variant += 1;
+        Some(self.augmented(self.current.as_ref().unwrap(), index))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (lower * self.k, upper.map(|upper| upper * self.k))
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len().map(|len| len * self.k)
+    }
+}
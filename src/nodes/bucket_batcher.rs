@@ -0,0 +1,126 @@
+use crate::{
+    stack_samples, Batch, Node, PreprocessingError, TokenizedLabel, TokenizedText, IGNORE_INDEX,
+};
+
+// Batches samples of similar length: the samples are sorted by their number of tokens and cut
+// into batches of `batch_size` consecutive samples, each padded to its own longest sample. This
+// keeps padding low without a fixed sequence length. The upstream node has to be indexable, its
+// samples are tokenized once to find the lengths (reusing the cached `lengths` of a tokenizer).
+// Batches are emitted from the shortest to the longest.
+//
+// With `with_worker` the batches are dealt out round-robin to `num_workers` workers, e.g. the
+// ranks of a distributed training run. Since consecutive batches have similar lengths, every
+// worker gets batches of all lengths and about the same number of tokens, while together the
+// workers cover every sample exactly once. If the batches don't split evenly, the first workers
+// get one batch more. With `with_even_batches` all workers get the same number of batches instead,
+// so that their steps line up for collective operations like an all-reduce: the workers that would
+// come up short continue with the first batches again (like PyTorch's `DistributedSampler`), so a
+// few samples are seen twice per epoch.
+pub struct BucketBatcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    tokenizer: T,
+    // Upstream indices of every batch, of all workers.
+    batches: Vec<Vec<usize>>,
+    num_workers: usize,
+    worker_id: usize,
+    even_batches: bool,
+    position: usize,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> BucketBatcher<S, T> {
+    pub fn new(tokenizer: T, batch_size: usize) -> Result<BucketBatcher<S, T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        let len = tokenizer.len().ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "BucketBatcher requires an indexable upstream node".to_string(),
+            )
+        })?;
+        let lengths: Vec<usize> = match tokenizer.lengths() {
+            Some(lengths) => lengths.iter().map(|&length| length as usize).collect(),
+            None => (0..len)
+                .map_while(|index| tokenizer.get(index))
+                .map(|sample| sample.encoding.input_ids.len())
+                .collect(),
+        };
+        let mut indices: Vec<usize> = (0..lengths.len()).collect();
+        // Stable, so samples of the same length keep their order.
+        indices.sort_by_key(|&index| lengths[index]);
+        let batches = indices
+            .chunks(batch_size)
+            .map(|batch| batch.to_vec())
+            .collect();
+        Ok(BucketBatcher {
+            tokenizer,
+            batches,
+            num_workers: 1,
+            worker_id: 0,
+            even_batches: false,
+            position: 0,
+        })
+    }
+    // Only emits the batches `worker_id, worker_id + num_workers, ...`.
+    pub fn with_worker(
+        mut self,
+        num_workers: usize,
+        worker_id: usize,
+    ) -> Result<BucketBatcher<S, T>, PreprocessingError> {
+        if worker_id >= num_workers {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Worker {} doesn't exist with {} workers",
+                worker_id, num_workers
+            )));
+        }
+        self.num_workers = num_workers;
+        self.worker_id = worker_id;
+        Ok(self)
+    }
+    // Pads the workers that come up short with the first batches, see above.
+    pub fn with_even_batches(mut self, even_batches: bool) -> BucketBatcher<S, T> {
+        self.even_batches = even_batches;
+        self
+    }
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for BucketBatcher<S, T> {
+    type Output = Batch<S::Batch>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        if index >= self.len()? {
+            return None;
+        }
+        let batch = &self.batches[(index * self.num_workers + self.worker_id) % self.batches.len()];
+        let samples: Vec<TokenizedText<S>> = batch
+            .iter()
+            .map(|&index| {
+                self.tokenizer
+                    .get(index)
+                    .expect("The sample was tokenized before")
+            })
+            .collect();
+        let seq_length = samples
+            .iter()
+            .map(|sample| sample.encoding.input_ids.len())
+            .max()
+            .unwrap_or(0);
+        Some(stack_samples(samples, seq_length, None, IGNORE_INDEX, None))
+    }
+    fn len(&self) -> Option<usize> {
+        let batches = self.batches.len();
+        if self.even_batches {
+            Some(batches.div_ceil(self.num_workers))
+        } else {
+            Some((batches + self.num_workers - 1 - self.worker_id) / self.num_workers)
+        }
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let batch = self.get(self.position)?;
+        self.position += 1;
+        Some(batch)
+    }
+    forward_hooks!(tokenizer);
+    fn set_epoch(&self, epoch: u64) {
+        self.tokenizer.set_epoch(epoch);
+    }
+}
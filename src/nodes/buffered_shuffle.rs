@@ -0,0 +1,85 @@
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed;
+use crate::{Node, PreprocessingError};
+
+// Approximate shuffling for streaming loaders, like `tf.data.Dataset.shuffle`. The first
+// `buffer_size` upstream outputs fill a buffer, every `next` emits a random one of them and
+// replaces it with the following upstream output. An output can thus only move up to
+// `buffer_size` positions ahead, so the shuffle is only as good as the buffer is large compared
+// to the dataset: a buffer as large as the dataset gives a full shuffle, a buffer of 1 none at
+// all. `get` isn't supported since the order depends on the random state, `len` is passed through.
+pub struct BufferedShuffle<T: Node> {
+    node: T,
+    buffer_size: usize,
+    rng: ChaCha8Rng,
+    buffer: Vec<T::Output>,
+    // Set by `finalize`, from then on only the buffer is emitted.
+    upstream_closed: bool,
+}
+
+impl<T: Node> BufferedShuffle<T> {
+    pub fn new(
+        node: T,
+        buffer_size: usize,
+        seed: Option<u64>,
+    ) -> Result<BufferedShuffle<T>, PreprocessingError> {
+        if buffer_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The buffer size has to be positive".to_string(),
+            ));
+        }
+        Ok(BufferedShuffle {
+            node,
+            buffer_size,
+            rng: seed::node_rng(seed),
+            buffer: Vec::with_capacity(buffer_size),
+            upstream_closed: false,
+        })
+    }
+}
+
+impl<T: Node> Node for BufferedShuffle<T> {
+    type Output = T::Output;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    // `get` isn't supported, the number of outputs is in `size_hint`.
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        while self.buffer.len() < self.buffer_size && !self.upstream_closed {
+            match self.node.next() {
+                Some(output) => self.buffer.push(output),
+                None => break,
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let index = self.rng.gen_range(0..self.buffer.len());
+        Some(self.buffer.swap_remove(index))
+    }
+    forward_hooks!(node, without finalize);
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.upstream_closed = !self.node.finalize();
+        true
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        let buffered = self.buffer.len();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        let estimate = self.node.estimated_len()?;
+        Some(estimate.saturating_add(self.buffer.len()))
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::iter;
+
+use ndarray::Array1;
+use tokenizers::tokenizer;
+
+use crate::{Label, Node, PreprocessingError, Sample, TokenizedText};
+
+type TokenizedSample<T> = TokenizedText<<<T as Sample>::Label as Label>::Tokenized>;
+
+// Byte-level tokenizer for models without a subword vocabulary: every UTF-8 byte of the text
+// becomes the token with the same id (0 to 255). `bos` is put in front of the sample and `eos`
+// after every sequence, so they should be ids outside of the byte range. The offset of every byte
+// is the character it belongs to, so character spans are tokenized the same way as with
+// `Tokenizer`.
+pub struct ByteTokenizer<T: Node>
+where
+    T::Output: Sample,
+{
+    loader: T,
+    bos: Option<u32>,
+    eos: Option<u32>,
+    pad_token: u32,
+    max_length: Option<usize>,
+}
+
+impl<T: Node> ByteTokenizer<T>
+where
+    T::Output: Sample,
+{
+    pub fn new(
+        loader: T,
+        bos: Option<u32>,
+        eos: Option<u32>,
+        pad_token: u32,
+        max_length: Option<usize>,
+    ) -> Result<ByteTokenizer<T>, PreprocessingError> {
+        Ok(ByteTokenizer {
+            loader,
+            bos,
+            eos,
+            pad_token,
+            max_length,
+        })
+    }
+    fn encode(&self, sample: &T::Output) -> tokenizer::Encoding {
+        let sequences: Vec<&[u8]> = (0..)
+            .map_while(|index| sample.sequence(index))
+            .map(str::as_bytes)
+            .collect();
+        // The character of every byte of every sequence.
+        let chars: Vec<Vec<usize>> = (0..)
+            .map_while(|index| sample.sequence(index))
+            .map(|sequence| {
+                sequence
+                    .chars()
+                    .enumerate()
+                    .flat_map(|(i, c)| iter::repeat_n(i, c.len_utf8()))
+                    .collect()
+            })
+            .collect();
+        let n_special = self.bos.is_some() as usize + self.eos.is_some() as usize * sequences.len();
+        // Truncation cuts the end of the last sequence first, like `OnlySecond` for pairs.
+        let mut budget = match self.max_length {
+            Some(max_length) => max_length.saturating_sub(n_special),
+            None => usize::MAX,
+        };
+        let mut lengths: Vec<usize> = sequences.iter().map(|bytes| bytes.len()).collect();
+        for length in lengths.iter_mut() {
+            *length = (*length).min(budget);
+            budget -= *length;
+        }
+
+        // (id, type id, offsets, special) of every token.
+        let mut tokens: Vec<(u32, u32, (usize, usize), u32)> = Vec::new();
+        let mut sequence_ranges = HashMap::new();
+        if let Some(bos) = self.bos {
+            tokens.push((bos, 0, (0, 0), 1));
+        }
+        for (sequence, (bytes, &length)) in sequences.iter().zip(&lengths).enumerate() {
+            let start = tokens.len();
+            let type_id = sequence as u32;
+            for (&byte, &c) in bytes[..length].iter().zip(&chars[sequence]) {
+                tokens.push((byte as u32, type_id, (c, c + 1), 0));
+            }
+            sequence_ranges.insert(sequence, start..tokens.len());
+            if let Some(eos) = self.eos {
+                tokens.push((eos, type_id, (0, 0), 1));
+            }
+        }
+        let ids = tokens.iter().map(|token| token.0).collect();
+        let type_ids = tokens.iter().map(|token| token.1).collect();
+        let offsets = tokens.iter().map(|token| token.2).collect();
+        let special_tokens_mask = tokens.iter().map(|token| token.3).collect();
+        let len = tokens.len();
+        tokenizer::Encoding::new(
+            ids,
+            type_ids,
+            vec![String::new(); len],
+            vec![None; len],
+            offsets,
+            special_tokens_mask,
+            vec![1; len],
+            Vec::new(),
+            sequence_ranges,
+        )
+    }
+    fn tokenize(&self, mut sample: T::Output) -> TokenizedSample<T::Output> {
+        let encoding = self.encode(&sample);
+        let metadata = sample.take_metadata();
+        let mut sample =
+            TokenizedText::from_tokenizer_encoding(encoding, sample.into_label(), self.pad_token);
+        sample.encoding.metadata = metadata;
+        sample
+    }
+}
+
+impl<T: Node> Node for ByteTokenizer<T>
+where
+    T::Output: Sample,
+{
+    type Output = TokenizedSample<T::Output>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.loader.get(index).map(|sample| self.tokenize(sample))
+    }
+    fn len(&self) -> Option<usize> {
+        self.loader.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.loader.next()?;
+        Some(self.tokenize(sample))
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        let lengths = (0..self.len()?)
+            .map(|index| Some(self.encode(&self.loader.get(index)?).len() as i64))
+            .collect::<Option<Vec<i64>>>()?;
+        Some(Array1::from_vec(lengths))
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.loader.label_mapping()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.loader.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.loader.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.loader.estimated_len()
+    }
+}
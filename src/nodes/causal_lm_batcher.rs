@@ -0,0 +1,129 @@
+use ndarray::Array2;
+
+use crate::{
+    stack_samples, Batch, BatchLMLabels, Encoding, NoTokenizedLabel, Node, PreprocessingError,
+    TokenizedText, IGNORE_INDEX,
+};
+
+// Batches tokenized lines for causal language modelling: `bos` is put in front of every sample
+// and `eos` after it, and the labels are the input ids with the ignore index (-100) at padding.
+// The labels aren't shifted, the model predicts label `i + 1` from position `i` like `transformers`
+// causal LMs do. Samples longer than `seq_length` lose the end of their text, so the `eos` is
+// always kept.
+pub struct CausalLMBatcher<T: Node<Output = TokenizedText<NoTokenizedLabel>>> {
+    node: T,
+    batch_size: usize,
+    seq_length: usize,
+    bos: Option<u32>,
+    eos: Option<u32>,
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> CausalLMBatcher<T> {
+    pub fn new(
+        node: T,
+        batch_size: usize,
+        seq_length: usize,
+        bos: Option<u32>,
+        eos: Option<u32>,
+    ) -> Result<CausalLMBatcher<T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        let n_special = bos.is_some() as usize + eos.is_some() as usize;
+        if seq_length <= n_special {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "The sequence length has to leave room for text next to {} special tokens",
+                n_special
+            )));
+        }
+        Ok(CausalLMBatcher {
+            node,
+            batch_size,
+            seq_length,
+            bos,
+            eos,
+        })
+    }
+    fn with_special_tokens(
+        &self,
+        sample: TokenizedText<NoTokenizedLabel>,
+    ) -> TokenizedText<NoTokenizedLabel> {
+        let budget = self.seq_length - self.bos.is_some() as usize - self.eos.is_some() as usize;
+        let text = sample.encoding.input_ids.iter().take(budget).copied();
+        let ids = self.bos.into_iter().chain(text).chain(self.eos).collect();
+        let mut encoding = Encoding::from_ids(ids, sample.encoding.pad_token);
+        encoding.prefix_special_tokens = self.bos.is_some() as usize;
+        encoding.context_start = encoding.prefix_special_tokens;
+        TokenizedText {
+            encoding,
+            label: NoTokenizedLabel,
+        }
+    }
+    fn create_batch(
+        &self,
+        samples: Vec<TokenizedText<NoTokenizedLabel>>,
+    ) -> Option<Batch<BatchLMLabels>> {
+        if samples.is_empty() {
+            return None;
+        }
+        let samples = samples
+            .into_iter()
+            .map(|sample| self.with_special_tokens(sample))
+            .collect();
+        let batch = stack_samples(samples, self.seq_length, None, IGNORE_INDEX, None);
+        let encoding = batch.encoding;
+        let mut labels: Array2<i64> = encoding.input_ids.mapv(|id| id as i64);
+        labels.zip_mut_with(&encoding.attention_mask, |label, &mask| {
+            if mask == 0 {
+                *label = IGNORE_INDEX;
+            }
+        });
+        Some(Batch {
+            encoding,
+            labels: BatchLMLabels(labels),
+        })
+    }
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> Node for CausalLMBatcher<T> {
+    type Output = Batch<BatchLMLabels>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let index = index * self.batch_size;
+        let samples = (index..index + self.batch_size)
+            .map_while(|i| self.node.get(i))
+            .collect();
+        self.create_batch(samples)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len().map(|len| len.div_ceil(self.batch_size))
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let samples = (0..self.batch_size)
+            .map_while(|_| self.node.next())
+            .collect();
+        self.create_batch(samples)
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (
+            lower.div_ceil(self.batch_size),
+            upper.map(|upper| upper.div_ceil(self.batch_size)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node
+            .estimated_len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+}
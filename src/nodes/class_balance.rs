@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed;
+use crate::{ClassLabel, Node, PreprocessingError, Text};
+
+// Oversamples minority classes for class-balanced batches: every output draws a class uniformly
+// and then one of its examples, both with replacement. The upstream node has to be indexable
+// since the examples are grouped by class up front, which reads every label once. The stream
+// ends after `epoch_size` outputs or never without one.
+pub struct ClassBalance<T: Node<Output = Text<ClassLabel>>> {
+    node: T,
+    // Upstream indices of every class, sorted by class id so a seed gives the same stream.
+    classes: Vec<Vec<usize>>,
+    epoch_size: Option<usize>,
+    rng: ChaCha8Rng,
+    emitted: usize,
+}
+
+impl<T: Node<Output = Text<ClassLabel>>> ClassBalance<T> {
+    pub fn new(
+        node: T,
+        epoch_size: Option<usize>,
+        seed: Option<u64>,
+    ) -> Result<ClassBalance<T>, PreprocessingError> {
+        let len = node.len().ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "ClassBalance requires an indexable upstream node".to_string(),
+            )
+        })?;
+        let mut classes: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for index in 0..len {
+            let label = node
+                .get(index)
+                .ok_or_else(|| {
+                    PreprocessingError::IncompatibleNode(format!(
+                        "The upstream node has no example {} although its length is {}",
+                        index, len
+                    ))
+                })?
+                .label;
+            classes.entry(label.0).or_default().push(index);
+        }
+        if classes.is_empty() {
+            return Err(PreprocessingError::InvalidArgument(
+                "The upstream node has no examples to balance".to_string(),
+            ));
+        }
+        Ok(ClassBalance {
+            node,
+            classes: classes.into_values().collect(),
+            epoch_size,
+            rng: seed::node_rng(seed),
+            emitted: 0,
+        })
+    }
+}
+
+impl<T: Node<Output = Text<ClassLabel>>> Node for ClassBalance<T> {
+    type Output = Text<ClassLabel>;
+    // Not implemented since the outputs depend on the random state.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    // `get` isn't supported, the number of outputs is in `size_hint`.
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.epoch_size == Some(self.emitted) {
+            return None;
+        }
+        self.emitted += 1;
+        let class = self.classes.choose(&mut self.rng).unwrap();
+        let index = *class.choose(&mut self.rng).unwrap();
+        self.node.get(index)
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    forward_hooks!(node);
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.epoch_size {
+            Some(epoch_size) => (epoch_size, Some(epoch_size)),
+            None => (usize::MAX, None),
+        }
+    }
+}
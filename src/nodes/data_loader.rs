@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use ndarray::Array1;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::nodes::{AnswerRetention, BatchStats, TruncationStats};
+use crate::seed;
+use crate::{Node, PreprocessingError};
+
+// Sends the outputs of a worker, `None` once the node is exhausted and the payload if it panicked.
+type Worker<T> = Receiver<thread::Result<Option<T>>>;
+
+// The order in which `DataLoader::next` hands out the prefetched outputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrainOrder {
+    // The order of the upstream node, whatever the number of workers.
+    Fifo,
+    // A random one of the next `num_workers * prefetch_factor` outputs, like a `BufferedShuffle`
+    // of that size. Every output is still emitted exactly once.
+    Shuffled,
+}
+
+impl FromStr for DrainOrder {
+    type Err = PreprocessingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(DrainOrder::Fifo),
+            "shuffled" => Ok(DrainOrder::Shuffled),
+            _ => Err(PreprocessingError::Parse(format!(
+                "Unknown value {:?}, expected \"fifo\" or \"shuffled\"",
+                s
+            ))),
+        }
+    }
+}
+
+// Builds the outputs of an indexable node on `num_workers` threads. Worker `w` produces the
+// indices `w, w + num_workers, ...` and keeps up to `prefetch_factor` of them queued, so
+// reading the workers round-robin returns the outputs in order. A panic of the node on a worker
+// is raised again by `next` once its output is due, after which the loader is exhausted.
+//
+// With `DrainOrder::Shuffled` the outputs read from the workers (still round-robin) go into a
+// buffer of `num_workers * prefetch_factor` outputs first, and `next` emits a random one of them.
+// An output thus moves at most that many positions, and given the seed the order is the same
+// however fast the workers are, since the buffer is always filled in index order.
+pub struct DataLoader<T: Node + 'static> {
+    node: Arc<T>,
+    num_workers: usize,
+    prefetch_factor: usize,
+    // Only set with `DrainOrder::Shuffled`.
+    rng: Option<ChaCha8Rng>,
+    buffer: Vec<T::Output>,
+    // Started on the first call to `next`. Only accessed through `&mut self`, the mutex just
+    // makes the loader `Sync`.
+    workers: Mutex<Vec<Worker<T::Output>>>,
+    index: usize,
+    exhausted: bool,
+}
+
+impl<T: Node + 'static> DataLoader<T>
+where
+    T::Output: Send,
+{
+    pub fn new(
+        node: T,
+        num_workers: usize,
+        prefetch_factor: usize,
+    ) -> Result<DataLoader<T>, PreprocessingError> {
+        if node.len().is_none() {
+            return Err(PreprocessingError::IncompatibleNode(
+                "DataLoader requires an indexable upstream node".to_string(),
+            ));
+        }
+        if num_workers == 0 || prefetch_factor == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "num_workers and prefetch_factor have to be positive".to_string(),
+            ));
+        }
+        Ok(DataLoader {
+            node: Arc::new(node),
+            num_workers,
+            prefetch_factor,
+            rng: None,
+            buffer: Vec::new(),
+            workers: Mutex::new(Vec::new()),
+            index: 0,
+            exhausted: false,
+        })
+    }
+    pub fn with_drain_order(mut self, drain_order: DrainOrder, seed: Option<u64>) -> Self {
+        self.rng = match drain_order {
+            DrainOrder::Fifo => None,
+            DrainOrder::Shuffled => Some(seed::node_rng(seed)),
+        };
+        self
+    }
+    fn start_workers(&mut self) {
+        let workers = self.workers.get_mut().unwrap();
+        for worker in 0..self.num_workers {
+            let (sender, receiver) = mpsc::sync_channel(self.prefetch_factor);
+            let node = Arc::clone(&self.node);
+            let num_workers = self.num_workers;
+            thread::spawn(move || {
+                for index in (worker..).step_by(num_workers) {
+                    let output = panic::catch_unwind(AssertUnwindSafe(|| node.get(index)));
+                    let done = !matches!(output, Ok(Some(_)));
+                    // Sending fails once the loader is dropped.
+                    if sender.send(output).is_err() || done {
+                        break;
+                    }
+                }
+            });
+            workers.push(receiver);
+        }
+    }
+    // The next output in order.
+    fn receive(&mut self) -> Option<T::Output> {
+        if self.exhausted {
+            return None;
+        }
+        if self.workers.get_mut().unwrap().is_empty() {
+            self.start_workers();
+        }
+        let worker = &self.workers.get_mut().unwrap()[self.index % self.num_workers];
+        match worker.recv() {
+            Ok(Ok(Some(output))) => {
+                self.index += 1;
+                Some(output)
+            }
+            Ok(Err(payload)) => {
+                self.exhausted = true;
+                self.workers.get_mut().unwrap().clear();
+                panic::resume_unwind(payload)
+            }
+            Ok(Ok(None)) | Err(_) => {
+                // Dropping the receivers stops the remaining workers.
+                self.exhausted = true;
+                self.workers.get_mut().unwrap().clear();
+                None
+            }
+        }
+    }
+}
+
+impl<T: Node + 'static> Node for DataLoader<T>
+where
+    T::Output: Send,
+{
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.rng.is_none() {
+            return self.receive();
+        }
+        while self.buffer.len() < self.num_workers * self.prefetch_factor {
+            match self.receive() {
+                Some(output) => self.buffer.push(output),
+                None => break,
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let index = self.rng.as_mut().unwrap().gen_range(0..self.buffer.len());
+        Some(self.buffer.swap_remove(index))
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        self.node.stats()
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.node.truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.node.max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        self.node.feature_stats()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.node
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.node.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,131 @@
+use tokenizers::tokenizer;
+
+use crate::retry::{self, Retry};
+use crate::{
+    resolve_pad_token, stack_samples, BatchEncoding, NoLabel, NoTokenizedLabel, Node,
+    PreprocessingError, Text, TokenizedText, IGNORE_INDEX,
+};
+
+// A batch of texts encoded by both tokenizers of a `DualTokenizer`, row `i` of both encodings is
+// the same text.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct DualBatch {
+    pub(crate) teacher: BatchEncoding,
+    pub(crate) student: BatchEncoding,
+}
+
+// Encodes every text with two tokenizers, e.g. for distilling a teacher into a student with a
+// different vocabulary. Both tokenizers encode the same batch of texts (in one `encode_batch`
+// call each), so the encodings stay aligned to their source. Both are padded or truncated to
+// `seq_length`, with the padding token of the respective tokenizer.
+pub struct DualTokenizer<T: Node<Output = Text<NoLabel>>> {
+    loader: T,
+    teacher: tokenizer::Tokenizer,
+    student: tokenizer::Tokenizer,
+    batch_size: usize,
+    seq_length: usize,
+    teacher_pad_token: u32,
+    student_pad_token: u32,
+}
+
+impl<T: Node<Output = Text<NoLabel>>> DualTokenizer<T> {
+    pub fn new<S: AsRef<str>>(
+        loader: T,
+        teacher: S,
+        student: S,
+        batch_size: usize,
+        seq_length: usize,
+        retry: &Retry,
+    ) -> Result<DualTokenizer<T>, PreprocessingError> {
+        let teacher = retry::from_pretrained(teacher.as_ref(), None, retry)?;
+        let student = retry::from_pretrained(student.as_ref(), None, retry)?;
+        DualTokenizer::from_tokenizers(loader, teacher, student, batch_size, seq_length)
+    }
+    pub fn from_tokenizers(
+        loader: T,
+        teacher: tokenizer::Tokenizer,
+        student: tokenizer::Tokenizer,
+        batch_size: usize,
+        seq_length: usize,
+    ) -> Result<DualTokenizer<T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        let teacher_pad_token = resolve_pad_token(&teacher, None)?;
+        let student_pad_token = resolve_pad_token(&student, None)?;
+        Ok(DualTokenizer {
+            loader,
+            teacher,
+            student,
+            batch_size,
+            seq_length,
+            teacher_pad_token,
+            student_pad_token,
+        })
+    }
+    fn encode(
+        &self,
+        tokenizer: &tokenizer::Tokenizer,
+        pad_token: u32,
+        texts: &[Text<NoLabel>],
+    ) -> BatchEncoding {
+        let texts = texts.iter().map(|text| text.text.as_str()).collect();
+        let samples: Vec<TokenizedText<NoTokenizedLabel>> = tokenizer
+            .encode_batch(texts, false)
+            .expect("Failed to tokenize")
+            .into_iter()
+            .map(|encoding| TokenizedText::from_tokenizer_encoding(encoding, NoLabel(), pad_token))
+            .collect();
+        stack_samples(samples, self.seq_length, None, IGNORE_INDEX, None).encoding
+    }
+    fn create_batch(&self, texts: Vec<Text<NoLabel>>) -> Option<DualBatch> {
+        if texts.is_empty() {
+            return None;
+        }
+        Some(DualBatch {
+            teacher: self.encode(&self.teacher, self.teacher_pad_token, &texts),
+            student: self.encode(&self.student, self.student_pad_token, &texts),
+        })
+    }
+}
+
+impl<T: Node<Output = Text<NoLabel>>> Node for DualTokenizer<T> {
+    type Output = DualBatch;
+    fn get(&self, index: usize) -> Option<DualBatch> {
+        let index = index * self.batch_size;
+        let texts = (index..index + self.batch_size)
+            .map_while(|i| self.loader.get(i))
+            .collect();
+        self.create_batch(texts)
+    }
+    fn len(&self) -> Option<usize> {
+        self.loader.len().map(|len| len.div_ceil(self.batch_size))
+    }
+    fn next(&mut self) -> Option<DualBatch> {
+        let texts = (0..self.batch_size)
+            .map_while(|_| self.loader.next())
+            .collect();
+        self.create_batch(texts)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.loader.size_hint();
+        (
+            lower.div_ceil(self.batch_size),
+            upper.map(|upper| upper.div_ceil(self.batch_size)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.loader
+            .estimated_len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.loader.finalize()
+    }
+}
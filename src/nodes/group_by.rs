@@ -0,0 +1,134 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use crate::nodes::RaggedBatch;
+use crate::{Node, PreprocessingError, TokenizedLabel, TokenizedText};
+
+// Puts all samples with the same key into the same batch. Groups are packed into batches of at
+// most `batch_size` samples in the order their keys are first seen, a group that doesn't fit into
+// the current batch starts a new one and groups larger than `batch_size` are split. Samples
+// without a key form a group of their own.
+//
+// The key of a sample isn't known before the whole upstream node has been read, so the first call
+// to `next` buffers every upstream sample. Memory use is therefore proportional to the size of the
+// tokenized dataset.
+pub struct GroupBy<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>, K, F> {
+    node: T,
+    batch_size: usize,
+    key: F,
+    // Filled on the first call to `next`.
+    batches: Option<VecDeque<Vec<TokenizedText<S>>>>,
+    _key: std::marker::PhantomData<fn() -> K>,
+}
+
+impl<S, T, K, F> GroupBy<S, T, K, F>
+where
+    S: TokenizedLabel,
+    T: Node<Output = TokenizedText<S>>,
+    K: Hash + Eq,
+    F: Fn(&TokenizedText<S>) -> Option<K> + Send + Sync,
+{
+    pub fn new(
+        node: T,
+        batch_size: usize,
+        key: F,
+    ) -> Result<GroupBy<S, T, K, F>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        Ok(GroupBy {
+            node,
+            batch_size,
+            key,
+            batches: None,
+            _key: std::marker::PhantomData,
+        })
+    }
+    fn group(&mut self) -> VecDeque<Vec<TokenizedText<S>>> {
+        let mut groups: Vec<Vec<TokenizedText<S>>> = Vec::new();
+        let mut group_indices: HashMap<K, usize> = HashMap::new();
+        while let Some(sample) = self.node.next() {
+            match (self.key)(&sample) {
+                Some(key) => {
+                    let index = *group_indices.entry(key).or_insert_with(|| {
+                        groups.push(Vec::new());
+                        groups.len() - 1
+                    });
+                    groups[index].push(sample);
+                }
+                None => groups.push(vec![sample]),
+            }
+        }
+        let mut batches = VecDeque::new();
+        let mut batch: Vec<TokenizedText<S>> = Vec::new();
+        for group in groups {
+            if batch.len() + group.len() > self.batch_size && !batch.is_empty() {
+                batches.push_back(std::mem::take(&mut batch));
+            }
+            let mut group = group.into_iter().peekable();
+            while group.peek().is_some() {
+                if batch.len() == self.batch_size {
+                    batches.push_back(std::mem::take(&mut batch));
+                }
+                let n = self.batch_size - batch.len();
+                batch.extend(group.by_ref().take(n));
+            }
+        }
+        if !batch.is_empty() {
+            batches.push_back(batch);
+        }
+        batches
+    }
+}
+
+// The variant constructed from Python, which can't pass a key function.
+pub type GroupByExample<S, T> = GroupBy<S, T, usize, fn(&TokenizedText<S>) -> Option<usize>>;
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> GroupByExample<S, T> {
+    // Groups the overflowing windows of each example, so the upstream tokenizer has to keep
+    // overflowing tokens.
+    pub fn by_example_index(node: T, batch_size: usize) -> Result<Self, PreprocessingError> {
+        GroupBy::new(node, batch_size, |sample: &TokenizedText<S>| {
+            sample.encoding.example_index
+        })
+    }
+}
+
+impl<S, T, K, F> Node for GroupBy<S, T, K, F>
+where
+    S: TokenizedLabel,
+    T: Node<Output = TokenizedText<S>>,
+    K: Hash + Eq,
+    F: Fn(&TokenizedText<S>) -> Option<K> + Send + Sync,
+{
+    type Output = RaggedBatch<S>;
+    // Not implemented since it requires grouping the whole upstream node.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.batches.is_none() {
+            self.batches = Some(self.group());
+        }
+        let samples = self.batches.as_mut().unwrap().pop_front()?;
+        let (encodings, labels) = samples
+            .into_iter()
+            .map(|TokenizedText { encoding, label }| (encoding, label))
+            .unzip();
+        Some(RaggedBatch { encodings, labels })
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    // Every batch has at least one and at most `batch_size` samples.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (lower.div_ceil(self.batch_size), upper)
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::nodes::AnswerRetention;
+use crate::{Node, PreprocessingError, TokenizedLabel, TokenizedText};
+
+// 64-bit FNV-1a, simple and fixed by its definition, so ids stay the same across runs, platforms
+// and Rust versions (unlike `DefaultHasher`).
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Gives every tokenized sample a hash of its text as id, which batchers stack into
+// `BatchEncoding::hash_ids`, e.g. to join predictions back to the inputs or to deduplicate across
+// runs. Like for `WithText`, the `Tokenizer` upstream is made to keep the texts and the sequences
+// of a pair are joined by a newline. The overflowing windows of a sample all get its id. Samples
+// encoded before (like a peeked one) have no text and get no id.
+pub struct HashId<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    node: T,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> HashId<S, T> {
+    pub fn new(mut node: T) -> Result<HashId<S, T>, PreprocessingError> {
+        if !node.keep_texts() {
+            return Err(PreprocessingError::IncompatibleNode(
+                "HashId requires a Tokenizer upstream".to_string(),
+            ));
+        }
+        Ok(HashId { node })
+    }
+}
+
+// The text is left in place for a `WithText` downstream.
+fn with_hash_id<S: TokenizedLabel>(mut sample: TokenizedText<S>) -> TokenizedText<S> {
+    sample.encoding.hash_id = sample
+        .encoding
+        .text
+        .as_deref()
+        .map(|text| fnv1a(text.as_bytes()));
+    sample
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for HashId<S, T> {
+    type Output = TokenizedText<S>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(with_hash_id)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.node.next().map(with_hash_id)
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        true
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
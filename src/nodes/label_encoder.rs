@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ndarray::Array1;
+
+use crate::datasets::MalformedPolicy;
+use crate::{ClassLabel, Node, PreprocessingError, StringLabel, Text};
+
+// Converts string labels into class ids. Without an explicit mapping, ids are assigned in the
+// order the labels are first seen, so for reproducible ids across runs either pass the mapping
+// returned by `label_mapping` of a previous run or only read the node sequentially. With an
+// explicit mapping, unknown labels are handled by `on_unknown`, an error by default. Skipping
+// them makes the indices of the samples unknown, so `get` and `len` aren't supported then.
+pub struct LabelEncoder<T: Node<Output = Text<StringLabel>>> {
+    node: T,
+    mapping: Mutex<HashMap<String, u32>>,
+    fixed: bool,
+    on_unknown: MalformedPolicy,
+}
+
+impl<T: Node<Output = Text<StringLabel>>> LabelEncoder<T> {
+    pub fn new(
+        node: T,
+        mapping: Option<HashMap<String, u32>>,
+    ) -> Result<LabelEncoder<T>, PreprocessingError> {
+        match mapping {
+            Some(mapping) => LabelEncoder::with_mapping(node, mapping),
+            None => Ok(LabelEncoder {
+                node,
+                mapping: Mutex::new(HashMap::new()),
+                fixed: false,
+                on_unknown: MalformedPolicy::Error,
+            }),
+        }
+    }
+    pub fn with_mapping(
+        node: T,
+        mapping: HashMap<String, u32>,
+    ) -> Result<LabelEncoder<T>, PreprocessingError> {
+        let mut ids: Vec<u32> = mapping.values().copied().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.len() != mapping.len() {
+            return Err(PreprocessingError::InvalidArgument(
+                "The label mapping assigns the same id to several labels".to_string(),
+            ));
+        }
+        Ok(LabelEncoder {
+            node,
+            mapping: Mutex::new(mapping),
+            fixed: true,
+            on_unknown: MalformedPolicy::Error,
+        })
+    }
+    pub fn with_on_unknown(mut self, on_unknown: MalformedPolicy) -> LabelEncoder<T> {
+        self.on_unknown = on_unknown;
+        self
+    }
+    fn skips(&self) -> bool {
+        self.fixed && self.on_unknown == MalformedPolicy::Skip
+    }
+    // Label names of predicted class ids, e.g. the argmax of a classifier's logits.
+    pub fn decode_labels(&self, ids: &Array1<u32>) -> Result<Vec<String>, PreprocessingError> {
+        decode_labels(&self.mapping.lock().unwrap(), ids)
+    }
+    fn encode(&self, sample: Text<StringLabel>) -> Option<Text<ClassLabel>> {
+        let Text {
+            text,
+            label,
+            metadata,
+        } = sample;
+        let mut mapping = self.mapping.lock().unwrap();
+        let id = match mapping.get(&label.0) {
+            Some(&id) => id,
+            None if self.fixed => {
+                let message = format!("Unknown label {:?}", label.0);
+                self.on_unknown.handle(&message, "the sample");
+                return None;
+            }
+            None => {
+                let id = mapping.len() as u32;
+                mapping.insert(label.0, id);
+                id
+            }
+        };
+        Some(Text {
+            text,
+            label: ClassLabel(id),
+            metadata,
+        })
+    }
+}
+
+// Inverts a mapping returned by `label_mapping`. Ids the mapping doesn't contain are an error.
+pub fn decode_labels(
+    mapping: &HashMap<String, u32>,
+    ids: &Array1<u32>,
+) -> Result<Vec<String>, PreprocessingError> {
+    let names: HashMap<u32, &str> = mapping
+        .iter()
+        .map(|(name, &id)| (id, name.as_str()))
+        .collect();
+    ids.iter()
+        .map(|id| {
+            names.get(id).map(|name| name.to_string()).ok_or_else(|| {
+                PreprocessingError::InvalidArgument(format!("Unknown label id {}", id))
+            })
+        })
+        .collect()
+}
+
+impl<T: Node<Output = Text<StringLabel>>> Node for LabelEncoder<T> {
+    type Output = Text<ClassLabel>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        if self.skips() {
+            return None;
+        }
+        self.encode(self.node.get(index)?)
+    }
+    fn len(&self) -> Option<usize> {
+        if self.skips() {
+            return None;
+        }
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let sample = self.node.next()?;
+            if let Some(sample) = self.encode(sample) {
+                return Some(sample);
+            }
+        }
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        Some(self.mapping.lock().unwrap().clone())
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        match self.skips() {
+            true => (0, upper),
+            false => (lower, upper),
+        }
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,94 @@
+use ndarray::Array2;
+
+use crate::{error, Batch, BatchClassLabel, BatchEncoding, Node, PreprocessingError};
+
+// A batch of class labels turned into soft targets by `LabelSmooth`.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct SmoothedBatch {
+    pub(crate) encoding: BatchEncoding,
+    // `(batch, num_classes)` probabilities of every class.
+    pub(crate) targets: Array2<f32>,
+}
+
+// Replaces the class ids of every batch with label smoothed targets: `1 - epsilon` on the true
+// class and `epsilon / (num_classes - 1)` on every other one, so every row sums to 1. Rows
+// without a label (the ignore index, e.g. of a padded batch) get all zeros, so they don't
+// contribute to a cross entropy loss.
+pub struct LabelSmooth<T: Node<Output = Batch<BatchClassLabel>>> {
+    node: T,
+    num_classes: usize,
+    epsilon: f32,
+}
+
+impl<T: Node<Output = Batch<BatchClassLabel>>> LabelSmooth<T> {
+    pub fn new(
+        node: T,
+        num_classes: usize,
+        epsilon: f32,
+    ) -> Result<LabelSmooth<T>, PreprocessingError> {
+        if num_classes < 2 {
+            return Err(PreprocessingError::InvalidArgument(
+                "Label smoothing needs at least two classes".to_string(),
+            ));
+        }
+        if !(0.0..1.0).contains(&epsilon) {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "The smoothing has to be in [0, 1), got {}",
+                epsilon
+            )));
+        }
+        Ok(LabelSmooth {
+            node,
+            num_classes,
+            epsilon,
+        })
+    }
+    fn smooth(&self, batch: Batch<BatchClassLabel>) -> SmoothedBatch {
+        let Batch { encoding, labels } = batch;
+        let labels = labels.0;
+        let other = self.epsilon / (self.num_classes - 1) as f32;
+        let mut targets = Array2::from_elem((labels.len(), self.num_classes), other);
+        for (mut row, &label) in targets.rows_mut().into_iter().zip(labels.iter()) {
+            if label < 0 {
+                row.fill(0.0);
+                continue;
+            }
+            let label = label as usize;
+            if label >= self.num_classes {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "Got class {} with {} classes",
+                    label, self.num_classes
+                )));
+            }
+            row[label] = 1.0 - self.epsilon;
+        }
+        SmoothedBatch { encoding, targets }
+    }
+}
+
+impl<T: Node<Output = Batch<BatchClassLabel>>> Node for LabelSmooth<T> {
+    type Output = SmoothedBatch;
+    fn get(&self, index: usize) -> Option<SmoothedBatch> {
+        self.node.get(index).map(|batch| self.smooth(batch))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<SmoothedBatch> {
+        let batch = self.node.next()?;
+        Some(self.smooth(batch))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ndarray::Array1;
+
+use crate::nodes::AnswerRetention;
+use crate::{
+    Both, ClassLabel, Node, PreprocessingError, TokenizedLabel, TokenizedSpan, TokenizedText,
+};
+
+// Converts a tokenized label into a different label scheme.
+pub trait LabelMapping: Send + Sync {
+    type From: TokenizedLabel;
+    type To: TokenizedLabel;
+    fn map(&self, label: Self::From) -> Self::To;
+}
+
+pub struct MapLabel<M: LabelMapping, T: Node<Output = TokenizedText<M::From>>> {
+    node: T,
+    mapping: M,
+}
+
+impl<M: LabelMapping, T: Node<Output = TokenizedText<M::From>>> MapLabel<M, T> {
+    pub fn with_mapping(node: T, mapping: M) -> MapLabel<M, T> {
+        MapLabel { node, mapping }
+    }
+    fn map(&self, sample: TokenizedText<M::From>) -> TokenizedText<M::To> {
+        let TokenizedText { encoding, label } = sample;
+        TokenizedText {
+            encoding,
+            label: self.mapping.map(label),
+        }
+    }
+}
+
+impl<M: LabelMapping, T: Node<Output = TokenizedText<M::From>>> Node for MapLabel<M, T> {
+    type Output = TokenizedText<M::To>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(|sample| self.map(sample))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.node.next()?;
+        Some(self.map(sample))
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.node
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.node.window_ranges(example_index)
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.node.max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.node.keep_texts()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
+
+// 1 if the span could be mapped to tokens, 0 otherwise.
+pub struct Answerable;
+
+impl LabelMapping for Answerable {
+    type From = TokenizedSpan;
+    type To = ClassLabel;
+    fn map(&self, label: TokenizedSpan) -> ClassLabel {
+        ClassLabel(label.0.is_some() as u32)
+    }
+}
+
+pub type SpanToAnswerable<T> = MapLabel<Answerable, T>;
+
+impl<T: Node<Output = TokenizedText<TokenizedSpan>>> SpanToAnswerable<T> {
+    pub fn new(node: T) -> Result<SpanToAnswerable<T>, PreprocessingError> {
+        Ok(MapLabel::with_mapping(node, Answerable))
+    }
+}
+
+// Keeps the span and adds whether it could be mapped to tokens (see `Answerable`) as a second
+// label, for a span head and an answerability head.
+pub struct WithAnswerable;
+
+impl LabelMapping for WithAnswerable {
+    type From = TokenizedSpan;
+    type To = Both<TokenizedSpan, ClassLabel>;
+    fn map(&self, label: TokenizedSpan) -> Both<TokenizedSpan, ClassLabel> {
+        let answerable = ClassLabel(label.0.is_some() as u32);
+        Both::new(label, answerable)
+    }
+}
+
+pub type SpanWithAnswerable<T> = MapLabel<WithAnswerable, T>;
+
+impl<T: Node<Output = TokenizedText<TokenizedSpan>>> SpanWithAnswerable<T> {
+    pub fn new(node: T) -> Result<SpanWithAnswerable<T>, PreprocessingError> {
+        Ok(MapLabel::with_mapping(node, WithAnswerable))
+    }
+}
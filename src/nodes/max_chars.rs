@@ -0,0 +1,57 @@
+use crate::{Node, PreprocessingError, Sample};
+
+// Drops samples with more than `max_chars` characters (over both texts of a pair) before they
+// are tokenized, as a cheap filter for giant inputs that would be discarded after tokenization
+// anyway. Since the remaining samples can't be located without reading everything before them,
+// `get` and `len` aren't supported.
+pub struct MaxChars<T: Node> {
+    node: T,
+    max_chars: usize,
+}
+
+impl<T: Node> MaxChars<T>
+where
+    T::Output: Sample,
+{
+    pub fn new(node: T, max_chars: usize) -> Result<MaxChars<T>, PreprocessingError> {
+        Ok(MaxChars { node, max_chars })
+    }
+}
+
+fn num_chars<S: Sample>(sample: &S) -> usize {
+    (0..)
+        .map_while(|index| sample.sequence(index))
+        .map(|sequence| sequence.chars().count())
+        .sum()
+}
+
+impl<T: Node> Node for MaxChars<T>
+where
+    T::Output: Sample,
+{
+    type Output = T::Output;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let sample = self.node.next()?;
+            if num_chars(&sample) <= self.max_chars {
+                return Some(sample);
+            }
+        }
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.node.size_hint().1)
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
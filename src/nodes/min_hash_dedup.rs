@@ -0,0 +1,143 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::seed::derive_seed;
+use crate::{NoLabel, Node, PreprocessingError, Text};
+
+// Drops near-duplicate documents, e.g. copies of a page that only differ in their boilerplate.
+// Every text is split into shingles of `shingle_size` consecutive words and summarized by a
+// MinHash signature of `num_hashes` values, the fraction of values two signatures share estimates
+// the Jaccard similarity of their shingle sets. A text is dropped if its estimated similarity to
+// an earlier kept text is at least `threshold`. Instead of comparing against every kept text,
+// signatures are split into bands and only texts sharing a band are compared (locality-sensitive
+// hashing), the number of bands is chosen so that pairs around `threshold` become candidates.
+//
+// Every kept text costs the `8 * num_hashes` bytes of its signature and an entry per band, so
+// memory grows with the number of unique texts rather than their length. Computing a signature
+// takes `num_hashes` hashes per shingle, so fewer hashes are faster and smaller but estimate the
+// similarity less precisely. Which copy is kept depends on the order, so `get` isn't supported
+// and the length is unknown.
+pub struct MinHashDedup<T: Node<Output = Text<NoLabel>>> {
+    node: T,
+    num_hashes: usize,
+    shingle_size: usize,
+    threshold: f64,
+    rows_per_band: usize,
+    signatures: Vec<Vec<u64>>,
+    // Indices into `signatures` of the kept texts, by band and hash of their values in the band.
+    bands: Vec<HashMap<u64, Vec<usize>>>,
+}
+
+impl<T: Node<Output = Text<NoLabel>>> MinHashDedup<T> {
+    pub fn new(
+        node: T,
+        num_hashes: usize,
+        shingle_size: usize,
+        threshold: f64,
+    ) -> Result<MinHashDedup<T>, PreprocessingError> {
+        if num_hashes == 0 || shingle_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The number of hashes and the shingle size have to be positive".to_string(),
+            ));
+        }
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            return Err(PreprocessingError::InvalidArgument(
+                "The threshold has to be in (0, 1]".to_string(),
+            ));
+        }
+        let rows_per_band = rows_per_band(num_hashes, threshold);
+        Ok(MinHashDedup {
+            node,
+            num_hashes,
+            shingle_size,
+            threshold,
+            rows_per_band,
+            signatures: Vec::new(),
+            bands: vec![HashMap::new(); num_hashes / rows_per_band],
+        })
+    }
+    fn signature(&self, text: &str) -> Vec<u64> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut signature = vec![u64::MAX; self.num_hashes];
+        // Texts shorter than a shingle are a single shingle.
+        for shingle in words.windows(self.shingle_size.min(words.len()).max(1)) {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            let hash = hasher.finish();
+            for (i, min) in signature.iter_mut().enumerate() {
+                *min = (*min).min(derive_seed(hash, i as u64));
+            }
+        }
+        signature
+    }
+    fn band_hashes<'a>(&self, signature: &'a [u64]) -> impl Iterator<Item = u64> + 'a {
+        signature.chunks(self.rows_per_band).map(|band| {
+            let mut hasher = DefaultHasher::new();
+            band.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+    fn is_duplicate(&self, signature: &[u64]) -> bool {
+        self.band_hashes(signature)
+            .zip(&self.bands)
+            .filter_map(|(hash, band)| band.get(&hash))
+            .flatten()
+            .any(|&index| similarity(signature, &self.signatures[index]) >= self.threshold)
+    }
+}
+
+// Rows per band out of the divisors of `num_hashes`, such that the similarity at which pairs
+// become candidates with probability 1/2, about `(1 / bands)^(1 / rows)`, is closest to
+// `threshold`.
+fn rows_per_band(num_hashes: usize, threshold: f64) -> usize {
+    let distance = |rows: usize| {
+        let bands = (num_hashes / rows) as f64;
+        ((1.0 / bands).powf(1.0 / rows as f64) - threshold).abs()
+    };
+    (1..=num_hashes)
+        .filter(|&rows| num_hashes.is_multiple_of(rows))
+        .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(1)
+}
+
+fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    let shared = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    shared as f64 / a.len() as f64
+}
+
+impl<T: Node<Output = Text<NoLabel>>> Node for MinHashDedup<T> {
+    type Output = Text<NoLabel>;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let sample = self.node.next()?;
+            let signature = self.signature(&sample.text);
+            if self.is_duplicate(&signature) {
+                continue;
+            }
+            let index = self.signatures.len();
+            let hashes: Vec<u64> = self.band_hashes(&signature).collect();
+            for (hash, band) in hashes.into_iter().zip(&mut self.bands) {
+                band.entry(hash).or_default().push(index);
+            }
+            self.signatures.push(signature);
+            return Some(sample);
+        }
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (lower.min(1), upper)
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{Node, PreprocessingError, TokenizedLabel, TokenizedText};
+
+// Drops samples with fewer than `min_length` tokens, special tokens included. Since the
+// remaining samples can't be located without tokenizing everything before them, `get` and `len`
+// aren't supported.
+pub struct MinLength<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    node: T,
+    min_length: usize,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> MinLength<S, T> {
+    pub fn new(node: T, min_length: usize) -> Result<MinLength<S, T>, PreprocessingError> {
+        Ok(MinLength { node, min_length })
+    }
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for MinLength<S, T> {
+    type Output = TokenizedText<S>;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        loop {
+            let sample = self.node.next()?;
+            if sample.encoding.input_ids.len() >= self.min_length {
+                return Some(sample);
+            }
+        }
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.node.size_hint().1)
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
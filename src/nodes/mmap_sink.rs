@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::{Batch, BatchLabel, Node, PreprocessingError};
+
+// Size of the `.npy` header, big enough for any shape so it can be rewritten in place once the
+// number of rows is known.
+const HEADER_LEN: usize = 128;
+
+fn npy_header(rows: usize, seq_length: usize) -> Vec<u8> {
+    let dict = format!(
+        "{{'descr': '<u4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, seq_length
+    );
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend(((HEADER_LEN - 10) as u16).to_le_bytes());
+    header.extend(dict.bytes());
+    header.resize(HEADER_LEN - 1, b' ');
+    header.push(b'\n');
+    header
+}
+
+// Writes the `input_ids` of every batch of the node into one `.npy` file at `path`, e.g. to
+// preprocess a corpus once and memory map it for training (`numpy.load(path, mmap_mode="r")`).
+// Returns the shape of the array, `(rows, seq_length)`. With a known length the file is
+// preallocated for `len` batches of the size of the first one, otherwise it grows with every
+// batch. Either way the header is rewritten with the number of rows written at the end, so a
+// short last batch is fine. All batches have to have the same sequence length.
+pub fn write_mmap<S: BatchLabel, T: Node<Output = Batch<S>> + ?Sized>(
+    node: &mut T,
+    path: &Path,
+) -> Result<(usize, usize), PreprocessingError> {
+    let mut file = File::create(path)?;
+    file.write_all(&npy_header(0, 0))?;
+    let mut rows = 0;
+    let mut seq_length = None;
+    {
+        let mut writer = BufWriter::new(&mut file);
+        while let Some(batch) = node.next() {
+            let input_ids = &batch.encoding.input_ids;
+            match seq_length {
+                None => {
+                    if let Some(len) = node.len() {
+                        let size = len * input_ids.len() * 4;
+                        writer.get_ref().set_len((HEADER_LEN + size) as u64)?;
+                    }
+                    seq_length = Some(input_ids.ncols());
+                }
+                Some(seq_length) if seq_length != input_ids.ncols() => {
+                    return Err(PreprocessingError::IncompatibleNode(format!(
+                        "Got a batch of sequence length {}, earlier batches had {}",
+                        input_ids.ncols(),
+                        seq_length
+                    )));
+                }
+                Some(_) => (),
+            }
+            for id in input_ids.iter() {
+                writer.write_all(&id.to_le_bytes())?;
+            }
+            rows += input_ids.nrows();
+        }
+        writer.flush()?;
+    }
+    let seq_length = seq_length.unwrap_or(0);
+    file.set_len((HEADER_LEN + rows * seq_length * 4) as u64)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&npy_header(rows, seq_length))?;
+    Ok((rows, seq_length))
+}
@@ -0,0 +1,165 @@
+// Passes the hooks that only look into the pipeline (statistics, the tokenizer, finalizing and
+// keeping texts) on to the wrapped node `self.$node`, for nodes that don't change the meaning of
+// any of them. The hooks that depend on the number or order of the outputs (`get`, `len`,
+// `lengths`, `set_epoch`, `size_hint`, `estimated_len` and `try_clone`) are left to the node.
+macro_rules! forward_hooks {
+    ($node:ident) => {
+        forward_hooks!($node, without finalize);
+        fn finalize(&mut self) -> bool {
+            self.$node.finalize()
+        }
+    };
+    // For nodes that have to finalize themselves, e.g. to drain a buffer.
+    ($node:ident, without finalize) => {
+        fn stats(&self) -> Option<$crate::nodes::BatchStats> {
+            self.$node.stats()
+        }
+        fn truncation_stats(&self) -> Option<$crate::nodes::TruncationStats> {
+            self.$node.truncation_stats()
+        }
+        fn answer_retention(&self) -> Option<$crate::nodes::AnswerRetention> {
+            self.$node.answer_retention()
+        }
+        fn max_length(&self) -> Option<usize> {
+            self.$node.max_length()
+        }
+        fn tokenizer(&self) -> Option<std::sync::Arc<tokenizers::Tokenizer>> {
+            self.$node.tokenizer()
+        }
+        fn total_tokens(&self) -> Option<u64> {
+            self.$node.total_tokens()
+        }
+        fn feature_stats(&self) -> Option<(ndarray::Array1<f64>, ndarray::Array1<f64>)> {
+            self.$node.feature_stats()
+        }
+        fn reconstruct(
+            &self,
+            example_index: usize,
+            window: usize,
+            start_token: usize,
+            end_token: usize,
+        ) -> Option<Option<String>> {
+            self.$node
+                .reconstruct(example_index, window, start_token, end_token)
+        }
+        fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+            self.$node.window_ranges(example_index)
+        }
+        fn label_mapping(&self) -> Option<std::collections::HashMap<String, u32>> {
+            self.$node.label_mapping()
+        }
+        fn keep_texts(&mut self) -> bool {
+            self.$node.keep_texts()
+        }
+    };
+}
+
+mod as_dict;
+mod augment;
+mod bucket_batcher;
+mod buffered_shuffle;
+mod byte_tokenizer;
+mod causal_lm_batcher;
+mod class_balance;
+mod data_loader;
+mod dual_tokenizer;
+mod group_by;
+mod hash_id;
+mod label_encoder;
+mod label_smooth;
+mod map_label;
+mod max_chars;
+mod min_hash_dedup;
+mod min_length;
+mod mmap_sink;
+mod multiple_choice_batcher;
+mod next_sentence;
+mod null_sink;
+mod one_hot;
+mod pack;
+mod pair_batcher;
+mod passage_chunk;
+mod peekable;
+mod pipelined;
+mod pre_tokenized;
+mod prompt_pair;
+mod ragged_batcher;
+mod random_crop;
+mod repeat;
+mod retrieval_batcher;
+mod sentence_order;
+mod sentence_split;
+mod shard;
+mod shuffle;
+mod span_corruption;
+mod standardize;
+mod stats;
+mod strip_html;
+mod take_batches;
+mod tee;
+mod throttle;
+mod timed;
+mod token_budget_batcher;
+mod token_dropout;
+mod unbatch;
+mod validate;
+mod weighted_sampler;
+mod window;
+mod with_metadata;
+mod with_text;
+
+pub use as_dict::{AsDict, NamedBatch};
+pub use augment::{Augment, Augmentation, WordDropout};
+pub use bucket_batcher::BucketBatcher;
+pub use buffered_shuffle::BufferedShuffle;
+pub use byte_tokenizer::ByteTokenizer;
+pub use causal_lm_batcher::CausalLMBatcher;
+pub use class_balance::ClassBalance;
+pub use data_loader::{DataLoader, DrainOrder};
+pub use dual_tokenizer::{DualBatch, DualTokenizer};
+pub use group_by::{GroupBy, GroupByExample};
+pub use hash_id::HashId;
+pub use label_encoder::{decode_labels, LabelEncoder};
+pub use label_smooth::{LabelSmooth, SmoothedBatch};
+pub use map_label::{
+    Answerable, LabelMapping, MapLabel, SpanToAnswerable, SpanWithAnswerable, WithAnswerable,
+};
+pub use max_chars::MaxChars;
+pub use min_hash_dedup::MinHashDedup;
+pub use min_length::MinLength;
+pub use mmap_sink::write_mmap;
+pub use multiple_choice_batcher::{MultipleChoiceBatch, MultipleChoiceBatcher};
+pub use next_sentence::NextSentencePrediction;
+pub use null_sink::{drain, DrainStats, NullSink};
+pub use one_hot::{OneHot, OneHotBatch, OneHotInputs};
+pub use pack::Pack;
+pub use pair_batcher::{PairBatch, PairBatcher};
+pub use passage_chunk::PassageChunk;
+pub use peekable::Peekable;
+pub use pipelined::Pipelined;
+pub use pre_tokenized::PreTokenized;
+pub use prompt_pair::PromptPair;
+pub use ragged_batcher::{RaggedBatch, RaggedBatcher};
+pub use random_crop::RandomCrop;
+pub use repeat::Repeat;
+pub use retrieval_batcher::{RetrievalBatch, RetrievalBatcher};
+pub use sentence_order::SentenceOrderPrediction;
+pub use sentence_split::SentenceSplit;
+pub use shard::Shard;
+pub use shuffle::Shuffle;
+pub use span_corruption::SpanCorruption;
+pub use standardize::{Standardize, StandardizedBatch};
+pub use stats::{length_histogram, AnswerRetention, BatchStats, Stats, TruncationStats};
+pub use strip_html::{HtmlSample, OffsetFreeLabel, StripHtml};
+pub use take_batches::TakeBatches;
+pub use tee::Tee;
+pub use throttle::Throttle;
+pub use timed::{Timed, Timer};
+pub use token_budget_batcher::TokenBudgetBatcher;
+pub use token_dropout::TokenDropout;
+pub use unbatch::{Unbatch, UnbatchLabel};
+pub use validate::Validate;
+pub use weighted_sampler::WeightedSampler;
+pub use window::Window;
+pub use with_metadata::WithMetadata;
+pub use with_text::WithText;
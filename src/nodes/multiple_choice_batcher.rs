@@ -0,0 +1,164 @@
+use ndarray::{Array1, Array2, Array3};
+use tokenizers::tokenizer;
+
+use crate::retry::{self, Retry};
+use crate::{
+    error, resolve_pad_token, stack_samples, MultipleChoice, NoLabel, NoTokenizedLabel, Node,
+    PreprocessingError, TokenizedText, IGNORE_INDEX,
+};
+
+// The `(context, option)` pairs of a batch of examples as `(batch, num_choices, seq_length)`
+// arrays, and the index of the right option of every example.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct MultipleChoiceBatch {
+    pub(crate) input_ids: Array3<u32>,
+    pub(crate) attention_mask: Array3<u32>,
+    pub(crate) token_type_ids: Array3<u32>,
+    pub(crate) labels: Array1<i64>,
+}
+
+// Batches multiple-choice examples for models that score every option, like the
+// `...ForMultipleChoice` models of transformers. The context is paired with each option, the
+// pairs are tokenized in one `encode_batch` call per batch and padded or truncated to
+// `seq_length`. Every example needs exactly `num_choices` options.
+pub struct MultipleChoiceBatcher<T: Node<Output = MultipleChoice>> {
+    loader: T,
+    tokenizer: tokenizer::Tokenizer,
+    batch_size: usize,
+    num_choices: usize,
+    seq_length: usize,
+    pad_token: u32,
+}
+
+impl<T: Node<Output = MultipleChoice>> MultipleChoiceBatcher<T> {
+    pub fn new<S: AsRef<str>>(
+        loader: T,
+        tokenizer: S,
+        batch_size: usize,
+        num_choices: usize,
+        seq_length: usize,
+        pad_token: Option<u32>,
+        retry: &Retry,
+    ) -> Result<MultipleChoiceBatcher<T>, PreprocessingError> {
+        let tokenizer = retry::from_pretrained(tokenizer.as_ref(), None, retry)?;
+        MultipleChoiceBatcher::from_tokenizer(
+            loader,
+            tokenizer,
+            batch_size,
+            num_choices,
+            seq_length,
+            pad_token,
+        )
+    }
+    pub fn from_tokenizer(
+        loader: T,
+        tokenizer: tokenizer::Tokenizer,
+        batch_size: usize,
+        num_choices: usize,
+        seq_length: usize,
+        pad_token: Option<u32>,
+    ) -> Result<MultipleChoiceBatcher<T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        if num_choices == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The number of choices has to be positive".to_string(),
+            ));
+        }
+        let pad_token = resolve_pad_token(&tokenizer, pad_token)?;
+        Ok(MultipleChoiceBatcher {
+            loader,
+            tokenizer,
+            batch_size,
+            num_choices,
+            seq_length,
+            pad_token,
+        })
+    }
+    fn create_batch(&self, examples: Vec<MultipleChoice>) -> Option<MultipleChoiceBatch> {
+        if examples.is_empty() {
+            return None;
+        }
+        let mut pairs: Vec<tokenizer::EncodeInput> = Vec::new();
+        for example in &examples {
+            if example.options.len() != self.num_choices {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "The example {:?} has {} options, expected {}",
+                    example.context,
+                    example.options.len(),
+                    self.num_choices
+                )));
+            }
+            for option in &example.options {
+                pairs.push((example.context.as_str(), option.as_str()).into());
+            }
+        }
+        let samples: Vec<TokenizedText<NoTokenizedLabel>> = self
+            .tokenizer
+            .encode_batch(pairs, false)
+            .expect("Failed to tokenize")
+            .into_iter()
+            .map(|encoding| {
+                TokenizedText::from_tokenizer_encoding(encoding, NoLabel(), self.pad_token)
+            })
+            .collect();
+        let encoding = stack_samples(samples, self.seq_length, None, IGNORE_INDEX, None).encoding;
+        let shape = (examples.len(), self.num_choices, self.seq_length);
+        let choices = |array: Array2<u32>| {
+            array
+                .into_shape(shape)
+                .expect("Every example has num_choices rows of seq_length tokens")
+        };
+        Some(MultipleChoiceBatch {
+            input_ids: choices(encoding.input_ids),
+            attention_mask: choices(encoding.attention_mask),
+            token_type_ids: choices(encoding.token_type_ids),
+            labels: examples
+                .iter()
+                .map(|example| example.label as i64)
+                .collect(),
+        })
+    }
+}
+
+impl<T: Node<Output = MultipleChoice>> Node for MultipleChoiceBatcher<T> {
+    type Output = MultipleChoiceBatch;
+    fn get(&self, index: usize) -> Option<MultipleChoiceBatch> {
+        let index = index * self.batch_size;
+        let examples = (index..index + self.batch_size)
+            .map_while(|i| self.loader.get(i))
+            .collect();
+        self.create_batch(examples)
+    }
+    fn len(&self) -> Option<usize> {
+        self.loader.len().map(|len| len.div_ceil(self.batch_size))
+    }
+    fn next(&mut self) -> Option<MultipleChoiceBatch> {
+        let examples = (0..self.batch_size)
+            .map_while(|_| self.loader.next())
+            .collect();
+        self.create_batch(examples)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.loader.size_hint();
+        (
+            lower.div_ceil(self.batch_size),
+            upper.map(|upper| upper.div_ceil(self.batch_size)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.loader
+            .estimated_len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.loader.finalize()
+    }
+}
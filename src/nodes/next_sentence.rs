@@ -0,0 +1,111 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed;
+use crate::{ClassLabel, NoLabel, Node, PreprocessingError, Text, TextPair};
+
+// Pairs every sentence with the sentence following it (class 1, "is next") or, with probability
+// `negative_ratio`, with a random earlier sentence (class 0), as in BERT's pretraining data. The
+// upstream node yields one sentence per sample, e.g. a `SentenceSplit`. Negatives are drawn from
+// a reservoir sample of up to `buffer_size` earlier sentences, so the first pair is always a
+// positive. Document boundaries aren't known, the last sentence of a text and the first of the
+// next one form a positive as well.
+pub struct NextSentencePrediction<T: Node<Output = Text<NoLabel>>> {
+    node: T,
+    negative_ratio: f64,
+    buffer_size: usize,
+    rng: ChaCha8Rng,
+    current: Option<String>,
+    buffer: Vec<String>,
+    // Number of sentences offered to the reservoir so far.
+    seen: usize,
+}
+
+impl<T: Node<Output = Text<NoLabel>>> NextSentencePrediction<T> {
+    pub fn new(
+        node: T,
+        negative_ratio: f64,
+        buffer_size: usize,
+        seed: Option<u64>,
+    ) -> Result<NextSentencePrediction<T>, PreprocessingError> {
+        if !(0.0..=1.0).contains(&negative_ratio) {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Negative ratio {} is not in [0, 1]",
+                negative_ratio
+            )));
+        }
+        if buffer_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The buffer size has to be positive".to_string(),
+            ));
+        }
+        Ok(NextSentencePrediction {
+            node,
+            negative_ratio,
+            buffer_size,
+            rng: seed::node_rng(seed),
+            current: None,
+            buffer: Vec::new(),
+            seen: 0,
+        })
+    }
+    fn remember(&mut self, sentence: String) {
+        self.seen += 1;
+        if self.buffer.len() < self.buffer_size {
+            self.buffer.push(sentence);
+            return;
+        }
+        let index = self.rng.gen_range(0..self.seen);
+        if index < self.buffer_size {
+            self.buffer[index] = sentence;
+        }
+    }
+}
+
+impl<T: Node<Output = Text<NoLabel>>> Node for NextSentencePrediction<T> {
+    type Output = TextPair<ClassLabel>;
+    // Not implemented since the pairs depend on the random state.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.current.is_none() {
+            self.current = Some(self.node.next()?.text);
+        }
+        let next = self.node.next()?.text;
+        let first = self.current.replace(next.clone()).unwrap();
+        let negative = !self.buffer.is_empty() && self.rng.gen_bool(self.negative_ratio);
+        let (second, is_next) = if negative {
+            (self.buffer.choose(&mut self.rng).unwrap().clone(), 0)
+        } else {
+            (next, 1)
+        };
+        self.remember(first.clone());
+        Some(TextPair {
+            text: (first, second),
+            label: ClassLabel(is_next),
+            metadata: None,
+        })
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    // A pair per sentence but the first.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (
+            lower.saturating_sub(1),
+            upper.map(|upper| upper.saturating_sub(1)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len().map(|len| len.saturating_sub(1))
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::{Duration, Instant};
+
+use crate::{Node, PreprocessingError};
+
+pub struct DrainStats {
+    pub count: usize,
+    pub elapsed: Duration,
+}
+
+impl DrainStats {
+    pub fn items_per_second(&self) -> f64 {
+        self.count as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+// Reads the node until it ends and discards the outputs, e.g. to measure the throughput of the
+// preprocessing without the cost of converting the outputs to Python objects.
+pub fn drain<T: Node + ?Sized>(node: &mut T) -> DrainStats {
+    let start = Instant::now();
+    let mut count = 0;
+    while node.next().is_some() {
+        count += 1;
+    }
+    DrainStats {
+        count,
+        elapsed: start.elapsed(),
+    }
+}
+
+// End of a pipeline that is only run for its throughput, see `drain`.
+pub struct NullSink<T: Node> {
+    node: T,
+}
+
+impl<T: Node> NullSink<T> {
+    pub fn new(node: T) -> Result<NullSink<T>, PreprocessingError> {
+        Ok(NullSink { node })
+    }
+    pub fn drain(&mut self) -> DrainStats {
+        drain(&mut self.node)
+    }
+}
@@ -0,0 +1,120 @@
+use ndarray::prelude::*;
+
+use crate::{warnings, Batch, BatchLabel, Node, PreprocessingError};
+
+// Dense one-hot arrays above this size per token are worth a warning.
+const LARGE_VOCAB_SIZE: usize = 10_000;
+
+// One-hot encoded input ids of a batch.
+#[derive(Clone)]
+pub enum OneHotInputs {
+    // `(batch, seq, vocab)` array with a 1 at the id of every token.
+    Dense(Array3<f32>),
+    // Coordinates `[row, position, id]` of the ones of the dense array, one row per token, and
+    // the dense shape, e.g. for `torch.sparse_coo_tensor`.
+    Sparse {
+        indices: Array2<i64>,
+        shape: (usize, usize, usize),
+    },
+}
+
+// A batch along with its one-hot encoded input ids.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct OneHotBatch<S: BatchLabel> {
+    pub(crate) inputs: OneHotInputs,
+    pub(crate) batch: Batch<S>,
+}
+
+// One-hot encodes the input ids of every batch for models without an embedding layer. Padding is
+// encoded like any other token, the attention mask of the batch tells them apart. Ids outside
+// the vocabulary get no one. A dense batch takes `4 * batch * seq * vocab_size` bytes, so large
+// vocabularies should use the sparse representation.
+pub struct OneHot<S: BatchLabel, T: Node<Output = Batch<S>>> {
+    node: T,
+    vocab_size: usize,
+    sparse: bool,
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> OneHot<S, T> {
+    pub fn new(
+        node: T,
+        vocab_size: usize,
+        sparse: bool,
+    ) -> Result<OneHot<S, T>, PreprocessingError> {
+        if vocab_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The vocabulary size has to be positive".to_string(),
+            ));
+        }
+        if !sparse && vocab_size > LARGE_VOCAB_SIZE {
+            warnings::warn(&format!(
+                "Dense one-hot batches take {} bytes per token with a vocabulary of {}, consider the sparse representation",
+                4 * vocab_size,
+                vocab_size
+            ));
+        }
+        Ok(OneHot {
+            node,
+            vocab_size,
+            sparse,
+        })
+    }
+    fn one_hot(&self, batch: Batch<S>) -> OneHotBatch<S> {
+        let input_ids = &batch.encoding.input_ids;
+        let (rows, seq_length) = input_ids.dim();
+        let tokens = input_ids
+            .indexed_iter()
+            .filter(|(_, &id)| (id as usize) < self.vocab_size);
+        let skipped = input_ids.len() - tokens.clone().count();
+        if skipped > 0 {
+            warnings::warn(&format!(
+                "{} ids of the batch are outside the vocabulary of {}",
+                skipped, self.vocab_size
+            ));
+        }
+        let inputs = if self.sparse {
+            let mut indices = Array2::zeros((input_ids.len() - skipped, 3));
+            for (mut index, ((row, position), &id)) in indices.rows_mut().into_iter().zip(tokens) {
+                index.assign(&array![row as i64, position as i64, id as i64]);
+            }
+            OneHotInputs::Sparse {
+                indices,
+                shape: (rows, seq_length, self.vocab_size),
+            }
+        } else {
+            let mut dense = Array3::zeros((rows, seq_length, self.vocab_size));
+            for ((row, position), &id) in tokens {
+                dense[[row, position, id as usize]] = 1.0;
+            }
+            OneHotInputs::Dense(dense)
+        };
+        OneHotBatch { inputs, batch }
+    }
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Node for OneHot<S, T> {
+    type Output = OneHotBatch<S>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(|batch| self.one_hot(batch))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let batch = self.node.next()?;
+        Some(self.one_hot(batch))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
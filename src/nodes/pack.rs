@@ -0,0 +1,119 @@
+use ndarray::{concatenate, Array1, Axis};
+
+use crate::{Encoding, NoTokenizedLabel, Node, PreprocessingError, TokenizedText};
+
+// Packs consecutive samples into sequences of up to `seq_length` tokens instead of padding every
+// sample to a row of its own. A sample is appended to the current sequence, after the `separator`
+// token if one is given, as long as the result still fits, otherwise the sequence is emitted and
+// a new one is started. Samples longer than `seq_length` make up a sequence of their own and are
+// truncated by the batcher. The `sequence_ids` of a packed encoding number its samples from 1,
+// a separator belongs to the sample before it. Since the number of packed sequences is only known
+// after packing, `get` and `len` aren't supported.
+pub struct Pack<T: Node<Output = TokenizedText<NoTokenizedLabel>>> {
+    node: T,
+    seq_length: usize,
+    separator: Option<u32>,
+    // Sample read from upstream that didn't fit into the previous sequence anymore.
+    pending: Option<Encoding>,
+    // Set by `finalize`, from then on only the pending sample is emitted.
+    upstream_closed: bool,
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> Pack<T> {
+    pub fn new(
+        node: T,
+        seq_length: usize,
+        separator: Option<u32>,
+    ) -> Result<Pack<T>, PreprocessingError> {
+        if seq_length == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The sequence length has to be positive".to_string(),
+            ));
+        }
+        Ok(Pack {
+            node,
+            seq_length,
+            separator,
+            pending: None,
+            upstream_closed: false,
+        })
+    }
+    fn read(&mut self) -> Option<Encoding> {
+        if self.upstream_closed {
+            return None;
+        }
+        Some(self.node.next()?.encoding)
+    }
+    fn append(&self, packed: &mut Encoding, encoding: Encoding) {
+        let mut sequence_ids = packed.sequence_ids.take().unwrap();
+        let sequence = sequence_ids[sequence_ids.len() - 1];
+        if let Some(separator) = self.separator {
+            let mut separator = Encoding::from_ids(vec![separator], packed.pad_token);
+            separator.owned_tokens[0] = 0;
+            extend(packed, &separator);
+            sequence_ids = concatenate![Axis(0), sequence_ids, Array1::from_elem(1, sequence)];
+        }
+        extend(packed, &encoding);
+        let len = encoding.input_ids.len();
+        packed.sequence_ids = Some(concatenate![
+            Axis(0),
+            sequence_ids,
+            Array1::from_elem(len, sequence + 1)
+        ]);
+    }
+}
+
+fn extend(packed: &mut Encoding, encoding: &Encoding) {
+    packed.input_ids = concatenate![Axis(0), packed.input_ids, encoding.input_ids];
+    packed.attention_mask = concatenate![Axis(0), packed.attention_mask, encoding.attention_mask];
+    packed.token_type_ids = concatenate![Axis(0), packed.token_type_ids, encoding.token_type_ids];
+    packed.owned_tokens = concatenate![Axis(0), packed.owned_tokens, encoding.owned_tokens];
+    packed.global_attention =
+        concatenate![Axis(0), packed.global_attention, encoding.global_attention];
+    packed.word_starts = concatenate![Axis(0), packed.word_starts, encoding.word_starts];
+    packed.context = concatenate![Axis(0), packed.context, encoding.context];
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> Node for Pack<T> {
+    type Output = TokenizedText<NoTokenizedLabel>;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let mut packed = match self.pending.take() {
+            Some(encoding) => encoding,
+            None => self.read()?,
+        };
+        packed.example_index = None;
+        packed.hash_id = None;
+        packed.metadata = None;
+        packed.sequence_ids = Some(Array1::ones(packed.input_ids.len()));
+        let separator_len = self.separator.is_some() as usize;
+        while let Some(encoding) = self.read() {
+            let len = packed.input_ids.len() + separator_len + encoding.input_ids.len();
+            if len > self.seq_length {
+                self.pending = Some(encoding);
+                break;
+            }
+            self.append(&mut packed, encoding);
+        }
+        Some(TokenizedText {
+            encoding: packed,
+            label: NoTokenizedLabel,
+        })
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.upstream_closed = !self.node.finalize();
+        true
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (lower.min(1), upper)
+    }
+}
@@ -0,0 +1,130 @@
+use ndarray::Array2;
+use tokenizers::tokenizer;
+
+use crate::retry::{self, Retry};
+use crate::{
+    resolve_pad_token, stack_samples, BatchEncoding, NoLabel, NoTokenizedLabel, Node,
+    PreprocessingError, TextPair, TokenizedText, IGNORE_INDEX,
+};
+
+// Anchors and positives of a batch, encoded separately. `labels[i][j]` is 1 if positive `j`
+// belongs to anchor `i`, i.e. the identity matrix, so every other positive of the batch serves as
+// an in-batch negative.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct PairBatch {
+    pub(crate) anchors: BatchEncoding,
+    pub(crate) positives: BatchEncoding,
+    pub(crate) labels: Array2<i64>,
+}
+
+// Batches `(anchor, positive)` pairs for contrastive training. The loader yields `TextPair`s with
+// the anchor as the first and the positive as the second text, e.g. a `PairTxtLoader` over lines
+// like `anchor<separator>positive`. Both texts are tokenized as single sequences (in one
+// `encode_batch` call per batch) and padded or truncated to `seq_length`.
+pub struct PairBatcher<T: Node<Output = TextPair<NoLabel>>> {
+    loader: T,
+    tokenizer: tokenizer::Tokenizer,
+    batch_size: usize,
+    seq_length: usize,
+    pad_token: u32,
+}
+
+impl<T: Node<Output = TextPair<NoLabel>>> PairBatcher<T> {
+    pub fn new<S: AsRef<str>>(
+        loader: T,
+        tokenizer: S,
+        batch_size: usize,
+        seq_length: usize,
+        pad_token: Option<u32>,
+        retry: &Retry,
+    ) -> Result<PairBatcher<T>, PreprocessingError> {
+        let tokenizer = retry::from_pretrained(tokenizer.as_ref(), None, retry)?;
+        PairBatcher::from_tokenizer(loader, tokenizer, batch_size, seq_length, pad_token)
+    }
+    pub fn from_tokenizer(
+        loader: T,
+        tokenizer: tokenizer::Tokenizer,
+        batch_size: usize,
+        seq_length: usize,
+        pad_token: Option<u32>,
+    ) -> Result<PairBatcher<T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        let pad_token = resolve_pad_token(&tokenizer, pad_token)?;
+        Ok(PairBatcher {
+            loader,
+            tokenizer,
+            batch_size,
+            seq_length,
+            pad_token,
+        })
+    }
+    fn create_batch(&self, pairs: Vec<TextPair<NoLabel>>) -> Option<PairBatch> {
+        if pairs.is_empty() {
+            return None;
+        }
+        let n_pairs = pairs.len();
+        let texts = pairs
+            .iter()
+            .map(|pair| pair.text.0.as_str())
+            .chain(pairs.iter().map(|pair| pair.text.1.as_str()))
+            .collect();
+        let mut samples: Vec<TokenizedText<NoTokenizedLabel>> = self
+            .tokenizer
+            .encode_batch(texts, false)
+            .expect("Failed to tokenize")
+            .into_iter()
+            .map(|encoding| {
+                TokenizedText::from_tokenizer_encoding(encoding, NoLabel(), self.pad_token)
+            })
+            .collect();
+        let positives = samples.split_off(n_pairs);
+        Some(PairBatch {
+            anchors: stack_samples(samples, self.seq_length, None, IGNORE_INDEX, None).encoding,
+            positives: stack_samples(positives, self.seq_length, None, IGNORE_INDEX, None).encoding,
+            labels: Array2::eye(n_pairs),
+        })
+    }
+}
+
+impl<T: Node<Output = TextPair<NoLabel>>> Node for PairBatcher<T> {
+    type Output = PairBatch;
+    fn get(&self, index: usize) -> Option<PairBatch> {
+        let index = index * self.batch_size;
+        let pairs = (index..index + self.batch_size)
+            .map_while(|i| self.loader.get(i))
+            .collect();
+        self.create_batch(pairs)
+    }
+    fn len(&self) -> Option<usize> {
+        self.loader.len().map(|len| len.div_ceil(self.batch_size))
+    }
+    fn next(&mut self) -> Option<PairBatch> {
+        let pairs = (0..self.batch_size)
+            .map_while(|_| self.loader.next())
+            .collect();
+        self.create_batch(pairs)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.loader.size_hint();
+        (
+            lower.div_ceil(self.batch_size),
+            upper.map(|upper| upper.div_ceil(self.batch_size)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.loader
+            .estimated_len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.loader.finalize()
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use tokenizers::tokenizer;
+
+use crate::{NoLabel, Node, PreprocessingError, Text};
+
+// Splits every document into overlapping passages of `size` characters, starting a new one every
+// `stride` characters, e.g. to build the corpus of a dense retrieval index. The last passage of a
+// document ends with it and may be shorter, with a stride larger than the size the text in
+// between is skipped. With `with_tokenizer` the passages are counted in tokens of the tokenizer
+// instead, and are the text from the first to the last of their tokens.
+// Every passage gets the metadata of its document plus a `passage_id`, which is `id_format` with
+// `{document}` replaced by the index of the document (in the order they are read) and `{chunk}`
+// by the index of the passage within it. Since the number of passages of a document is only
+// known after splitting it, `get` and `len` aren't supported.
+pub struct PassageChunk<T: Node<Output = Text<NoLabel>>> {
+    node: T,
+    size: usize,
+    stride: usize,
+    id_format: String,
+    tokenizer: Option<tokenizer::Tokenizer>,
+    passages: VecDeque<Text<NoLabel>>,
+    // Documents read so far.
+    documents: usize,
+}
+
+impl<T: Node<Output = Text<NoLabel>>> PassageChunk<T> {
+    pub fn new(
+        node: T,
+        size: usize,
+        stride: usize,
+        id_format: &str,
+    ) -> Result<PassageChunk<T>, PreprocessingError> {
+        if size == 0 || stride == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The passage size and stride have to be positive".to_string(),
+            ));
+        }
+        // The passages of a document would all get the same id.
+        if !id_format.contains("{chunk}") {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "The passage id format {:?} doesn't contain {{chunk}}",
+                id_format
+            )));
+        }
+        Ok(PassageChunk {
+            node,
+            size,
+            stride,
+            id_format: id_format.to_string(),
+            tokenizer: None,
+            passages: VecDeque::new(),
+            documents: 0,
+        })
+    }
+    pub fn with_tokenizer(mut self, tokenizer: tokenizer::Tokenizer) -> PassageChunk<T> {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+    // Byte ranges of the units (characters or tokens) of the text.
+    fn units(&self, text: &str) -> Vec<(usize, usize)> {
+        match &self.tokenizer {
+            Some(tokenizer) => tokenizer
+                .encode(text, false)
+                .expect("Failed to tokenize")
+                .get_offsets()
+                .to_vec(),
+            None => text
+                .char_indices()
+                .map(|(index, c)| (index, index + c.len_utf8()))
+                .collect(),
+        }
+    }
+    fn split(&mut self, document: Text<NoLabel>) {
+        let units = self.units(&document.text);
+        let mut start = 0;
+        let mut chunk = 0;
+        while start < units.len() {
+            let end = (start + self.size).min(units.len());
+            let passage = &document.text[units[start].0..units[end - 1].1];
+            let id = self
+                .id_format
+                .replace("{document}", &self.documents.to_string())
+                .replace("{chunk}", &chunk.to_string());
+            let mut metadata = document.metadata.clone().unwrap_or_default();
+            metadata.insert("passage_id".to_string(), id);
+            self.passages.push_back(Text {
+                text: passage.to_string(),
+                label: NoLabel(),
+                metadata: Some(metadata),
+            });
+            if end == units.len() {
+                break;
+            }
+            start += self.stride;
+            chunk += 1;
+        }
+        self.documents += 1;
+    }
+}
+
+impl<T: Node<Output = Text<NoLabel>>> Node for PassageChunk<T> {
+    type Output = Text<NoLabel>;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        while self.passages.is_empty() {
+            let document = self.node.next()?;
+            self.split(document);
+        }
+        self.passages.pop_front()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    // A document can have any number of passages, an empty one none.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.passages.len(), None)
+    }
+}
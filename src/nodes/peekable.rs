@@ -0,0 +1,52 @@
+use ndarray::Array1;
+
+use crate::Node;
+
+// Buffers one sample so the next one can be looked at without consuming it.
+pub struct Peekable<T: Node> {
+    node: T,
+    peeked: Option<Option<T::Output>>,
+}
+
+impl<T: Node> Peekable<T> {
+    pub fn new(node: T) -> Peekable<T> {
+        Peekable { node, peeked: None }
+    }
+    pub fn peek(&mut self) -> Option<&T::Output> {
+        let node = &mut self.node;
+        self.peeked.get_or_insert_with(|| node.next()).as_ref()
+    }
+}
+
+impl<T: Node> Node for Peekable<T> {
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.node.next(),
+        }
+    }
+    forward_hooks!(node);
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+    // Clones start from the beginning, so the peeked sample doesn't matter.
+    fn try_clone(&self) -> Option<Box<dyn Node<Output = Self::Output>>> {
+        self.node.try_clone()
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use ndarray::Array1;
+
+use crate::nodes::{AnswerRetention, BatchStats, TruncationStats};
+use crate::{Node, PreprocessingError};
+
+// Runs `next` of the upstream node on a thread of its own, up to `buffer_size` outputs ahead,
+// so the stages before and after it run concurrently. Unlike a `DataLoader` it works with
+// streaming nodes, but it only adds a single thread. Putting one between a loader and its
+// `Tokenizer` and one after the `Tokenizer` overlaps reading (IO) with tokenizing (CPU), see
+// `PipelinedTokenizer` in Python. The thread starts on the first call to `next` and the order of
+// the outputs is kept.
+pub struct Pipelined<T: Node + 'static> {
+    // Locked by the thread for every `next`, so the other methods can still reach the node.
+    node: Arc<Mutex<T>>,
+    buffer_size: usize,
+    // Only accessed through `&mut self`, the mutex just makes the node `Sync`.
+    receiver: Mutex<Option<Receiver<thread::Result<T::Output>>>>,
+    exhausted: bool,
+}
+
+impl<T: Node + 'static> Pipelined<T>
+where
+    T::Output: Send,
+{
+    pub fn new(node: T, buffer_size: usize) -> Result<Pipelined<T>, PreprocessingError> {
+        if buffer_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The buffer size has to be positive".to_string(),
+            ));
+        }
+        Ok(Pipelined {
+            node: Arc::new(Mutex::new(node)),
+            buffer_size,
+            receiver: Mutex::new(None),
+            exhausted: false,
+        })
+    }
+    // A panic of the upstream node is sent on as well, so `next` raises it.
+    fn start(node: Arc<Mutex<T>>, buffer_size: usize) -> Receiver<thread::Result<T::Output>> {
+        let (sender, receiver) = mpsc::sync_channel(buffer_size);
+        thread::spawn(move || loop {
+            let output = panic::catch_unwind(AssertUnwindSafe(|| lock(&node).next()));
+            let output = match output {
+                Ok(Some(output)) => Ok(output),
+                Ok(None) => break,
+                Err(payload) => Err(payload),
+            };
+            let panicked = output.is_err();
+            // Sending fails once the node is dropped.
+            if sender.send(output).is_err() || panicked {
+                break;
+            }
+        });
+        receiver
+    }
+    fn node(&self) -> MutexGuard<'_, T> {
+        lock(&self.node)
+    }
+}
+
+// A panic while the thread held the lock leaves the node in whatever state it was, which the
+// panic is reported for anyway.
+fn lock<T>(node: &Mutex<T>) -> MutexGuard<'_, T> {
+    node.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T: Node + 'static> Node for Pipelined<T>
+where
+    T::Output: Send,
+{
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node().get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node().len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.exhausted {
+            return None;
+        }
+        let receiver = self
+            .receiver
+            .get_mut()
+            .unwrap()
+            .get_or_insert_with(|| Self::start(Arc::clone(&self.node), self.buffer_size));
+        match receiver.recv() {
+            Ok(Ok(output)) => Some(output),
+            Ok(Err(payload)) => {
+                self.exhausted = true;
+                panic::resume_unwind(payload)
+            }
+            // The upstream node is exhausted.
+            Err(_) => {
+                self.exhausted = true;
+                *self.receiver.get_mut().unwrap() = None;
+                None
+            }
+        }
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        self.node().stats()
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.node().truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node().answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.node().max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node().tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node().total_tokens()
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node().lengths()
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        self.node().feature_stats()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.node()
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.node().window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node().label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node().set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node().finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.node().keep_texts()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node().size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node().estimated_len()
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{Encoding, Ids, NoTokenizedLabel, Node, PreprocessingError, TokenizedText};
+
+// Passes token ids that were computed beforehand (e.g. by an `IdsTxtLoader`) on to the batchers
+// without tokenizing. Ids past `max_length` are cut off, special tokens have to be part of the
+// ids already.
+pub struct PreTokenized<T: Node<Output = Ids>> {
+    node: T,
+    pad_token: u32,
+    max_length: Option<usize>,
+}
+
+impl<T: Node<Output = Ids>> PreTokenized<T> {
+    pub fn new(
+        node: T,
+        pad_token: u32,
+        max_length: Option<usize>,
+    ) -> Result<PreTokenized<T>, PreprocessingError> {
+        Ok(PreTokenized {
+            node,
+            pad_token,
+            max_length,
+        })
+    }
+    fn wrap(&self, ids: Ids) -> TokenizedText<NoTokenizedLabel> {
+        let mut ids = ids.0;
+        if let Some(max_length) = self.max_length {
+            ids.truncate(max_length);
+        }
+        TokenizedText {
+            encoding: Encoding::from_ids(ids, self.pad_token),
+            label: NoTokenizedLabel,
+        }
+    }
+}
+
+impl<T: Node<Output = Ids>> Node for PreTokenized<T> {
+    type Output = TokenizedText<NoTokenizedLabel>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(|ids| self.wrap(ids))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let ids = self.node.next()?;
+        Some(self.wrap(ids))
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{Label, Node, PreprocessingError, Text, TextPair};
+
+// Pairs every text with a fixed prompt as the first sequence, e.g. an instruction for prompt based
+// classification, so single texts can be fed to pair models. The label is kept, spans still refer
+// to the text since they point into the last sequence by default.
+pub struct PromptPair<T: Node> {
+    node: T,
+    prompt: String,
+}
+
+impl<L: Label, T: Node<Output = Text<L>>> PromptPair<T> {
+    pub fn new<S: Into<String>>(node: T, prompt: S) -> Result<PromptPair<T>, PreprocessingError> {
+        Ok(PromptPair {
+            node,
+            prompt: prompt.into(),
+        })
+    }
+    fn pair(&self, sample: Text<L>) -> TextPair<L> {
+        let Text {
+            text,
+            label,
+            metadata,
+        } = sample;
+        TextPair {
+            text: (self.prompt.clone(), text),
+            label,
+            metadata,
+        }
+    }
+}
+
+impl<L: Label, T: Node<Output = Text<L>>> Node for PromptPair<T> {
+    type Output = TextPair<L>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(|sample| self.pair(sample))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.node.next()?;
+        Some(self.pair(sample))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use crate::nodes::AnswerRetention;
+use crate::{Encoding, Node, PreprocessingError, TokenizedLabel, TokenizedText};
+
+// Unpadded batch, so padding can be left to the training framework.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct RaggedBatch<S: TokenizedLabel> {
+    pub(crate) encodings: Vec<Encoding>,
+    pub(crate) labels: Vec<S>,
+}
+
+pub struct RaggedBatcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    tokenizer: T,
+    batch_size: usize,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> RaggedBatcher<S, T> {
+    pub fn new(tokenizer: T, batch_size: usize) -> Result<RaggedBatcher<S, T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        Ok(RaggedBatcher {
+            tokenizer,
+            batch_size,
+        })
+    }
+    fn create_batch(samples: Vec<TokenizedText<S>>) -> Option<RaggedBatch<S>> {
+        if samples.is_empty() {
+            return None;
+        }
+        let (encodings, labels) = samples
+            .into_iter()
+            .map(|TokenizedText { encoding, label }| (encoding, label))
+            .unzip();
+        Some(RaggedBatch { encodings, labels })
+    }
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for RaggedBatcher<S, T> {
+    type Output = RaggedBatch<S>;
+    fn get(&self, index: usize) -> Option<RaggedBatch<S>> {
+        let index = index * self.batch_size;
+        let samples = (index..index + self.batch_size)
+            .map_while(|i| self.tokenizer.get(i))
+            .collect();
+        Self::create_batch(samples)
+    }
+    fn len(&self) -> Option<usize> {
+        self.tokenizer
+            .len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+    fn next(&mut self) -> Option<RaggedBatch<S>> {
+        let samples = (0..self.batch_size)
+            .map_while(|_| self.tokenizer.next())
+            .collect();
+        Self::create_batch(samples)
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.tokenizer
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.tokenizer.window_ranges(example_index)
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.tokenizer.answer_retention()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.tokenizer.total_tokens()
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.tokenizer.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.tokenizer.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.tokenizer.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.tokenizer.size_hint();
+        (
+            lower.div_ceil(self.batch_size),
+            upper.map(|upper| upper.div_ceil(self.batch_size)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.tokenizer
+            .estimated_len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use ndarray::{s, Array1};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed::{self, derive_seed, epoch_seed};
+use crate::{Encoding, NoTokenizedLabel, Node, PreprocessingError, TokenizedText};
+
+// Cuts `crop_len` consecutive tokens at a random offset out of every sample longer than that,
+// shorter samples are passed on as they are. Special tokens are cropped like any other token, so
+// a crop usually has neither `[CLS]` nor `[SEP]`. Like `Augment`, sample `i` is cropped with the
+// same seed in an epoch, so `get` and `next` agree and `set_epoch` draws new crops.
+pub struct RandomCrop<T: Node<Output = TokenizedText<NoTokenizedLabel>>> {
+    node: T,
+    crop_len: usize,
+    seed: u64,
+    epoch: AtomicU64,
+    sample_index: usize,
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> RandomCrop<T> {
+    pub fn new(
+        node: T,
+        crop_len: usize,
+        seed: Option<u64>,
+    ) -> Result<RandomCrop<T>, PreprocessingError> {
+        if crop_len == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The crop length has to be positive".to_string(),
+            ));
+        }
+        Ok(RandomCrop {
+            node,
+            crop_len,
+            seed: seed::node_rng(seed).gen(),
+            epoch: AtomicU64::new(0),
+            sample_index: 0,
+        })
+    }
+    fn cropped(
+        &self,
+        mut sample: TokenizedText<NoTokenizedLabel>,
+        index: usize,
+    ) -> TokenizedText<NoTokenizedLabel> {
+        let len = sample.encoding.input_ids.len();
+        if len > self.crop_len {
+            let seed = epoch_seed(self.seed, self.epoch.load(Ordering::Relaxed));
+            let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(seed, index as u64));
+            let start = rng.gen_range(0..=len - self.crop_len);
+            crop(&mut sample.encoding, start, self.crop_len);
+        }
+        sample
+    }
+}
+
+fn crop(encoding: &mut Encoding, start: usize, len: usize) {
+    let slice = |array: &Array1<u32>| array.slice(s![start..start + len]).to_owned();
+    encoding.input_ids = slice(&encoding.input_ids);
+    encoding.attention_mask = slice(&encoding.attention_mask);
+    encoding.token_type_ids = slice(&encoding.token_type_ids);
+    encoding.owned_tokens = slice(&encoding.owned_tokens);
+    encoding.global_attention = slice(&encoding.global_attention);
+    encoding.word_starts = encoding
+        .word_starts
+        .slice(s![start..start + len])
+        .to_owned();
+    encoding.context = encoding.context.slice(s![start..start + len]).to_owned();
+    encoding.sequence_ids = encoding.sequence_ids.as_ref().map(slice);
+    encoding.prefix_special_tokens = encoding.prefix_special_tokens.saturating_sub(start);
+    encoding.context_start = encoding.context_start.saturating_sub(start).min(len);
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> Node for RandomCrop<T> {
+    type Output = TokenizedText<NoTokenizedLabel>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let sample = self.node.get(index)?;
+        Some(self.cropped(sample, index))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.node.next()?;
+        self.sample_index += 1;
+        Some(self.cropped(sample, self.sample_index - 1))
+    }
+    fn max_length(&self) -> Option<usize> {
+        let max_length = self.node.max_length().unwrap_or(self.crop_len);
+        Some(max_length.min(self.crop_len))
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.node.keep_texts()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
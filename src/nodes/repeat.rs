@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Node, PreprocessingError};
+
+// Replays an indexable node for `epochs` passes (forever without `epochs`). Before every pass
+// the node is moved to the next epoch with `set_epoch`, so stochastic nodes like a `Shuffle` get
+// a new permutation per pass that is still reproducible. `set_epoch` on the `Repeat` sets the
+// epoch of its first pass. `get` isn't supported since the upstream node can only be in one epoch
+// at a time.
+pub struct Repeat<T: Node> {
+    node: T,
+    epochs: Option<usize>,
+    first_epoch: AtomicU64,
+    // Passes started so far and the position in the current one.
+    pass: usize,
+    position: usize,
+}
+
+impl<T: Node> Repeat<T> {
+    pub fn new(node: T, epochs: Option<usize>) -> Result<Repeat<T>, PreprocessingError> {
+        if node.len().is_none() {
+            return Err(PreprocessingError::IncompatibleNode(
+                "Repeat needs a node with a known length to replay it".to_string(),
+            ));
+        }
+        Ok(Repeat {
+            node,
+            epochs,
+            first_epoch: AtomicU64::new(0),
+            pass: 0,
+            position: 0,
+        })
+    }
+}
+
+impl<T: Node> Node for Repeat<T> {
+    type Output = T::Output;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    // `get` isn't supported, the number of outputs is in `size_hint`.
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let len = self.node.len()?;
+        if len == 0 {
+            return None;
+        }
+        if self.position == len {
+            self.position = 0;
+            self.pass += 1;
+        }
+        if self.position == 0 {
+            if self.epochs.is_some_and(|epochs| self.pass >= epochs) {
+                return None;
+            }
+            let epoch = self.first_epoch.load(Ordering::Relaxed) + self.pass as u64;
+            self.node.set_epoch(epoch);
+        }
+        let output = self.node.get(self.position)?;
+        self.position += 1;
+        Some(output)
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.first_epoch.store(epoch, Ordering::Relaxed);
+    }
+    // Endless and too many outputs to count have no upper bound.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.node.len().unwrap_or(0);
+        match self.epochs {
+            _ if len == 0 => (0, Some(0)),
+            Some(epochs) => match len.checked_mul(epochs) {
+                Some(total) => (total, Some(total)),
+                None => (usize::MAX, None),
+            },
+            None => (usize::MAX, None),
+        }
+    }
+}
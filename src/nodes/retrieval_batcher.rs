@@ -0,0 +1,146 @@
+use ndarray::Array2;
+use tokenizers::tokenizer;
+
+use crate::retry::{self, Retry};
+use crate::{
+    error, resolve_pad_token, stack_samples, BatchEncoding, NoLabel, NoTokenizedLabel, Node,
+    PreprocessingError, Retrieval, TokenizedText, IGNORE_INDEX,
+};
+
+// The `(query, passage)` pairs of a batch of queries, one row per pair with the pairs of a query
+// next to each other. `encoding.overflow_to_sample_mapping` holds the query of every row.
+// `labels[i][j]` is the relevance of passage `j` of query `i`, the ignore index (-100) past the
+// passages of queries with fewer than the most passages in the batch.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct RetrievalBatch {
+    pub(crate) encoding: BatchEncoding,
+    pub(crate) labels: Array2<i64>,
+}
+
+// Batches queries with their candidate passages for cross-encoders (rerankers). Every query is
+// paired with each of its passages, the pairs are tokenized in one `encode_batch` call per batch
+// and padded or truncated to `seq_length`. Every query needs at least one passage.
+pub struct RetrievalBatcher<T: Node<Output = Retrieval>> {
+    loader: T,
+    tokenizer: tokenizer::Tokenizer,
+    batch_size: usize,
+    seq_length: usize,
+    pad_token: u32,
+}
+
+impl<T: Node<Output = Retrieval>> RetrievalBatcher<T> {
+    pub fn new<S: AsRef<str>>(
+        loader: T,
+        tokenizer: S,
+        batch_size: usize,
+        seq_length: usize,
+        pad_token: Option<u32>,
+        retry: &Retry,
+    ) -> Result<RetrievalBatcher<T>, PreprocessingError> {
+        let tokenizer = retry::from_pretrained(tokenizer.as_ref(), None, retry)?;
+        RetrievalBatcher::from_tokenizer(loader, tokenizer, batch_size, seq_length, pad_token)
+    }
+    pub fn from_tokenizer(
+        loader: T,
+        tokenizer: tokenizer::Tokenizer,
+        batch_size: usize,
+        seq_length: usize,
+        pad_token: Option<u32>,
+    ) -> Result<RetrievalBatcher<T>, PreprocessingError> {
+        if batch_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The batch size has to be positive".to_string(),
+            ));
+        }
+        let pad_token = resolve_pad_token(&tokenizer, pad_token)?;
+        Ok(RetrievalBatcher {
+            loader,
+            tokenizer,
+            batch_size,
+            seq_length,
+            pad_token,
+        })
+    }
+    fn create_batch(&self, queries: Vec<Retrieval>) -> Option<RetrievalBatch> {
+        if queries.is_empty() {
+            return None;
+        }
+        let max_passages = queries
+            .iter()
+            .map(|query| query.passages.len())
+            .max()
+            .unwrap_or(0);
+        let mut labels = Array2::from_elem((queries.len(), max_passages), IGNORE_INDEX);
+        let mut pairs: Vec<tokenizer::EncodeInput> = Vec::new();
+        let mut query_indices = Vec::new();
+        for (i, query) in queries.iter().enumerate() {
+            if query.passages.is_empty() {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "The query {:?} has no passages",
+                    query.query
+                )));
+            }
+            for (j, passage) in query.passages.iter().enumerate() {
+                labels[[i, j]] = passage.label.0 as i64;
+                pairs.push((query.query.as_str(), passage.text.as_str()).into());
+                query_indices.push(i);
+            }
+        }
+        let samples: Vec<TokenizedText<NoTokenizedLabel>> = self
+            .tokenizer
+            .encode_batch(pairs, false)
+            .expect("Failed to tokenize")
+            .into_iter()
+            .zip(query_indices)
+            .map(|(encoding, query_index)| {
+                let mut sample =
+                    TokenizedText::from_tokenizer_encoding(encoding, NoLabel(), self.pad_token);
+                sample.encoding.example_index = Some(query_index);
+                sample
+            })
+            .collect();
+        Some(RetrievalBatch {
+            encoding: stack_samples(samples, self.seq_length, None, IGNORE_INDEX, None).encoding,
+            labels,
+        })
+    }
+}
+
+impl<T: Node<Output = Retrieval>> Node for RetrievalBatcher<T> {
+    type Output = RetrievalBatch;
+    fn get(&self, index: usize) -> Option<RetrievalBatch> {
+        let index = index * self.batch_size;
+        let queries = (index..index + self.batch_size)
+            .map_while(|i| self.loader.get(i))
+            .collect();
+        self.create_batch(queries)
+    }
+    fn len(&self) -> Option<usize> {
+        self.loader.len().map(|len| len.div_ceil(self.batch_size))
+    }
+    fn next(&mut self) -> Option<RetrievalBatch> {
+        let queries = (0..self.batch_size)
+            .map_while(|_| self.loader.next())
+            .collect();
+        self.create_batch(queries)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.loader.size_hint();
+        (
+            lower.div_ceil(self.batch_size),
+            upper.map(|upper| upper.div_ceil(self.batch_size)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.loader
+            .estimated_len()
+            .map(|len| len.div_ceil(self.batch_size))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.loader.finalize()
+    }
+}
@@ -0,0 +1,77 @@
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed;
+use crate::{ClassLabel, NoLabel, Node, PreprocessingError, Text, TextPair};
+
+// Sentence-order prediction data as in ALBERT's pretraining: every two consecutive sentences form
+// a pair, which is swapped with probability `swap_ratio`. The class is 1 for swapped pairs and 0
+// for pairs in their original order. Unlike `NextSentencePrediction` both sentences of a pair
+// always come from the same stretch of text, so the task is about coherence rather than topic.
+// The upstream node yields one sentence per sample, e.g. a `SentenceSplit`. Every sentence is part
+// of one pair, a single sentence left at the end is dropped.
+pub struct SentenceOrderPrediction<T: Node<Output = Text<NoLabel>>> {
+    node: T,
+    swap_ratio: f64,
+    rng: ChaCha8Rng,
+}
+
+impl<T: Node<Output = Text<NoLabel>>> SentenceOrderPrediction<T> {
+    pub fn new(
+        node: T,
+        swap_ratio: f64,
+        seed: Option<u64>,
+    ) -> Result<SentenceOrderPrediction<T>, PreprocessingError> {
+        if !(0.0..=1.0).contains(&swap_ratio) {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Swap ratio {} is not in [0, 1]",
+                swap_ratio
+            )));
+        }
+        Ok(SentenceOrderPrediction {
+            node,
+            swap_ratio,
+            rng: seed::node_rng(seed),
+        })
+    }
+}
+
+impl<T: Node<Output = Text<NoLabel>>> Node for SentenceOrderPrediction<T> {
+    type Output = TextPair<ClassLabel>;
+    // Not implemented since the pairs depend on the random state.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    // `get` isn't supported, the number of pairs is in `size_hint`.
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let first = self.node.next()?.text;
+        let second = self.node.next()?.text;
+        let swapped = self.rng.gen_bool(self.swap_ratio);
+        let text = if swapped {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        Some(TextPair {
+            text,
+            label: ClassLabel(swapped as u32),
+            metadata: None,
+        })
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (lower / 2, upper.map(|upper| upper / 2))
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len().map(|len| len / 2)
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+
+use crate::{Metadata, NoLabel, Node, PreprocessingError, Text};
+
+// Splits every text into sentences and emits them one by one. A sentence ends at a `.`, `!` or
+// `?` (optionally followed by closing quotes or brackets) that is followed by whitespace or the
+// end of the text. The splitter knows nothing about abbreviations, so "Dr. Smith" or "e.g. this"
+// are split as well, while sentences without a space after the punctuation are kept together.
+// Since the number of sentences of a text is only known after splitting it, `get` and `len`
+// aren't supported. The sentences all get the metadata of their text.
+pub struct SentenceSplit<T: Node<Output = Text<NoLabel>>> {
+    node: T,
+    sentences: VecDeque<String>,
+    // The metadata of the text the sentences are from.
+    metadata: Option<Metadata>,
+}
+
+impl<T: Node<Output = Text<NoLabel>>> SentenceSplit<T> {
+    pub fn new(node: T) -> Result<SentenceSplit<T>, PreprocessingError> {
+        Ok(SentenceSplit {
+            node,
+            sentences: VecDeque::new(),
+            metadata: None,
+        })
+    }
+}
+
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        while let Some(&(_, c)) = chars.peek() {
+            if !matches!(c, '.' | '!' | '?' | '"' | '\'' | ')' | ']' | '”' | '’') {
+                break;
+            }
+            chars.next();
+        }
+        let end = chars.peek().map_or(text.len(), |&(index, _)| index);
+        if chars.peek().is_none_or(|&(_, c)| c.is_whitespace()) {
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    sentences.push(&text[start..]);
+    sentences
+        .into_iter()
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .collect()
+}
+
+impl<T: Node<Output = Text<NoLabel>>> Node for SentenceSplit<T> {
+    type Output = Text<NoLabel>;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        while self.sentences.is_empty() {
+            let Text { text, metadata, .. } = self.node.next()?;
+            self.metadata = metadata;
+            self.sentences
+                .extend(split_sentences(&text).into_iter().map(str::to_string));
+        }
+        self.sentences.pop_front().map(|text| Text {
+            text,
+            label: NoLabel(),
+            metadata: self.metadata.clone(),
+        })
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    // A text can have any number of sentences, including none.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.sentences.len(), None)
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ndarray::Array1;
+
+use crate::nodes::{AnswerRetention, BatchStats, TruncationStats};
+use crate::{Node, PreprocessingError};
+
+// Every `num_shards`-th output of the upstream node starting at output `index`, so that
+// `num_shards` workers (like the workers of a PyTorch `DataLoader`) each read a disjoint part of
+// the data. Indexable nodes are read with `get`, so every worker only builds its own outputs.
+// Streaming nodes have to be read in order, every worker reads the whole stream and skips the
+// outputs of the other shards.
+pub struct Shard<T: Node> {
+    node: T,
+    index: usize,
+    num_shards: usize,
+    // Outputs of this shard returned by `next` so far, or upstream outputs read for streams.
+    position: usize,
+}
+
+impl<T: Node> Shard<T> {
+    pub fn new(node: T, index: usize, num_shards: usize) -> Result<Shard<T>, PreprocessingError> {
+        if index >= num_shards {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Shard {} doesn't exist with {} shards",
+                index, num_shards
+            )));
+        }
+        Ok(Shard {
+            node,
+            index,
+            num_shards,
+            position: 0,
+        })
+    }
+    fn shard_len(&self, len: usize) -> usize {
+        len.saturating_sub(self.index).div_ceil(self.num_shards)
+    }
+}
+
+impl<T: Node> Node for Shard<T> {
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index * self.num_shards + self.index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len().map(|len| self.shard_len(len))
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.node.len().is_some() {
+            let output = self.get(self.position)?;
+            self.position += 1;
+            return Some(output);
+        }
+        loop {
+            let output = self.node.next()?;
+            self.position += 1;
+            if (self.position - 1) % self.num_shards == self.index {
+                return Some(output);
+            }
+        }
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        self.node.stats()
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.node.truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.node.max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        self.node.feature_stats()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.node
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.node.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.node.keep_texts()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (
+            self.shard_len(lower),
+            upper.map(|upper| self.shard_len(upper)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len().map(|len| self.shard_len(len))
+    }
+}
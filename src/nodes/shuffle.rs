@@ -0,0 +1,65 @@
+use std::sync::RwLock;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed::{self, epoch_seed};
+use crate::{Node, PreprocessingError};
+
+// Full shuffle of an indexable node: output `i` is upstream output `permutation[i]`. Every epoch
+// has its own permutation drawn from `epoch_seed(seed, epoch)`, so `set_epoch` reshuffles while
+// each epoch stays reproducible. `next` walks the permutation of the current epoch. For streaming
+// nodes without a length, see `BufferedShuffle`.
+pub struct Shuffle<T: Node> {
+    node: T,
+    seed: u64,
+    permutation: RwLock<Vec<usize>>,
+    position: usize,
+}
+
+fn permutation(len: usize, seed: u64, epoch: u64) -> Vec<usize> {
+    let mut permutation: Vec<usize> = (0..len).collect();
+    permutation.shuffle(&mut ChaCha8Rng::seed_from_u64(epoch_seed(seed, epoch)));
+    permutation
+}
+
+impl<T: Node> Shuffle<T> {
+    pub fn new(node: T, seed: Option<u64>) -> Result<Shuffle<T>, PreprocessingError> {
+        let len = node.len().ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "Shuffle needs a node with a known length, use BufferedShuffle for streaming nodes"
+                    .to_string(),
+            )
+        })?;
+        let seed = seed::node_rng(seed).gen();
+        Ok(Shuffle {
+            node,
+            seed,
+            permutation: RwLock::new(permutation(len, seed, 0)),
+            position: 0,
+        })
+    }
+}
+
+impl<T: Node> Node for Shuffle<T> {
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let index = *self.permutation.read().unwrap().get(index)?;
+        self.node.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let output = self.get(self.position)?;
+        self.position += 1;
+        Some(output)
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        let len = self.permutation.read().unwrap().len();
+        *self.permutation.write().unwrap() = permutation(len, self.seed, epoch);
+        self.node.set_epoch(epoch);
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed::{self, derive_seed, epoch_seed};
+use crate::{
+    Encoding, MultiLabel, NoTokenizedLabel, Node, PreprocessingError, SpecialTokenIds,
+    TokenizedText,
+};
+
+// Lengths of `parts` non-empty segments that `n` items are split into at random.
+fn random_segments(n: usize, parts: usize, rng: &mut ChaCha8Rng) -> Vec<usize> {
+    let mut cuts: Vec<usize> = rand::seq::index::sample(rng, n - 1, parts - 1)
+        .into_iter()
+        .map(|cut| cut + 1)
+        .collect();
+    cuts.sort_unstable();
+    cuts.push(n);
+    let mut start = 0;
+    cuts.into_iter()
+        .map(|cut| {
+            let len = cut - start;
+            start = cut;
+            len
+        })
+        .collect()
+}
+
+// Span corruption for T5-style pretraining. About `corruption_rate` of the tokens of every sample
+// are removed in spans of `mean_span_length` tokens on average, every span is replaced by a
+// sentinel token in the input, and the labels are the sentinels each followed by the tokens of its
+// span. The sentinels count down from `first_sentinel`, like `<extra_id_0>`, `<extra_id_1>`, ... of
+// T5 (`first_sentinel` 32099), so there are at most `num_sentinels` spans per sample. The special
+// tokens at the start (see `Encoding::prefix_special_tokens`) and those of the tokenizer upstream
+// at the end (like `</s>`) are kept in place. The spans are placed like in the original
+// implementation: the number of corrupted tokens and of spans is fixed by the length, the lengths
+// of the spans and of the kept segments between them are random, and the first token is kept.
+// Like `Augment`, sample `i` is always corrupted with the same seed in an epoch, so `get` and
+// `next` agree.
+pub struct SpanCorruption<T: Node<Output = TokenizedText<NoTokenizedLabel>>> {
+    node: T,
+    first_sentinel: u32,
+    num_sentinels: usize,
+    corruption_rate: f64,
+    mean_span_length: f64,
+    special_tokens: Vec<u32>,
+    seed: u64,
+    epoch: AtomicU64,
+    index: usize,
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> SpanCorruption<T> {
+    pub fn new(
+        node: T,
+        first_sentinel: u32,
+        num_sentinels: usize,
+        corruption_rate: f64,
+        mean_span_length: f64,
+        seed: Option<u64>,
+    ) -> Result<SpanCorruption<T>, PreprocessingError> {
+        if num_sentinels == 0 || num_sentinels > first_sentinel as usize + 1 {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Can't count {} sentinels down from {}",
+                num_sentinels, first_sentinel
+            )));
+        }
+        if !(corruption_rate > 0.0 && corruption_rate < 1.0) {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "The corruption rate has to be in (0, 1), got {}",
+                corruption_rate
+            )));
+        }
+        if mean_span_length.is_nan() || mean_span_length < 1.0 {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "The mean span length has to be at least 1, got {}",
+                mean_span_length
+            )));
+        }
+        let special_tokens = match node.tokenizer() {
+            Some(tokenizer) => {
+                let ids = SpecialTokenIds::of(&tokenizer);
+                [ids.pad, ids.cls, ids.sep, ids.mask]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        Ok(SpanCorruption {
+            node,
+            first_sentinel,
+            num_sentinels,
+            corruption_rate,
+            mean_span_length,
+            special_tokens,
+            seed: seed::node_rng(seed).gen(),
+            epoch: AtomicU64::new(0),
+            index: 0,
+        })
+    }
+    fn corrupt(
+        &self,
+        sample: TokenizedText<NoTokenizedLabel>,
+        index: usize,
+    ) -> TokenizedText<MultiLabel> {
+        let encoding = sample.encoding;
+        let ids = encoding.input_ids.to_vec();
+        let start = encoding.prefix_special_tokens.min(ids.len());
+        let mut end = ids.len();
+        while end > start && self.special_tokens.contains(&ids[end - 1]) {
+            end -= 1;
+        }
+        let len = end - start;
+        // At least one token has to be kept and one corrupted.
+        if len < 2 {
+            return TokenizedText {
+                encoding,
+                label: MultiLabel(Vec::new()),
+            };
+        }
+        let seed = epoch_seed(self.seed, self.epoch.load(Ordering::Relaxed));
+        let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(seed, index as u64));
+        let noise = ((len as f64 * self.corruption_rate).round() as usize).clamp(1, len - 1);
+        let spans = ((noise as f64 / self.mean_span_length).round() as usize)
+            .clamp(1, noise.min(len - noise))
+            .min(self.num_sentinels);
+        let noise_lengths = random_segments(noise, spans, &mut rng);
+        let kept_lengths = random_segments(len - noise, spans, &mut rng);
+        let mut inputs = ids[..start].to_vec();
+        let mut targets = Vec::with_capacity(noise + spans);
+        let mut position = start;
+        for (span, (kept, noise)) in kept_lengths.into_iter().zip(noise_lengths).enumerate() {
+            inputs.extend(&ids[position..position + kept]);
+            position += kept;
+            let sentinel = self.first_sentinel - span as u32;
+            inputs.push(sentinel);
+            targets.push(sentinel);
+            targets.extend(&ids[position..position + noise]);
+            position += noise;
+        }
+        inputs.extend(&ids[end..]);
+        let mut corrupted = Encoding::from_ids(inputs, encoding.pad_token);
+        corrupted.prefix_special_tokens = start;
+        corrupted.example_index = encoding.example_index;
+        corrupted.text = encoding.text;
+        corrupted.hash_id = encoding.hash_id;
+        corrupted.metadata = encoding.metadata;
+        TokenizedText {
+            encoding: corrupted,
+            label: MultiLabel(targets),
+        }
+    }
+}
+
+impl<T: Node<Output = TokenizedText<NoTokenizedLabel>>> Node for SpanCorruption<T> {
+    type Output = TokenizedText<MultiLabel>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let sample = self.node.get(index)?;
+        Some(self.corrupt(sample, index))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.node.next()?;
+        self.index += 1;
+        Some(self.corrupt(sample, self.index - 1))
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+
+use ndarray::{Array1, Array2, ArrayD, Axis, IxDyn};
+
+use crate::nodes::NamedBatch;
+use crate::{error, NamedArrays, Node, PreprocessingError};
+
+// A `NamedBatch` with one of its arrays standardized, which becomes a float array (`f32`) under
+// the same name in Python.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct StandardizedBatch {
+    pub(crate) arrays: NamedArrays,
+    pub(crate) name: String,
+    pub(crate) feature: ArrayD<f32>,
+}
+
+// Running mean and variance of every feature (Welford's algorithm).
+#[derive(Clone, Default)]
+struct FeatureStats {
+    count: u64,
+    mean: Array1<f64>,
+    // Sum of squared differences from the mean.
+    m2: Array1<f64>,
+}
+
+impl FeatureStats {
+    fn add(&mut self, rows: &Array2<f64>) {
+        if self.count == 0 {
+            self.mean = Array1::zeros(rows.ncols());
+            self.m2 = Array1::zeros(rows.ncols());
+        }
+        if rows.ncols() != self.mean.len() {
+            error::raise(PreprocessingError::InvalidArgument(format!(
+                "Got {} features, earlier batches had {}",
+                rows.ncols(),
+                self.mean.len()
+            )));
+        }
+        for row in rows.rows() {
+            self.count += 1;
+            let delta = &row - &self.mean;
+            self.mean += &(&delta / self.count as f64);
+            self.m2 += &(&delta * &(&row - &self.mean));
+        }
+    }
+    // Population standard deviation.
+    fn std(&self) -> Array1<f64> {
+        (&self.m2 / self.count.max(1) as f64).mapv(f64::sqrt)
+    }
+}
+
+// Standardizes the array `feature` of every batch to zero mean and unit variance, e.g. numeric
+// side features. The values along the last axis are separate features (a 1-D array is a single
+// one), each with its own mean and standard deviation. Constant features are only centered.
+//
+// Without given statistics they are computed over the stream: every batch updates the running
+// statistics, and is standardized with the statistics of all batches read so far. The first
+// `warmup` batches are held back until all of them were read, so they aren't standardized with
+// the statistics of just a few batches. `get` isn't supported then since the outputs depend on
+// the batches read before. With `with_stats`, e.g. ones persisted from a training run, the
+// batches are standardized with those and the statistics aren't updated.
+pub struct Standardize<T: Node<Output = NamedBatch>> {
+    node: T,
+    feature: String,
+    warmup: usize,
+    stats: FeatureStats,
+    // The mean and standard deviation given by `with_stats`.
+    fixed: Option<(Array1<f64>, Array1<f64>)>,
+    buffer: VecDeque<NamedBatch>,
+    warmed_up: bool,
+}
+
+impl<T: Node<Output = NamedBatch>> Standardize<T> {
+    pub fn new(
+        node: T,
+        feature: String,
+        warmup: usize,
+    ) -> Result<Standardize<T>, PreprocessingError> {
+        Ok(Standardize {
+            node,
+            feature,
+            warmup,
+            stats: FeatureStats::default(),
+            fixed: None,
+            buffer: VecDeque::new(),
+            warmed_up: false,
+        })
+    }
+    pub fn with_stats(
+        mut self,
+        mean: Array1<f64>,
+        std: Array1<f64>,
+    ) -> Result<Standardize<T>, PreprocessingError> {
+        if mean.len() != std.len() {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Got {} means but {} standard deviations",
+                mean.len(),
+                std.len()
+            )));
+        }
+        if std.iter().any(|&std| std < 0.0 || std.is_nan()) {
+            return Err(PreprocessingError::InvalidArgument(
+                "The standard deviations can't be negative".to_string(),
+            ));
+        }
+        self.fixed = Some((mean, std));
+        Ok(self)
+    }
+    // The values of the feature as rows of features.
+    fn rows(&self, batch: &NamedBatch) -> Array2<f64> {
+        let array = batch
+            .0
+            .iter()
+            .find(|(name, _)| *name == self.feature)
+            .map(|(_, array)| array)
+            .unwrap_or_else(|| {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "The batch has no array {:?}",
+                    self.feature
+                )))
+            });
+        let n_features = match array.ndim() {
+            0 | 1 => 1,
+            ndim => array.shape()[ndim - 1],
+        };
+        let rows = array.len() / n_features.max(1);
+        array
+            .mapv(|value| value as f64)
+            .into_shape((rows, n_features))
+            .expect("The array has rows * n_features elements")
+    }
+    fn read(&mut self) -> Option<NamedBatch> {
+        let batch = self.node.next()?;
+        if self.fixed.is_none() {
+            let rows = self.rows(&batch);
+            self.stats.add(&rows);
+        }
+        Some(batch)
+    }
+    fn standardize(&self, batch: NamedBatch) -> StandardizedBatch {
+        let rows = self.rows(&batch);
+        let (mean, std) = match &self.fixed {
+            Some((mean, std)) => (mean.clone(), std.clone()),
+            None => (self.stats.mean.clone(), self.stats.std()),
+        };
+        if rows.ncols() != mean.len() {
+            error::raise(PreprocessingError::InvalidArgument(format!(
+                "Got {} features, the statistics have {}",
+                rows.ncols(),
+                mean.len()
+            )));
+        }
+        let std = std.mapv(|std| if std > 0.0 { std } else { 1.0 });
+        let standardized = (rows - &mean.insert_axis(Axis(0))) / &std.insert_axis(Axis(0));
+        let NamedBatch(mut arrays) = batch;
+        let position = arrays
+            .iter()
+            .position(|(name, _)| *name == self.feature)
+            .expect("The feature was found before");
+        let (_, array) = arrays.remove(position);
+        let feature = standardized
+            .mapv(|value| value as f32)
+            .into_shape(IxDyn(array.shape()))
+            .expect("The feature keeps its shape");
+        StandardizedBatch {
+            arrays,
+            name: self.feature.clone(),
+            feature,
+        }
+    }
+}
+
+impl<T: Node<Output = NamedBatch>> Node for Standardize<T> {
+    type Output = StandardizedBatch;
+    fn get(&self, index: usize) -> Option<StandardizedBatch> {
+        self.fixed.as_ref()?;
+        self.node.get(index).map(|batch| self.standardize(batch))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<StandardizedBatch> {
+        if !self.warmed_up {
+            while self.buffer.len() < self.warmup {
+                match self.read() {
+                    Some(batch) => self.buffer.push_back(batch),
+                    None => break,
+                }
+            }
+            self.warmed_up = true;
+        }
+        let batch = match self.buffer.pop_front() {
+            Some(batch) => batch,
+            None => self.read()?,
+        };
+        Some(self.standardize(batch))
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        match &self.fixed {
+            Some(stats) => Some(stats.clone()),
+            None => Some((self.stats.mean.clone(), self.stats.std())),
+        }
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        let buffered = self.buffer.len();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        let estimate = self.node.estimated_len()?;
+        Some(estimate.saturating_add(self.buffer.len()))
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ndarray::Array1;
+
+use crate::{Batch, BatchEncoding, BatchLabel, Node, PreprocessingError};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchStats {
+    samples: usize,
+    // Non-padding tokens after truncation.
+    real_tokens: usize,
+    // Allocated cells of the input matrices.
+    cells: usize,
+    // Longest sample before truncation.
+    max_length: usize,
+}
+
+impl BatchStats {
+    fn add(&mut self, encoding: &BatchEncoding) {
+        let seq_length = encoding.input_ids.ncols();
+        self.cells += encoding.input_ids.len();
+        for (i, &length) in encoding.lengths.iter().enumerate() {
+            // Rows repeated by `pad_batch` count as padding.
+            if let Some(0) = encoding.sample_mask.as_ref().map(|mask| mask[i]) {
+                continue;
+            }
+            self.samples += 1;
+            self.real_tokens += length.min(seq_length);
+            self.max_length = self.max_length.max(length);
+        }
+    }
+    // Fraction of the allocated cells that are padding.
+    pub fn padding_ratio(&self) -> f64 {
+        if self.cells == 0 {
+            return 0.0;
+        }
+        1.0 - self.real_tokens as f64 / self.cells as f64
+    }
+    // Average number of non-padding tokens per sample.
+    pub fn avg_length(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.real_tokens as f64 / self.samples as f64
+    }
+    pub fn max_length_seen(&self) -> usize {
+        self.max_length
+    }
+}
+
+// Sequences that were longer than the sequence length of a `StaticBatcher` and cut off.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TruncationStats {
+    sequences: usize,
+    // Tokens removed over all truncated sequences.
+    tokens: usize,
+}
+
+impl TruncationStats {
+    pub(crate) fn add(&mut self, length: usize, seq_length: usize) {
+        if length > seq_length {
+            self.sequences += 1;
+            self.tokens += length - seq_length;
+        }
+    }
+    pub fn truncated_sequences(&self) -> usize {
+        self.sequences
+    }
+    pub fn truncated_tokens(&self) -> usize {
+        self.tokens
+    }
+}
+
+// Examples with an answer (like a `Span`) that a `Tokenizer` read, and how many of them still had
+// their answer in the encoding after truncation. With overflowing windows an answer counts as
+// retained if any window of the example contains it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnswerRetention {
+    answers: usize,
+    retained: usize,
+}
+
+impl AnswerRetention {
+    pub(crate) fn add(&mut self, retained: bool) {
+        self.answers += 1;
+        self.retained += retained as usize;
+    }
+    pub fn answers(&self) -> usize {
+        self.answers
+    }
+    pub fn retained(&self) -> usize {
+        self.retained
+    }
+    // Fraction of answers that survived truncation, 1 if there were no answers.
+    pub fn rate(&self) -> f64 {
+        if self.answers == 0 {
+            return 1.0;
+        }
+        self.retained as f64 / self.answers as f64
+    }
+}
+
+// Histogram of token lengths for picking a sequence length: `buckets` buckets of equal (integer)
+// width from 0 to past the longest length. Returns the `buckets + 1` bucket edges and the counts,
+// bucket `i` counts the lengths in `edges[i]..edges[i + 1]`.
+pub fn length_histogram(
+    lengths: &Array1<i64>,
+    buckets: usize,
+) -> Result<(Array1<i64>, Array1<i64>), PreprocessingError> {
+    if buckets == 0 {
+        return Err(PreprocessingError::InvalidArgument(
+            "The number of buckets has to be positive".to_string(),
+        ));
+    }
+    let max_length = lengths.iter().copied().max().unwrap_or(0);
+    let width = ((max_length + 1) as usize).div_ceil(buckets) as i64;
+    let edges = Array1::from_iter((0..=buckets as i64).map(|bucket| bucket * width));
+    let mut counts = Array1::zeros(buckets);
+    for &length in lengths {
+        counts[(length / width) as usize] += 1;
+    }
+    Ok((edges, counts))
+}
+
+// Passes batches through while accumulating padding statistics about them.
+pub struct Stats<S: BatchLabel, T: Node<Output = Batch<S>>> {
+    node: T,
+    // `get` only has shared access, so the counters need interior mutability.
+    stats: Mutex<BatchStats>,
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Stats<S, T> {
+    pub fn new(node: T) -> Result<Stats<S, T>, PreprocessingError> {
+        Ok(Stats {
+            node,
+            stats: Mutex::new(BatchStats::default()),
+        })
+    }
+    fn record(&self, batch: Batch<S>) -> Batch<S> {
+        self.stats.lock().unwrap().add(&batch.encoding);
+        batch
+    }
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Node for Stats<S, T> {
+    type Output = Batch<S>;
+    fn get(&self, index: usize) -> Option<Batch<S>> {
+        self.node.get(index).map(|batch| self.record(batch))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Batch<S>> {
+        let batch = self.node.next()?;
+        Some(self.record(batch))
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        Some(*self.stats.lock().unwrap())
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.node.truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.node
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.node.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
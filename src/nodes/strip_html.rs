@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use crate::{ClassLabel, Label, NoLabel, Node, PreprocessingError, StringLabel, Text, TextPair};
+
+// Labels that don't refer to character offsets, so the text can be rewritten without
+// invalidating them. Spans would point at the wrong characters once tags are removed.
+pub trait OffsetFreeLabel: Label {}
+
+impl OffsetFreeLabel for NoLabel {}
+impl OffsetFreeLabel for ClassLabel {}
+impl OffsetFreeLabel for StringLabel {}
+
+// Samples whose texts `StripHtml` converts.
+pub trait HtmlSample {
+    fn strip_html(self) -> Self;
+}
+
+impl<L: OffsetFreeLabel> HtmlSample for Text<L> {
+    fn strip_html(self) -> Self {
+        Text {
+            text: strip_html(&self.text),
+            label: self.label,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl<L: OffsetFreeLabel> HtmlSample for TextPair<L> {
+    fn strip_html(self) -> Self {
+        TextPair {
+            text: (strip_html(&self.text.0), strip_html(&self.text.1)),
+            label: self.label,
+            metadata: self.metadata,
+        }
+    }
+}
+
+// Converts HTML to plain text for web-scraped corpora: removes tags, comments and the contents of
+// `<script>` and `<style>` elements, decodes the common character references and collapses
+// whitespace, so paragraphs end up on one line. Tags other than inline ones like `<b>` or `<a>`
+// separate words. Only samples without character offsets in their labels are supported.
+pub struct StripHtml<T: Node> {
+    node: T,
+}
+
+impl<T: Node> StripHtml<T>
+where
+    T::Output: HtmlSample,
+{
+    pub fn new(node: T) -> Result<StripHtml<T>, PreprocessingError> {
+        Ok(StripHtml { node })
+    }
+}
+
+const INLINE_TAGS: [&str; 16] = [
+    "a", "abbr", "b", "bdi", "cite", "code", "em", "font", "i", "mark", "q", "s", "small", "span",
+    "strong", "u",
+];
+
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with('&') {
+            match decode_reference(rest) {
+                Some((c, len)) => {
+                    text.push(c);
+                    rest = &rest[len..];
+                }
+                None => {
+                    text.push('&');
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        // A `<` that doesn't start a tag, like in "a < b", is text.
+        let starts_tag = rest[1..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let end = match rest.find('>') {
+            Some(end) if starts_tag => end,
+            _ => {
+                text.push('<');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !tag.starts_with('/') && (name == "script" || name == "style") {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(start) => rest[start..]
+                    .find('>')
+                    .map_or("", |end| &rest[start + end + 1..]),
+                None => "",
+            };
+        }
+        if !INLINE_TAGS.contains(&name.as_str()) {
+            text.push(' ');
+        }
+    }
+    text.push_str(rest);
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// The character of a reference like `&amp;` or `&#8212;` at the start of `text` and the length
+// of the reference.
+fn decode_reference(text: &str) -> Option<(char, usize)> {
+    let (end, _) = text.char_indices().take(12).find(|&(_, c)| c == ';')?;
+    let name = &text[1..end];
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((c, end + 1))
+}
+
+impl<T: Node> Node for StripHtml<T>
+where
+    T::Output: HtmlSample,
+{
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(HtmlSample::strip_html)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.node.next().map(HtmlSample::strip_html)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{Batch, BatchLabel, Node, PreprocessingError};
+
+// At most the first `num_batches` batches of a batcher, e.g. for learning rate finder or sanity
+// runs that should take the same number of steps whatever the size of the dataset. Only the
+// batches that are read are built, the rest of the upstream node is never read.
+pub struct TakeBatches<S: BatchLabel, T: Node<Output = Batch<S>>> {
+    node: T,
+    num_batches: usize,
+    // Batches returned by `next` so far.
+    taken: usize,
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> TakeBatches<S, T> {
+    pub fn new(node: T, num_batches: usize) -> Result<TakeBatches<S, T>, PreprocessingError> {
+        Ok(TakeBatches {
+            node,
+            num_batches,
+            taken: 0,
+        })
+    }
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Node for TakeBatches<S, T> {
+    type Output = Batch<S>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        if index >= self.num_batches {
+            return None;
+        }
+        self.node.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len().map(|len| len.min(self.num_batches))
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.taken >= self.num_batches {
+            return None;
+        }
+        let batch = self.node.next()?;
+        self.taken += 1;
+        Some(batch)
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if let Some(len) = self.len() {
+            return (len, Some(len));
+        }
+        let (lower, upper) = self.node.size_hint();
+        (
+            lower.min(self.num_batches),
+            Some(upper.map_or(self.num_batches, |upper| upper.min(self.num_batches))),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        let estimate = self.node.estimated_len()?;
+        Some(estimate.min(self.num_batches))
+    }
+}
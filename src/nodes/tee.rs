@@ -0,0 +1,144 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use ndarray::Array1;
+
+use crate::nodes::{AnswerRetention, BatchStats, TruncationStats};
+use crate::{error, Node, PreprocessingError};
+
+struct TeeState<T: Node> {
+    node: T,
+    // Outputs read from upstream by one handle that the other handle hasn't taken yet.
+    buffers: [VecDeque<T::Output>; 2],
+}
+
+// One of two handles returned by `Tee::new` that both see every output of the upstream node.
+// The upstream node is advanced by whichever handle is ahead, its output is cloned into the
+// buffer of the other handle. A handle may get at most `buffer_size` outputs ahead of the other
+// one. Reading beyond that raises an `InvalidArgument` error unless upstream has ended (blocking
+// instead would deadlock, since both handles are usually consumed from the same thread, and
+// returning `None` would end the stream early). The output read is held back, so once the other
+// handle has caught up the first one continues where it stopped. `get` and `len` are passed
+// through and don't touch the buffers.
+pub struct Tee<T: Node> {
+    state: Arc<Mutex<TeeState<T>>>,
+    side: usize,
+    buffer_size: usize,
+}
+
+impl<T: Node> Tee<T>
+where
+    T::Output: Clone + Send,
+{
+    pub fn new(node: T, buffer_size: usize) -> Result<(Tee<T>, Tee<T>), PreprocessingError> {
+        if buffer_size == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The buffer size has to be positive".to_string(),
+            ));
+        }
+        let state = Arc::new(Mutex::new(TeeState {
+            node,
+            buffers: [VecDeque::new(), VecDeque::new()],
+        }));
+        let handle = |side| Tee {
+            state: Arc::clone(&state),
+            side,
+            buffer_size,
+        };
+        Ok((handle(0), handle(1)))
+    }
+    // A panic of the upstream node in one handle doesn't make the other one panic as well.
+    fn state(&self) -> MutexGuard<'_, TeeState<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Node> Node for Tee<T>
+where
+    T::Output: Clone + Send,
+{
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.state().node.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.state().node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let mut state = self.state();
+        if state.buffers[1 - self.side].len() <= self.buffer_size {
+            if let Some(output) = state.buffers[self.side].pop_front() {
+                return Some(output);
+            }
+            let output = state.node.next()?;
+            state.buffers[1 - self.side].push_back(output.clone());
+            if state.buffers[1 - self.side].len() <= self.buffer_size {
+                return Some(output);
+            }
+            // Held back until the other handle has caught up, so no output is lost.
+            state.buffers[self.side].push_back(output);
+        }
+        drop(state);
+        error::raise(PreprocessingError::InvalidArgument(format!(
+            "This Tee handle is {} outputs ahead of the other one, the limit of the buffer size. \
+             Consume the other handle first or use a larger buffer size",
+            self.buffer_size
+        )))
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        self.state().node.stats()
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.state().node.truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.state().node.answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.state().node.max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.state().node.tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.state().node.total_tokens()
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.state().node.lengths()
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        self.state().node.feature_stats()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.state()
+            .node
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.state().node.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.state().node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.state().node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.state().node.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.state().node.keep_texts()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.state().node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.state().node.estimated_len()
+    }
+}
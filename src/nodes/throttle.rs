@@ -0,0 +1,49 @@
+use std::thread;
+use std::time::Duration;
+
+use ndarray::Array1;
+
+use crate::{Node, PreprocessingError};
+
+// Diagnostic node that delays every sample to simulate a slow producer.
+pub struct Throttle<T: Node> {
+    node: T,
+    delay: Duration,
+}
+
+impl<T: Node> Throttle<T> {
+    pub fn new(node: T, millis: u64) -> Result<Throttle<T>, PreprocessingError> {
+        Ok(Throttle {
+            node,
+            delay: Duration::from_millis(millis),
+        })
+    }
+}
+
+impl<T: Node> Node for Throttle<T> {
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        thread::sleep(self.delay);
+        self.node.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        thread::sleep(self.delay);
+        self.node.next()
+    }
+    forward_hooks!(node);
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ndarray::Array1;
+
+use crate::nodes::{AnswerRetention, BatchStats, TruncationStats};
+use crate::{Node, PreprocessingError};
+
+// Time a `Timed` node spent so far, shared with the node so it can be read while the pipeline
+// runs (e.g. on the workers of a `DataLoader`).
+#[derive(Clone)]
+pub struct Timer {
+    name: String,
+    nanos: Arc<AtomicU64>,
+}
+
+impl Timer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+// Adds up the time spent in `get` and `next` of the upstream node, to find the bottleneck of a
+// pipeline. The time includes everything upstream, so with several `Timed` stages the time of a
+// stage is its time minus that of the `Timed` node before it.
+pub struct Timed<T: Node> {
+    node: T,
+    timer: Timer,
+}
+
+impl<T: Node> Timed<T> {
+    pub fn new(node: T, name: String) -> Result<Timed<T>, PreprocessingError> {
+        Ok(Timed {
+            node,
+            timer: Timer {
+                name,
+                nanos: Arc::new(AtomicU64::new(0)),
+            },
+        })
+    }
+    fn record(&self, start: Instant) {
+        let nanos = start.elapsed().as_nanos() as u64;
+        self.timer.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl<T: Node> Node for Timed<T> {
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let start = Instant::now();
+        let output = self.node.get(index);
+        self.record(start);
+        output
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let start = Instant::now();
+        let output = self.node.next();
+        self.record(start);
+        output
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        self.node.stats()
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.node.truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.node.max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        self.node.feature_stats()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.node
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.node.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.node.keep_texts()
+    }
+    fn timer(&self) -> Option<Timer> {
+        Some(self.timer.clone())
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::nodes::AnswerRetention;
+use crate::{
+    stack_samples, Batch, Node, PreprocessingError, TokenizedLabel, TokenizedText, IGNORE_INDEX,
+};
+
+// Batches samples by the number of tokens instead of a fixed number of samples, so memory use
+// stays bounded independently of the sequence lengths. Samples are added until the padded batch,
+// i.e. the longest sample times the number of samples, would exceed `max_tokens` or the batch
+// holds `max_samples` samples. Every batch is padded to its own longest sample, so both the
+// batch size and the sequence length vary. A sample that is longer than `max_tokens` on its own
+// is emitted as a batch of one.
+pub struct TokenBudgetBatcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    tokenizer: T,
+    max_tokens: usize,
+    max_samples: Option<usize>,
+    // First sample of the next batch, read while checking whether it fits into the current one.
+    pending: Option<TokenizedText<S>>,
+    // Set by `finalize`, from then on only the pending sample is emitted.
+    upstream_closed: bool,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> TokenBudgetBatcher<S, T> {
+    pub fn new(
+        tokenizer: T,
+        max_tokens: usize,
+        max_samples: Option<usize>,
+    ) -> Result<TokenBudgetBatcher<S, T>, PreprocessingError> {
+        if max_tokens == 0 || max_samples == Some(0) {
+            return Err(PreprocessingError::InvalidArgument(
+                "max_tokens and max_samples have to be positive".to_string(),
+            ));
+        }
+        Ok(TokenBudgetBatcher {
+            tokenizer,
+            max_tokens,
+            max_samples,
+            pending: None,
+            upstream_closed: false,
+        })
+    }
+    fn read(&mut self) -> Option<TokenizedText<S>> {
+        if self.upstream_closed {
+            return None;
+        }
+        self.tokenizer.next()
+    }
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for TokenBudgetBatcher<S, T> {
+    type Output = Batch<S::Batch>;
+    // Not implemented since the batch boundaries depend on the lengths of all previous samples.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let mut samples: Vec<TokenizedText<S>> = Vec::new();
+        let mut max_length = 0;
+        while let Some(sample) = self.pending.take().or_else(|| self.read()) {
+            let length = max_length.max(sample.encoding.input_ids.len());
+            let full = self.max_samples == Some(samples.len());
+            if !samples.is_empty() && (full || length * (samples.len() + 1) > self.max_tokens) {
+                self.pending = Some(sample);
+                break;
+            }
+            max_length = length;
+            samples.push(sample);
+        }
+        if samples.is_empty() {
+            None
+        } else {
+            Some(stack_samples(samples, max_length, None, IGNORE_INDEX, None))
+        }
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.tokenizer.answer_retention()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.tokenizer.total_tokens()
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.tokenizer.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.tokenizer.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.upstream_closed = !self.tokenizer.finalize();
+        true
+    }
+    // Every batch has at least one and at most `max_samples` samples.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.tokenizer.size_hint();
+        let lower = match (self.upstream_closed, self.max_samples) {
+            (true, _) => 0,
+            (false, Some(max_samples)) => lower.div_ceil(max_samples),
+            (false, None) => lower.min(1),
+        };
+        (lower, upper)
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed::{self, derive_seed, epoch_seed};
+use crate::{Node, PreprocessingError, SpecialTokenIds, TokenizedLabel, TokenizedText};
+
+// Replaces every token of the tokenized samples with the pad token with probability `p`, as
+// regularization during training. Unlike masking for MLM it adds no labels. The tokens are replaced
+// rather than removed, so spans and other labels pointing at tokens stay valid. The special tokens
+// of the tokenizer upstream (`[CLS]`, `[SEP]`, `[MASK]` and the pad token, see `SpecialTokenIds`)
+// are never replaced, without a tokenizer upstream (e.g. after `PreTokenized`) only the pad token
+// is kept. Like `Augment`, sample `i` is always dropped out with the same seed in an epoch, so
+// `get` and `next` agree.
+pub struct TokenDropout<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    node: T,
+    p: f64,
+    special_tokens: Vec<u32>,
+    seed: u64,
+    epoch: AtomicU64,
+    index: usize,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> TokenDropout<S, T> {
+    pub fn new(
+        node: T,
+        p: f64,
+        seed: Option<u64>,
+    ) -> Result<TokenDropout<S, T>, PreprocessingError> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Dropout probability {} is not in [0, 1]",
+                p
+            )));
+        }
+        let special_tokens = match node.tokenizer() {
+            Some(tokenizer) => {
+                let ids = SpecialTokenIds::of(&tokenizer);
+                [ids.pad, ids.cls, ids.sep, ids.mask]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        Ok(TokenDropout {
+            node,
+            p,
+            special_tokens,
+            seed: seed::node_rng(seed).gen(),
+            epoch: AtomicU64::new(0),
+            index: 0,
+        })
+    }
+    fn drop_tokens(&self, mut sample: TokenizedText<S>, index: usize) -> TokenizedText<S> {
+        let seed = epoch_seed(self.seed, self.epoch.load(Ordering::Relaxed));
+        let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(seed, index as u64));
+        let pad_token = sample.encoding.pad_token;
+        for id in sample.encoding.input_ids.iter_mut() {
+            if *id != pad_token && !self.special_tokens.contains(id) && rng.gen_bool(self.p) {
+                *id = pad_token;
+            }
+        }
+        sample
+    }
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for TokenDropout<S, T> {
+    type Output = TokenizedText<S>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let sample = self.node.get(index)?;
+        Some(self.drop_tokens(sample, index))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.node.next()?;
+        self.index += 1;
+        Some(self.drop_tokens(sample, self.index - 1))
+    }
+    forward_hooks!(node);
+    fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
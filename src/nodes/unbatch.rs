@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use ndarray::{s, Array1, Array2};
+
+use crate::{
+    Batch, BatchClassLabel, BatchEncoding, BatchLabel, BatchMultiLabel, BatchSpan, ClassLabel,
+    Encoding, MultiLabel, NoBatchLabel, NoTokenizedLabel, Node, PreprocessingError, TokenizedLabel,
+    TokenizedSpan, TokenizedText,
+};
+
+// Batched labels that can be split back into the labels of their rows, the inverse of
+// `TokenizedLabel::to_batch`. Rows without a label (the ignore index, e.g. of a padded batch) are
+// `None`.
+pub trait UnbatchLabel: BatchLabel {
+    type Row: TokenizedLabel;
+    fn into_rows(self, rows: usize) -> Vec<Option<Self::Row>>;
+}
+
+impl UnbatchLabel for NoBatchLabel {
+    type Row = NoTokenizedLabel;
+    fn into_rows(self, rows: usize) -> Vec<Option<NoTokenizedLabel>> {
+        vec![Some(NoTokenizedLabel); rows]
+    }
+}
+
+impl UnbatchLabel for BatchClassLabel {
+    type Row = ClassLabel;
+    fn into_rows(self, _rows: usize) -> Vec<Option<ClassLabel>> {
+        self.0
+            .iter()
+            .map(|&label| (label >= 0).then_some(ClassLabel(label as u32)))
+            .collect()
+    }
+}
+
+impl UnbatchLabel for BatchMultiLabel {
+    type Row = MultiLabel;
+    fn into_rows(self, _rows: usize) -> Vec<Option<MultiLabel>> {
+        self.0
+            .rows()
+            .into_iter()
+            .map(|row| {
+                let ids = row.iter().filter(|&&id| id >= 0).map(|&id| id as u32);
+                Some(MultiLabel(ids.collect()))
+            })
+            .collect()
+    }
+}
+
+// Unanswerable rows get no span, rows without an answer text (an empty one in the batch) no text
+// and rows without a character span (the ignore index) none.
+impl UnbatchLabel for BatchSpan {
+    type Row = TokenizedSpan;
+    fn into_rows(self, rows: usize) -> Vec<Option<TokenizedSpan>> {
+        (0..rows)
+            .map(|row| {
+                let span = (self.answerable[row] == 1)
+                    .then(|| (self.start[row] as usize, self.end[row] as usize));
+                let answer = self
+                    .answers
+                    .as_ref()
+                    .map(|answers| answers[row].clone())
+                    .filter(|answer| !answer.is_empty());
+                let char_span = match (&self.answer_char_start, &self.answer_char_end) {
+                    (Some(start), Some(end)) if start[row] >= 0 => {
+                        Some((start[row] as usize, end[row] as usize))
+                    }
+                    _ => None,
+                };
+                Some(TokenizedSpan(span, answer, char_span))
+            })
+            .collect()
+    }
+}
+
+// Splits every batch back into the tokenized samples it was stacked from, e.g. to log samples
+// after batch-level nodes. The padding is cut off using the lengths of the rows, so the samples
+// are the original ones up to truncation to the sequence length. The repeated rows of a padded
+// batch and rows without a label are skipped. The text of the samples isn't part of the batch, so
+// they have none, and `Encoding::global_attention` is only kept if the batch has a
+// `global_attention_mask`. The number of samples is only known after reading the batches, so
+// `get` and `len` aren't supported.
+pub struct Unbatch<S: UnbatchLabel, T: Node<Output = Batch<S>>> {
+    node: T,
+    samples: VecDeque<TokenizedText<S::Row>>,
+}
+
+impl<S: UnbatchLabel, T: Node<Output = Batch<S>>> Unbatch<S, T> {
+    pub fn new(node: T) -> Result<Unbatch<S, T>, PreprocessingError> {
+        Ok(Unbatch {
+            node,
+            samples: VecDeque::new(),
+        })
+    }
+    fn split(&mut self, batch: Batch<S>) {
+        let Batch { encoding, labels } = batch;
+        let rows = encoding.input_ids.nrows();
+        for (row, label) in labels.into_rows(rows).into_iter().enumerate() {
+            let repeated = encoding
+                .sample_mask
+                .as_ref()
+                .is_some_and(|mask| mask[row] == 0);
+            if let (false, Some(label)) = (repeated, label) {
+                self.samples.push_back(TokenizedText {
+                    encoding: row_encoding(&encoding, row),
+                    label,
+                });
+            }
+        }
+    }
+}
+
+fn row_encoding(encoding: &BatchEncoding, row: usize) -> Encoding {
+    let len = encoding.lengths[row].min(encoding.input_ids.ncols());
+    let cut = |matrix: &Array2<u32>| matrix.slice(s![row, ..len]).to_owned();
+    let cut_u8 = |matrix: &Array2<u8>| matrix.slice(s![row, ..len]).to_owned();
+    Encoding {
+        input_ids: cut(&encoding.input_ids),
+        attention_mask: cut(&encoding.attention_mask),
+        token_type_ids: cut(&encoding.token_type_ids),
+        pad_token: encoding.pad_token,
+        example_index: encoding
+            .overflow_to_sample_mapping
+            .as_ref()
+            .map(|mapping| mapping[row] as usize),
+        owned_tokens: cut(&encoding.owned_tokens),
+        global_attention: match &encoding.global_attention_mask {
+            Some(mask) => cut(mask),
+            None => Array1::zeros(len),
+        },
+        word_starts: cut_u8(&encoding.word_start_mask),
+        context: cut_u8(&encoding.context_mask),
+        sequence_ids: encoding
+            .sequence_ids
+            .as_ref()
+            .map(|ids| ids.slice(s![row, ..len]).mapv(|id| id as u32)),
+        prefix_special_tokens: (encoding.prefix_special_tokens[row] as usize).min(len),
+        context_start: (encoding.context_start[row] as usize).min(len),
+        text: None,
+        hash_id: encoding.hash_ids.as_ref().map(|hash_ids| hash_ids[row]),
+        metadata: encoding
+            .metadata
+            .as_ref()
+            .map(|metadata| metadata[row].clone()),
+    }
+}
+
+impl<S: UnbatchLabel, T: Node<Output = Batch<S>>> Node for Unbatch<S, T> {
+    type Output = TokenizedText<S::Row>;
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        while self.samples.is_empty() {
+            let batch = self.node.next()?;
+            self.split(batch);
+        }
+        self.samples.pop_front()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.node.tokenizer()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+}
@@ -0,0 +1,144 @@
+use ndarray::Array1;
+
+use crate::{error, Batch, BatchEncoding, BatchLabel, Node, PreprocessingError};
+
+// Checks that every batch has `batch_size` rows and `seq_length` columns (either unchecked if
+// `None`) and that all arrays of its encoding agree on them, raising an `InvalidArgument` error
+// naming the offending array otherwise. A debugging aid to catch a misconfigured pipeline at the
+// first bad batch rather than in the model, e.g. a short last batch with `partial_batch="short"`
+// where every batch was expected to be full. The element types are fixed by `BatchEncoding`, so
+// only shapes can differ.
+pub struct Validate<S: BatchLabel, T: Node<Output = Batch<S>>> {
+    node: T,
+    batch_size: Option<usize>,
+    seq_length: Option<usize>,
+    // Batches emitted by `next`, for the error messages.
+    seen: usize,
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Validate<S, T> {
+    pub fn new(
+        node: T,
+        batch_size: Option<usize>,
+        seq_length: Option<usize>,
+    ) -> Result<Validate<S, T>, PreprocessingError> {
+        if batch_size == Some(0) || seq_length == Some(0) {
+            return Err(PreprocessingError::InvalidArgument(
+                "The expected batch size and sequence length have to be positive".to_string(),
+            ));
+        }
+        Ok(Validate {
+            node,
+            batch_size,
+            seq_length,
+            seen: 0,
+        })
+    }
+    fn validate(&self, batch: Batch<S>, index: usize) -> Batch<S> {
+        let encoding = &batch.encoding;
+        let (rows, columns) = encoding.input_ids.dim();
+        if let Some(batch_size) = self.batch_size {
+            if rows != batch_size {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "Batch {} has {} rows, expected {} (a short last batch needs \
+                     `partial_batch=\"drop\"` or `pad_batch=True`)",
+                    index, rows, batch_size
+                )));
+            }
+        }
+        if let Some(seq_length) = self.seq_length {
+            if columns != seq_length {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "Batch {} has a sequence length of {}, expected {}",
+                    index, columns, seq_length
+                )));
+            }
+        }
+        for (name, shape) in matrix_shapes(encoding) {
+            if shape != (rows, columns) {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "The {} of batch {} have the shape {:?}, the input ids {:?}",
+                    name,
+                    index,
+                    shape,
+                    (rows, columns)
+                )));
+            }
+        }
+        for (name, len) in row_lengths(encoding) {
+            if len != rows {
+                error::raise(PreprocessingError::InvalidArgument(format!(
+                    "The {} of batch {} have {} rows, the input ids {}",
+                    name, index, len, rows
+                )));
+            }
+        }
+        batch
+    }
+}
+
+fn matrix_shapes(encoding: &BatchEncoding) -> Vec<(&'static str, (usize, usize))> {
+    let mut shapes = vec![
+        ("attention_mask", encoding.attention_mask.dim()),
+        ("token_type_ids", encoding.token_type_ids.dim()),
+        ("owned_tokens", encoding.owned_tokens.dim()),
+        ("word_start_mask", encoding.word_start_mask.dim()),
+        ("context_mask", encoding.context_mask.dim()),
+    ];
+    if let Some(ids) = &encoding.sequence_ids {
+        shapes.push(("sequence_ids", ids.dim()));
+    }
+    if let Some(mask) = &encoding.global_attention_mask {
+        shapes.push(("global_attention_mask", mask.dim()));
+    }
+    shapes
+}
+
+fn row_lengths(encoding: &BatchEncoding) -> Vec<(&'static str, usize)> {
+    let mut lengths = vec![
+        ("lengths", encoding.lengths.len()),
+        ("truncated", encoding.truncated.len()),
+        (
+            "prefix_special_tokens",
+            encoding.prefix_special_tokens.len(),
+        ),
+        ("context_start", encoding.context_start.len()),
+    ];
+    if let Some(mapping) = &encoding.overflow_to_sample_mapping {
+        lengths.push(("overflow_to_sample_mapping", mapping.len()));
+    }
+    if let Some(mask) = &encoding.sample_mask {
+        lengths.push(("sample_mask", mask.len()));
+    }
+    lengths
+}
+
+impl<S: BatchLabel, T: Node<Output = Batch<S>>> Node for Validate<S, T> {
+    type Output = Batch<S>;
+    fn get(&self, index: usize) -> Option<Batch<S>> {
+        let batch = self.node.get(index)?;
+        Some(self.validate(batch, index))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Batch<S>> {
+        let batch = self.node.next()?;
+        let batch = self.validate(batch, self.seen);
+        self.seen += 1;
+        Some(batch)
+    }
+    forward_hooks!(node);
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.node.lengths()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -0,0 +1,94 @@
+use ndarray::Array1;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand_chacha::ChaCha8Rng;
+
+use crate::seed;
+use crate::{Node, PreprocessingError};
+
+// Draws `epoch_size` samples with replacement, sample `i` with probability proportional to
+// `weights[i]`, e.g. for importance sampling or a curriculum. The upstream node has to be
+// indexable since the drawn indices are read with `get`. The outputs are a random stream, so
+// `get` isn't supported and two samplers only agree given the same seed.
+pub struct WeightedSampler<T: Node> {
+    node: T,
+    weights: WeightedIndex<f64>,
+    epoch_size: usize,
+    rng: ChaCha8Rng,
+    emitted: usize,
+}
+
+impl<T: Node> WeightedSampler<T> {
+    pub fn new(
+        node: T,
+        weights: Array1<f64>,
+        epoch_size: usize,
+        seed: Option<u64>,
+    ) -> Result<WeightedSampler<T>, PreprocessingError> {
+        let len = node.len().ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "WeightedSampler requires an indexable upstream node".to_string(),
+            )
+        })?;
+        if weights.len() != len {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Got {} weights for {} samples",
+                weights.len(),
+                len
+            )));
+        }
+        let weights = WeightedIndex::new(weights.iter()).map_err(|err| {
+            PreprocessingError::InvalidArgument(format!("Invalid sampling weights: {}", err))
+        })?;
+        Ok(WeightedSampler {
+            node,
+            weights,
+            epoch_size,
+            rng: seed::node_rng(seed),
+            emitted: 0,
+        })
+    }
+    // Weights every sample by `weight`, e.g. of its label. Reads every sample once up front.
+    pub fn with_weight_fn<F: Fn(&T::Output) -> f64>(
+        node: T,
+        weight: F,
+        epoch_size: usize,
+        seed: Option<u64>,
+    ) -> Result<WeightedSampler<T>, PreprocessingError> {
+        let weights: Option<Array1<f64>> = (0..node.len().unwrap_or(0))
+            .map(|index| node.get(index).map(|sample| weight(&sample)))
+            .collect();
+        let weights = weights.ok_or_else(|| {
+            PreprocessingError::IncompatibleNode(
+                "The upstream node has fewer examples than its length".to_string(),
+            )
+        })?;
+        WeightedSampler::new(node, weights, epoch_size, seed)
+    }
+}
+
+impl<T: Node> Node for WeightedSampler<T> {
+    type Output = T::Output;
+    // Not implemented since the outputs depend on the random state.
+    fn get(&self, _index: usize) -> Option<Self::Output> {
+        None
+    }
+    // `get` isn't supported, the number of outputs is in `size_hint`.
+    fn len(&self) -> Option<usize> {
+        None
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.emitted == self.epoch_size {
+            return None;
+        }
+        self.emitted += 1;
+        let index = self.weights.sample(&mut self.rng);
+        self.node.get(index)
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    forward_hooks!(node);
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.epoch_size, Some(self.epoch_size))
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{Node, PreprocessingError};
+
+// Emits every `size` consecutive upstream outputs as one output, advancing by `stride` outputs
+// between windows, e.g. a sentence together with its neighbours. With a stride larger than the
+// size the outputs in between are skipped. A last window with fewer than `size` outputs is
+// dropped.
+pub struct Window<T: Node> {
+    node: T,
+    size: usize,
+    stride: usize,
+    buffer: VecDeque<T::Output>,
+    started: bool,
+}
+
+impl<T: Node> Window<T>
+where
+    T::Output: Clone,
+{
+    pub fn new(node: T, size: usize, stride: usize) -> Result<Window<T>, PreprocessingError> {
+        if size == 0 || stride == 0 {
+            return Err(PreprocessingError::InvalidArgument(
+                "The window size and stride have to be positive".to_string(),
+            ));
+        }
+        Ok(Window {
+            node,
+            size,
+            stride,
+            buffer: VecDeque::with_capacity(size),
+            started: false,
+        })
+    }
+    fn n_windows(&self, len: usize) -> usize {
+        if len < self.size {
+            0
+        } else {
+            (len - self.size) / self.stride + 1
+        }
+    }
+}
+
+impl<T: Node> Node for Window<T>
+where
+    T::Output: Clone,
+{
+    type Output = Vec<T::Output>;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let start = index * self.stride;
+        (start..start + self.size)
+            .map(|i| self.node.get(i))
+            .collect()
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len().map(|len| self.n_windows(len))
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        if self.started {
+            for _ in 0..self.stride {
+                if self.buffer.pop_front().is_none() {
+                    self.node.next()?;
+                }
+            }
+        }
+        while self.buffer.len() < self.size {
+            self.buffer.push_back(self.node.next()?);
+        }
+        self.started = true;
+        Some(self.buffer.iter().cloned().collect())
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.node.size_hint();
+        (
+            self.n_windows(lower),
+            upper.map(|upper| self.n_windows(upper)),
+        )
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len().map(|len| self.n_windows(len))
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use crate::{error, Metadata, Node, PreprocessingError, Sample};
+
+// Attaches `metadata[i]` to sample `i` of the node, e.g. ids or source files to join predictions
+// back to. Tokenizers move it into the encodings (every overflowing window gets a copy) and
+// batchers collect it into `BatchEncoding::metadata`, so it reaches the batches without a custom
+// label type. Samples are counted in the order they are read, so it has to come before nodes that
+// reorder or drop samples, like `Shuffle` or `MaxChars`. Samples combining several others (like
+// those of `NSP`) have no metadata.
+pub struct WithMetadata<T: Node>
+where
+    T::Output: Sample,
+{
+    node: T,
+    metadata: Vec<Metadata>,
+    index: usize,
+}
+
+impl<T: Node> WithMetadata<T>
+where
+    T::Output: Sample,
+{
+    pub fn new(node: T, metadata: Vec<Metadata>) -> Result<WithMetadata<T>, PreprocessingError> {
+        if let Some(len) = node.len() {
+            if len != metadata.len() {
+                return Err(PreprocessingError::InvalidArgument(format!(
+                    "Got metadata for {} samples, the node has {}",
+                    metadata.len(),
+                    len
+                )));
+            }
+        }
+        Ok(WithMetadata {
+            node,
+            metadata,
+            index: 0,
+        })
+    }
+    fn attach(&self, mut sample: T::Output, index: usize) -> T::Output {
+        match self.metadata.get(index) {
+            Some(metadata) => sample.set_metadata(metadata.clone()),
+            None => error::raise(PreprocessingError::InvalidArgument(format!(
+                "Got metadata for {} samples, the node has more",
+                self.metadata.len()
+            ))),
+        }
+        sample
+    }
+}
+
+impl<T: Node> Node for WithMetadata<T>
+where
+    T::Output: Sample,
+{
+    type Output = T::Output;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        let sample = self.node.get(index)?;
+        Some(self.attach(sample, index))
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        let sample = self.node.next()?;
+        self.index += 1;
+        Some(self.attach(sample, self.index - 1))
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
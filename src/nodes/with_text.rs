@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::nodes::AnswerRetention;
+use crate::{Node, PreprocessingError, TokenizedLabel, TokenizedText};
+
+// Pairs every tokenized sample with the text it was encoded from, e.g. to log which inputs
+// produced which encodings. The sequences of a pair are joined by a newline. The `Tokenizer`
+// upstream is made to keep the texts, the overflowing windows of a sample all get its full text.
+pub struct WithText<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> {
+    node: T,
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> WithText<S, T> {
+    pub fn new(mut node: T) -> Result<WithText<S, T>, PreprocessingError> {
+        if !node.keep_texts() {
+            return Err(PreprocessingError::IncompatibleNode(
+                "WithText requires a Tokenizer upstream".to_string(),
+            ));
+        }
+        Ok(WithText { node })
+    }
+}
+
+// Samples encoded before `keep_texts` (like a peeked one) have no text and get an empty one.
+fn with_text<S: TokenizedLabel>(mut sample: TokenizedText<S>) -> (String, TokenizedText<S>) {
+    (sample.encoding.text.take().unwrap_or_default(), sample)
+}
+
+impl<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>> Node for WithText<S, T> {
+    type Output = (String, TokenizedText<S>);
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.node.get(index).map(with_text)
+    }
+    fn len(&self) -> Option<usize> {
+        self.node.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.node.next().map(with_text)
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.node.answer_retention()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.node.total_tokens()
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.node.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.node.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.node.finalize()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.node.estimated_len()
+    }
+}
@@ -1,19 +1,67 @@
-use std::any::Any;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+#[cfg(feature = "dlpack")]
+use std::ffi::c_void;
+use std::io;
+use std::iter;
+#[cfg(feature = "dlpack")]
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex, Once, OnceLock, PoisonError, RwLock};
+use std::thread;
+use std::time::Duration;
 use std::usize;
 
 use numpy::ndarray::prelude::*;
-use numpy::{PyArray1, PyArray2, ToPyArray};
-use pyo3::exceptions::PyBaseException;
+use numpy::{IntoPyArray, PyArray1, PyArray2, ToPyArray};
+use pyo3::exceptions::{
+    PyFileNotFoundError, PyIOError, PyIndexError, PyKeyError, PyPermissionError, PyRuntimeError,
+    PyStopAsyncIteration, PyTimeoutError, PyTypeError, PyValueError,
+};
+#[cfg(feature = "dlpack")]
+use pyo3::ffi;
+use pyo3::panic::PanicException;
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+#[cfg(feature = "dlpack")]
+use crate::dlpack::DlManagedTensor;
 
 use crate::{
-    datasets::{SQuADLoader, TxtLoader},
-    Batch, BatchEncoding, BatchLabel, Encoding, Label, NoLabel, NoTokenizedLabel, Node, Span,
-    StaticBatcher, Text, TextPair, TokenizedLabel, TokenizedSpan, TokenizedText, Tokenizer,
+    datasets::{
+        AnswerPolicy, HfCacheLoader, HfDataset, HfSample, IdsTxtLoader, InvalidUtf8,
+        LabelledTxtLoader, LineTrim, ListLoader, MalformedPolicy, MultiSpanLoader, MultiSpanSample,
+        MultipleChoiceLoader, PairTxtLoader, PairedFilesLoader, RetrievalLoader, RowLabel,
+        SQuADLoader, StdinLoader, TokenSpanTxtLoader, TxtLoader,
+    },
+    nodes::{
+        decode_labels, drain, length_histogram, write_mmap, AnswerRetention, AsDict, Augment,
+        BatchStats, BucketBatcher, BufferedShuffle, ByteTokenizer, CausalLMBatcher, ClassBalance,
+        DataLoader, DrainOrder, DrainStats, DualBatch, DualTokenizer, GroupByExample, HashId,
+        LabelEncoder, LabelSmooth, MaxChars, MinHashDedup, MinLength, MultipleChoiceBatch,
+        MultipleChoiceBatcher, NamedBatch, NextSentencePrediction, OneHot, OneHotBatch,
+        OneHotInputs, Pack, PairBatch, PairBatcher, PassageChunk, Peekable, Pipelined,
+        PreTokenized, PromptPair, RaggedBatch, RaggedBatcher, RandomCrop, Repeat, RetrievalBatch,
+        RetrievalBatcher, SentenceOrderPrediction, SentenceSplit, Shard, Shuffle, SmoothedBatch,
+        SpanCorruption, SpanToAnswerable, SpanWithAnswerable, Standardize, StandardizedBatch,
+        Stats, StripHtml, TakeBatches, Tee, Throttle, Timed, Timer, TokenBudgetBatcher,
+        TokenDropout, TruncationStats, Unbatch, Validate, WeightedSampler, Window, WithMetadata,
+        WithText, WordDropout,
+    },
+    retry::Retry,
+    seed, warnings, word_spans, Batch, BatchEncoding, BatchLabel, Encoding, Label, Metadata,
+    MultipleChoice, NoLabel, NoTokenizedLabel, Node, PartialBatch, PreprocessingError, Retrieval,
+    Sample, Span, SpecialTokenIds, StaticBatcher, StringLabel, Text, TextPair, TimeoutPolicy,
+    TokenizedLabel, TokenizedSpan, TokenizedText, Tokenizer, TruncationSide,
+};
+use crate::{
+    BatchClassLabel, BatchLMLabels, BatchMultiLabel, BatchOptionalLabel, BatchSpan, BatchSpans,
+    Both, ClassLabel, Ids, MultiLabel, NamedArrays, NoBatchLabel, OptionalLabel, Spans, TokenSpan,
+    TokenizedSpans,
 };
-use crate::{BatchSpan, NoBatchLabel};
 
-pub trait ToPyObjectConsume: Send {
+pub trait ToPyObjectConsume: Send + Sync {
     fn to_object_consume(self, py: Python<'_>) -> PyObject;
 }
 
@@ -23,6 +71,42 @@ impl<T: Label> ToPyObjectConsume for Text<T> {
     }
 }
 
+// A tuple of the query and a list of `(passage, label)` tuples.
+impl ToPyObjectConsume for Retrieval {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let passages: Vec<(String, u32)> = self
+            .passages
+            .into_iter()
+            .map(|passage| (passage.text, passage.label.0))
+            .collect();
+        (self.query, passages).to_object(py)
+    }
+}
+
+// A tuple of the context, the list of options and the index of the right one.
+impl ToPyObjectConsume for MultipleChoice {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        (self.context, self.options, self.label).to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for Ids {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.to_object(py)
+    }
+}
+
+// Windows of consecutive samples become lists.
+impl<T: ToPyObjectConsume> ToPyObjectConsume for Vec<T> {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let outputs: Vec<PyObject> = self
+            .into_iter()
+            .map(|output| output.to_object_consume(py))
+            .collect();
+        outputs.to_object(py)
+    }
+}
+
 impl<T: Label> ToPyObjectConsume for TextPair<T> {
     fn to_object_consume(self, py: Python<'_>) -> PyObject {
         self.text.to_object(py)
@@ -36,17 +120,130 @@ impl<T: TokenizedLabel> ToPyObjectConsume for TokenizedText<T> {
     }
 }
 
+// Samples paired with their source text by `WithText` become `(text, sample)` tuples.
+impl<T: ToPyObjectConsume> ToPyObjectConsume for (String, T) {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let (text, sample) = self;
+        (text, sample.to_object_consume(py)).into_py(py)
+    }
+}
+
+impl From<PreprocessingError> for PyErr {
+    fn from(err: PreprocessingError) -> PyErr {
+        let message = err.to_string();
+        match err {
+            PreprocessingError::Io(err) => match err.kind() {
+                io::ErrorKind::NotFound => PyFileNotFoundError::new_err(message),
+                io::ErrorKind::PermissionDenied => PyPermissionError::new_err(message),
+                io::ErrorKind::InvalidInput => PyValueError::new_err(message),
+                io::ErrorKind::TimedOut => PyTimeoutError::new_err(message),
+                _ => PyIOError::new_err(message),
+            },
+            PreprocessingError::Tokenizer(_) => PyRuntimeError::new_err(message),
+            PreprocessingError::IncompatibleNode(_) => PyTypeError::new_err(message),
+            PreprocessingError::NodeConsumed => PyRuntimeError::new_err(message),
+            PreprocessingError::Parse(_) | PreprocessingError::InvalidArgument(_) => {
+                PyValueError::new_err(message)
+            }
+        }
+    }
+}
+
+// Accepts "lossy", "skip" or "error".
+impl<'source> FromPyObject<'source> for InvalidUtf8 {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+// Accepts "skip" or "error".
+impl<'source> FromPyObject<'source> for MalformedPolicy {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+// Accepts "first", "last", "shortest" or "longest".
+impl<'source> FromPyObject<'source> for AnswerPolicy {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+// Accepts "none", "line_ending", "end" or "both".
+impl<'source> FromPyObject<'source> for LineTrim {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+impl<'source> FromPyObject<'source> for TruncationSide {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+impl<'source> FromPyObject<'source> for PartialBatch {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+// Accepts "skip" or "raise".
+impl<'source> FromPyObject<'source> for TimeoutPolicy {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
+// Accepts "fifo" or "shuffled".
+impl<'source> FromPyObject<'source> for DrainOrder {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let value: &str = ob.extract()?;
+        Ok(value.parse::<Self>()?)
+    }
+}
+
 #[pyclass(name = "Encoding")]
 pub struct EncodingPy {
     input_ids: Array1<u32>,
+    attention_mask: Array1<u32>,
+    token_type_ids: Array1<u32>,
+    owned_tokens: Array1<u32>,
+    sequence_ids: Option<Array1<u32>>,
+    first_subword_indices: Array1<i64>,
     #[pyo3(get)]
     pad_token: u32,
+    // Special tokens in front of the first sequence, e.g. 1 for `[CLS]`.
+    #[pyo3(get)]
+    prefix_special_tokens: usize,
+    // Index of the first token of the last sequence, like the context of a QA pair.
+    #[pyo3(get)]
+    context_start: usize,
+    // Dict attached by `WithMetadata`, `None` without one.
+    #[pyo3(get)]
+    metadata: Option<Metadata>,
 }
 impl ToPyObjectConsume for Encoding {
     fn to_object_consume(self, py: Python<'_>) -> PyObject {
         let encoding = EncodingPy {
+            first_subword_indices: self.first_subword_indices(),
             input_ids: self.input_ids,
+            attention_mask: self.attention_mask,
+            token_type_ids: self.token_type_ids,
+            owned_tokens: self.owned_tokens,
+            sequence_ids: self.sequence_ids,
             pad_token: self.pad_token,
+            metadata: self.metadata,
+            prefix_special_tokens: self.prefix_special_tokens,
+            context_start: self.context_start,
         };
         encoding.into_py(py)
     }
@@ -58,6 +255,32 @@ impl EncodingPy {
     fn get_input_ids(&self, py: Python<'_>) -> Py<PyArray1<u32>> {
         self.input_ids.to_pyarray(py).to_owned()
     }
+    #[getter]
+    fn get_attention_mask(&self, py: Python<'_>) -> Py<PyArray1<u32>> {
+        self.attention_mask.to_pyarray(py).to_owned()
+    }
+    #[getter]
+    fn get_token_type_ids(&self, py: Python<'_>) -> Py<PyArray1<u32>> {
+        self.token_type_ids.to_pyarray(py).to_owned()
+    }
+    #[getter]
+    fn get_owned_tokens(&self, py: Python<'_>) -> Py<PyArray1<bool>> {
+        self.owned_tokens
+            .mapv(|owned| owned == 1)
+            .to_pyarray(py)
+            .to_owned()
+    }
+    #[getter]
+    fn get_sequence_ids(&self, py: Python<'_>) -> Option<Py<PyArray1<u32>>> {
+        self.sequence_ids
+            .as_ref()
+            .map(|ids| ids.to_pyarray(py).to_owned())
+    }
+    // Index of the first subword of every word, to gather per-word logits at.
+    #[getter]
+    fn get_first_subword_indices(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
+        self.first_subword_indices.to_pyarray(py).to_owned()
+    }
 }
 
 #[pyclass(name = "BatchEncoding")]
@@ -67,10 +290,217 @@ struct BatchEncodingPy {
 
 #[pymethods]
 impl BatchEncodingPy {
+    #[getter]
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+    #[getter]
+    fn seq_length(&self) -> usize {
+        self.inner.seq_length()
+    }
+    // Id the rows are padded with.
+    #[getter]
+    fn pad_token(&self) -> u32 {
+        self.inner.pad_token()
+    }
     #[getter]
     fn input_ids(&self, py: Python<'_>) -> Py<PyArray2<u32>> {
         self.inner.input_ids.to_pyarray(py).to_owned()
     }
+    #[getter]
+    fn attention_mask(&self, py: Python<'_>) -> Py<PyArray2<u32>> {
+        self.inner.attention_mask.to_pyarray(py).to_owned()
+    }
+    // For frameworks like JAX that expect boolean masks.
+    #[getter]
+    fn bool_attention_mask(&self, py: Python<'_>) -> Py<PyArray2<bool>> {
+        self.inner.bool_attention_mask().to_pyarray(py).to_owned()
+    }
+    #[getter]
+    fn token_type_ids(&self, py: Python<'_>) -> Py<PyArray2<u32>> {
+        self.inner.token_type_ids.to_pyarray(py).to_owned()
+    }
+    // Tokens of the windowed sequence that aren't part of the previous window's overlap.
+    #[getter]
+    fn owned_tokens(&self, py: Python<'_>) -> Py<PyArray2<bool>> {
+        self.inner
+            .owned_tokens
+            .mapv(|owned| owned == 1)
+            .to_pyarray(py)
+            .to_owned()
+    }
+    #[getter]
+    fn overflow_to_sample_mapping(&self, py: Python<'_>) -> Option<Py<PyArray1<i64>>> {
+        self.inner
+            .overflow_to_sample_mapping
+            .as_ref()
+            .map(|mapping| mapping.to_pyarray(py).to_owned())
+    }
+    // Text hash of every row, only present with `HashId`.
+    #[getter]
+    fn hash_ids(&self, py: Python<'_>) -> Option<Py<PyArray1<u64>>> {
+        self.inner
+            .hash_ids
+            .as_ref()
+            .map(|hash_ids| hash_ids.to_pyarray(py).to_owned())
+    }
+    // List of the metadata dicts of the rows, only present with `WithMetadata`.
+    #[getter]
+    fn metadata(&self) -> Option<Vec<Metadata>> {
+        self.inner.metadata.clone()
+    }
+    #[getter]
+    fn sample_mask(&self, py: Python<'_>) -> Option<Py<PyArray1<i64>>> {
+        self.inner
+            .sample_mask
+            .as_ref()
+            .map(|mask| mask.to_pyarray(py).to_owned())
+    }
+    // Only present if the `StaticBatcher` was created with `global_attention=True` or
+    // `num_global_tokens`.
+    #[getter]
+    fn global_attention_mask(&self, py: Python<'_>) -> Option<Py<PyArray2<u32>>> {
+        self.inner
+            .global_attention_mask
+            .as_ref()
+            .map(|mask| mask.to_pyarray(py).to_owned())
+    }
+    // 1 for the rows cut off at the sequence length of the batcher, 0 otherwise.
+    #[getter]
+    fn truncated(&self, py: Python<'_>) -> Py<PyArray1<u8>> {
+        self.inner.truncated.to_pyarray(py).to_owned()
+    }
+    // 1 for the first subword of every word, 0 for the other subwords, special tokens and padding.
+    #[getter]
+    fn word_start_mask(&self, py: Python<'_>) -> Py<PyArray2<u8>> {
+        self.inner.word_start_mask.to_pyarray(py).to_owned()
+    }
+    // 1 for the context tokens (the last sequence of a pair), 0 for the question, special tokens and
+    // padding.
+    #[getter]
+    fn context_mask(&self, py: Python<'_>) -> Py<PyArray2<u8>> {
+        self.inner.context_mask.to_pyarray(py).to_owned()
+    }
+    // Token index of the first subword of every word of every row, padded with -1.
+    #[getter]
+    fn first_subword_indices(&self, py: Python<'_>) -> Py<PyArray2<i64>> {
+        self.inner
+            .first_subword_indices()
+            .into_pyarray(py)
+            .to_owned()
+    }
+    // Packed sequence of every token, only present for samples packed by `Pack`.
+    #[getter]
+    fn sequence_ids(&self, py: Python<'_>) -> Option<Py<PyArray2<i64>>> {
+        self.inner
+            .sequence_ids
+            .as_ref()
+            .map(|ids| ids.to_pyarray(py).to_owned())
+    }
+    // Number of special tokens in front of the first sequence of every row, e.g. 1 for `[CLS]`.
+    #[getter]
+    fn prefix_special_tokens(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
+        self.inner.prefix_special_tokens.to_pyarray(py).to_owned()
+    }
+    // Index of the first token of the last sequence of every row, i.e. where the context of a QA
+    // pair starts. Token `i` of the context is at `context_start + i` in the window.
+    #[getter]
+    fn context_start(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
+        self.inner.context_start.to_pyarray(py).to_owned()
+    }
+    // The model inputs (`input_ids`, `attention_mask`, `token_type_ids` and the
+    // `global_attention_mask` if present) as a dict for `model(**inputs)`, built in one call
+    // instead of one per getter.
+    fn to_dict<'py>(&self, py: Python<'py>) -> &'py PyDict {
+        let dict = PyDict::new(py);
+        let inputs = [
+            ("input_ids", Some(&self.inner.input_ids)),
+            ("attention_mask", Some(&self.inner.attention_mask)),
+            ("token_type_ids", Some(&self.inner.token_type_ids)),
+            (
+                "global_attention_mask",
+                self.inner.global_attention_mask.as_ref(),
+            ),
+        ];
+        for (name, array) in inputs {
+            if let Some(array) = array {
+                dict.set_item(name, array.to_pyarray(py))
+                    .expect("Setting a dict item can't fail");
+            }
+        }
+        dict
+    }
+    // `pyarrow.RecordBatch` taking over the buffers through the Arrow C Data Interface.
+    fn to_arrow(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let record_batch = py.import("pyarrow")?.getattr("RecordBatch")?;
+        let mut exported = self.inner.to_arrow()?.export();
+        let imported = record_batch.call_method1(
+            "_import_from_c",
+            (exported.array_address(), exported.schema_address()),
+        )?;
+        Ok(imported.into())
+    }
+    // One of the model inputs of `to_dict` as a DLPack capsule of uint32, for
+    // `torch.from_dlpack(batch.to_dlpack("input_ids"))`. The tensor shares the memory of the
+    // matrix and keeps the batch alive until the consumer frees it once the tensor is dropped (or
+    // the capsule, if it's never consumed), so the batcher doesn't reuse the matrix meanwhile.
+    #[cfg(feature = "dlpack")]
+    fn to_dlpack(slf: &PyCell<Self>, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        let array = match name {
+            "input_ids" => Some(&this.inner.input_ids),
+            "attention_mask" => Some(&this.inner.attention_mask),
+            "token_type_ids" => Some(&this.inner.token_type_ids),
+            "global_attention_mask" => this.inner.global_attention_mask.as_ref(),
+            _ => None,
+        };
+        let array =
+            array.ok_or_else(|| PyKeyError::new_err(format!("The batch has no input {}", name)))?;
+        let owner: Py<BatchEncodingPy> = slf.into();
+        // No method changes the matrices, they are only handed back to the batcher once the
+        // object is dropped (see `Drop`).
+        let tensor = unsafe { DlManagedTensor::from_array2(array, Box::new(owner)) };
+        dlpack_capsule(py, tensor)
+    }
+}
+
+// Name of unconsumed DLPack capsules, consumers rename them to "used_dltensor".
+#[cfg(feature = "dlpack")]
+const DLTENSOR: &[u8] = b"dltensor\0";
+
+#[cfg(feature = "dlpack")]
+fn dlpack_capsule(py: Python<'_>, tensor: *mut DlManagedTensor) -> PyResult<PyObject> {
+    let name = DLTENSOR.as_ptr() as *const c_char;
+    unsafe {
+        let capsule = ffi::PyCapsule_New(tensor as *mut c_void, name, Some(delete_unconsumed));
+        if capsule.is_null() {
+            if let Some(deleter) = (*tensor).deleter {
+                deleter(tensor);
+            }
+            return Err(PyErr::fetch(py));
+        }
+        Ok(PyObject::from_owned_ptr(py, capsule))
+    }
+}
+
+// A consumed capsule belongs to the consumer, which calls the deleter itself.
+#[cfg(feature = "dlpack")]
+unsafe extern "C" fn delete_unconsumed(capsule: *mut ffi::PyObject) {
+    let name = DLTENSOR.as_ptr() as *const c_char;
+    if ffi::PyCapsule_IsValid(capsule, name) == 1 {
+        let tensor = ffi::PyCapsule_GetPointer(capsule, name) as *mut DlManagedTensor;
+        if let Some(deleter) = (*tensor).deleter {
+            deleter(tensor);
+        }
+    }
+}
+
+// Every getter copies and DLPack tensors keep the object alive, so nothing refers to the matrices
+// anymore once the object is dropped.
+impl Drop for BatchEncodingPy {
+    fn drop(&mut self) {
+        self.inner.recycle();
+    }
 }
 
 impl ToPyObjectConsume for BatchEncoding {
@@ -90,6 +520,127 @@ impl<T: BatchLabel> ToPyObjectConsume for Batch<T> {
     }
 }
 
+// Dense inputs become a `(batch, seq, vocab)` float32 array, sparse ones a tuple of the
+// `(tokens, 3)` indices and the dense shape.
+impl<T: BatchLabel> ToPyObjectConsume for OneHotBatch<T> {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let inputs = match self.inputs {
+            OneHotInputs::Dense(dense) => dense.into_pyarray(py).to_object(py),
+            OneHotInputs::Sparse { indices, shape } => {
+                (indices.into_pyarray(py), shape).to_object(py)
+            }
+        };
+        (
+            inputs,
+            self.batch.encoding.to_object_consume(py),
+            self.batch.labels.to_object_consume(py),
+        )
+            .to_object(py)
+    }
+}
+
+// The arrays are moved into the dict rather than copied.
+fn named_arrays_dict(arrays: NamedArrays, py: Python<'_>) -> &PyDict {
+    let dict = PyDict::new(py);
+    for (name, array) in arrays {
+        dict.set_item(name, array.into_pyarray(py))
+            .expect("Setting a dict item can't fail");
+    }
+    dict
+}
+
+impl ToPyObjectConsume for NamedBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        named_arrays_dict(self.0, py).to_object(py)
+    }
+}
+
+// The soft targets take the place of the labels of the batch.
+impl ToPyObjectConsume for SmoothedBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        (
+            self.encoding.to_object_consume(py),
+            self.targets.into_pyarray(py),
+        )
+            .to_object(py)
+    }
+}
+
+// Like a `NamedBatch`, with the standardized feature as a float array.
+impl ToPyObjectConsume for StandardizedBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let dict = named_arrays_dict(self.arrays, py);
+        dict.set_item(self.name, self.feature.into_pyarray(py))
+            .expect("Setting a dict item can't fail");
+        dict.to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for PairBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        (
+            self.anchors.to_object_consume(py),
+            self.positives.to_object_consume(py),
+            self.labels.into_pyarray(py).to_object(py),
+        )
+            .to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for DualBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        (
+            self.teacher.to_object_consume(py),
+            self.student.to_object_consume(py),
+        )
+            .to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for RetrievalBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        (
+            self.encoding.to_object_consume(py),
+            self.labels.into_pyarray(py).to_object(py),
+        )
+            .to_object(py)
+    }
+}
+
+// A dict of the `(batch, num_choices, seq_length)` input arrays, like the inputs of
+// `...ForMultipleChoice` models, and the labels.
+impl ToPyObjectConsume for MultipleChoiceBatch {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let inputs = PyDict::new(py);
+        for (name, array) in [
+            ("input_ids", self.input_ids),
+            ("attention_mask", self.attention_mask),
+            ("token_type_ids", self.token_type_ids),
+        ] {
+            inputs
+                .set_item(name, array.into_pyarray(py))
+                .expect("Setting a dict item can't fail");
+        }
+        (inputs, self.labels.into_pyarray(py)).to_object(py)
+    }
+}
+
+impl<T: TokenizedLabel> ToPyObjectConsume for RaggedBatch<T> {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let input_ids: Vec<PyObject> = self
+            .encodings
+            .into_iter()
+            .map(|encoding| encoding.input_ids.into_pyarray(py).to_object(py))
+            .collect();
+        let labels: Vec<PyObject> = self
+            .labels
+            .into_iter()
+            .map(|label| label.to_object_consume(py))
+            .collect();
+        (input_ids, labels).to_object(py)
+    }
+}
+
 impl ToPyObjectConsume for NoLabel {
     fn to_object_consume(self, py: Python<'_>) -> PyObject {
         let none: Option<()> = None;
@@ -97,6 +648,18 @@ impl ToPyObjectConsume for NoLabel {
     }
 }
 impl ToPyObjectConsume for Span {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.span.to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for Spans {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for TokenSpan {
     fn to_object_consume(self, py: Python<'_>) -> PyObject {
         self.0.to_object(py)
     }
@@ -115,6 +678,77 @@ impl ToPyObjectConsume for TokenizedSpan {
     }
 }
 
+impl ToPyObjectConsume for TokenizedSpans {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for ClassLabel {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for MultiLabel {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for StringLabel {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.to_object(py)
+    }
+}
+
+// A label pair, at every stage of the pipeline.
+impl<A: ToPyObjectConsume, B: ToPyObjectConsume> ToPyObjectConsume for Both<A, B> {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        let Both(first, second) = self;
+        (first.to_object_consume(py), second.to_object_consume(py)).into_py(py)
+    }
+}
+
+// The label, or `None` for unlabelled samples.
+impl<T: ToPyObjectConsume> ToPyObjectConsume for OptionalLabel<T> {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        match self.0 {
+            Some(label) => label.to_object_consume(py),
+            None => py.None(),
+        }
+    }
+}
+
+impl ToPyObjectConsume for BatchClassLabel {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.into_pyarray(py).to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for BatchLMLabels {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.into_pyarray(py).to_object(py)
+    }
+}
+
+impl ToPyObjectConsume for BatchMultiLabel {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        self.0.into_pyarray(py).to_object(py)
+    }
+}
+
+// A tuple of the labels and the mask of the labelled rows.
+impl<T: ToPyObjectConsume> ToPyObjectConsume for BatchOptionalLabel<T> {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        (
+            self.labels.to_object_consume(py),
+            self.present.into_pyarray(py).to_object(py),
+        )
+            .into_py(py)
+    }
+}
+
 impl ToPyObjectConsume for NoBatchLabel {
     fn to_object_consume(self, py: Python<'_>) -> PyObject {
         let none: Option<()> = None;
@@ -124,15 +758,30 @@ impl ToPyObjectConsume for NoBatchLabel {
 
 #[pyclass(name = "BatchSpan")]
 pub struct BatchSpanPy {
-    start: Array1<usize>,
-    end: Array1<usize>,
+    start: Array1<i64>,
+    end: Array1<i64>,
+    answers: Option<Vec<String>>,
+    answerable: Array1<i64>,
+    answer_char_start: Option<Array1<i64>>,
+    answer_char_end: Option<Array1<i64>>,
 }
 impl ToPyObjectConsume for BatchSpan {
     fn to_object_consume(self, py: Python<'_>) -> PyObject {
-        let BatchSpan { start, end } = self;
+        let BatchSpan {
+            start,
+            end,
+            answers,
+            answerable,
+            answer_char_start,
+            answer_char_end,
+        } = self;
         BatchSpanPy {
             start: start,
             end: end,
+            answers,
+            answerable,
+            answer_char_start,
+            answer_char_end,
         }
         .into_py(py)
     }
@@ -140,61 +789,384 @@ impl ToPyObjectConsume for BatchSpan {
 #[pymethods]
 impl BatchSpanPy {
     #[getter]
-    fn get_start(&self, py: Python<'_>) -> Py<PyArray1<usize>> {
+    fn get_start(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
         self.start.to_pyarray(py).to_owned()
     }
     #[getter]
-    fn get_end(&self, py: Python<'_>) -> Py<PyArray1<usize>> {
+    fn get_end(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
         self.end.to_pyarray(py).to_owned()
     }
-}
-
-struct NodeWrapper<T: ToPyObjectConsume>(Box<dyn Node<Output = T>>);
-
-impl<T: ToPyObjectConsume> Node for NodeWrapper<T> {
-    type Output = T;
-    fn get(&self, index: usize) -> Option<Self::Output> {
-        self.0.get(index)
+    // Same positions as `start` and `end` under the names `transformers` question answering
+    // models take them as.
+    #[getter]
+    fn get_start_positions(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
+        self.start.to_pyarray(py).to_owned()
     }
-    fn len(&self) -> Option<usize> {
-        self.0.len()
+    #[getter]
+    fn get_end_positions(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
+        self.end.to_pyarray(py).to_owned()
     }
-    fn next(&mut self) -> Option<Self::Output> {
-        self.0.next()
+    // The start and end positions as the columns of one `(batch, 2)` array.
+    #[getter]
+    fn get_positions(&self, py: Python<'_>) -> Py<PyArray2<i64>> {
+        numpy::ndarray::stack![Axis(1), self.start, self.end]
+            .to_pyarray(py)
+            .to_owned()
+    }
+    // Gold answer texts, `None` unless the loader keeps them, like
+    // `SQuADLoader(keep_answers=True)`.
+    #[getter]
+    fn get_answers(&self) -> Option<Vec<String>> {
+        self.answers.clone()
+    }
+    // 1 for the rows whose answer is in the encoding, e.g. as target of an answerability head.
+    #[getter]
+    fn get_answerable(&self, py: Python<'_>) -> Py<PyArray1<i64>> {
+        self.answerable.to_pyarray(py).to_owned()
+    }
+    // Character span of every answer in the context, `context[start:end]`, `None` unless the
+    // loader keeps them, like `SQuADLoader(char_spans=True)`. -100 for rows without an answer.
+    #[getter]
+    fn get_answer_char_start(&self, py: Python<'_>) -> Option<Py<PyArray1<i64>>> {
+        let start = self.answer_char_start.as_ref()?;
+        Some(start.to_pyarray(py).to_owned())
+    }
+    #[getter]
+    fn get_answer_char_end(&self, py: Python<'_>) -> Option<Py<PyArray1<i64>>> {
+        let end = self.answer_char_end.as_ref()?;
+        Some(end.to_pyarray(py).to_owned())
+    }
+}
+
+// Start and end token of every span, the ignore index (-100 by default) where a row has fewer
+// spans.
+#[pyclass(name = "BatchSpans")]
+pub struct BatchSpansPy {
+    inner: BatchSpans,
+}
+impl ToPyObjectConsume for BatchSpans {
+    fn to_object_consume(self, py: Python<'_>) -> PyObject {
+        BatchSpansPy { inner: self }.into_py(py)
+    }
+}
+#[pymethods]
+impl BatchSpansPy {
+    #[getter]
+    fn get_start(&self, py: Python<'_>) -> Py<PyArray2<i64>> {
+        self.inner.start.to_pyarray(py).to_owned()
+    }
+    #[getter]
+    fn get_end(&self, py: Python<'_>) -> Py<PyArray2<i64>> {
+        self.inner.end.to_pyarray(py).to_owned()
+    }
+}
+
+// Type erased node with outputs of type `T`, as returned by `NodePy::take`.
+pub struct NodeWrapper<T: ToPyObjectConsume>(Box<dyn Node<Output = T>>);
+
+impl<T: ToPyObjectConsume> Node for NodeWrapper<T> {
+    type Output = T;
+    fn get(&self, index: usize) -> Option<Self::Output> {
+        self.0.get(index)
+    }
+    fn len(&self) -> Option<usize> {
+        self.0.len()
+    }
+    fn next(&mut self) -> Option<Self::Output> {
+        self.0.next()
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        self.0.stats()
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        self.0.truncation_stats()
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        self.0.answer_retention()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.0.max_length()
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        self.0.tokenizer()
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        self.0.total_tokens()
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        self.0.lengths()
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        self.0.feature_stats()
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        self.0
+            .reconstruct(example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        self.0.window_ranges(example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        self.0.label_mapping()
+    }
+    fn set_epoch(&self, epoch: u64) {
+        self.0.set_epoch(epoch);
+    }
+    fn finalize(&mut self) -> bool {
+        self.0.finalize()
+    }
+    fn keep_texts(&mut self) -> bool {
+        self.0.keep_texts()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        self.0.estimated_len()
+    }
+    fn try_clone(&self) -> Option<Box<dyn Node<Output = Self::Output>>> {
+        self.0.try_clone()
     }
 }
 
 trait NodePyOutput {
     fn get(&self, index: usize, py: Python<'_>) -> Option<PyObject>;
+    fn get_many(&self, start: usize, count: usize, py: Python<'_>) -> Vec<PyObject>;
     fn len(&self) -> Option<usize>;
     fn next(&mut self, py: Python<'_>) -> Option<PyObject>;
+    fn peek(&mut self, py: Python<'_>) -> Option<PyObject>;
+    fn drain(&mut self, py: Python<'_>) -> DrainStats;
+    fn stats(&self) -> Option<BatchStats>;
+    fn truncation_stats(&self) -> Option<TruncationStats>;
+    fn answer_retention(&self) -> Option<AnswerRetention>;
+    fn total_tokens(&self) -> Option<u64>;
+    fn lengths(&self) -> Option<Array1<i64>>;
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)>;
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>>;
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>>;
+    fn label_mapping(&self) -> Option<HashMap<String, u32>>;
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>>;
+    fn size_hint(&self) -> (usize, Option<usize>);
+    fn estimated_len(&self) -> Option<usize>;
+    fn try_clone(&self) -> Option<Box<dyn NodePyOutput + Send>>;
+    fn set_epoch(&self, epoch: u64);
+    fn finalize(&mut self) -> bool;
+    fn shard(
+        self: Box<Self>,
+        index: usize,
+        num_shards: usize,
+    ) -> Result<Box<dyn NodePyOutput + Send>, PreprocessingError>;
     fn get_any(self: Box<Self>) -> Box<dyn Any>;
+    // `std::any::type_name` of the outputs, for the errors of incompatible constructors.
+    fn output_type_name(&self) -> &'static str;
 }
 
-impl<S: ToPyObjectConsume + 'static, T: Node<Output = S> + 'static> NodePyOutput for T {
+// Nodes are kept in a `Peekable`, so `peek` works for every node and a peeked sample is passed
+// on when the node is chained. The GIL is released while the Rust side of the pipeline produces
+// the next sample.
+impl<S: ToPyObjectConsume + Clone + 'static, T: Node<Output = S> + 'static> NodePyOutput
+    for Peekable<T>
+{
     fn next(&mut self, py: Python<'_>) -> Option<PyObject> {
-        match self.next() {
-            Some(output) => Some(output.to_object_consume(py)),
-            None => None,
-        }
+        py.allow_threads(|| Node::next(self))
+            .map(|output| output.to_object_consume(py))
+    }
+    fn peek(&mut self, py: Python<'_>) -> Option<PyObject> {
+        py.allow_threads(|| self.peek().cloned())
+            .map(|output| output.to_object_consume(py))
+    }
+    fn drain(&mut self, py: Python<'_>) -> DrainStats {
+        py.allow_threads(|| drain(self))
     }
     fn len(&self) -> Option<usize> {
-        self.len()
+        Node::len(self)
     }
     fn get(&self, index: usize, py: Python<'_>) -> Option<PyObject> {
-        match self.get(index) {
-            Some(output) => Some(output.to_object_consume(py)),
-            None => None,
-        }
+        Node::get(self, index).map(|output| output.to_object_consume(py))
+    }
+    fn get_many(&self, start: usize, count: usize, py: Python<'_>) -> Vec<PyObject> {
+        py.allow_threads(|| Node::get_many(self, start, count))
+            .into_iter()
+            .map(|output| output.to_object_consume(py))
+            .collect()
+    }
+    fn stats(&self) -> Option<BatchStats> {
+        Node::stats(self)
+    }
+    fn truncation_stats(&self) -> Option<TruncationStats> {
+        Node::truncation_stats(self)
+    }
+    fn answer_retention(&self) -> Option<AnswerRetention> {
+        Node::answer_retention(self)
+    }
+    fn total_tokens(&self) -> Option<u64> {
+        Node::total_tokens(self)
+    }
+    fn lengths(&self) -> Option<Array1<i64>> {
+        Node::lengths(self)
+    }
+    fn feature_stats(&self) -> Option<(Array1<f64>, Array1<f64>)> {
+        Node::feature_stats(self)
+    }
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        window: usize,
+        start_token: usize,
+        end_token: usize,
+    ) -> Option<Option<String>> {
+        Node::reconstruct(self, example_index, window, start_token, end_token)
+    }
+    fn window_ranges(&self, example_index: usize) -> Option<Vec<(usize, usize)>> {
+        Node::window_ranges(self, example_index)
+    }
+    fn label_mapping(&self) -> Option<HashMap<String, u32>> {
+        Node::label_mapping(self)
+    }
+    fn tokenizer(&self) -> Option<Arc<tokenizers::Tokenizer>> {
+        Node::tokenizer(self)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Node::size_hint(self)
+    }
+    fn estimated_len(&self) -> Option<usize> {
+        Node::estimated_len(self)
+    }
+    fn try_clone(&self) -> Option<Box<dyn NodePyOutput + Send>> {
+        let node = Node::try_clone(self)?;
+        Some(Box::new(Peekable::new(NodeWrapper(node))))
+    }
+    fn set_epoch(&self, epoch: u64) {
+        Node::set_epoch(self, epoch)
+    }
+    fn finalize(&mut self) -> bool {
+        Node::finalize(self)
+    }
+    fn shard(
+        self: Box<Self>,
+        index: usize,
+        num_shards: usize,
+    ) -> Result<Box<dyn NodePyOutput + Send>, PreprocessingError> {
+        // Type erased so that sharding again doesn't nest the type.
+        let node = Shard::new(NodeWrapper(Box::new(*self)), index, num_shards)?;
+        Ok(Box::new(Peekable::new(node)))
     }
     fn get_any(self: Box<Self>) -> Box<dyn Any> {
         Box::new(NodeWrapper(Box::new(*self)))
     }
+    fn output_type_name(&self) -> &'static str {
+        std::any::type_name::<S>()
+    }
+}
+
+// A type name without module paths, e.g. `Text<NoLabel>` for
+// `preprocessing::Text<preprocessing::NoLabel>`.
+pub(crate) fn short_type_name(name: &str) -> String {
+    let mut short = String::new();
+    let mut path = String::new();
+    for c in name.chars().chain(iter::once(' ')) {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+            continue;
+        }
+        short.push_str(path.rsplit("::").next().unwrap_or_default());
+        path.clear();
+        short.push(c);
+    }
+    short.pop();
+    short
+}
+
+// Error of a constructor given a node whose outputs aren't one of the `accepted` types.
+pub(crate) fn incompatible_node(
+    constructor: &str,
+    accepted: &[&str],
+    provided: &str,
+) -> PreprocessingError {
+    let accepted: Vec<String> = accepted.iter().map(|name| short_type_name(name)).collect();
+    let expected = match accepted.as_slice() {
+        [accepted] => format!("of type {}", accepted),
+        accepted => format!("of one of the types {}", accepted.join(", ")),
+    };
+    PreprocessingError::IncompatibleNode(format!(
+        "{} expects a node with outputs {}, got one with outputs of type {}",
+        constructor,
+        expected,
+        short_type_name(provided)
+    ))
 }
 
 #[pyclass(name = "Node")]
-struct NodePy {
+pub struct NodePy {
     inner: Option<Box<dyn NodePyOutput + Send>>,
+    // Timers of the `Timed` nodes of the pipeline, from upstream to downstream. Passed on to the
+    // node chained onto this one.
+    timers: Vec<Timer>,
+    anext: AsyncWorker,
+}
+
+// Used by downstream crates to add their own nodes, see the README.
+impl NodePy {
+    pub fn from_node<T: Node + 'static>(node: T) -> NodePy
+    where
+        T::Output: ToPyObjectConsume + Clone,
+    {
+        NodePy {
+            inner: Some(Box::new(Peekable::new(node))),
+            timers: Vec::new(),
+            anext: AsyncWorker::default(),
+        }
+    }
+    // Takes the node and its timers out for a constructor of the stage `adapters` selects from
+    // the registry. The node is consumed even if its output type isn't registered for the stage.
+    // `constructor` and `stage` name the function taking the node and its stage in errors.
+    fn take_stage<S: ?Sized>(
+        &mut self,
+        constructor: &str,
+        stage: &str,
+        adapters: fn(&Registry) -> &HashMap<TypeId, Adapter<S>>,
+    ) -> Result<(Box<S>, Vec<Timer>), PreprocessingError> {
+        let timers = std::mem::take(&mut self.timers);
+        let node = self.inner.take().ok_or(PreprocessingError::NodeConsumed)?;
+        let provided = node.output_type_name();
+        let node = node.get_any();
+        let adapter = adapters(&registry().read().unwrap())
+            .get(&(*node).type_id())
+            .copied();
+        match adapter {
+            Some(adapter) => Ok((adapter(node), timers)),
+            None => Err(PreprocessingError::IncompatibleNode(format!(
+                "{} expects a node of the {} stage, got one with outputs of type {}",
+                constructor,
+                stage,
+                short_type_name(provided)
+            ))),
+        }
+    }
+    // Takes the node out to chain another node onto it. Like for the built-in constructors, the
+    // node is consumed even if its outputs aren't of type `T`.
+    pub fn take<T: ToPyObjectConsume + 'static>(&mut self) -> PyResult<NodeWrapper<T>> {
+        let node = self.inner.take().ok_or(PreprocessingError::NodeConsumed)?;
+        let provided = node.output_type_name();
+        match node.get_any().downcast::<NodeWrapper<T>>() {
+            Ok(node) => Ok(*node),
+            Err(_) => {
+                Err(incompatible_node("The node", &[std::any::type_name::<T>()], provided).into())
+            }
+        }
+    }
 }
 
 #[pymethods]
@@ -204,87 +1176,1684 @@ impl NodePy {
     }
     fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
         match self {
-            NodePy { inner: None } => Err(PyErr::new::<PyBaseException, _>(
-                "This node is already in use by another node.",
-            )),
-            NodePy { inner: Some(node) } => Ok(node.next(py)),
+            NodePy { inner: None, .. } => Err(PreprocessingError::NodeConsumed.into()),
+            NodePy {
+                inner: Some(node), ..
+            } => read_node(py, || node.next(py)),
+        }
+    }
+    // The sample the next `__next__` returns, without advancing the node.
+    fn peek(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        match &mut self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => read_node(py, || node.peek(py)),
+        }
+    }
+    // Reads the rest of the node without creating Python objects, returns the number of outputs
+    // and the elapsed seconds.
+    fn drain(&mut self, py: Python<'_>) -> PyResult<(usize, f64)> {
+        match &mut self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => {
+                let stats = read_node(py, || node.drain(py))?;
+                Ok((stats.count, stats.elapsed.as_secs_f64()))
+            }
+        }
+    }
+    // Reads the rest of the node into memory, so the outputs can be indexed and iterated over
+    // any number of times. Like `drain`, the node stays in place for its statistics.
+    fn collect(&mut self, py: Python<'_>) -> PyResult<InMemoryDatasetPy> {
+        match &mut self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => {
+                let outputs = read_node(py, || std::iter::from_fn(|| node.next(py)).collect())?;
+                Ok(InMemoryDatasetPy { outputs })
+            }
+        }
+    }
+    // Outputs `start..start + count` of an indexable node in one call, with the GIL released
+    // while all of them are produced.
+    fn get_slice(&self, py: Python<'_>, start: usize, count: usize) -> PyResult<Vec<PyObject>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) if node.len().is_none() => Err(PreprocessingError::IncompatibleNode(
+                "get_slice requires an indexable node".to_string(),
+            )
+            .into()),
+            Some(node) => read_node(py, || node.get_many(start, count, py)),
+        }
+    }
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    // Returns an asyncio future resolved from the worker thread of the node (see `AsyncWorker`).
+    // Futures requested before the previous one is resolved are resolved in order.
+    fn __anext__(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let event_loop: PyObject = py
+            .import("asyncio")?
+            .call_method0("get_running_loop")?
+            .into();
+        let future = event_loop.call_method0(py, "create_future")?;
+        // Only borrowed once no Python code runs anymore, which could let the worker thread run
+        // and hand the node back.
+        let mut this = slf.borrow_mut();
+        let node = match this.inner.take() {
+            Some(node) => Some(node),
+            // The worker has the node and hands it back once all requests are resolved.
+            None if this.anext.busy => None,
+            None => return Err(PreprocessingError::NodeConsumed.into()),
+        };
+        this.anext.busy = true;
+        let requests = this
+            .anext
+            .requests
+            .get_or_insert_with(AsyncWorker::spawn)
+            .clone();
+        drop(this);
+        let request = AsyncRequest {
+            owner: slf.into(),
+            node,
+            event_loop,
+            future: future.clone_ref(py),
+        };
+        requests
+            .send(request)
+            .expect("The worker thread runs as long as its node");
+        Ok(Some(future))
+    }
+    fn padding_ratio(&self) -> PyResult<f64> {
+        Ok(self.batch_stats()?.padding_ratio())
+    }
+    fn avg_length(&self) -> PyResult<f64> {
+        Ok(self.batch_stats()?.avg_length())
+    }
+    fn max_length_seen(&self) -> PyResult<usize> {
+        Ok(self.batch_stats()?.max_length_seen())
+    }
+    // Number of sequences cut off by the `StaticBatcher` and the number of tokens removed.
+    fn truncation_stats(&self) -> PyResult<(usize, usize)> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => match node.truncation_stats() {
+                Some(stats) => Ok((stats.truncated_sequences(), stats.truncated_tokens())),
+                None => Err(PreprocessingError::IncompatibleNode(
+                    "Truncation statistics are only available for StaticBatcher nodes.".to_string(),
+                )
+                .into()),
+            },
+        }
+    }
+    // Fraction of the examples with an answer whose answer survived truncation, see
+    // `AnswerRetention`.
+    fn answer_retention_rate(&self) -> PyResult<f64> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => match node.answer_retention() {
+                Some(retention) => Ok(retention.rate()),
+                None => Err(PreprocessingError::IncompatibleNode(
+                    "Answer retention is only tracked by Tokenizer nodes.".to_string(),
+                )
+                .into()),
+            },
+        }
+    }
+    // Non-padding tokens produced so far, see `Node::total_tokens`.
+    fn total_tokens(&self) -> PyResult<u64> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => node.total_tokens().ok_or_else(|| {
+                PreprocessingError::IncompatibleNode(
+                    "Tokens are only counted by Tokenizer and StaticBatcher nodes.".to_string(),
+                )
+                .into()
+            }),
+        }
+    }
+    // Name, seconds and seconds excluding the previous stage of every `Timed` node of the
+    // pipeline, from upstream to downstream. The time of a stage includes its upstream, so the
+    // exclusive time subtracts the `Timed` node before it, which assumes a linear pipeline.
+    fn timings(&self) -> Vec<(String, f64, f64)> {
+        let mut previous = Duration::ZERO;
+        self.timers
+            .iter()
+            .map(|timer| {
+                let elapsed = timer.elapsed();
+                let exclusive = elapsed.saturating_sub(previous);
+                previous = elapsed;
+                (
+                    timer.name().to_string(),
+                    elapsed.as_secs_f64(),
+                    exclusive.as_secs_f64(),
+                )
+            })
+            .collect()
+    }
+    fn lengths(&self, py: Python<'_>) -> PyResult<Py<PyArray1<i64>>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => match node.lengths() {
+                Some(lengths) => Ok(lengths.to_pyarray(py).to_owned()),
+                None => Err(PreprocessingError::IncompatibleNode(
+                    "Lengths are only available for indexable tokenizer nodes.".to_string(),
+                )
+                .into()),
+            },
+        }
+    }
+    // Mean and standard deviation of every feature of a `Standardize` node, e.g. to persist them
+    // and pass them to the `Standardize` node of the evaluation pipeline.
+    fn feature_stats(&self, py: Python<'_>) -> PyResult<PyObject> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => match node.feature_stats() {
+                Some((mean, std)) => Ok((mean.to_pyarray(py), std.to_pyarray(py)).into_py(py)),
+                None => Err(PreprocessingError::IncompatibleNode(
+                    "Feature statistics are only available for Standardize nodes.".to_string(),
+                )
+                .into()),
+            },
+        }
+    }
+    // Bucket edges and counts of the token lengths, see `nodes::length_histogram`. Tokenizes the
+    // whole upstream once, so it needs an indexable tokenizer node (or one wrapping it).
+    #[args(buckets = "10")]
+    fn length_histogram(&self, py: Python<'_>, buckets: usize) -> PyResult<PyObject> {
+        let lengths = self.lengths(py)?;
+        let lengths = lengths.as_ref(py).to_owned_array();
+        let (edges, counts) = length_histogram(&lengths, buckets)?;
+        Ok((edges.to_pyarray(py), counts.to_pyarray(py)).into_py(py))
+    }
+    // Answer text of the (inclusive) token span, `None` if the tokens can't be mapped back.
+    #[args(window = "0")]
+    fn reconstruct(
+        &self,
+        example_index: usize,
+        start_token: usize,
+        end_token: usize,
+        window: usize,
+    ) -> PyResult<Option<String>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => node
+                .reconstruct(example_index, window, start_token, end_token)
+                .ok_or_else(|| {
+                    PreprocessingError::IncompatibleNode(
+                        "Reconstructing answers requires an indexable tokenizer in the pipeline."
+                            .to_string(),
+                    )
+                    .into()
+                }),
+        }
+    }
+    // `(start, end)` context tokens of every overflowing window of an example, see
+    // `Node::window_ranges`.
+    fn window_ranges(&self, example_index: usize) -> PyResult<Vec<(usize, usize)>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => node.window_ranges(example_index).ok_or_else(|| {
+                PreprocessingError::IncompatibleNode(
+                    "Window ranges require an indexable tokenizer in the pipeline.".to_string(),
+                )
+                .into()
+            }),
+        }
+    }
+    // Lower and upper bound on the number of outputs, see `Node::size_hint`.
+    fn size_hint(&self) -> PyResult<(usize, Option<usize>)> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => Ok(node.size_hint()),
+        }
+    }
+    // Estimated number of outputs, e.g. the total of a progress bar, see `Node::estimated_len`.
+    fn estimated_len(&self) -> PyResult<Option<usize>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => Ok(node.estimated_len()),
+        }
+    }
+    // Reseeds the stochastic nodes of the pipeline for a new epoch, see `seed::epoch_seed`. A
+    // peeked sample and the samples a `DataLoader` already built keep the previous epoch.
+    fn set_epoch(&self, epoch: u64) -> PyResult<()> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => {
+                node.set_epoch(epoch);
+                Ok(())
+            }
         }
     }
+    // Makes the buffering nodes of the pipeline emit what they hold back without reading further,
+    // see `Node::finalize`. Returns whether the pipeline contains a buffering node.
+    fn finalize(&mut self) -> PyResult<bool> {
+        match &mut self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => Ok(node.finalize()),
+        }
+    }
+    // Restricts the node to the share of one PyTorch `DataLoader` worker, see `Shard` and the
+    // README. Meant to be called from a `worker_init_fn`, before the node is read.
+    fn configure_worker(&mut self, worker_id: usize, num_workers: usize) -> PyResult<()> {
+        if worker_id >= num_workers {
+            return Err(PreprocessingError::InvalidArgument(format!(
+                "Worker {} doesn't exist with {} workers",
+                worker_id, num_workers
+            ))
+            .into());
+        }
+        let node = self.inner.take().ok_or(PreprocessingError::NodeConsumed)?;
+        self.inner = Some(node.shard(worker_id, num_workers)?);
+        Ok(())
+    }
+    // Independent copy of the node that reads from the start, so several pipelines can be built
+    // on the same data. Only supported by nodes that share their data, like `SQuADLoader`.
+    fn clone(&self) -> PyResult<NodePy> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => match node.try_clone() {
+                Some(inner) => Ok(NodePy {
+                    inner: Some(inner),
+                    timers: Vec::new(),
+                    anext: AsyncWorker::default(),
+                }),
+                None => Err(PreprocessingError::IncompatibleNode(
+                    "This node can't be cloned.".to_string(),
+                )
+                .into()),
+            },
+        }
+    }
+    // Label names of class ids, using the mapping of the `LabelEncoder` of the pipeline.
+    fn decode_labels(&self, ids: Vec<u32>) -> PyResult<Vec<String>> {
+        let mapping = self.label_mapping()?;
+        Ok(decode_labels(&mapping, &Array1::from_vec(ids))?)
+    }
+    // Label to id dictionary learned (or given) by the `LabelEncoder` of the pipeline.
+    fn label_mapping(&self) -> PyResult<HashMap<String, u32>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => node.label_mapping().ok_or_else(|| {
+                PreprocessingError::IncompatibleNode(
+                    "The pipeline doesn't contain a LabelEncoder.".to_string(),
+                )
+                .into()
+            }),
+        }
+    }
+    // Vocabulary size of the tokenizer of the pipeline, added tokens included, e.g. for the
+    // embedding layer of a model.
+    fn vocab_size(&self) -> PyResult<usize> {
+        Ok(self.hf_tokenizer()?.get_vocab_size(true))
+    }
+    // Ids of the special tokens of the tokenizer of the pipeline, `None` if it has no such token,
+    // see `SpecialTokenIds`.
+    fn pad_token_id(&self) -> PyResult<Option<u32>> {
+        Ok(self.special_token_ids()?.pad)
+    }
+    fn cls_token_id(&self) -> PyResult<Option<u32>> {
+        Ok(self.special_token_ids()?.cls)
+    }
+    fn sep_token_id(&self) -> PyResult<Option<u32>> {
+        Ok(self.special_token_ids()?.sep)
+    }
+    fn mask_token_id(&self) -> PyResult<Option<u32>> {
+        Ok(self.special_token_ids()?.mask)
+    }
 }
 
-macro_rules! add_node_constructor {
-    ($rust_constructor_name:ident: $py_constructor_name:expr => (node: &mut $input_node:ty, $($arg_name:ident: $arg_type:ty,)*) => $node_type_rust:ty { $($input_type:ty),+ }) => {
-        #[pyfunction(name = $py_constructor_name)]
-        fn $rust_constructor_name(node: &mut $input_node, $($arg_name: $arg_type,)*) -> PyResult<NodePy> {
-            #[allow(unused_assignments)] {
-                match node.inner.take() {
-                    None => {
-                        return Err(PyErr::new::<PyBaseException, _>(
-                            "This node is already in use by another node.",
-                        ))
-                    },
-                    Some(node) => {
-                        let mut node = node.get_any();
-                        add_node_constructor!(call node, $node_type_rust, ($($input_type,)+), ($($arg_name),*));
+impl NodePy {
+    fn batch_stats(&self) -> PyResult<BatchStats> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => node.stats().ok_or_else(|| {
+                PreprocessingError::IncompatibleNode(
+                    "This node doesn't collect batch statistics.".to_string(),
+                )
+                .into()
+            }),
+        }
+    }
+    fn special_token_ids(&self) -> PyResult<SpecialTokenIds> {
+        let tokenizer = self.hf_tokenizer()?;
+        Ok(SpecialTokenIds::of(&tokenizer))
+    }
+    fn hf_tokenizer(&self) -> PyResult<Arc<tokenizers::Tokenizer>> {
+        match &self.inner {
+            None => Err(PreprocessingError::NodeConsumed.into()),
+            Some(node) => node.tokenizer().ok_or_else(|| {
+                PreprocessingError::IncompatibleNode(
+                    "The pipeline doesn't contain a Tokenizer.".to_string(),
+                )
+                .into()
+            }),
+        }
+    }
+}
+
+// Outputs of a node collected with `Node.collect`. Indexing returns the stored objects, so
+// iterating again gives the same objects rather than rebuilding them.
+#[pyclass(name = "InMemoryDataset")]
+struct InMemoryDatasetPy {
+    outputs: Vec<PyObject>,
+}
+
+#[pymethods]
+impl InMemoryDatasetPy {
+    fn __len__(&self) -> usize {
+        self.outputs.len()
+    }
+    // Negative indices count from the end, like for lists.
+    fn __getitem__(&self, py: Python<'_>, index: isize) -> PyResult<PyObject> {
+        let position = if index < 0 {
+            index + self.outputs.len() as isize
+        } else {
+            index
+        };
+        usize::try_from(position)
+            .ok()
+            .and_then(|position| self.outputs.get(position))
+            .map(|output| output.clone_ref(py))
+            .ok_or_else(|| PyIndexError::new_err("InMemoryDataset index out of range"))
+    }
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyAny::iter(PyList::new(py, &self.outputs))?.into())
+    }
+}
+
+// The thread producing the outputs of `__anext__` of a node, started by its first call. The node
+// is handed to the thread with the first request and handed back once no request is left, so it
+// is only missing from its `NodePy` while futures are pending. It is handed back even if the node
+// panics, the panic is raised by the future instead.
+#[derive(Default)]
+struct AsyncWorker {
+    requests: Option<mpsc::Sender<AsyncRequest>>,
+    // Whether the thread has the node.
+    busy: bool,
+}
+
+struct AsyncRequest {
+    owner: Py<NodePy>,
+    // Only sent with the first request while the thread doesn't have the node yet.
+    node: Option<Box<dyn NodePyOutput + Send>>,
+    event_loop: PyObject,
+    future: PyObject,
+}
+
+impl AsyncWorker {
+    // The thread ends once the `NodePy` and with it the sender is dropped.
+    fn spawn() -> mpsc::Sender<AsyncRequest> {
+        let (sender, receiver) = mpsc::channel::<AsyncRequest>();
+        thread::spawn(move || {
+            let mut node = None;
+            while let Ok(request) = receiver.recv() {
+                // Requests queued meanwhile are handled without giving the GIL to `__anext__` in
+                // between, so it doesn't see the node handed back while a request is still queued.
+                let mut request = Some(request);
+                Python::with_gil(|py| {
+                    while let Some(mut current) = request.take() {
+                        let mut owned = current
+                            .node
+                            .take()
+                            .or_else(|| node.take())
+                            .expect("The node is sent with the first request");
+                        // The GIL is released inside `next` while the sample is produced.
+                        let output = panic::catch_unwind(AssertUnwindSafe(|| owned.next(py)))
+                            .map_err(caught_panic);
+                        let output = raise_warnings(py).and(output);
+                        // Handed back before the future is resolved, so the node is in place
+                        // when the code awaiting it continues.
+                        request = receiver.try_recv().ok();
+                        match request {
+                            Some(_) => node = Some(owned),
+                            None => {
+                                let mut owner = current.owner.borrow_mut(py);
+                                owner.inner = Some(owned);
+                                owner.anext.busy = false;
+                            }
+                        }
+                        resolve(py, &current, output);
                     }
-                }
+                });
             }
-            return Err(PyErr::new::<PyBaseException, _>(
-                "The provided input node is not compatible :(",
-            ))
-        }
+        });
+        sender
+    }
+}
+
+// Resolves the future on its event loop, which fails if the loop is closed.
+fn resolve(py: Python<'_>, request: &AsyncRequest, output: PyResult<Option<PyObject>>) {
+    let output = match output {
+        Ok(Some(output)) => (Some(output), None),
+        Ok(None) => (None, Some(PyStopAsyncIteration::new_err(()).into_py(py))),
+        Err(err) => (None, Some(err.into_py(py))),
+    };
+    let resolved = wrap_pyfunction!(resolve_future, py).and_then(|resolve| {
+        request.event_loop.call_method1(
+            py,
+            "call_soon_threadsafe",
+            (resolve, request.future.clone_ref(py), output.0, output.1),
+        )
+    });
+    if let Err(err) = resolved {
+        err.print(py);
+    }
+}
+
+// Runs on the event loop. The future might have been cancelled while the sample was produced.
+#[pyfunction]
+fn resolve_future(
+    future: &PyAny,
+    output: Option<PyObject>,
+    exception: Option<PyObject>,
+) -> PyResult<()> {
+    if future.call_method0("done")?.is_true()? {
+        return Ok(());
+    }
+    match exception {
+        Some(exception) => future.call_method1("set_exception", (exception,))?,
+        None => future.call_method1("set_result", (output,))?,
+    };
+    Ok(())
+}
+
+// Arguments can be given Python defaults with `name: type = "default"`. Instead of a node type,
+// `fn constructor` builds the node by calling `constructor(node, args...)`. Loaders built by a
+// `fn constructor` can take the arguments after `*,` as keyword-only arguments, which all need
+// defaults.
+macro_rules! add_node_constructor {
+    ($rust_constructor_name:ident: $py_constructor_name:expr => (node: &mut $input_node:ty, $($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => fn $constructor:ident { $($input_type:ty),+ }) => {
+        add_node_constructor!(chain $rust_constructor_name: $py_constructor_name => (node: &mut $input_node, $($arg_name: $arg_type $(= $default)?,)*) => ($constructor) { $($input_type),+ });
+    };
+    ($rust_constructor_name:ident: $py_constructor_name:expr => (node: &mut $input_node:ty, $($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => $node_type_rust:ty { $($input_type:ty),+ }) => {
+        add_node_constructor!(chain $rust_constructor_name: $py_constructor_name => (node: &mut $input_node, $($arg_name: $arg_type $(= $default)?,)*) => (<$node_type_rust>::new) { $($input_type),+ });
+    };
+    ($rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => fn $constructor:ident) => {
+        add_node_constructor!(loader $rust_constructor_name: $py_constructor_name => ($($arg_name: $arg_type $(= $default)?,)*) => ($constructor));
+    };
+    ($rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty,)* *, $($kw_name:ident: $kw_type:ty = $kw_default:literal,)*) => fn $constructor:ident) => {
+        add_node_constructor!(loader $rust_constructor_name: $py_constructor_name => ($($arg_name: $arg_type,)*) * ($($kw_name: $kw_type = $kw_default,)*) => ($constructor));
+    };
+    ($rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => $node_type_rust:tt) => {
+        add_node_constructor!(loader $rust_constructor_name: $py_constructor_name => ($($arg_name: $arg_type $(= $default)?,)*) => (<$node_type_rust>::new));
     };
-    ($rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty,)*) => $node_type_rust:tt) => {
-        #[pyfunction(name = $py_constructor_name)]
+    (loader $rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => ($($constructor:tt)+)) => {
+        #[allow(clippy::too_many_arguments)]
+        #[pyfunction(name = $py_constructor_name $($(, $arg_name = $default)?)*)]
         fn $rust_constructor_name($($arg_name: $arg_type,)*) -> PyResult<NodePy> {
-            match <$node_type_rust>::new($($arg_name,)*) {
-                Err(err) => Err(PyErr::new::<PyBaseException, _>(format!("{}", err))),
+            match $($constructor)+($($arg_name,)*) {
+                Err(err) => Err(PreprocessingError::from(err).into()),
+                Ok(node) => Ok(NodePy {
+                    inner: Some(Box::new(Peekable::new(node))),
+                    timers: Vec::new(),
+                    anext: AsyncWorker::default(),
+                }),
+            }
+        }
+    };
+    (loader $rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty,)*) * ($($kw_name:ident: $kw_type:ty = $kw_default:literal,)*) => ($($constructor:tt)+)) => {
+        #[allow(clippy::too_many_arguments)]
+        #[pyfunction(name = $py_constructor_name, "*" $(, $kw_name = $kw_default)*)]
+        fn $rust_constructor_name($($arg_name: $arg_type,)* $($kw_name: $kw_type,)*) -> PyResult<NodePy> {
+            match $($constructor)+($($arg_name,)* $($kw_name,)*) {
+                Err(err) => Err(PreprocessingError::from(err).into()),
                 Ok(node) => Ok(NodePy {
-                    inner: Some(Box::new(node)),
+                    inner: Some(Box::new(Peekable::new(node))),
+                    timers: Vec::new(),
+                    anext: AsyncWorker::default(),
                 }),
             }
         }
     };
-    (call $node:ident, $node_type_rust:ty, ($($input_type:ty,)+), $args:tt) => {
+    (chain $rust_constructor_name:ident: $py_constructor_name:expr => (node: &mut $input_node:ty, $($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => ($($constructor:tt)+) { $($input_type:ty),+ }) => {
+        // Takes the same arguments as the Python constructor, however many there are.
+        #[allow(clippy::too_many_arguments)]
+        #[pyfunction(name = $py_constructor_name $($(, $arg_name = $default)?)*)]
+        fn $rust_constructor_name(node: &mut $input_node, $($arg_name: $arg_type,)*) -> PyResult<NodePy> {
+            let timers = std::mem::take(&mut node.timers);
+            let node = node.inner.take().ok_or(PreprocessingError::NodeConsumed)?;
+            let provided = node.output_type_name();
+            let node = node.get_any();
+            // Returns if one of the types matches, the node of none of them is dropped.
+            let _rejected: Box<dyn Any> = add_node_constructor!(call node, timers, ($($constructor)+), ($($input_type,)+), ($($arg_name),*));
+            let accepted = [$(std::any::type_name::<$input_type>()),+];
+            Err(incompatible_node($py_constructor_name, &accepted, provided).into())
+        }
+    };
+    (call $node:ident, $timers:ident, $constructor:tt, ($($input_type:ty,)+), $args:tt) => {{
         $(
-            match $node.downcast::<NodeWrapper<$input_type>>() {
+            let $node = match $node.downcast::<NodeWrapper<$input_type>>() {
                 Ok(node) => {
                     let node = *node;
-                    return match add_node_constructor!(hi node, $node_type_rust, $args) {
-                        Err(err) => Err(PyErr::new::<PyBaseException, _>(format!("{}", err))),
+                    return match add_node_constructor!(hi node, $constructor, $args) {
+                        Err(err) => Err(PreprocessingError::from(err).into()),
                         Ok(tokenizer) => {
+                            let mut timers = $timers;
+                            timers.extend(tokenizer.timer());
                             Ok(NodePy {
-                                inner: Some(Box::new(tokenizer)),
+                                inner: Some(Box::new(Peekable::new(tokenizer))),
+                                timers,
+                                anext: AsyncWorker::default(),
                             })
                         }
                     }
                 }
-                Err(node) => {
-                    $node = node;
+                Err(node) => node,
+            };
+        )+
+        $node
+    }};
+    (hi $node:ident, ($($constructor:tt)+), ($($arg_name:ident),*)) => {
+        $($constructor)+($node, $($arg_name,)*)
+    };
+}
+
+// Constructors of the nodes that accept all registered types of a stage of the pipeline, e.g.
+// the tokenizers accept samples with any label. A stage is a trait with a method per constructor,
+// implemented for the `NodeWrapper`s of all types that satisfy its bounds. The `Registry` maps the
+// type ids of the registered `NodeWrapper`s to adapters that turn the type erased node back into
+// the trait object of the stage, so a new sample type only has to be registered once (see
+// `register_sample`) instead of being added to the type list of every constructor.
+macro_rules! add_stage {
+    ($adapters:ident: impl<$param:ident> $stage:ident $(: $supertrait:ident)? for $node:ty where ($($bounds:tt)+) {
+        $($rust_constructor_name:ident: $py_constructor_name:expr => ($($arg_name:ident: $arg_type:ty $(= $default:literal)?,)*) => $constructor:expr;)+
+    }) => {
+        trait $stage $(: $supertrait)? {
+            $(
+                #[allow(clippy::too_many_arguments)]
+                fn $rust_constructor_name(self: Box<Self>, timers: Vec<Timer>, $($arg_name: $arg_type,)*) -> Result<NodePy, PreprocessingError>;
+            )+
+        }
+        impl<$param> $stage for $node where $($bounds)+ {
+            $(
+                fn $rust_constructor_name(self: Box<Self>, mut timers: Vec<Timer>, $($arg_name: $arg_type,)*) -> Result<NodePy, PreprocessingError> {
+                    let node = ($constructor)(*self, $($arg_name,)*)?;
+                    timers.extend(node.timer());
+                    Ok(NodePy {
+                        inner: Some(Box::new(Peekable::new(node))),
+                        timers,
+                        anext: AsyncWorker::default(),
+                    })
                 }
+            )+
+        }
+        $(
+            // Takes the same arguments as the Python constructor, however many there are.
+            #[allow(clippy::too_many_arguments)]
+            #[pyfunction(name = $py_constructor_name $($(, $arg_name = $default)?)*)]
+            fn $rust_constructor_name(node: &mut NodePy, $($arg_name: $arg_type,)*) -> PyResult<NodePy> {
+                let (node, timers) = node.take_stage($py_constructor_name, stringify!($adapters), |registry| &registry.$adapters)?;
+                Ok(node.$rust_constructor_name(timers, $($arg_name,)*)?)
             }
         )+
     };
-    (hi $node:ident, $node_type_rust:ty, ($($arg_name:ident),*)) => {
-        <$node_type_rust>::new($node, $($arg_name,)*)
+}
+
+type Adapter<S> = fn(Box<dyn Any>) -> Box<S>;
+
+// The adapters of every stage, keyed by the type id of the `NodeWrapper` of the registered type.
+// Every type is in `outputs`, for the nodes that accept any input.
+#[derive(Default)]
+struct Registry {
+    outputs: HashMap<TypeId, Adapter<dyn OutputNode>>,
+    samples: HashMap<TypeId, Adapter<dyn SampleNode>>,
+    tokenized: HashMap<TypeId, Adapter<dyn TokenizedNode>>,
+    batches: HashMap<TypeId, Adapter<dyn BatchNode>>,
+}
+
+fn unwrap_node<T: ToPyObjectConsume + 'static>(node: Box<dyn Any>) -> Box<NodeWrapper<T>> {
+    node.downcast()
+        .expect("Adapters are registered under the type id of their node")
+}
+
+impl Registry {
+    // The outputs of the built-in nodes. The samples bring their tokenized samples and batches.
+    fn builtin() -> Registry {
+        let mut registry = Registry::default();
+        registry.add_sample::<Text<NoLabel>>();
+        registry.add_sample::<Text<Span>>();
+        registry.add_sample::<Text<ClassLabel>>();
+        registry.add_sample::<Text<TokenSpan>>();
+        registry.add_sample::<Text<MultiLabel>>();
+        registry.add_sample::<Text<OptionalLabel<ClassLabel>>>();
+        registry.add_sample::<Text<Spans>>();
+        registry.add_sample::<TextPair<NoLabel>>();
+        registry.add_sample::<TextPair<Span>>();
+        registry.add_sample::<TextPair<ClassLabel>>();
+        registry.add_sample::<TextPair<MultiLabel>>();
+        registry.add_sample::<TextPair<OptionalLabel<ClassLabel>>>();
+        registry.add_sample::<TextPair<Spans>>();
+        registry.add_sample::<TextPair<Both<Span, ClassLabel>>>();
+        registry.add_batch::<BatchLMLabels>();
+        registry.add_output::<Text<StringLabel>>();
+        registry.add_output::<Ids>();
+        registry.add_output::<SmoothedBatch>();
+        registry
+    }
+    fn add_output<T: ToPyObjectConsume + Clone + 'static>(&mut self) {
+        let adapter: Adapter<dyn OutputNode> = |node| unwrap_node::<T>(node);
+        self.outputs.insert(TypeId::of::<NodeWrapper<T>>(), adapter);
+    }
+    fn add_sample<S>(&mut self)
+    where
+        S: Sample + ToPyObjectConsume + Clone + 'static,
+        <S::Label as Label>::Tokenized: 'static,
+        <<S::Label as Label>::Tokenized as TokenizedLabel>::Batch: 'static,
+    {
+        let adapter: Adapter<dyn SampleNode> = |node| unwrap_node::<S>(node);
+        self.samples.insert(TypeId::of::<NodeWrapper<S>>(), adapter);
+        self.add_output::<S>();
+        self.add_tokenized::<<S::Label as Label>::Tokenized>();
+    }
+    fn add_tokenized<S: TokenizedLabel + 'static>(&mut self)
+    where
+        S::Batch: 'static,
+    {
+        let adapter: Adapter<dyn TokenizedNode> = |node| unwrap_node::<TokenizedText<S>>(node);
+        self.tokenized
+            .insert(TypeId::of::<NodeWrapper<TokenizedText<S>>>(), adapter);
+        self.add_output::<TokenizedText<S>>();
+        self.add_output::<RaggedBatch<S>>();
+        self.add_batch::<S::Batch>();
+    }
+    fn add_batch<S: BatchLabel + 'static>(&mut self) {
+        let adapter: Adapter<dyn BatchNode> = |node| unwrap_node::<Batch<S>>(node);
+        self.batches
+            .insert(TypeId::of::<NodeWrapper<Batch<S>>>(), adapter);
+        self.add_output::<Batch<S>>();
+    }
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(Registry::builtin()))
+}
+
+// Makes the built-in constructors accept samples of type `S` (e.g. with a label type of a
+// downstream crate), and their tokenized samples and batches. Registering a type again has no
+// effect.
+pub fn register_sample<S>()
+where
+    S: Sample + ToPyObjectConsume + Clone + 'static,
+    <S::Label as Label>::Tokenized: 'static,
+    <<S::Label as Label>::Tokenized as TokenizedLabel>::Batch: 'static,
+{
+    registry().write().unwrap().add_sample::<S>();
+}
+
+// Like `register_sample` for batches of a downstream batcher.
+pub fn register_batch<S: BatchLabel + 'static>() {
+    registry().write().unwrap().add_batch::<S>();
+}
+
+// Like `register_sample` for other outputs, which only the nodes accepting any input (like
+// `DataLoader`) accept.
+pub fn register_output<T: ToPyObjectConsume + Clone + 'static>() {
+    registry().write().unwrap().add_output::<T>();
+}
+
+type TeeHandles = (Box<dyn NodePyOutput + Send>, Box<dyn NodePyOutput + Send>);
+
+trait TeeNode {
+    fn tee(self: Box<Self>, buffer_size: usize) -> Result<TeeHandles, PreprocessingError>;
+}
+
+impl<T: ToPyObjectConsume + Clone + 'static> TeeNode for NodeWrapper<T> {
+    fn tee(self: Box<Self>, buffer_size: usize) -> Result<TeeHandles, PreprocessingError> {
+        let (first, second) = Tee::new(*self, buffer_size)?;
+        Ok((
+            Box::new(Peekable::new(first)),
+            Box::new(Peekable::new(second)),
+        ))
+    }
+}
+
+trait MmapNode {
+    fn write_mmap(
+        self: Box<Self>,
+        path: String,
+        py: Python<'_>,
+    ) -> Result<(usize, usize), PreprocessingError>;
+}
+
+impl<S: BatchLabel + 'static> MmapNode for NodeWrapper<Batch<S>> {
+    fn write_mmap(
+        self: Box<Self>,
+        path: String,
+        py: Python<'_>,
+    ) -> Result<(usize, usize), PreprocessingError> {
+        let mut node = *self;
+        py.allow_threads(|| write_mmap(&mut node, Path::new(&path)))
+    }
+}
+
+fn new_txt_loader(
+    filename: String,
+    on_invalid_utf8: InvalidUtf8,
+    trim: LineTrim,
+) -> io::Result<TxtLoader> {
+    Ok(TxtLoader::new(filename, on_invalid_utf8)?.with_trim(trim))
+}
+
+fn new_labelled_txt_loader(
+    filename: String,
+    separator: String,
+    on_invalid_utf8: InvalidUtf8,
+    on_malformed: MalformedPolicy,
+) -> io::Result<LabelledTxtLoader> {
+    Ok(
+        LabelledTxtLoader::new(filename, separator, on_invalid_utf8)?
+            .with_on_malformed(on_malformed),
+    )
+}
+
+fn new_paired_files_loader(
+    text_file: String,
+    label_file: String,
+    mapping: Option<HashMap<String, u32>>,
+    on_invalid_utf8: InvalidUtf8,
+    on_malformed: MalformedPolicy,
+) -> io::Result<PairedFilesLoader> {
+    let loader = PairedFilesLoader::new(text_file, label_file, mapping, on_invalid_utf8)?;
+    Ok(loader.with_on_malformed(on_malformed))
+}
+
+// Glue for the keyword arguments of the Python constructor, the Rust loader takes its options
+// through builders.
+#[allow(clippy::too_many_arguments)]
+fn new_squad_loader(
+    filename: String,
+    end_inclusive: bool,
+    normalize_answers: bool,
+    max_questions_per_context: Option<usize>,
+    split_context: Option<usize>,
+    keep_answers: bool,
+    trim_answers: bool,
+    snap_window: usize,
+    answer_policy: AnswerPolicy,
+    impossible_to_cls: bool,
+    char_spans: bool,
+) -> Result<SQuADLoader, PreprocessingError> {
+    new_squad_loader_multi(
+        vec![filename],
+        end_inclusive,
+        normalize_answers,
+        max_questions_per_context,
+        split_context,
+        keep_answers,
+        trim_answers,
+        snap_window,
+        answer_policy,
+        impossible_to_cls,
+        char_spans,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_squad_loader_multi(
+    filenames: Vec<String>,
+    end_inclusive: bool,
+    normalize_answers: bool,
+    max_questions_per_context: Option<usize>,
+    split_context: Option<usize>,
+    keep_answers: bool,
+    trim_answers: bool,
+    snap_window: usize,
+    answer_policy: AnswerPolicy,
+    impossible_to_cls: bool,
+    char_spans: bool,
+) -> Result<SQuADLoader, PreprocessingError> {
+    let mut loader = SQuADLoader::from_paths(filenames)?
+        .with_end_inclusive(end_inclusive)
+        .with_normalize_answers(normalize_answers)
+        .with_keep_answers(keep_answers)
+        .with_trim_answers(trim_answers)
+        .with_answer_policy(answer_policy)
+        .with_snap_window(snap_window)
+        .with_impossible_to_cls(impossible_to_cls)
+        .with_char_spans(char_spans);
+    if let Some(max_questions) = max_questions_per_context {
+        loader = loader.with_max_questions_per_context(max_questions);
+    }
+    if let Some(max_chars) = split_context {
+        loader = loader.with_split_context(max_chars)?;
+    }
+    Ok(loader)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_tokenizer<T: Node>(
+    node: T,
+    tokenizer: String,
+    max_length: Option<usize>,
+    stride: usize,
+    overflow: bool,
+    pad_token: Option<u32>,
+    encode_batch_size: usize,
+    truncation_side: TruncationSide,
+    separator: Option<String>,
+    cls_token: Option<String>,
+    eos_token: Option<String>,
+    retries: u32,
+    retry_delay: f64,
+    timeout: Option<f64>,
+    on_timeout: TimeoutPolicy,
+    longer_second: bool,
+    revision: Option<String>,
+    normalize: bool,
+) -> Result<Tokenizer<T>, PreprocessingError>
+where
+    T::Output: Sample + 'static,
+{
+    let retry = retry(retries, retry_delay)?;
+    let tokenizer = Tokenizer::new(node, tokenizer, revision.as_deref(), pad_token, &retry)?;
+    let tokenizer = template(tokenizer, separator, cls_token, eos_token)?;
+    let tokenizer = truncate(tokenizer, max_length, stride, overflow)?
+        .with_encode_batch_size(encode_batch_size)?
+        .with_truncation_side(truncation_side)?
+        .with_longer_second(longer_second)
+        .with_normalization(normalize);
+    with_timeout(tokenizer, timeout, on_timeout)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_tokenizer_from_json<T: Node>(
+    node: T,
+    json: String,
+    max_length: Option<usize>,
+    stride: usize,
+    overflow: bool,
+    pad_token: Option<u32>,
+    encode_batch_size: usize,
+    truncation_side: TruncationSide,
+    separator: Option<String>,
+    cls_token: Option<String>,
+    eos_token: Option<String>,
+    timeout: Option<f64>,
+    on_timeout: TimeoutPolicy,
+    longer_second: bool,
+    normalize: bool,
+) -> Result<Tokenizer<T>, PreprocessingError>
+where
+    T::Output: Sample + 'static,
+{
+    let tokenizer = Tokenizer::from_json(node, &json, pad_token)?;
+    let tokenizer = template(tokenizer, separator, cls_token, eos_token)?;
+    let tokenizer = truncate(tokenizer, max_length, stride, overflow)?
+        .with_encode_batch_size(encode_batch_size)?
+        .with_truncation_side(truncation_side)?
+        .with_longer_second(longer_second)
+        .with_normalization(normalize);
+    with_timeout(tokenizer, timeout, on_timeout)
+}
+
+// A `Tokenizer` reading from a `Pipelined` loader, itself behind a `Pipelined` node: the loader,
+// the tokenizer and the rest of the pipeline each run on their own thread. `load_buffer` samples
+// and `tokenize_buffer` encodings are kept ready.
+#[allow(clippy::too_many_arguments)]
+fn new_pipelined_tokenizer<T: Node + 'static>(
+    node: T,
+    tokenizer: String,
+    max_length: Option<usize>,
+    stride: usize,
+    overflow: bool,
+    pad_token: Option<u32>,
+    encode_batch_size: usize,
+    truncation_side: TruncationSide,
+    separator: Option<String>,
+    cls_token: Option<String>,
+    eos_token: Option<String>,
+    retries: u32,
+    retry_delay: f64,
+    longer_second: bool,
+    revision: Option<String>,
+    normalize: bool,
+    load_buffer: usize,
+    tokenize_buffer: usize,
+) -> Result<Pipelined<Tokenizer<Pipelined<T>>>, PreprocessingError>
+where
+    T::Output: Sample + 'static,
+{
+    let tokenizer = new_tokenizer(
+        Pipelined::new(node, load_buffer)?,
+        tokenizer,
+        max_length,
+        stride,
+        overflow,
+        pad_token,
+        encode_batch_size,
+        truncation_side,
+        separator,
+        cls_token,
+        eos_token,
+        retries,
+        retry_delay,
+        None,
+        TimeoutPolicy::Raise,
+        longer_second,
+        revision,
+        normalize,
+    )?;
+    Pipelined::new(tokenizer, tokenize_buffer)
+}
+
+// `timeout` is in seconds, `None` encodes without a timeout.
+fn with_timeout<T: Node>(
+    tokenizer: Tokenizer<T>,
+    timeout: Option<f64>,
+    on_timeout: TimeoutPolicy,
+) -> Result<Tokenizer<T>, PreprocessingError>
+where
+    T::Output: Sample + 'static,
+{
+    match timeout {
+        None => Ok(tokenizer),
+        Some(timeout) if timeout > 0.0 && timeout.is_finite() => {
+            Ok(tokenizer.with_timeout(Duration::from_secs_f64(timeout), on_timeout))
+        }
+        Some(timeout) => Err(PreprocessingError::InvalidArgument(format!(
+            "Timeout {} is not a positive number of seconds",
+            timeout
+        ))),
+    }
+}
+
+// `retry_delay` is in seconds.
+fn retry(retries: u32, retry_delay: f64) -> Result<Retry, PreprocessingError> {
+    if !(retry_delay >= 0.0 && retry_delay.is_finite()) {
+        return Err(PreprocessingError::InvalidArgument(format!(
+            "Retry delay {} is not a non-negative number of seconds",
+            retry_delay
+        )));
+    }
+    Ok(Retry::new(retries, Duration::from_secs_f64(retry_delay)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_pair_batcher<T: Node<Output = TextPair<NoLabel>>>(
+    node: T,
+    tokenizer: String,
+    batch_size: usize,
+    seq_length: usize,
+    pad_token: Option<u32>,
+    retries: u32,
+    retry_delay: f64,
+) -> Result<PairBatcher<T>, PreprocessingError> {
+    let retry = retry(retries, retry_delay)?;
+    PairBatcher::new(node, tokenizer, batch_size, seq_length, pad_token, &retry)
+}
+
+fn new_dual_tokenizer<T: Node<Output = Text<NoLabel>>>(
+    node: T,
+    teacher: String,
+    student: String,
+    batch_size: usize,
+    seq_length: usize,
+    retries: u32,
+    retry_delay: f64,
+) -> Result<DualTokenizer<T>, PreprocessingError> {
+    let retry = retry(retries, retry_delay)?;
+    DualTokenizer::new(node, teacher, student, batch_size, seq_length, &retry)
+}
+
+// The passages are counted in tokens of `tokenizer` if one is given, in characters otherwise.
+#[allow(clippy::too_many_arguments)]
+fn new_passage_chunk<T: Node<Output = Text<NoLabel>>>(
+    node: T,
+    size: usize,
+    stride: usize,
+    id_format: String,
+    tokenizer: Option<String>,
+    retries: u32,
+    retry_delay: f64,
+) -> Result<PassageChunk<T>, PreprocessingError> {
+    let chunk = PassageChunk::new(node, size, stride, &id_format)?;
+    match tokenizer {
+        Some(tokenizer) => {
+            let retry = retry(retries, retry_delay)?;
+            Ok(chunk.with_tokenizer(crate::retry::from_pretrained(&tokenizer, None, &retry)?))
+        }
+        None => Ok(chunk),
+    }
+}
+
+fn new_label_encoder<T: Node<Output = Text<StringLabel>>>(
+    node: T,
+    mapping: Option<HashMap<String, u32>>,
+    on_unknown: MalformedPolicy,
+) -> Result<LabelEncoder<T>, PreprocessingError> {
+    Ok(LabelEncoder::new(node, mapping)?.with_on_unknown(on_unknown))
+}
+
+fn new_retrieval_batcher<T: Node<Output = Retrieval>>(
+    node: T,
+    tokenizer: String,
+    batch_size: usize,
+    seq_length: usize,
+    pad_token: Option<u32>,
+    retries: u32,
+    retry_delay: f64,
+) -> Result<RetrievalBatcher<T>, PreprocessingError> {
+    let retry = retry(retries, retry_delay)?;
+    RetrievalBatcher::new(node, tokenizer, batch_size, seq_length, pad_token, &retry)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_multiple_choice_batcher<T: Node<Output = MultipleChoice>>(
+    node: T,
+    tokenizer: String,
+    batch_size: usize,
+    num_choices: usize,
+    seq_length: usize,
+    pad_token: Option<u32>,
+    retries: u32,
+    retry_delay: f64,
+) -> Result<MultipleChoiceBatcher<T>, PreprocessingError> {
+    let retry = retry(retries, retry_delay)?;
+    MultipleChoiceBatcher::new(
+        node,
+        tokenizer,
+        batch_size,
+        num_choices,
+        seq_length,
+        pad_token,
+        &retry,
+    )
+}
+
+// The tokenizer's own post-processor is kept unless any of the special tokens is given.
+fn template<T: Node>(
+    tokenizer: Tokenizer<T>,
+    separator: Option<String>,
+    cls_token: Option<String>,
+    eos_token: Option<String>,
+) -> Result<Tokenizer<T>, PreprocessingError>
+where
+    T::Output: Sample + 'static,
+{
+    if separator.is_none() && cls_token.is_none() && eos_token.is_none() {
+        return Ok(tokenizer);
+    }
+    tokenizer.with_pair_template(
+        separator.as_deref().unwrap_or(""),
+        cls_token.as_deref(),
+        eos_token.as_deref(),
+    )
+}
+
+fn truncate<T: Node>(
+    tokenizer: Tokenizer<T>,
+    max_length: Option<usize>,
+    stride: usize,
+    overflow: bool,
+) -> Result<Tokenizer<T>, PreprocessingError>
+where
+    T::Output: Sample + 'static,
+{
+    match max_length {
+        Some(max_length) => tokenizer.with_truncation(max_length, stride, overflow),
+        None => Ok(tokenizer),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_static_batcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>>(
+    node: T,
+    batch_size: usize,
+    seq_length: usize,
+    pad_batch: bool,
+    partial_batch: PartialBatch,
+    ignore_index: i64,
+    global_attention: bool,
+    num_global_tokens: usize,
+    strict_max_length: bool,
+    dedup_within_batch: bool,
+) -> Result<StaticBatcher<S, T>, PreprocessingError> {
+    let batcher = if strict_max_length {
+        StaticBatcher::new_strict(node, batch_size, seq_length)?
+    } else {
+        StaticBatcher::new(node, batch_size, seq_length)?
+    };
+    Ok(batcher
+        .with_pad_batch(pad_batch)
+        .with_partial_batch(partial_batch)
+        .with_ignore_index(ignore_index)
+        .with_global_attention(global_attention)
+        .with_num_global_tokens(num_global_tokens)
+        .with_dedup_within_batch(dedup_within_batch))
+}
+
+fn new_weighted_sampler<T: Node>(
+    node: T,
+    weights: Vec<f64>,
+    epoch_size: usize,
+    seed: Option<u64>,
+) -> Result<WeightedSampler<T>, PreprocessingError> {
+    WeightedSampler::new(node, Array1::from_vec(weights), epoch_size, seed)
+}
+
+fn new_data_loader<T: Node + 'static>(
+    node: T,
+    num_workers: usize,
+    prefetch_factor: usize,
+    drain_order: DrainOrder,
+    seed: Option<u64>,
+) -> Result<DataLoader<T>, PreprocessingError>
+where
+    T::Output: Send,
+{
+    Ok(DataLoader::new(node, num_workers, prefetch_factor)?.with_drain_order(drain_order, seed))
+}
+
+fn new_standardize<T: Node<Output = NamedBatch>>(
+    node: T,
+    feature: String,
+    warmup: usize,
+    mean: Option<Vec<f64>>,
+    std: Option<Vec<f64>>,
+) -> Result<Standardize<T>, PreprocessingError> {
+    let standardize = Standardize::new(node, feature, warmup)?;
+    match (mean, std) {
+        (Some(mean), Some(std)) => standardize.with_stats(Array1::from(mean), Array1::from(std)),
+        (None, None) => Ok(standardize),
+        _ => Err(PreprocessingError::InvalidArgument(
+            "Either both or neither of mean and std have to be given".to_string(),
+        )),
+    }
+}
+
+fn new_augment<T: Node<Output = Text<NoLabel>>>(
+    node: T,
+    k: usize,
+    p: f64,
+    seed: Option<u64>,
+) -> Result<Augment<T, WordDropout>, PreprocessingError> {
+    Augment::new(node, WordDropout::new(p)?, k, seed)
+}
+
+fn new_nsp<T: Node<Output = Text<NoLabel>>>(
+    node: T,
+    negative_ratio: f64,
+    seed: Option<u64>,
+    buffer_size: usize,
+) -> Result<NextSentencePrediction<T>, PreprocessingError> {
+    NextSentencePrediction::new(node, negative_ratio, buffer_size, seed)
+}
+
+fn new_global_max_batcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>>(
+    node: T,
+    batch_size: usize,
+    pad_batch: bool,
+    ignore_index: i64,
+) -> Result<StaticBatcher<S, T>, PreprocessingError> {
+    Ok(StaticBatcher::with_global_max(node, batch_size)?
+        .with_pad_batch(pad_batch)
+        .with_ignore_index(ignore_index))
+}
+
+fn new_bucket_batcher<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>>(
+    node: T,
+    batch_size: usize,
+    num_workers: usize,
+    worker_id: usize,
+    even_batches: bool,
+) -> Result<BucketBatcher<S, T>, PreprocessingError> {
+    Ok(BucketBatcher::new(node, batch_size)?
+        .with_worker(num_workers, worker_id)?
+        .with_even_batches(even_batches))
+}
+
+fn new_group_by<S: TokenizedLabel, T: Node<Output = TokenizedText<S>>>(
+    node: T,
+    batch_size: usize,
+) -> Result<GroupByExample<S, T>, PreprocessingError> {
+    GroupByExample::by_example_index(node, batch_size)
+}
+
+add_node_constructor!(create_txt_loader: "TxtLoader" => (filename: String, on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error", trim: LineTrim = "LineTrim::LineEnding",) => fn new_txt_loader);
+add_node_constructor!(create_pair_txt_loader: "PairTxtLoader" => (filename: String, separator: String, on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error",) => PairTxtLoader);
+add_node_constructor!(create_token_span_txt_loader: "TokenSpanTxtLoader" => (filename: String, separator: String, on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error",) => TokenSpanTxtLoader);
+add_node_constructor!(create_ids_txt_loader: "IdsTxtLoader" => (filename: String,) => IdsTxtLoader);
+add_node_constructor!(create_retrieval_loader: "RetrievalLoader" => (filename: String, on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error",) => RetrievalLoader);
+add_node_constructor!(create_multiple_choice_loader: "MultipleChoiceLoader" => (filename: String, on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error",) => MultipleChoiceLoader);
+add_node_constructor!(create_labelled_txt_loader: "LabelledTxtLoader" => (filename: String, separator: String, on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error", on_malformed: MalformedPolicy = "MalformedPolicy::Error",) => fn new_labelled_txt_loader);
+add_node_constructor!(create_paired_files_loader: "PairedFilesLoader" => (text_file: String, label_file: String, mapping: Option<HashMap<String, u32>> = "None", on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error", on_malformed: MalformedPolicy = "MalformedPolicy::Error",) => fn new_paired_files_loader);
+add_node_constructor!(create_stdin_loader: "StdinLoader" => (on_invalid_utf8: InvalidUtf8 = "InvalidUtf8::Error",) => StdinLoader);
+add_node_constructor!(create_squad_loader: "SQuADLoader" => (filename: String, *, end_inclusive: bool = "true", normalize_answers: bool = "false", max_questions_per_context: Option<usize> = "None", split_context: Option<usize> = "None", keep_answers: bool = "false", trim_answers: bool = "true", snap_window: usize = "0", answer_policy: AnswerPolicy = "AnswerPolicy::First", impossible_to_cls: bool = "false", char_spans: bool = "false",) => fn new_squad_loader);
+// `SQuADLoader` already builds each pair on demand from the contexts it keeps once, so the lazy
+// variant is the same loader under the name asked for by code that wants the memory bound.
+add_node_constructor!(create_squad_loader_lazy: "SQuADLoaderLazy" => (filename: String, *, end_inclusive: bool = "true", normalize_answers: bool = "false", max_questions_per_context: Option<usize> = "None", split_context: Option<usize> = "None", keep_answers: bool = "false", trim_answers: bool = "true", snap_window: usize = "0", answer_policy: AnswerPolicy = "AnswerPolicy::First", impossible_to_cls: bool = "false", char_spans: bool = "false",) => fn new_squad_loader);
+add_node_constructor!(create_squad_loader_multi: "SQuADLoaderMulti" => (filenames: Vec<String>, *, end_inclusive: bool = "true", normalize_answers: bool = "false", max_questions_per_context: Option<usize> = "None", split_context: Option<usize> = "None", keep_answers: bool = "false", trim_answers: bool = "true", snap_window: usize = "0", answer_policy: AnswerPolicy = "AnswerPolicy::First", impossible_to_cls: bool = "false", char_spans: bool = "false",) => fn new_squad_loader_multi);
+add_stage!(samples: impl<S> SampleNode for NodeWrapper<S> where (
+    S: Sample + ToPyObjectConsume + Clone + 'static,
+    <S::Label as Label>::Tokenized: 'static,
+) {
+    create_tokenizer: "Tokenizer" => (tokenizer: String, max_length: Option<usize> = "None", stride: usize = "0", overflow: bool = "false", pad_token: Option<u32> = "None", encode_batch_size: usize = "1", truncation_side: TruncationSide = "TruncationSide::Right", separator: Option<String> = "None", cls_token: Option<String> = "None", eos_token: Option<String> = "None", retries: u32 = "0", retry_delay: f64 = "1.0", timeout: Option<f64> = "None", on_timeout: TimeoutPolicy = "TimeoutPolicy::Raise", longer_second: bool = "false", revision: Option<String> = "None", normalize: bool = "true",) => new_tokenizer;
+    create_tokenizer_from_json: "TokenizerFromJson" => (json: String, max_length: Option<usize> = "None", stride: usize = "0", overflow: bool = "false", pad_token: Option<u32> = "None", encode_batch_size: usize = "1", truncation_side: TruncationSide = "TruncationSide::Right", separator: Option<String> = "None", cls_token: Option<String> = "None", eos_token: Option<String> = "None", timeout: Option<f64> = "None", on_timeout: TimeoutPolicy = "TimeoutPolicy::Raise", longer_second: bool = "false", normalize: bool = "true",) => new_tokenizer_from_json;
+    create_pipelined_tokenizer: "PipelinedTokenizer" => (tokenizer: String, max_length: Option<usize> = "None", stride: usize = "0", overflow: bool = "false", pad_token: Option<u32> = "None", encode_batch_size: usize = "1", truncation_side: TruncationSide = "TruncationSide::Right", separator: Option<String> = "None", cls_token: Option<String> = "None", eos_token: Option<String> = "None", retries: u32 = "0", retry_delay: f64 = "1.0", longer_second: bool = "false", revision: Option<String> = "None", normalize: bool = "true", load_buffer: usize = "1024", tokenize_buffer: usize = "256",) => new_pipelined_tokenizer;
+    create_byte_tokenizer: "ByteTokenizer" => (bos: Option<u32> = "None", eos: Option<u32> = "None", pad_token: u32 = "0", max_length: Option<usize> = "None",) => ByteTokenizer::new;
+    create_with_metadata: "WithMetadata" => (metadata: Vec<Metadata>,) => WithMetadata::new;
+});
+add_node_constructor!(create_pre_tokenized: "PreTokenized" => (node: &mut NodePy, pad_token: u32 = "0", max_length: Option<usize> = "None",) => PreTokenized<_> {Ids});
+add_stage!(tokenized: impl<S> TokenizedNode for NodeWrapper<TokenizedText<S>> where (S: TokenizedLabel + 'static, S::Batch: 'static) {
+    create_static_batcher: "StaticBatcher" => (batch_size: usize, seq_length: usize, pad_batch: bool = "false", partial_batch: PartialBatch = "PartialBatch::Short", ignore_index: i64 = "-100", global_attention: bool = "false", num_global_tokens: usize = "0", strict_max_length: bool = "false", dedup_within_batch: bool = "false",) => new_static_batcher;
+    create_global_max_batcher: "GlobalMaxBatcher" => (batch_size: usize, pad_batch: bool = "false", ignore_index: i64 = "-100",) => new_global_max_batcher;
+    create_ragged_batcher: "RaggedBatcher" => (batch_size: usize,) => RaggedBatcher::new;
+    create_bucket_batcher: "BucketBatcher" => (batch_size: usize, num_workers: usize = "1", worker_id: usize = "0", even_batches: bool = "false",) => new_bucket_batcher;
+    create_group_by: "GroupBy" => (batch_size: usize,) => new_group_by;
+    create_token_budget_batcher: "TokenBudgetBatcher" => (max_tokens: usize, max_samples: Option<usize> = "None",) => TokenBudgetBatcher::new;
+    create_min_length: "MinLength" => (min_length: usize,) => MinLength::new;
+    create_with_text: "WithText" => () => WithText::new;
+    create_hash_id: "HashId" => () => HashId::new;
+    create_token_dropout: "TokenDropout" => (p: f64, seed: Option<u64> = "None",) => TokenDropout::new;
+});
+add_node_constructor!(create_contrastive_batcher: "ContrastiveBatcher" => (node: &mut NodePy, tokenizer: String, batch_size: usize, seq_length: usize, pad_token: Option<u32> = "None", retries: u32 = "0", retry_delay: f64 = "1.0",) => fn new_pair_batcher {TextPair<NoLabel>});
+add_node_constructor!(create_dual_tokenizer: "DualTokenizer" => (node: &mut NodePy, teacher: String, student: String, batch_size: usize, seq_length: usize, retries: u32 = "0", retry_delay: f64 = "1.0",) => fn new_dual_tokenizer {Text<NoLabel>});
+add_node_constructor!(create_retrieval_batcher: "RetrievalBatcher" => (node: &mut NodePy, tokenizer: String, batch_size: usize, seq_length: usize, pad_token: Option<u32> = "None", retries: u32 = "0", retry_delay: f64 = "1.0",) => fn new_retrieval_batcher {Retrieval});
+add_node_constructor!(create_multiple_choice_batcher: "MultipleChoiceBatcher" => (node: &mut NodePy, tokenizer: String, batch_size: usize, num_choices: usize, seq_length: usize, pad_token: Option<u32> = "None", retries: u32 = "0", retry_delay: f64 = "1.0",) => fn new_multiple_choice_batcher {MultipleChoice});
+add_node_constructor!(create_causal_lm_batcher: "CausalLMBatcher" => (node: &mut NodePy, batch_size: usize, seq_length: usize, bos: Option<u32> = "None", eos: Option<u32> = "None",) => CausalLMBatcher<_> {TokenizedText<NoTokenizedLabel>});
+add_node_constructor!(create_pack: "Pack" => (node: &mut NodePy, seq_length: usize, separator: Option<u32> = "None",) => Pack<_> {TokenizedText<NoTokenizedLabel>});
+add_node_constructor!(create_span_corruption: "SpanCorruption" => (node: &mut NodePy, first_sentinel: u32, num_sentinels: usize = "100", corruption_rate: f64 = "0.15", mean_span_length: f64 = "3.0", seed: Option<u64> = "None",) => SpanCorruption<_> {TokenizedText<NoTokenizedLabel>});
+add_node_constructor!(create_random_crop: "RandomCrop" => (node: &mut NodePy, crop_len: usize, seed: Option<u64> = "None",) => RandomCrop<_> {TokenizedText<NoTokenizedLabel>});
+add_node_constructor!(create_span_to_answerable: "SpanToAnswerable" => (node: &mut NodePy,) => SpanToAnswerable<_> {TokenizedText<TokenizedSpan>});
+add_node_constructor!(create_span_with_answerable: "SpanWithAnswerable" => (node: &mut NodePy,) => SpanWithAnswerable<_> {TokenizedText<TokenizedSpan>});
+add_stage!(batches: impl<S> BatchNode: MmapNode for NodeWrapper<Batch<S>> where (S: BatchLabel + 'static) {
+    create_stats: "Stats" => () => Stats::new;
+    create_one_hot: "OneHot" => (vocab_size: usize, sparse: bool = "false",) => OneHot::new;
+    create_as_dict: "AsDict" => () => AsDict::new;
+    create_validate: "Validate" => (batch_size: Option<usize> = "None", seq_length: Option<usize> = "None",) => Validate::new;
+    create_take_batches: "TakeBatches" => (num_batches: usize,) => TakeBatches::new;
+});
+add_node_constructor!(create_unbatch: "Unbatch" => (node: &mut NodePy,) => Unbatch<_, _> {Batch<NoBatchLabel>, Batch<BatchClassLabel>, Batch<BatchMultiLabel>, Batch<BatchSpan>});
+add_node_constructor!(create_standardize: "Standardize" => (node: &mut NodePy, feature: String, warmup: usize = "0", mean: Option<Vec<f64>> = "None", std: Option<Vec<f64>> = "None",) => fn new_standardize {NamedBatch});
+add_node_constructor!(create_label_smooth: "LabelSmooth" => (node: &mut NodePy, num_classes: usize, epsilon: f32 = "0.1",) => LabelSmooth<_> {Batch<BatchClassLabel>});
+add_node_constructor!(create_label_encoder: "LabelEncoder" => (node: &mut NodePy, mapping: Option<HashMap<String, u32>> = "None", on_unknown: MalformedPolicy = "MalformedPolicy::Error",) => fn new_label_encoder {Text<StringLabel>});
+add_node_constructor!(create_sentence_split: "SentenceSplit" => (node: &mut NodePy,) => SentenceSplit<_> {Text<NoLabel>});
+add_node_constructor!(create_passage_chunk: "PassageChunk" => (node: &mut NodePy, size: usize, stride: usize, id_format: String = "\"{document}-{chunk}\".to_string()", tokenizer: Option<String> = "None", retries: u32 = "0", retry_delay: f64 = "1.0",) => fn new_passage_chunk {Text<NoLabel>});
+add_node_constructor!(create_min_hash_dedup: "MinHashDedup" => (node: &mut NodePy, num_hashes: usize = "128", shingle_size: usize = "5", threshold: f64 = "0.8",) => MinHashDedup<_> {Text<NoLabel>});
+add_node_constructor!(create_max_chars: "MaxChars" => (node: &mut NodePy, max_chars: usize,) => MaxChars<_> {Text<NoLabel>, Text<Span>, Text<ClassLabel>, Text<StringLabel>, Text<TokenSpan>, TextPair<NoLabel>, TextPair<Span>, TextPair<ClassLabel>});
+add_node_constructor!(create_strip_html: "StripHtml" => (node: &mut NodePy,) => StripHtml<_> {Text<NoLabel>, Text<ClassLabel>, TextPair<NoLabel>, TextPair<ClassLabel>});
+add_node_constructor!(create_prompt_pair: "PromptPair" => (node: &mut NodePy, prompt: String,) => PromptPair<_> {Text<NoLabel>, Text<Span>, Text<ClassLabel>});
+add_node_constructor!(create_class_balance: "ClassBalance" => (node: &mut NodePy, epoch_size: Option<usize> = "None", seed: Option<u64> = "None",) => ClassBalance<_> {Text<ClassLabel>});
+add_node_constructor!(create_nsp: "NSP" => (node: &mut NodePy, negative_ratio: f64 = "0.5", seed: Option<u64> = "None", buffer_size: usize = "10000",) => fn new_nsp {Text<NoLabel>});
+add_node_constructor!(create_sop: "SOP" => (node: &mut NodePy, swap_ratio: f64 = "0.5", seed: Option<u64> = "None",) => SentenceOrderPrediction<_> {Text<NoLabel>});
+add_node_constructor!(create_augment: "Augment" => (node: &mut NodePy, k: usize, p: f64, seed: Option<u64> = "None",) => fn new_augment {Text<NoLabel>});
+add_stage!(outputs: impl<T> OutputNode: TeeNode for NodeWrapper<T> where (T: ToPyObjectConsume + Clone + 'static) {
+    create_timed: "Timed" => (name: String,) => Timed::new;
+    create_throttle: "Throttle" => (millis: u64,) => Throttle::new;
+    create_weighted_sampler: "WeightedSampler" => (weights: Vec<f64>, epoch_size: usize, seed: Option<u64> = "None",) => new_weighted_sampler;
+    create_buffered_shuffle: "BufferedShuffle" => (buffer_size: usize, seed: Option<u64> = "None",) => BufferedShuffle::new;
+    create_shuffle: "Shuffle" => (seed: Option<u64> = "None",) => Shuffle::new;
+    create_repeat: "Repeat" => (epochs: Option<usize> = "None",) => Repeat::new;
+    create_window: "Window" => (size: usize, stride: usize = "1",) => Window::new;
+    create_data_loader: "DataLoader" => (num_workers: usize = "2", prefetch_factor: usize = "2", drain_order: DrainOrder = "DrainOrder::Fifo", seed: Option<u64> = "None",) => new_data_loader;
+});
+
+// Splits a node into two handles, see `Tee`.
+#[pyfunction(name = "tee", buffer_size = "1024")]
+fn create_tee(node: &mut NodePy, buffer_size: usize) -> PyResult<(NodePy, NodePy)> {
+    let (node, timers) = node.take_stage("tee", "outputs", |registry| &registry.outputs)?;
+    let (first, second) = node.tee(buffer_size)?;
+    Ok((
+        NodePy {
+            inner: Some(first),
+            timers: timers.clone(),
+            anext: AsyncWorker::default(),
+        },
+        NodePy {
+            inner: Some(second),
+            timers,
+            anext: AsyncWorker::default(),
+        },
+    ))
+}
+
+// Writes the input ids of every batch into the `.npy` file `path` and returns its shape, see
+// `nodes::write_mmap`. Consumes the node.
+#[pyfunction(name = "write_mmap")]
+fn write_mmap_py(node: &mut NodePy, path: String, py: Python<'_>) -> PyResult<(usize, usize)> {
+    let (node, _) = node.take_stage("write_mmap", "batches", |registry| &registry.batches)?;
+    Ok(node.write_mmap(path, py)?)
+}
+
+// The sample type depends on the features of the dataset: one or two texts, with a class label if
+// the dataset declares one or `label_column` names a feature of class ids or label names. Label
+// names get ids in the order they are first seen, or the ids of `mapping` (the `label_mapping` of
+// the loader of another split). With `optional_labels`, rows with class id -1 are unlabelled
+// samples of an `OptionalLabel`.
+#[pyfunction(
+    name = "HfCacheLoader",
+    label_column = "None",
+    mapping = "None",
+    optional_labels = "false"
+)]
+fn create_hf_cache_loader(
+    path: String,
+    split: String,
+    label_column: Option<String>,
+    mapping: Option<HashMap<String, u32>>,
+    optional_labels: bool,
+) -> PyResult<NodePy> {
+    fn loader<T: HfSample + ToPyObjectConsume + Send + 'static>(
+        dataset: &HfDataset,
+        split: &str,
+        mapping: Option<HashMap<String, u32>>,
+    ) -> io::Result<Box<dyn NodePyOutput + Send>> {
+        let loader = match mapping {
+            Some(mapping) => HfCacheLoader::<T>::with_mapping(dataset, split, mapping)?,
+            None => HfCacheLoader::<T>::new(dataset, split)?,
+        };
+        Ok(Box::new(Peekable::new(loader)))
+    }
+    fn load(
+        path: String,
+        split: String,
+        label_column: Option<String>,
+        mapping: Option<HashMap<String, u32>>,
+        optional_labels: bool,
+    ) -> io::Result<Box<dyn NodePyOutput + Send>> {
+        let mut dataset = HfDataset::open(path)?;
+        if let Some(label_column) = label_column {
+            dataset = dataset.with_label_column(&label_column)?;
+        }
+        match (dataset.n_texts(), dataset.has_label(), optional_labels) {
+            (1, false, _) => loader::<Text<NoLabel>>(&dataset, &split, mapping),
+            (1, true, false) => loader::<Text<ClassLabel>>(&dataset, &split, mapping),
+            (1, true, true) => loader::<Text<OptionalLabel<ClassLabel>>>(&dataset, &split, mapping),
+            (_, false, _) => loader::<TextPair<NoLabel>>(&dataset, &split, mapping),
+            (_, true, false) => loader::<TextPair<ClassLabel>>(&dataset, &split, mapping),
+            (_, true, true) => {
+                loader::<TextPair<OptionalLabel<ClassLabel>>>(&dataset, &split, mapping)
+            }
+        }
+    }
+    match load(path, split, label_column, mapping, optional_labels) {
+        Err(err) => Err(PreprocessingError::from(err).into()),
+        Ok(node) => Ok(NodePy {
+            inner: Some(node),
+            timers: Vec::new(),
+            anext: AsyncWorker::default(),
+        }),
+    }
+}
+
+// Samples from a list of dicts, converted when the loader is created. `text_pair_field` makes the
+// samples text pairs and `label_field` labels them with the class ids or label names of that
+// field, see `ListLoader`. With `optional_labels`, rows without the field or with a `None` label
+// are unlabelled samples of an `OptionalLabel`.
+#[pyfunction(
+    name = "PyListLoader",
+    text_field = "\"text\"",
+    text_pair_field = "None",
+    label_field = "None",
+    mapping = "None",
+    optional_labels = "false"
+)]
+fn create_py_list_loader(
+    rows: &PyList,
+    text_field: &str,
+    text_pair_field: Option<&str>,
+    label_field: Option<&str>,
+    mapping: Option<HashMap<String, u32>>,
+    optional_labels: bool,
+) -> PyResult<NodePy> {
+    fn loader<T: HfSample + ToPyObjectConsume + Send + 'static>(
+        rows: Vec<(Vec<String>, Option<RowLabel>)>,
+        mapping: Option<HashMap<String, u32>>,
+    ) -> Result<Box<dyn NodePyOutput + Send>, PreprocessingError> {
+        Ok(Box::new(Peekable::new(ListLoader::<T>::new(
+            rows, mapping,
+        )?)))
+    }
+    let field = |row: &PyDict, name: &str| -> PyResult<PyObject> {
+        match row.get_item(name) {
+            Some(value) => Ok(value.into()),
+            None => Err(PyKeyError::new_err(format!("Missing field {:?}", name))),
+        }
+    };
+    let py = rows.py();
+    let mut samples = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        let row: &PyDict = row.downcast()?;
+        let mut texts = vec![field(row, text_field)?.extract(py)?];
+        if let Some(name) = text_pair_field {
+            texts.push(field(row, name)?.extract(py)?);
+        }
+        let label = match label_field {
+            None => None,
+            Some(name) if optional_labels && row.get_item(name).is_none_or(PyAny::is_none) => None,
+            Some(name) => {
+                let label = field(row, name)?;
+                Some(match label.extract(py) {
+                    Ok(id) => RowLabel::Id(id),
+                    Err(_) => RowLabel::Name(label.extract(py)?),
+                })
+            }
+        };
+        samples.push((texts, label));
+    }
+    let labelled = label_field.is_some();
+    let node = match (text_pair_field.is_some(), labelled, optional_labels) {
+        (false, false, _) => loader::<Text<NoLabel>>(samples, mapping),
+        (false, true, false) => loader::<Text<ClassLabel>>(samples, mapping),
+        (false, true, true) => loader::<Text<OptionalLabel<ClassLabel>>>(samples, mapping),
+        (true, false, _) => loader::<TextPair<NoLabel>>(samples, mapping),
+        (true, true, false) => loader::<TextPair<ClassLabel>>(samples, mapping),
+        (true, true, true) => loader::<TextPair<OptionalLabel<ClassLabel>>>(samples, mapping),
+    }?;
+    Ok(NodePy {
+        inner: Some(node),
+        timers: Vec::new(),
+        anext: AsyncWorker::default(),
+    })
+}
+
+// `TextPair`s of the question and the context, or `Text`s of the context with `pair=False`, see
+// `MultiSpanLoader`.
+#[pyfunction(
+    name = "MultiSpanLoader",
+    pair = "true",
+    on_invalid_utf8 = "InvalidUtf8::Error",
+    on_malformed = "MalformedPolicy::Error"
+)]
+fn create_multi_span_loader(
+    filename: String,
+    pair: bool,
+    on_invalid_utf8: InvalidUtf8,
+    on_malformed: MalformedPolicy,
+) -> PyResult<NodePy> {
+    fn loader<T: MultiSpanSample + Clone + 'static>(
+        filename: String,
+        on_invalid_utf8: InvalidUtf8,
+        on_malformed: MalformedPolicy,
+    ) -> io::Result<Box<dyn NodePyOutput + Send>> {
+        let loader =
+            MultiSpanLoader::<T>::new(filename, on_invalid_utf8)?.with_on_malformed(on_malformed);
+        Ok(Box::new(Peekable::new(loader)))
+    }
+    let node = match pair {
+        true => loader::<TextPair<Spans>>(filename, on_invalid_utf8, on_malformed),
+        false => loader::<Text<Spans>>(filename, on_invalid_utf8, on_malformed),
+    }
+    .map_err(PreprocessingError::from)?;
+    Ok(NodePy {
+        inner: Some(node),
+        timers: Vec::new(),
+        anext: AsyncWorker::default(),
+    })
+}
+
+// Seeds stochastic nodes constructed afterwards without an explicit seed, see `crate::seed`.
+#[pyfunction(name = "set_global_seed")]
+fn py_set_global_seed(seed: Option<u64>) {
+    seed::set_global_seed(seed);
+}
+
+// The character ranges of the whitespace-delimited words of `text`, see `crate::word_spans`.
+#[pyfunction(name = "word_spans")]
+fn py_word_spans(text: &str) -> Vec<(usize, usize)> {
+    word_spans(text)
+}
+
+// Warnings of the nodes, queued by the hook set in `add_to_module` until they are raised by one of
+// the methods reading a node. Nodes may warn on threads that can't take the GIL while the main
+// thread waits on them.
+static PENDING_WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn queue_warning(message: &str) {
+    PENDING_WARNINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(message.to_string());
+}
+
+// Reads from a node with `read` and raises what the nodes reported meanwhile: the queued warnings
+// and the errors that `next` and `get` raise as panics (see `error::raise`) as the exception of
+// the error. Other panics are passed on.
+fn read_node<T>(py: Python<'_>, read: impl FnOnce() -> T) -> PyResult<T> {
+    let output = panic::catch_unwind(AssertUnwindSafe(read));
+    raise_warnings(py)?;
+    match output {
+        Ok(output) => Ok(output),
+        Err(payload) => match payload.downcast::<PreprocessingError>() {
+            Ok(err) => Err((*err).into()),
+            Err(payload) => panic::resume_unwind(payload),
+        },
+    }
+}
+
+// Like `read_node` for a panic caught outside of a call from Python, where PyO3 doesn't turn it
+// into a `PanicException`.
+fn caught_panic(payload: Box<dyn Any + Send>) -> PyErr {
+    let payload = match payload.downcast::<PreprocessingError>() {
+        Ok(err) => return (*err).into(),
+        Err(payload) => payload,
+    };
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panic from Rust code".to_string(),
+        },
     };
+    PanicException::new_err(message)
 }
 
-add_node_constructor!(create_txt_loader: "TxtLoader" => (filename: String,) => TxtLoader);
-add_node_constructor!(create_squad_loader: "SQuADLoader" => (filename: String,) => SQuADLoader);
-add_node_constructor!(create_tokenizer: "Tokenizer" => (node: &mut NodePy, tokenizer: String,) => Tokenizer<_> {Text<NoLabel>, Text<Span>, TextPair<Span>});
-add_node_constructor!(create_static_batcher: "StaticBatcher" => (node: &mut NodePy, batch_size: usize, seq_length: usize,) => StaticBatcher<_, _> {TokenizedText<NoTokenizedLabel>, TokenizedText<TokenizedSpan>});
+// Raises the queued warnings as `UserWarning`s, which fails if a warning filter turns them into
+// errors.
+fn raise_warnings(py: Python<'_>) -> PyResult<()> {
+    let messages = std::mem::take(
+        &mut *PENDING_WARNINGS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    );
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let category = py.import("builtins")?.getattr("UserWarning")?;
+    for message in messages {
+        PyErr::warn(py, category, &message, 1)?;
+    }
+    Ok(())
+}
 
 #[pymodule]
 #[pyo3(name = "ayp")]
 fn pyo3_test(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    add_to_module(m)
+}
+
+// Adds the classes and constructors of this crate to `m`, for downstream crates that build their
+// own Python module.
+pub fn add_to_module(m: &PyModule) -> PyResult<()> {
+    warnings::set_hook(Some(Box::new(queue_warning)));
+    // Errors raised by the nodes become exceptions (see `read_node`), so they aren't reported as
+    // panics. Once, since downstream modules call this as well.
+    static PANIC_HOOK: Once = Once::new();
+    PANIC_HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !info.payload().is::<PreprocessingError>() {
+                hook(info);
+            }
+        }));
+    });
+    m.add_function(wrap_pyfunction!(py_set_global_seed, m)?)?;
+    m.add_function(wrap_pyfunction!(py_word_spans, m)?)?;
     m.add_function(wrap_pyfunction!(create_txt_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_pair_txt_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_labelled_txt_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_retrieval_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_multiple_choice_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_multi_span_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_token_span_txt_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_ids_txt_loader, m)?)?;
     m.add_function(wrap_pyfunction!(create_squad_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_squad_loader_lazy, m)?)?;
+    m.add_function(wrap_pyfunction!(create_squad_loader_multi, m)?)?;
+    m.add_function(wrap_pyfunction!(create_hf_cache_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_py_list_loader, m)?)?;
     m.add_function(wrap_pyfunction!(create_tokenizer, m)?)?;
+    m.add_function(wrap_pyfunction!(create_tokenizer_from_json, m)?)?;
+    m.add_function(wrap_pyfunction!(create_pipelined_tokenizer, m)?)?;
+    m.add_function(wrap_pyfunction!(create_byte_tokenizer, m)?)?;
+    m.add_function(wrap_pyfunction!(create_with_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(create_pre_tokenized, m)?)?;
     m.add_function(wrap_pyfunction!(create_static_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_global_max_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_ragged_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_bucket_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_pack, m)?)?;
+    m.add_function(wrap_pyfunction!(create_span_corruption, m)?)?;
+    m.add_function(wrap_pyfunction!(create_random_crop, m)?)?;
+    m.add_function(wrap_pyfunction!(create_min_length, m)?)?;
+    m.add_function(wrap_pyfunction!(create_group_by, m)?)?;
+    m.add_function(wrap_pyfunction!(create_token_budget_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_tee, m)?)?;
+    m.add_function(wrap_pyfunction!(write_mmap_py, m)?)?;
+    m.add_function(wrap_pyfunction!(create_paired_files_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_contrastive_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_dual_tokenizer, m)?)?;
+    m.add_function(wrap_pyfunction!(create_retrieval_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_multiple_choice_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_causal_lm_batcher, m)?)?;
+    m.add_function(wrap_pyfunction!(create_label_smooth, m)?)?;
+    m.add_function(wrap_pyfunction!(create_stdin_loader, m)?)?;
+    m.add_function(wrap_pyfunction!(create_sentence_split, m)?)?;
+    m.add_function(wrap_pyfunction!(create_passage_chunk, m)?)?;
+    m.add_function(wrap_pyfunction!(create_strip_html, m)?)?;
+    m.add_function(wrap_pyfunction!(create_max_chars, m)?)?;
+    m.add_function(wrap_pyfunction!(create_min_hash_dedup, m)?)?;
+    m.add_function(wrap_pyfunction!(create_with_text, m)?)?;
+    m.add_function(wrap_pyfunction!(create_hash_id, m)?)?;
+    m.add_function(wrap_pyfunction!(create_token_dropout, m)?)?;
+    m.add_function(wrap_pyfunction!(create_nsp, m)?)?;
+    m.add_function(wrap_pyfunction!(create_sop, m)?)?;
+    m.add_function(wrap_pyfunction!(create_window, m)?)?;
+    m.add_function(wrap_pyfunction!(create_buffered_shuffle, m)?)?;
+    m.add_function(wrap_pyfunction!(create_shuffle, m)?)?;
+    m.add_function(wrap_pyfunction!(create_repeat, m)?)?;
+    m.add_function(wrap_pyfunction!(create_weighted_sampler, m)?)?;
+    m.add_function(wrap_pyfunction!(create_prompt_pair, m)?)?;
+    m.add_function(wrap_pyfunction!(create_span_to_answerable, m)?)?;
+    m.add_function(wrap_pyfunction!(create_span_with_answerable, m)?)?;
+    m.add_function(wrap_pyfunction!(create_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(create_as_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(create_validate, m)?)?;
+    m.add_function(wrap_pyfunction!(create_take_batches, m)?)?;
+    m.add_function(wrap_pyfunction!(create_unbatch, m)?)?;
+    m.add_function(wrap_pyfunction!(create_standardize, m)?)?;
+    m.add_function(wrap_pyfunction!(create_one_hot, m)?)?;
+    m.add_function(wrap_pyfunction!(create_label_encoder, m)?)?;
+    m.add_function(wrap_pyfunction!(create_class_balance, m)?)?;
+    m.add_function(wrap_pyfunction!(create_augment, m)?)?;
+    m.add_function(wrap_pyfunction!(create_throttle, m)?)?;
+    m.add_function(wrap_pyfunction!(create_timed, m)?)?;
+    m.add_function(wrap_pyfunction!(create_data_loader, m)?)?;
     m.add_class::<EncodingPy>()?;
     m.add_class::<BatchEncodingPy>()?;
     m.add_class::<BatchSpanPy>()?;
+    m.add_class::<BatchSpansPy>()?;
     m.add_class::<NodePy>()?;
+    m.add_class::<InMemoryDatasetPy>()?;
     Ok(())
 }
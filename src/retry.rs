@@ -0,0 +1,122 @@
+// Retrying of network calls like downloading a pretrained tokenizer, so a transient failure (a
+// timeout or a rate limit of the Hugging Face Hub) doesn't fail the whole run.
+use std::env;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use cached_path::CacheBuilder;
+use tokenizers::tokenizer;
+
+use crate::PreprocessingError;
+
+// Up to `retries` further attempts after a failed one. The first retry waits `base_delay`, every
+// further one twice as long as the one before. The default doesn't retry at all.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retry {
+    retries: u32,
+    base_delay: Duration,
+}
+
+impl Retry {
+    pub fn new(retries: u32, base_delay: Duration) -> Retry {
+        Retry {
+            retries,
+            base_delay,
+        }
+    }
+    // Calls `attempt` until it succeeds, fails with an error that isn't `transient` or the retries
+    // are used up.
+    pub fn run<T, E>(
+        &self,
+        mut attempt: impl FnMut() -> Result<T, E>,
+        transient: impl Fn(&E) -> bool,
+    ) -> Result<T, RetryError<E>> {
+        let mut delay = self.base_delay;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match attempt() {
+                Ok(output) => return Ok(output),
+                Err(error) if attempts > self.retries || !transient(&error) => {
+                    return Err(RetryError { attempts, error })
+                }
+                Err(_) => {}
+            }
+            thread::sleep(delay);
+            delay = delay.saturating_mul(2);
+        }
+    }
+}
+
+// Error of the last attempt of `Retry::run`.
+#[derive(Debug)]
+pub struct RetryError<E> {
+    pub attempts: u32,
+    pub error: E,
+}
+
+// Whether downloading might succeed when tried again: network and IO failures, rate limits and
+// server errors. A missing model or revision (404) or a denied access fails right away.
+pub(crate) fn is_transient(error: &cached_path::Error) -> bool {
+    match error {
+        cached_path::Error::HttpTimeoutError
+        | cached_path::Error::HttpError
+        | cached_path::Error::IoError(_) => true,
+        cached_path::Error::HttpStatusError(status) => *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
+
+// The cache directory of `tokenizer::Tokenizer::from_pretrained`, so both share downloads.
+fn cache_dir() -> PathBuf {
+    match env::var("TOKENIZERS_CACHE") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => dirs::cache_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join("huggingface")
+            .join("tokenizers"),
+    }
+}
+
+// `tokenizer::Tokenizer::from_pretrained` with retries of the download. `revision` is a branch,
+// tag or commit of the repository on the Hub, `None` loads the default branch. The download is
+// done here rather than by `from_pretrained`, which turns every error into the same message, so
+// only transient errors are retried.
+pub(crate) fn from_pretrained(
+    identifier: &str,
+    revision: Option<&str>,
+    retry: &Retry,
+) -> Result<tokenizer::Tokenizer, PreprocessingError> {
+    let url = format!(
+        "https://huggingface.co/{}/resolve/{}/tokenizer.json",
+        identifier,
+        revision.unwrap_or("main")
+    );
+    let download = || {
+        CacheBuilder::new()
+            .dir(cache_dir())
+            .build()?
+            .cached_path(&url)
+    };
+    let path = retry.run(download, is_transient).map_err(|err| {
+        let message = match err.error {
+            cached_path::Error::HttpStatusError(404) => format!(
+                "Model \"{}\" on the Hub doesn't have a tokenizer",
+                identifier
+            ),
+            error => format!("Downloading the tokenizer {} failed: {}", identifier, error),
+        };
+        match err.attempts {
+            1 => PreprocessingError::Tokenizer(message.into()),
+            attempts => PreprocessingError::Tokenizer(
+                format!(
+                    "Loading the tokenizer {} failed {} times, last error: {}",
+                    identifier, attempts, message
+                )
+                .into(),
+            ),
+        }
+    })?;
+    tokenizer::Tokenizer::from_file(path).map_err(PreprocessingError::Tokenizer)
+}
@@ -0,0 +1,83 @@
+// Seeds for stochastic nodes.
+//
+// A node constructed with an explicit seed uses `ChaCha8Rng::seed_from_u64(seed)`. Otherwise, if a
+// global seed was set, the n-th stochastic node constructed after `set_global_seed` (counting
+// from 0) is seeded with `derive_seed(global_seed, n)`, so a pipeline built in the same order
+// gets the same random streams. Without either, the RNG is seeded from the OS.
+//
+// Both `seed_from_u64` (rand_core) and `derive_seed` are stable, so given the same seeds and
+// construction order results stay reproducible across versions.
+//
+// Stochastic nodes that support `get` (like `Augment`) don't keep an RNG stream but seed an RNG
+// per sample with `derive_seed(epoch_seed(node_seed, epoch), index)`. The workers of a
+// `DataLoader` build disjoint indices, so they never produce the same random data, and every
+// sample is the same whichever worker builds it and however many workers there are. `set_epoch`
+// on the pipeline changes the epoch, which gives every epoch new random data while each epoch
+// stays reproducible.
+use std::sync::{Mutex, PoisonError};
+
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// The global seed and the number of nodes seeded from it. `node_rng` uses the one of the process,
+// tests use their own so they don't depend on the other tests running at the same time.
+pub(crate) struct GlobalSeed {
+    seed: Option<u64>,
+    nodes: u64,
+}
+
+impl GlobalSeed {
+    pub(crate) const fn new(seed: Option<u64>) -> GlobalSeed {
+        GlobalSeed { seed, nodes: 0 }
+    }
+    // RNG for a newly constructed stochastic node, see the top of the file.
+    pub(crate) fn node_rng(&mut self, seed: Option<u64>) -> ChaCha8Rng {
+        if let Some(seed) = seed {
+            return ChaCha8Rng::seed_from_u64(seed);
+        }
+        match self.seed {
+            Some(master_seed) => {
+                let salt = self.nodes;
+                self.nodes += 1;
+                ChaCha8Rng::seed_from_u64(derive_seed(master_seed, salt))
+            }
+            None => ChaCha8Rng::seed_from_u64(rand::thread_rng().next_u64()),
+        }
+    }
+}
+
+static GLOBAL_SEED: Mutex<GlobalSeed> = Mutex::new(GlobalSeed::new(None));
+
+// Setting (or clearing) the global seed restarts the per-node salts.
+pub fn set_global_seed(seed: Option<u64>) {
+    *global_seed() = GlobalSeed::new(seed);
+}
+
+fn global_seed() -> std::sync::MutexGuard<'static, GlobalSeed> {
+    GLOBAL_SEED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// SplitMix64 finalizer over the master seed offset by the salt.
+pub fn derive_seed(master_seed: u64, salt: u64) -> u64 {
+    let mut z = master_seed.wrapping_add(salt.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Epoch 0 keeps the node seed, so pipelines that never call `set_epoch` are unaffected. The
+// epochs are hashed rather than added to the seed, so seeds `s` and `s + 1` don't share epochs.
+pub fn epoch_seed(node_seed: u64, epoch: u64) -> u64 {
+    if epoch == 0 {
+        return node_seed;
+    }
+    derive_seed(node_seed, u64::MAX - epoch)
+}
+
+// RNG for a newly constructed stochastic node.
+pub fn node_rng(seed: Option<u64>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => global_seed().node_rng(None),
+    }
+}
@@ -1,6 +1,133 @@
 #[cfg(test)]
 mod tests {
-    use crate::Node;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use ndarray::{array, s, Array1, Array2};
+    use rand::Rng;
+    use tokenizers::models::bpe::BPE;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::models::wordpiece::WordPiece;
+    use tokenizers::normalizers::bert::BertNormalizer;
+    use tokenizers::pre_tokenizers::bert::BertPreTokenizer;
+    use tokenizers::pre_tokenizers::whitespace::WhitespaceSplit;
+    use tokenizers::processors::bert::BertProcessing;
+    use tokenizers::tokenizer::{self, PaddingParams};
+
+    use crate::datasets::{
+        AnswerPolicy, HfCacheLoader, HfDataset, IdsTxtLoader, InvalidUtf8, LabelledTxtLoader,
+        LineTrim, ListLoader, MalformedPolicy, MultiSpanLoader, MultipleChoiceLoader,
+        PairTxtLoader, PairedFilesLoader, RetrievalLoader, RowLabel, SQuADLoader,
+        TokenSpanTxtLoader, TxtLoader,
+    };
+    use crate::nodes::{
+        write_mmap, AsDict, Augment, BucketBatcher, BufferedShuffle, ByteTokenizer,
+        CausalLMBatcher, ClassBalance, DataLoader, DrainOrder, DualTokenizer, GroupBy, HashId,
+        LabelEncoder, LabelSmooth, MaxChars, MinHashDedup, MinLength, MultipleChoiceBatcher,
+        NamedBatch, NextSentencePrediction, NullSink, OneHot, OneHotInputs, Pack, PairBatcher,
+        PassageChunk, Peekable, Pipelined, PreTokenized, PromptPair, RaggedBatcher, RandomCrop,
+        Repeat, RetrievalBatcher, SentenceOrderPrediction, SentenceSplit, Shard, Shuffle,
+        SpanCorruption, SpanToAnswerable, SpanWithAnswerable, Standardize, Stats, StripHtml,
+        TakeBatches, Tee, Throttle, Timed, TokenBudgetBatcher, TokenDropout, Unbatch, Validate,
+        WeightedSampler, Window, WithMetadata, WithText, WordDropout,
+    };
+    use crate::retry::Retry;
+    use crate::seed::{self, derive_seed};
+    use crate::{
+        Batch, BatchLabel, Both, ClassLabel, Encoding, MultiLabel, NamedArrays, NoBatchLabel,
+        NoLabel, NoTokenizedLabel, Node, NodeOutput, OptionalLabel, PartialBatch,
+        PreprocessingError, Sample, Span, Spans, SpecialTokenIds, StaticBatcher, Text, TextPair,
+        TimeoutPolicy, TokenSpan, TokenizedText, Tokenizer, TruncationSide,
+    };
+
+    // The error raised by `f` through `error::raise`.
+    fn raised<T>(f: impl FnOnce() -> T) -> PreprocessingError {
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+            .err()
+            .expect("Expected an error to be raised");
+        *payload
+            .downcast::<PreprocessingError>()
+            .expect("Expected a PreprocessingError")
+    }
+
+    fn texts(lines: &[&str]) -> Vec<Text<NoLabel>> {
+        lines
+            .iter()
+            .map(|line| Text {
+                text: line.to_string(),
+                label: NoLabel(),
+                metadata: None,
+            })
+            .collect()
+    }
+
+    // Writes a fixture file into the temporary directory.
+    fn fixture(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("preprocessing-{}", name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    // In-memory node over a fixed list of samples.
+    struct VecNode<T> {
+        samples: Vec<T>,
+        current_index: usize,
+    }
+
+    impl<T> VecNode<T> {
+        fn new(samples: Vec<T>) -> VecNode<T> {
+            VecNode {
+                samples,
+                current_index: 0,
+            }
+        }
+    }
+
+    impl<T: Clone + NodeOutput> Node for VecNode<T> {
+        type Output = T;
+        fn get(&self, index: usize) -> Option<T> {
+            self.samples.get(index).cloned()
+        }
+        fn len(&self) -> Option<usize> {
+            Some(self.samples.len())
+        }
+        fn next(&mut self) -> Option<T> {
+            let sample = self.get(self.current_index)?;
+            self.current_index += 1;
+            Some(sample)
+        }
+    }
+
+    // Small BERT-like tokenizer so the tests don't need network access.
+    const VOCAB: &[&str] = &[
+        "[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]", "who", "wrote", "the", "book", "?", "it",
+        "was", "written", "by", "alice", "bob", "in", "paris", "where", "is", "a", "city", ".",
+        "##s", "long", "story",
+    ];
+
+    fn test_tokenizer() -> tokenizer::Tokenizer {
+        let vocab: HashMap<String, u32> = VOCAB
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+        let model = WordPiece::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = tokenizer::Tokenizer::new(model);
+        tokenizer.with_normalizer(BertNormalizer::default());
+        tokenizer.with_pre_tokenizer(BertPreTokenizer);
+        tokenizer.with_post_processor(BertProcessing::new(
+            ("[SEP]".to_string(), 3),
+            ("[CLS]".to_string(), 2),
+        ));
+        tokenizer
+    }
 
     #[test]
     fn it_works() {
@@ -9,11 +136,5973 @@ mod tests {
     }
     #[test]
     fn integration_test() {
-        let txt_loader = crate::datasets::TxtLoader::new("test.txt").unwrap();
-        let plain_tokenizer = crate::Tokenizer::new(txt_loader, "bert-base-uncased").unwrap();
+        let txt_loader = crate::datasets::TxtLoader::new("test.txt", InvalidUtf8::Error).unwrap();
+        let plain_tokenizer = crate::Tokenizer::new(
+            txt_loader,
+            "bert-base-uncased",
+            None,
+            None,
+            &Retry::default(),
+        )
+        .unwrap();
         let mut static_batcher = crate::StaticBatcher::new(plain_tokenizer, 3, 32).unwrap();
         while let Some(batch) = static_batcher.next() {
             println!("{:?}", batch.encoding.input_ids);
         }
     }
+    #[test]
+    fn span_sequence_index() {
+        let tokenizer = test_tokenizer();
+
+        // "alice" in a single sequence, default (last) sequence index.
+        let text = Text {
+            text: "it was alice".to_string(),
+            label: Span::new(Some((7, 11))),
+            metadata: None,
+        };
+        assert_eq!(text.tokenize(&tokenizer).label.0, Some((2, 2)));
+
+        // Answer in the context (last sequence) of a pair.
+        let pair = TextPair {
+            text: ("who wrote the book ?".to_string(), "it was bob".to_string()),
+            label: Span::new(Some((7, 9))),
+            metadata: None,
+        };
+        assert_eq!(pair.tokenize(&tokenizer).label.0, Some((7, 7)));
+
+        // Answer in the first sequence of a pair.
+        let pair = TextPair {
+            text: ("who wrote the book ?".to_string(), "it was bob".to_string()),
+            label: Span::in_sequence(Some((14, 17)), 0),
+            metadata: None,
+        };
+        assert_eq!(pair.tokenize(&tokenizer).label.0, Some((3, 3)));
+
+        // Out of range sequence indices don't map to anything.
+        let text = Text {
+            text: "it was alice".to_string(),
+            label: Span::in_sequence(Some((7, 11)), 1),
+            metadata: None,
+        };
+        assert_eq!(text.tokenize(&tokenizer).label.0, None);
+    }
+    #[test]
+    fn throttle_delays_and_passes_through() {
+        let mut throttle = Throttle::new(VecNode::new(texts(&["a", "b", "c"])), 10).unwrap();
+        assert_eq!(throttle.len(), Some(3));
+        let start = Instant::now();
+        let mut lines = Vec::new();
+        while let Some(sample) = throttle.next() {
+            lines.push(sample.text);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(lines, vec!["a", "b", "c"]);
+        assert_eq!(throttle.get(1).unwrap().text, "b");
+    }
+    #[test]
+    fn overflow_to_sample_mapping() {
+        let question = "who".to_string();
+        let pairs = vec![
+            // 8 context tokens -> 2 windows, "alice" is in both.
+            TextPair {
+                text: (
+                    question.clone(),
+                    "it was written by alice in paris .".to_string(),
+                ),
+                label: Span::new(Some((18, 22))),
+                metadata: None,
+            },
+            // 11 context tokens -> 3 windows.
+            TextPair {
+                text: (
+                    question,
+                    "it was a long story by bob in paris it was".to_string(),
+                ),
+                label: Span::new(None),
+                metadata: None,
+            },
+        ];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(6, 2, true)
+            .unwrap();
+        assert_eq!(tokenizer.len(), None);
+        let mut batcher = StaticBatcher::new(tokenizer, 8, 6).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(
+            batch.encoding.overflow_to_sample_mapping.unwrap().to_vec(),
+            vec![0, 0, 1, 1, 1]
+        );
+        assert_eq!(batch.encoding.input_ids.nrows(), 5);
+        // The question is repeated and the answer is found in both windows of the first example.
+        assert!(batch.encoding.input_ids.column(0).iter().all(|&id| id == 5));
+        assert_eq!(batch.labels.start.to_vec(), vec![5, 2, 0, 0, 0]);
+        assert!(batcher.next().is_none());
+    }
+    #[test]
+    fn span_to_answerable() {
+        let context = "it was alice".to_string();
+        let pairs = vec![
+            TextPair {
+                text: ("who".to_string(), context.clone()),
+                label: Span::new(Some((7, 11))),
+                metadata: None,
+            },
+            TextPair {
+                text: ("who".to_string(), context),
+                label: Span::new(None),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None).unwrap();
+        let answerable = SpanToAnswerable::new(tokenizer).unwrap();
+        assert_eq!(answerable.get(1).unwrap().label.0, 0);
+        let mut batcher = StaticBatcher::new(answerable, 2, 8).unwrap();
+        assert_eq!(batcher.next().unwrap().labels.0.to_vec(), vec![1, 0]);
+    }
+    #[test]
+    fn batch_stats() {
+        // 3, 5 and 9 tokens.
+        let samples = texts(&[
+            "who wrote it",
+            "it was written by bob",
+            "it was a long story by alice in paris",
+        ]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 2, 8).unwrap();
+        let mut stats = Throttle::new(Stats::new(batcher).unwrap(), 0).unwrap();
+        while stats.next().is_some() {}
+        let stats = stats.stats().unwrap();
+        // Real tokens: 3 + 5 + 8 (truncated) in a 2x8 and a 1x8 batch.
+        assert!((stats.padding_ratio() - (1.0 - 16.0 / 24.0)).abs() < 1e-9);
+        assert!((stats.avg_length() - 16.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.max_length_seen(), 9);
+    }
+    #[test]
+    fn pair_txt_loader() {
+        let path = fixture("pairs.tsv", b"who wrote it\tbob\nwhere is paris\t\n");
+        let mut loader = PairTxtLoader::new(&path, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        let pair = loader.next().unwrap();
+        assert_eq!(pair.text, ("who wrote it".to_string(), "bob".to_string()));
+        let pair = loader.next().unwrap();
+        assert_eq!(pair.text, ("where is paris".to_string(), String::new()));
+        assert!(loader.next().is_none());
+    }
+    #[test]
+    fn pair_txt_loader_missing_separator() {
+        let path = fixture("pairs-invalid.tsv", b"who wrote it\tbob\tand alice\n");
+        let mut loader = PairTxtLoader::new(&path, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        match raised(|| loader.next()) {
+            PreprocessingError::Parse(message) => assert!(message.contains("Expected exactly one")),
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+    #[test]
+    fn seeds() {
+        let first: u64 = seed::node_rng(Some(42)).gen();
+        assert_eq!(first, seed::node_rng(Some(42)).gen::<u64>());
+        assert_ne!(first, seed::node_rng(Some(43)).gen::<u64>());
+        assert_eq!(derive_seed(7, 0), derive_seed(7, 0));
+        assert_ne!(derive_seed(7, 0), derive_seed(7, 1));
+        assert_ne!(derive_seed(7, 0), derive_seed(8, 0));
+
+        // The global seed gives every node a distinct stream, reproducibly. The global seed of the
+        // process would be changed by the other tests building nodes at the same time.
+        let streams = |global_seed: &mut seed::GlobalSeed| -> Vec<u64> {
+            (0..2).map(|_| global_seed.node_rng(None).gen()).collect()
+        };
+        let first = streams(&mut seed::GlobalSeed::new(Some(7)));
+        assert_ne!(first[0], first[1]);
+        assert_eq!(first, streams(&mut seed::GlobalSeed::new(Some(7))));
+        assert_ne!(first, streams(&mut seed::GlobalSeed::new(Some(8))));
+        // An explicit seed doesn't count as a node of the global seed.
+        let mut global_seed = seed::GlobalSeed::new(Some(7));
+        global_seed.node_rng(Some(42));
+        assert_eq!(first, streams(&mut global_seed));
+    }
+    #[test]
+    fn ragged_batcher() {
+        let samples = texts(&["who wrote it", "it was bob", "where is paris"]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let mut batcher = RaggedBatcher::new(tokenizer, 2).unwrap();
+        assert_eq!(batcher.len(), Some(2));
+        assert_eq!(batcher.get(1).unwrap().encodings.len(), 1);
+        let batch = batcher.next().unwrap();
+        let input_ids: Vec<Vec<u32>> = batch
+            .encodings
+            .iter()
+            .map(|encoding| encoding.input_ids.to_vec())
+            .collect();
+        assert_eq!(input_ids, vec![vec![5, 6, 10], vec![10, 11, 15]]);
+        assert_eq!(batch.labels.len(), 2);
+        assert_eq!(batcher.next().unwrap().encodings.len(), 1);
+        assert!(batcher.next().is_none());
+    }
+    // The second line contains an invalid byte.
+    const INVALID_UTF8: &[u8] = b"who wrote it\r\nit was b\xffob\nwhere is paris";
+
+    fn read_lines(name: &str, on_invalid_utf8: InvalidUtf8) -> Vec<String> {
+        let path = fixture(name, INVALID_UTF8);
+        let mut loader = TxtLoader::new(&path, on_invalid_utf8).unwrap();
+        let mut lines = Vec::new();
+        while let Some(text) = loader.next() {
+            lines.push(text.text);
+        }
+        lines
+    }
+    #[test]
+    fn txt_loader_lossy_utf8() {
+        assert_eq!(
+            read_lines("lossy.txt", InvalidUtf8::Lossy),
+            vec!["who wrote it", "it was b\u{FFFD}ob", "where is paris"]
+        );
+    }
+    #[test]
+    fn txt_loader_skip_invalid_utf8() {
+        assert_eq!(
+            read_lines("skip.txt", InvalidUtf8::Skip),
+            vec!["who wrote it", "where is paris"]
+        );
+    }
+    #[test]
+    fn txt_loader_invalid_utf8_error() {
+        match raised(|| read_lines("error.txt", InvalidUtf8::Error)) {
+            PreprocessingError::Parse(message) => {
+                assert!(message.contains("Invalid UTF-8 in line 2"))
+            }
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+    #[test]
+    fn pad_batch() {
+        let samples = texts(&[
+            "who", "wrote", "the", "book", "?", "it", "was", "bob", "in", "paris", "a", "city",
+            "alice",
+        ]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 8, 4)
+            .unwrap()
+            .with_pad_batch(true);
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.encoding.sample_mask.unwrap().to_vec(), vec![1; 8]);
+        // The 5 sample tail is filled up by repeating "alice".
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.encoding.input_ids.nrows(), 8);
+        assert_eq!(
+            batch.encoding.sample_mask.unwrap().to_vec(),
+            vec![1, 1, 1, 1, 1, 0, 0, 0]
+        );
+        assert!(batch
+            .encoding
+            .input_ids
+            .column(0)
+            .slice(s![4..])
+            .iter()
+            .all(|&id| id == 14));
+        assert!(batcher.next().is_none());
+    }
+    #[test]
+    fn span_end_convention() {
+        let tokenizer = test_tokenizer();
+        // "written by bob" are the tokens 7 to 9 of the pair.
+        let pair = TextPair {
+            text: (
+                "who wrote the book ?".to_string(),
+                "it was written by bob".to_string(),
+            ),
+            label: Span::new(Some((7, 20))),
+            metadata: None,
+        };
+        assert_eq!(pair.clone().tokenize(&tokenizer).label.0, Some((7, 9)));
+        let pair = TextPair {
+            label: pair.label.with_end_inclusive(false),
+            ..pair
+        };
+        let tokenized = pair.tokenize(&tokenizer);
+        assert_eq!(tokenized.label.0, Some((7, 10)));
+        let batch = StaticBatcher::new(VecNode::new(vec![tokenized]), 1, 12)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(batch.labels.end.to_vec(), vec![10]);
+    }
+    // Flatbuffers values for writing Arrow metadata in tests. Alignment is ignored.
+    enum Fb {
+        Byte(u8),
+        Int(i32),
+        Long(i64),
+        Str(&'static str),
+        Table(Vec<Option<Fb>>),
+        Tables(Vec<Vec<Option<Fb>>>),
+        // Vector of structs made of two longs.
+        Longs(Vec<(i64, i64)>),
+    }
+
+    fn patch_offset(buf: &mut [u8], at: usize, target: usize) {
+        buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+    }
+
+    fn write_object(buf: &mut Vec<u8>, object: &Fb) -> usize {
+        let pos = buf.len();
+        match object {
+            Fb::Str(string) => {
+                buf.extend((string.len() as u32).to_le_bytes());
+                buf.extend(string.as_bytes());
+                buf.push(0);
+            }
+            Fb::Table(fields) => return write_table(buf, fields),
+            Fb::Tables(tables) => {
+                buf.extend((tables.len() as u32).to_le_bytes());
+                buf.extend(vec![0; 4 * tables.len()]);
+                for (i, table) in tables.iter().enumerate() {
+                    let table = write_table(buf, table);
+                    patch_offset(buf, pos + 4 + 4 * i, table);
+                }
+            }
+            Fb::Longs(pairs) => {
+                buf.extend((pairs.len() as u32).to_le_bytes());
+                for (first, second) in pairs {
+                    buf.extend(first.to_le_bytes());
+                    buf.extend(second.to_le_bytes());
+                }
+            }
+            _ => unreachable!(),
+        }
+        pos
+    }
+
+    // Writes the vtable followed by the table and returns the table's position.
+    fn write_table(buf: &mut Vec<u8>, fields: &[Option<Fb>]) -> usize {
+        let vtable = buf.len();
+        buf.extend(vec![0; 4 + 2 * fields.len()]);
+        let table = buf.len();
+        buf.extend(((table - vtable) as i32).to_le_bytes());
+        let mut offsets = Vec::new();
+        let mut deferred = Vec::new();
+        for field in fields {
+            let offset = buf.len() - table;
+            match field {
+                None => offsets.push(0),
+                Some(Fb::Byte(value)) => buf.push(*value),
+                Some(Fb::Int(value)) => buf.extend(value.to_le_bytes()),
+                Some(Fb::Long(value)) => buf.extend(value.to_le_bytes()),
+                Some(object) => {
+                    deferred.push((buf.len(), object));
+                    buf.extend([0; 4]);
+                }
+            }
+            if field.is_some() {
+                offsets.push(offset as u16);
+            }
+        }
+        let table_len = (buf.len() - table) as u16;
+        buf[vtable..vtable + 2].copy_from_slice(&((4 + 2 * fields.len()) as u16).to_le_bytes());
+        buf[vtable + 2..vtable + 4].copy_from_slice(&table_len.to_le_bytes());
+        for (i, offset) in offsets.into_iter().enumerate() {
+            buf[vtable + 4 + 2 * i..vtable + 6 + 2 * i].copy_from_slice(&offset.to_le_bytes());
+        }
+        for (at, object) in deferred {
+            let target = write_object(buf, object);
+            patch_offset(buf, at, target);
+        }
+        table
+    }
+
+    fn write_message(stream: &mut Vec<u8>, header_type: u8, header: Fb, body: &[u8]) {
+        let mut metadata = vec![0; 4];
+        let message = vec![
+            None,
+            Some(Fb::Byte(header_type)),
+            Some(header),
+            Some(Fb::Long(body.len() as i64)),
+        ];
+        let root = write_table(&mut metadata, &message);
+        patch_offset(&mut metadata, 0, root);
+        stream.extend((-1i32).to_le_bytes());
+        stream.extend((metadata.len() as i32).to_le_bytes());
+        stream.extend(metadata);
+        stream.extend(body);
+    }
+
+    enum TestColumn {
+        Utf8(Vec<&'static str>),
+        Int64(Vec<i64>),
+    }
+
+    // Arrow stream with one record batch per entry of `batches`.
+    fn arrow_stream(names: &[&'static str], batches: Vec<Vec<TestColumn>>) -> Vec<u8> {
+        let fields = batches[0]
+            .iter()
+            .zip(names)
+            .map(|(column, name)| {
+                let (type_type, type_fields) = match column {
+                    TestColumn::Utf8(_) => (5, vec![]),
+                    TestColumn::Int64(_) => (2, vec![Some(Fb::Int(64)), Some(Fb::Byte(1))]),
+                };
+                vec![
+                    Some(Fb::Str(name)),
+                    Some(Fb::Byte(1)),
+                    Some(Fb::Byte(type_type)),
+                    Some(Fb::Table(type_fields)),
+                    None,
+                    Some(Fb::Tables(vec![])),
+                ]
+            })
+            .collect();
+        let mut stream = Vec::new();
+        write_message(
+            &mut stream,
+            1,
+            Fb::Table(vec![None, Some(Fb::Tables(fields))]),
+            &[],
+        );
+        for columns in batches {
+            let mut body: Vec<u8> = Vec::new();
+            let mut nodes = Vec::new();
+            let mut buffers = Vec::new();
+            let mut length = 0;
+            for column in columns {
+                // No validity bitmaps, every value is present.
+                buffers.push((body.len() as i64, 0));
+                let mut push_buffer = |bytes: Vec<u8>| {
+                    buffers.push((body.len() as i64, bytes.len() as i64));
+                    body.extend(bytes);
+                };
+                match column {
+                    TestColumn::Utf8(values) => {
+                        length = values.len();
+                        let mut offsets = vec![0i32];
+                        let mut data = Vec::new();
+                        for value in values {
+                            data.extend(value.as_bytes());
+                            offsets.push(data.len() as i32);
+                        }
+                        push_buffer(
+                            offsets
+                                .iter()
+                                .flat_map(|offset| offset.to_le_bytes())
+                                .collect(),
+                        );
+                        push_buffer(data);
+                    }
+                    TestColumn::Int64(values) => {
+                        length = values.len();
+                        push_buffer(
+                            values
+                                .iter()
+                                .flat_map(|value| value.to_le_bytes())
+                                .collect(),
+                        );
+                    }
+                }
+                nodes.push((length as i64, 0));
+            }
+            let record_batch = vec![
+                Some(Fb::Long(length as i64)),
+                Some(Fb::Longs(nodes)),
+                Some(Fb::Longs(buffers)),
+            ];
+            write_message(&mut stream, 3, Fb::Table(record_batch), &body);
+        }
+        stream.extend((-1i32).to_le_bytes());
+        stream.extend(0i32.to_le_bytes());
+        stream
+    }
+
+    #[test]
+    fn hf_cache_loader() {
+        let root = std::env::temp_dir().join("preprocessing-hf-cache/nli");
+        let dir = root.join("default/0.0.0/0123abcd");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("dataset_info.json"),
+            r#"{"features": {
+                "premise": {"dtype": "string", "id": null, "_type": "Value"},
+                "idx": {"dtype": "int64", "id": null, "_type": "Value"},
+                "hypothesis": {"dtype": "string", "id": null, "_type": "Value"},
+                "label": {"num_classes": 2, "names": ["no", "yes"], "_type": "ClassLabel"}
+            }}"#,
+        )
+        .unwrap();
+        let batch = |premises, indices, hypotheses, labels| {
+            vec![
+                TestColumn::Utf8(premises),
+                TestColumn::Int64(indices),
+                TestColumn::Utf8(hypotheses),
+                TestColumn::Int64(labels),
+            ]
+        };
+        let stream = arrow_stream(
+            &["premise", "idx", "hypothesis", "label"],
+            vec![
+                batch(
+                    vec!["bob wrote it", "it was alice"],
+                    vec![0, 1],
+                    vec!["bob", "bob"],
+                    vec![1, 0],
+                ),
+                batch(vec!["paris is a city"], vec![2], vec!["paris"], vec![1]),
+            ],
+        );
+        fs::write(dir.join("nli-train.arrow"), stream).unwrap();
+
+        let dataset = HfDataset::open(&root).unwrap();
+        assert_eq!((dataset.n_texts(), dataset.has_label()), (2, true));
+        let mut loader = HfCacheLoader::<TextPair<ClassLabel>>::new(&dataset, "train").unwrap();
+        assert_eq!(loader.len(), Some(3));
+        let pair = loader.next().unwrap();
+        assert_eq!(pair.text, ("bob wrote it".to_string(), "bob".to_string()));
+        assert_eq!(pair.label.0, 1);
+        let pair = loader.get(2).unwrap();
+        assert_eq!(
+            pair.text,
+            ("paris is a city".to_string(), "paris".to_string())
+        );
+        assert!(HfCacheLoader::<TextPair<ClassLabel>>::new(&dataset, "test").is_err());
+        assert!(HfCacheLoader::<Text<ClassLabel>>::new(&dataset, "train").is_err());
+    }
+    #[test]
+    fn arrow_malformed() {
+        use rand::SeedableRng;
+
+        let stream = arrow_stream(
+            &["text", "label"],
+            vec![vec![
+                TestColumn::Utf8(vec!["bob wrote it", "it was alice"]),
+                TestColumn::Int64(vec![1, 0]),
+            ]],
+        );
+        let read = |bytes: &[u8]| crate::datasets::arrow::read_columns(bytes, |_| true);
+        assert_eq!(read(&stream).unwrap().len(), 2);
+        // Truncated files and corrupted bytes are errors (or happen to decode), never panics.
+        for len in 0..stream.len() {
+            let _ = read(&stream[..len]);
+        }
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let extremes: [&[u8]; 4] = [&[0xff; 8], &[0x80; 8], &[0x7f; 8], &[0; 8]];
+        for _ in 0..5000 {
+            let mut corrupted = stream.clone();
+            for _ in 0..rng.gen_range(1..4) {
+                let pos = rng.gen_range(0..corrupted.len());
+                if rng.gen_bool(0.5) {
+                    corrupted[pos] = rng.gen();
+                } else {
+                    let value = extremes[rng.gen_range(0..extremes.len())];
+                    let end = (pos + rng.gen_range(1..=8)).min(corrupted.len());
+                    corrupted[pos..end].copy_from_slice(&value[..end - pos]);
+                }
+            }
+            let _ = read(&corrupted);
+        }
+    }
+
+    #[test]
+    fn hf_cache_unlabelled_rows() {
+        let dir = std::env::temp_dir().join("preprocessing-hf-cache/unlabelled");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("dataset_info.json"),
+            r#"{"features": {
+                "text": {"dtype": "string", "id": null, "_type": "Value"},
+                "label": {"num_classes": 2, "names": ["no", "yes"], "_type": "ClassLabel"}
+            }}"#,
+        )
+        .unwrap();
+        let stream = arrow_stream(
+            &["text", "label"],
+            vec![vec![
+                TestColumn::Utf8(vec!["bob wrote it", "it was alice"]),
+                TestColumn::Int64(vec![1, -1]),
+            ]],
+        );
+        fs::write(dir.join("unlabelled-test.arrow"), stream).unwrap();
+        let dataset = HfDataset::open(&dir).unwrap();
+        let mut loader =
+            HfCacheLoader::<Text<OptionalLabel<ClassLabel>>>::new(&dataset, "test").unwrap();
+        assert_eq!(loader.next().unwrap().label.0.map(|label| label.0), Some(1));
+        assert!(loader.next().unwrap().label.0.is_none());
+        assert!(HfCacheLoader::<Text<ClassLabel>>::new(&dataset, "test").is_err());
+
+        let stream = arrow_stream(
+            &["text", "label"],
+            vec![vec![
+                TestColumn::Utf8(vec!["bob wrote it"]),
+                TestColumn::Int64(vec![-2]),
+            ]],
+        );
+        fs::write(dir.join("unlabelled-test.arrow"), stream).unwrap();
+        assert!(HfCacheLoader::<Text<OptionalLabel<ClassLabel>>>::new(&dataset, "test").is_err());
+    }
+
+    #[test]
+    fn hf_cache_string_labels() {
+        let dir = std::env::temp_dir().join("preprocessing-hf-cache/reviews");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("dataset_info.json"),
+            r#"{"features": {
+                "text": {"dtype": "string", "id": null, "_type": "Value"},
+                "sentiment": {"dtype": "string", "id": null, "_type": "Value"},
+                "stars": {"dtype": "int64", "id": null, "_type": "Value"}
+            }}"#,
+        )
+        .unwrap();
+        let split = |texts, sentiments, stars| {
+            arrow_stream(
+                &["text", "sentiment", "stars"],
+                vec![vec![
+                    TestColumn::Utf8(texts),
+                    TestColumn::Utf8(sentiments),
+                    TestColumn::Int64(stars),
+                ]],
+            )
+        };
+        fs::write(
+            dir.join("reviews-train.arrow"),
+            split(
+                vec!["good", "bad", "fine"],
+                vec!["pos", "neg", "pos"],
+                vec![5, 1, 4],
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("reviews-test.arrow"),
+            split(vec!["awful", "great"], vec!["neg", "pos"], vec![1, 5]),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("reviews-validation.arrow"),
+            split(vec!["meh"], vec!["neutral"], vec![3]),
+        )
+        .unwrap();
+        let labels = |loader: &mut HfCacheLoader<Text<ClassLabel>>| -> Vec<u32> {
+            std::iter::from_fn(|| loader.next())
+                .map(|sample| sample.label.0)
+                .collect()
+        };
+
+        // Two string features are two texts unless one of them is the label column.
+        let dataset = HfDataset::open(&dir).unwrap();
+        assert_eq!((dataset.n_texts(), dataset.has_label()), (2, false));
+        let dataset = dataset.with_label_column("sentiment").unwrap();
+        assert_eq!((dataset.n_texts(), dataset.has_label()), (1, true));
+        let mut train = HfCacheLoader::<Text<ClassLabel>>::new(&dataset, "train").unwrap();
+        assert_eq!(labels(&mut train), [0, 1, 0]);
+        let mapping = train.label_mapping().unwrap();
+        assert_eq!(
+            mapping,
+            HashMap::from([("pos".to_string(), 0), ("neg".to_string(), 1)])
+        );
+        // Other splits keep the ids of the training split, unseen labels are an error.
+        let mut test =
+            HfCacheLoader::<Text<ClassLabel>>::with_mapping(&dataset, "test", mapping.clone())
+                .unwrap();
+        assert_eq!(labels(&mut test), [1, 0]);
+        assert!(
+            HfCacheLoader::<Text<ClassLabel>>::with_mapping(&dataset, "validation", mapping)
+                .is_err()
+        );
+
+        // Integer features are used as class ids directly.
+        let dataset = HfDataset::open(&dir)
+            .unwrap()
+            .with_label_column("stars")
+            .unwrap();
+        assert_eq!((dataset.n_texts(), dataset.has_label()), (2, true));
+        let mut train = HfCacheLoader::<TextPair<ClassLabel>>::new(&dataset, "train").unwrap();
+        assert_eq!(train.next().unwrap().label.0, 5);
+        assert!(train.label_mapping().is_none());
+        assert!(HfDataset::open(&dir)
+            .unwrap()
+            .with_label_column("rating")
+            .is_err());
+    }
+    #[test]
+    fn lengths() {
+        let samples = texts(&["who wrote it", "it was written by bob", "paris"]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let throttle = Throttle::new(tokenizer, 0).unwrap();
+        assert_eq!(throttle.lengths().unwrap().to_vec(), vec![3, 5, 1]);
+        // Cached on the second call.
+        assert_eq!(throttle.lengths().unwrap().to_vec(), vec![3, 5, 1]);
+
+        let pairs = vec![TextPair {
+            text: ("who".to_string(), "it was a long story".to_string()),
+            label: Span::new(None),
+            metadata: None,
+        }];
+        let overflowing = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(4, 1, true)
+            .unwrap();
+        assert!(overflowing.lengths().is_none());
+    }
+    #[test]
+    fn padding_values_per_field() {
+        let mut tokenizer = test_tokenizer();
+        // A nonzero pad token, so it can be told apart from the other fields' padding.
+        tokenizer.with_padding(Some(PaddingParams {
+            pad_id: 4,
+            ..PaddingParams::default()
+        }));
+        let pairs = vec![TextPair {
+            text: ("who".to_string(), "it was bob".to_string()),
+            label: NoLabel(),
+            metadata: None,
+        }];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(pairs), tokenizer, None).unwrap();
+        let batch = StaticBatcher::new(tokenizer, 1, 6).unwrap().next().unwrap();
+        let encoding = batch.encoding;
+        assert_eq!(
+            encoding.input_ids.row(0).to_vec(),
+            vec![5, 10, 11, 15, 4, 4]
+        );
+        assert_eq!(
+            encoding.attention_mask.row(0).to_vec(),
+            vec![1, 1, 1, 1, 0, 0]
+        );
+        assert_eq!(
+            encoding.token_type_ids.row(0).to_vec(),
+            vec![0, 1, 1, 1, 0, 0]
+        );
+    }
+    #[test]
+    fn reconstruct() {
+        let pairs = vec![TextPair {
+            text: (
+                "who wrote it ?".to_string(),
+                "It was written by Alice in Paris".to_string(),
+            ),
+            label: Span::new(None),
+            metadata: None,
+        }];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(pairs.clone()), test_tokenizer(), None).unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 1, 16).unwrap();
+        // Tokens 8 and 9 are "alice in", the original casing is kept.
+        assert_eq!(
+            batcher.reconstruct(0, 0, 8, 9),
+            Some(Some("Alice in".to_string()))
+        );
+        // A span across the question and the context.
+        assert_eq!(batcher.reconstruct(0, 0, 2, 5), Some(None));
+        assert_eq!(batcher.reconstruct(1, 0, 8, 9), None);
+
+        // The second window starts at "by".
+        let overflowing = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(8, 1, true)
+            .unwrap();
+        assert_eq!(
+            overflowing.reconstruct(0, 1, 5, 6),
+            Some(Some("Alice in".to_string()))
+        );
+        assert_eq!(overflowing.reconstruct(0, 5, 5, 6), Some(None));
+    }
+    #[test]
+    fn tokenizer_from_json() {
+        let json = test_tokenizer().to_string(false).unwrap();
+        let tokenizer =
+            Tokenizer::from_json(VecNode::new(texts(&["who wrote it"])), &json, None).unwrap();
+        assert_eq!(
+            tokenizer.get(0).unwrap().encoding.input_ids.to_vec(),
+            vec![5, 6, 10]
+        );
+        assert!(Tokenizer::from_json(VecNode::new(texts(&[])), "{", None).is_err());
+    }
+    #[test]
+    fn data_loader_keeps_order() {
+        let lines: Vec<String> = (0..23).map(|i| "it was ".repeat(i % 5 + 1)).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let batches = |node: &mut dyn Node<Output = Batch<NoBatchLabel>>| {
+            let mut batches = Vec::new();
+            while let Some(batch) = node.next() {
+                batches.push(batch.encoding.input_ids);
+            }
+            batches
+        };
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let expected = batches(&mut StaticBatcher::new(tokenizer, 4, 10).unwrap());
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 4, 10).unwrap();
+        let mut loader = DataLoader::new(batcher, 3, 2).unwrap();
+        assert_eq!(batches(&mut loader), expected);
+        assert_eq!(expected.len(), 6);
+        assert!(loader.next().is_none());
+
+        let streaming =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(4, 1, true)
+                .unwrap();
+        assert!(DataLoader::new(streaming, 2, 2).is_err());
+    }
+
+    // Panics when asked for `index`.
+    struct PanicAt {
+        texts: Vec<Text<NoLabel>>,
+        index: usize,
+    }
+
+    impl Node for PanicAt {
+        type Output = Text<NoLabel>;
+        fn get(&self, index: usize) -> Option<Text<NoLabel>> {
+            assert_ne!(index, self.index, "Failed to build output");
+            self.texts.get(index).cloned()
+        }
+        fn len(&self) -> Option<usize> {
+            Some(self.texts.len())
+        }
+        // Only read through `get` by the `DataLoader`.
+        fn next(&mut self) -> Option<Text<NoLabel>> {
+            None
+        }
+    }
+
+    #[test]
+    fn data_loader_worker_panic() {
+        let node = PanicAt {
+            texts: texts(&["who", "wrote", "the", "book"]),
+            index: 2,
+        };
+        let mut loader = DataLoader::new(node, 2, 2).unwrap();
+        assert_eq!(loader.next().unwrap().text, "who");
+        assert_eq!(loader.next().unwrap().text, "wrote");
+        // The panic of the worker is raised instead of ending the loader early.
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            loader.next();
+        }))
+        .unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.contains("Failed to build output"));
+        assert!(loader.next().is_none());
+    }
+
+    // Streaming version of `PanicAt`.
+    #[cfg(all(feature = "python", python_tests))]
+    struct StreamPanicAt {
+        texts: Vec<Text<NoLabel>>,
+        index: usize,
+        position: usize,
+    }
+
+    #[cfg(all(feature = "python", python_tests))]
+    impl Node for StreamPanicAt {
+        type Output = Text<NoLabel>;
+        fn get(&self, _index: usize) -> Option<Text<NoLabel>> {
+            None
+        }
+        fn len(&self) -> Option<usize> {
+            None
+        }
+        fn next(&mut self) -> Option<Text<NoLabel>> {
+            self.position += 1;
+            assert_ne!(self.position - 1, self.index, "Failed to build output");
+            self.texts.get(self.position - 1).cloned()
+        }
+    }
+
+    // Calls into Python, so the test binary has to be linked against libpython, which the
+    // `extension-module` feature of PyO3 leaves out. Only built with the `python_tests` cfg:
+    // `RUSTFLAGS="--cfg python_tests -l python3.11" cargo test python_`
+    #[test]
+    #[cfg(all(feature = "python", python_tests))]
+    fn python_anext() {
+        use pyo3::types::PyDict;
+        use pyo3::{Py, Python};
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let node = StreamPanicAt {
+                texts: texts(&["who", "wrote", "the", "book", "bob"]),
+                index: 2,
+                position: 0,
+            };
+            let globals = PyDict::new(py);
+            let node = Py::new(py, crate::python::NodePy::from_node(node)).unwrap();
+            globals.set_item("node", node).unwrap();
+            globals
+                .set_item("__builtins__", py.import("builtins").unwrap())
+                .unwrap();
+            // Overlapping futures are resolved in order, a panic is raised by its future and the
+            // node is back in place once the futures are resolved.
+            py.run(
+                r#"
+import asyncio
+
+async def read(count):
+    return await asyncio.gather(
+        *[node.__anext__() for _ in range(count)], return_exceptions=True
+    )
+
+outputs = asyncio.run(read(4))
+assert outputs[:2] == ["who", "wrote"], outputs
+assert type(outputs[2]).__name__ == "PanicException", outputs
+assert "Failed to build output" in str(outputs[2])
+assert outputs[3] == "book", outputs
+assert next(node) == "bob"
+assert isinstance(asyncio.run(read(1))[0], StopAsyncIteration)
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn augment_word_dropout() {
+        let lines = ["who wrote the book", "it was written by alice in paris"];
+        let mut augment = Augment::new(
+            VecNode::new(texts(&lines)),
+            WordDropout::new(0.5).unwrap(),
+            3,
+            Some(42),
+        )
+        .unwrap();
+        assert_eq!(augment.len(), Some(6));
+        let mut variants = Vec::new();
+        while let Some(sample) = augment.next() {
+            variants.push(sample.text);
+        }
+        assert_eq!(variants.len(), 6);
+        for (i, variant) in variants.iter().enumerate() {
+            // Variants keep a non-empty subsequence of the words of their source line.
+            let mut words = lines[i / 3].split_whitespace();
+            assert!(!variant.is_empty());
+            assert!(variant.split(' ').all(|word| words.any(|w| w == word)));
+            assert_eq!(augment.get(i).unwrap().text, *variant);
+        }
+        assert!(augment.get(6).is_none());
+
+        let mut again = Augment::new(
+            VecNode::new(texts(&lines)),
+            WordDropout::new(0.5).unwrap(),
+            3,
+            Some(42),
+        )
+        .unwrap();
+        assert_eq!(again.next().unwrap().text, variants[0]);
+
+        let mut keep_all = Augment::new(
+            VecNode::new(texts(&lines)),
+            WordDropout::new(0.0).unwrap(),
+            2,
+            None,
+        )
+        .unwrap();
+        assert_eq!(keep_all.next().unwrap().text, lines[0]);
+        assert!(WordDropout::new(1.5).is_err());
+    }
+
+    #[test]
+    fn partial_batch_get() {
+        let lines = ["who", "wrote", "the", "book", "alice"];
+        let batcher = |partial_batch| {
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                    .unwrap();
+            StaticBatcher::new(tokenizer, 2, 4)
+                .unwrap()
+                .with_partial_batch(partial_batch)
+        };
+        let first_tokens = |batch: Batch<NoBatchLabel>| batch.encoding.input_ids.column(0).to_vec();
+
+        let short = batcher(PartialBatch::Short);
+        assert_eq!(short.len(), Some(2));
+        assert_eq!(first_tokens(short.get(2).unwrap()), vec![14]);
+
+        let drop = batcher(PartialBatch::Drop);
+        assert_eq!(drop.len(), Some(2));
+        assert_eq!(first_tokens(drop.get(1).unwrap()), vec![7, 8]);
+        assert!(drop.get(2).is_none());
+
+        let mut wrap = batcher(PartialBatch::Wrap);
+        assert_eq!(wrap.len(), Some(3));
+        assert_eq!(first_tokens(wrap.get(2).unwrap()), vec![14, 5]);
+        assert!(wrap.get(3).is_none());
+        // Streaming still ends with the short batch.
+        let mut last = None;
+        while let Some(batch) = wrap.next() {
+            last = Some(batch);
+        }
+        assert_eq!(first_tokens(last.unwrap()), vec![14]);
+    }
+
+    #[test]
+    fn label_encoder() {
+        let file = fixture(
+            "labelled.txt",
+            b"positive\tit was a long story\nnegative\twho wrote the book\npositive\tbob\n",
+        );
+        let loader = LabelledTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        let mut encoder = LabelEncoder::new(loader, None).unwrap();
+        let mut samples = Vec::new();
+        while let Some(sample) = encoder.next() {
+            samples.push((sample.text, sample.label.0));
+        }
+        assert_eq!(
+            samples,
+            vec![
+                ("it was a long story".to_string(), 0),
+                ("who wrote the book".to_string(), 1),
+                ("bob".to_string(), 0),
+            ]
+        );
+        let mapping = encoder.label_mapping().unwrap();
+        assert_eq!(mapping.len(), 2);
+        assert_eq!(mapping["negative"], 1);
+        // Decoding the encoded ids gives back the original labels.
+        let ids = Array1::from_iter(samples.iter().map(|sample| sample.1));
+        assert_eq!(
+            encoder.decode_labels(&ids).unwrap(),
+            vec!["positive", "negative", "positive"]
+        );
+        assert!(matches!(
+            encoder.decode_labels(&array![2]),
+            Err(PreprocessingError::InvalidArgument(_))
+        ));
+
+        // An explicit mapping is used as is and survives tokenization and batching.
+        let mapping: HashMap<String, u32> = [("negative", 0), ("positive", 1)]
+            .into_iter()
+            .map(|(label, id)| (label.to_string(), id))
+            .collect();
+        let loader = LabelledTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        let encoder = LabelEncoder::new(loader, Some(mapping.clone())).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(encoder, test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 3, 8).unwrap();
+        assert_eq!(batcher.next().unwrap().labels.0.to_vec(), vec![1, 0, 1]);
+        assert_eq!(batcher.label_mapping(), Some(mapping.clone()));
+        // Also through nodes between the tokenizer and the batcher.
+        let loader = LabelledTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        let encoder = LabelEncoder::new(loader, Some(mapping.clone())).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(encoder, test_tokenizer(), None).unwrap();
+        let dropout = TokenDropout::new(tokenizer, 0.1, Some(0)).unwrap();
+        let batcher = StaticBatcher::new(dropout, 3, 8).unwrap();
+        assert_eq!(batcher.label_mapping(), Some(mapping.clone()));
+
+        let duplicate = [("a".to_string(), 0), ("b".to_string(), 0)]
+            .into_iter()
+            .collect();
+        let loader = LabelledTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        assert!(LabelEncoder::new(loader, Some(duplicate)).is_err());
+    }
+
+    #[test]
+    fn skip_malformed_labels() {
+        let file = fixture(
+            "labelled_malformed.txt",
+            b"positive\tbob\nno separator\nneutral\tparis\nnegative\twho\n",
+        );
+        let warnings = crate::warnings::count();
+        let loader = LabelledTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error)
+            .unwrap()
+            .with_on_malformed(MalformedPolicy::Skip);
+        let mapping = [("negative", 0), ("positive", 1)]
+            .into_iter()
+            .map(|(label, id)| (label.to_string(), id))
+            .collect();
+        let mut encoder = LabelEncoder::new(loader, Some(mapping))
+            .unwrap()
+            .with_on_unknown(MalformedPolicy::Skip);
+        assert!(encoder.get(0).is_none() && encoder.len().is_none());
+        let samples: Vec<_> = std::iter::from_fn(|| encoder.next())
+            .map(|sample| (sample.text, sample.label.0))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![("bob".to_string(), 1), ("who".to_string(), 0)]
+        );
+        // The line without separator and the unknown label. Other tests may warn at the same time.
+        assert!(crate::warnings::count() >= warnings + 2);
+
+        let texts = fixture("paired_skip_texts.txt", b"it was bob\nparis\na city\n");
+        let ids = fixture("paired_skip_ids.txt", b"1\nparis\n");
+        let mut loader = PairedFilesLoader::new(&texts, &ids, None, InvalidUtf8::Error)
+            .unwrap()
+            .with_on_malformed(MalformedPolicy::Skip);
+        assert_eq!(loader.next().unwrap().text, "it was bob");
+        assert!(loader.next().is_none());
+    }
+
+    #[test]
+    fn global_max_batcher() {
+        let lines = ["who", "it was written by alice", "the book"];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::with_global_max(tokenizer, 2).unwrap();
+        // Both batches are as wide as the longest sample, not just their own longest one.
+        assert_eq!(batcher.next().unwrap().encoding.input_ids.dim(), (2, 5));
+        assert_eq!(batcher.next().unwrap().encoding.input_ids.dim(), (1, 5));
+
+        let streaming =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(4, 1, true)
+                .unwrap();
+        assert!(StaticBatcher::with_global_max(streaming, 2).is_err());
+    }
+
+    #[test]
+    fn byte_tokenizer() {
+        let mut tokenizer = ByteTokenizer::new(
+            VecNode::new(texts(&["hé", "abcdef"])),
+            Some(256),
+            Some(257),
+            258,
+            Some(5),
+        )
+        .unwrap();
+        let first = tokenizer.next().unwrap();
+        assert_eq!(
+            first.encoding.input_ids.to_vec(),
+            vec![256, 104, 195, 169, 257]
+        );
+        // Truncation keeps the special tokens.
+        let second = tokenizer.next().unwrap();
+        assert_eq!(
+            second.encoding.input_ids.to_vec(),
+            vec![256, 97, 98, 99, 257]
+        );
+        assert_eq!(tokenizer.lengths().unwrap().to_vec(), vec![5, 5]);
+
+        let pair = TextPair {
+            text: ("ab".to_string(), "where is it".to_string()),
+            label: Span::new(Some((6, 7))),
+            metadata: None,
+        };
+        let tokenizer =
+            ByteTokenizer::new(VecNode::new(vec![pair]), None, Some(0), 0, None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 1, 16).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(
+            batch.encoding.token_type_ids.row(0).to_vec(),
+            [vec![0; 3], vec![1; 12], vec![0]].concat()
+        );
+        assert_eq!(batch.encoding.input_ids[[0, 15]], 0);
+        // "is" starts after "ab", the separator and "where ".
+        assert_eq!(batch.labels.start.to_vec(), vec![9]);
+        assert_eq!(batch.labels.end.to_vec(), vec![10]);
+    }
+
+    #[test]
+    fn error_kinds() {
+        let missing = SQuADLoader::new("/nonexistent/squad.json").err().unwrap();
+        assert!(
+            matches!(missing, PreprocessingError::Io(ref err) if err.kind() == std::io::ErrorKind::NotFound)
+        );
+        let invalid = fixture("invalid-squad.json", b"{\"data\": 1}");
+        assert!(matches!(
+            SQuADLoader::new(invalid),
+            Err(PreprocessingError::Parse(_))
+        ));
+        assert!(matches!(
+            "often".parse::<InvalidUtf8>(),
+            Err(PreprocessingError::Parse(_))
+        ));
+
+        let streaming =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["who"])), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(4, 1, true)
+                .unwrap();
+        assert!(matches!(
+            DataLoader::new(streaming, 2, 2),
+            Err(PreprocessingError::IncompatibleNode(_))
+        ));
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["who"])), test_tokenizer(), None)
+                .unwrap();
+        assert!(matches!(
+            tokenizer.with_truncation(4, 4, false),
+            Err(PreprocessingError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn owned_tokens_with_stride() {
+        let line = "who wrote the book it was written by alice in paris";
+        let mut tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&[line])), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(4, 2, true)
+                .unwrap();
+        let mut owned = Vec::new();
+        while let Some(sample) = tokenizer.next() {
+            owned.push(sample.encoding.owned_tokens.to_vec());
+        }
+        // Every window repeats the last two tokens of the previous one.
+        assert_eq!(
+            owned,
+            vec![
+                vec![1, 1, 1, 1],
+                vec![0, 0, 1, 1],
+                vec![0, 0, 1, 1],
+                vec![0, 0, 1, 1],
+                vec![0, 0, 1],
+            ]
+        );
+
+        // Only the context of a pair is owned, and the ownership ends up in the batch.
+        let pair = TextPair {
+            text: (
+                "where is it".to_string(),
+                "alice wrote the book in paris".to_string(),
+            ),
+            label: NoLabel(),
+            metadata: None,
+        };
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(vec![pair]), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(6, 1, true)
+            .unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 4, 7).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(
+            batch.encoding.owned_tokens,
+            array![
+                [0, 0, 0, 1, 1, 1, 0],
+                [0, 0, 0, 0, 1, 1, 0],
+                [0, 0, 0, 0, 1, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn peekable() {
+        let mut node = Peekable::new(VecNode::new(texts(&["who", "wrote"])));
+        assert_eq!(node.peek().unwrap().text, "who");
+        assert_eq!(node.peek().unwrap().text, "who");
+        assert_eq!(node.next().unwrap().text, "who");
+        assert_eq!(node.next().unwrap().text, "wrote");
+        assert!(node.peek().is_none());
+        assert!(node.next().is_none());
+
+        // A peeked sample is still passed on to the downstream node.
+        let mut node = Peekable::new(VecNode::new(texts(&["who", "wrote"])));
+        node.peek();
+        let mut batcher = StaticBatcher::new(
+            Tokenizer::from_tokenizer(node, test_tokenizer(), None).unwrap(),
+            2,
+            2,
+        )
+        .unwrap();
+        assert_eq!(
+            batcher.next().unwrap().encoding.input_ids,
+            array![[5, 0], [6, 0]]
+        );
+    }
+
+    #[test]
+    fn min_length() {
+        let lines = ["who", "who wrote the book", "bob", "it was bob"];
+        let labelled = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| Text {
+                text: line.to_string(),
+                label: ClassLabel(i as u32),
+                metadata: None,
+            })
+            .collect();
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(labelled), test_tokenizer(), None).unwrap();
+        let mut filtered = MinLength::new(tokenizer, 2).unwrap();
+        assert_eq!(filtered.len(), None);
+        // The upstream estimate is an upper bound, the hooks of the tokenizer are forwarded.
+        assert_eq!(filtered.estimated_len(), Some(4));
+        assert_eq!(
+            filtered.reconstruct(1, 0, 1, 2),
+            Some(Some("wrote the".to_string()))
+        );
+        assert_eq!(filtered.window_ranges(1), Some(vec![(0, 4)]));
+        let mut kept = Vec::new();
+        while let Some(sample) = filtered.next() {
+            kept.push((sample.encoding.input_ids.len(), sample.label.0));
+        }
+        assert_eq!(kept, vec![(4, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn tokenizer_without_pad_token() {
+        // Byte-level BPE like GPT-2's, where id 0 is a real token and there is no pad token.
+        let vocab: HashMap<String, u32> = ["!", "a", "b", "ab", "<|endoftext|>"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+        let gpt2 = || {
+            let model = BPE::builder()
+                .vocab_and_merges(vocab.clone(), vec![("a".to_string(), "b".to_string())])
+                .build()
+                .unwrap();
+            tokenizer::Tokenizer::new(model)
+        };
+        assert!(matches!(
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["ab"])), gpt2(), None),
+            Err(PreprocessingError::InvalidArgument(_))
+        ));
+        assert!(Tokenizer::from_tokenizer(VecNode::new(texts(&["ab"])), gpt2(), Some(9)).is_err());
+
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["ab", "ba"])), gpt2(), Some(4)).unwrap();
+        let batch = StaticBatcher::new(tokenizer, 2, 3).unwrap().next().unwrap();
+        assert_eq!(batch.encoding.input_ids, array![[3, 4, 4], [2, 1, 4]]);
+        assert_eq!(batch.encoding.pad_token, 4);
+    }
+
+    #[test]
+    fn group_by_example_index() {
+        let lines = ["who wrote the book it was", "paris", "alice in paris"];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(2, 0, true)
+                .unwrap();
+        let mut group_by = GroupBy::by_example_index(tokenizer, 2).unwrap();
+        let mut batches = Vec::new();
+        while let Some(batch) = group_by.next() {
+            let indices: Vec<Option<usize>> = batch
+                .encodings
+                .iter()
+                .map(|encoding| encoding.example_index)
+                .collect();
+            batches.push(indices);
+        }
+        // The three windows of the first example are split, the last example starts a new batch
+        // instead of being split across two.
+        assert_eq!(
+            batches,
+            vec![
+                vec![Some(0), Some(0)],
+                vec![Some(0), Some(1)],
+                vec![Some(2), Some(2)],
+            ]
+        );
+        // The windows of an example can be mapped back to its text.
+        assert_eq!(
+            group_by.window_ranges(0),
+            Some(vec![(0, 2), (2, 4), (4, 6)])
+        );
+        assert_eq!(
+            group_by.reconstruct(0, 1, 0, 1),
+            Some(Some("the book".to_string()))
+        );
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        assert!(GroupBy::by_example_index(tokenizer, 0).is_err());
+    }
+
+    #[test]
+    fn bool_attention_mask() {
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["paris", "who wrote it"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batch = StaticBatcher::new(tokenizer, 2, 4).unwrap().next().unwrap();
+        let mask = batch.encoding.bool_attention_mask();
+        assert_eq!(mask, batch.encoding.attention_mask.mapv(|mask| mask == 1));
+        assert_eq!(
+            mask,
+            array![[true, false, false, false], [true, true, true, false]]
+        );
+    }
+
+    #[test]
+    fn token_budget_batcher() {
+        let lines = [
+            "paris",
+            "it was bob",
+            "paris",
+            "paris",
+            "who wrote the book it was",
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let mut batcher = TokenBudgetBatcher::new(tokenizer, 6, Some(2)).unwrap();
+        let mut shapes = Vec::new();
+        while let Some(batch) = batcher.next() {
+            shapes.push(batch.encoding.input_ids.dim());
+        }
+        // The third "paris" would exceed the sample cap, the long sample alone exceeds the budget.
+        assert_eq!(shapes, vec![(2, 3), (2, 1), (1, 6)]);
+
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        assert!(TokenBudgetBatcher::new(tokenizer, 6, Some(0)).is_err());
+    }
+
+    #[test]
+    fn tee() {
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["paris", "it was bob", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let (first, second) = Tee::new(tokenizer, 3).unwrap();
+        let mut static_batcher = StaticBatcher::new(first, 2, 3).unwrap();
+        let mut ragged_batcher = RaggedBatcher::new(second, 2).unwrap();
+        let static_batches: Vec<_> = (0..2).map(|_| static_batcher.next().unwrap()).collect();
+        assert!(static_batcher.next().is_none());
+        assert_eq!(
+            static_batches[0].encoding.input_ids,
+            array![[17, 0, 0], [10, 11, 15]]
+        );
+        let ragged_sizes: Vec<usize> = std::iter::from_fn(|| ragged_batcher.next())
+            .map(|batch| batch.encodings.len())
+            .collect();
+        assert_eq!(ragged_sizes, vec![2, 1]);
+    }
+
+    #[test]
+    fn tee_backpressure() {
+        let (mut first, mut second) =
+            Tee::new(VecNode::new(texts(&["a", "b", "c", "d", "e"])), 2).unwrap();
+        let read = |tee: &mut Tee<VecNode<Text<NoLabel>>>, count| -> Vec<String> {
+            (0..count).map(|_| tee.next().unwrap().text).collect()
+        };
+        // The first handle raises once it is 2 outputs ahead, without losing the next one.
+        assert_eq!(read(&mut first, 2), vec!["a", "b"]);
+        match raised(|| first.next()) {
+            PreprocessingError::InvalidArgument(message) => assert!(message.contains('2')),
+            err => panic!("Expected an invalid argument error, got {:?}", err),
+        }
+        assert_eq!(read(&mut second, 4), vec!["a", "b", "c", "d"]);
+        assert_eq!(read(&mut first, 3), vec!["c", "d", "e"]);
+        assert_eq!(read(&mut second, 1), vec!["e"]);
+        assert!(first.next().is_none() && second.next().is_none());
+
+        // A handle that is at the limit when upstream ends just ends.
+        let (mut first, mut second) = Tee::new(VecNode::new(texts(&["a", "b"])), 2).unwrap();
+        assert_eq!(read(&mut first, 2), vec!["a", "b"]);
+        assert!(first.next().is_none());
+        assert_eq!(read(&mut second, 2), vec!["a", "b"]);
+        assert!(second.next().is_none());
+    }
+
+    #[test]
+    fn multi_span_labels() {
+        // alice 0-4, wrote 6-10, the 12-14, book 16-19, in 21-22, paris 24-28
+        let samples = vec![
+            Text {
+                text: "alice wrote the book in paris".to_string(),
+                label: Spans::new(vec![(24, 28), (6, 14), (12, 19), (0, 4), (100, 105)]),
+                metadata: None,
+            },
+            Text {
+                text: "it was bob".to_string(),
+                label: Spans::new(vec![(7, 9)]),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let batch = StaticBatcher::new(tokenizer, 2, 6).unwrap().next().unwrap();
+        // Overlapping spans are merged and the out of range span is dropped.
+        assert_eq!(batch.labels.start, array![[0, 1, 5], [2, -100, -100]]);
+        assert_eq!(batch.labels.end, array![[0, 3, 5], [2, -100, -100]]);
+    }
+
+    #[test]
+    fn encode_batch_size() {
+        let lines = [
+            "who wrote the book",
+            "it was bob",
+            "paris",
+            "alice wrote it in paris",
+        ];
+        let features = |encode_batch_size: usize, overflow: bool| {
+            let mut tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                    .unwrap()
+                    .with_truncation(3, 1, overflow)
+                    .unwrap()
+                    .with_encode_batch_size(encode_batch_size)
+                    .unwrap();
+            std::iter::from_fn(|| tokenizer.next())
+                .map(|sample| {
+                    (
+                        sample.encoding.input_ids.to_vec(),
+                        sample.encoding.example_index,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        for overflow in [false, true] {
+            let expected = features(1, overflow);
+            assert_eq!(features(3, overflow), expected);
+            assert_eq!(features(8, overflow), expected);
+        }
+        assert_eq!(features(3, true).last().unwrap().1, Some(3));
+    }
+
+    // Compares encoding one sample at a time with `encode_batch`, run with
+    // `cargo test --release encode_batch_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn encode_batch_benchmark() {
+        let line = "alice wrote the book in paris . it was written by bob ".repeat(20);
+        let lines: Vec<&str> = (0..20_000).map(|_| line.as_str()).collect();
+        for encode_batch_size in [1, 256] {
+            let mut tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                    .unwrap()
+                    .with_encode_batch_size(encode_batch_size)
+                    .unwrap();
+            let start = Instant::now();
+            while tokenizer.next().is_some() {}
+            println!(
+                "encode_batch_size {}: {:?}",
+                encode_batch_size,
+                start.elapsed()
+            );
+        }
+    }
+
+    // Reads a file of short lines, run with
+    // `cargo test --release txt_loader_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn txt_loader_benchmark() {
+        let lines: String = (0..2_000_000)
+            .map(|i| format!("line {} of the corpus\n", i))
+            .collect();
+        let file = fixture("benchmark.txt", lines.as_bytes());
+        let mut loader = TxtLoader::new(&file, InvalidUtf8::Error).unwrap();
+        let start = Instant::now();
+        let mut count = 0;
+        while loader.next().is_some() {
+            count += 1;
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "{} lines in {:?} ({:.1} MB/s)",
+            count,
+            elapsed,
+            lines.len() as f64 / elapsed.as_secs_f64() / 1e6
+        );
+    }
+
+    // `cargo test --release batch_buffers_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn batch_buffers_benchmark() {
+        let lines: String = (0..20_000)
+            .map(|i| format!("2 {} {} 3\n", i % 1000 + 5, i % 700 + 5))
+            .collect();
+        let file = fixture("buffer-benchmark.txt", lines.as_bytes());
+        for recycle in [false, true] {
+            let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 0, None).unwrap();
+            let mut batcher = StaticBatcher::new(node, 64, 512).unwrap();
+            let start = Instant::now();
+            while let Some(mut batch) = batcher.next() {
+                if recycle {
+                    batch.encoding.recycle();
+                }
+            }
+            println!("recycle={}: {:?}", recycle, start.elapsed());
+        }
+    }
+
+    // Compares tokenizing a file on one thread with a loader and tokenizer on threads of their
+    // own, run with `cargo test --release pipelined_tokenizer_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn pipelined_tokenizer_benchmark() {
+        let lines: String = (0..200_000)
+            .map(|i| {
+                format!(
+                    "alice wrote the book {} in paris . it was written by bob\n",
+                    i
+                )
+            })
+            .collect();
+        let file = fixture("pipelined-benchmark.txt", lines.as_bytes());
+        let loader = || TxtLoader::new(&file, InvalidUtf8::Error).unwrap();
+        let start = Instant::now();
+        let mut tokenizer = Tokenizer::from_tokenizer(loader(), test_tokenizer(), None).unwrap();
+        while tokenizer.next().is_some() {}
+        println!("synchronous: {:?}", start.elapsed());
+        let start = Instant::now();
+        let loader = Pipelined::new(loader(), 1024).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        let mut tokenizer = Pipelined::new(tokenizer, 256).unwrap();
+        while tokenizer.next().is_some() {}
+        println!("pipelined: {:?}", start.elapsed());
+    }
+
+    // Compares tokenizing a large file with and without the normalizer, run with
+    // `cargo test --release normalization_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn normalization_benchmark() {
+        let lines: String = (0..200_000)
+            .map(|i| {
+                format!(
+                    "alice wrote the book {} in paris . it was written by bob\n",
+                    i
+                )
+            })
+            .collect();
+        let file = fixture("normalization-benchmark.txt", lines.as_bytes());
+        for normalize in [true, false] {
+            let loader = TxtLoader::new(&file, InvalidUtf8::Error).unwrap();
+            let mut tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None)
+                .unwrap()
+                .with_normalization(normalize);
+            let start = Instant::now();
+            while tokenizer.next().is_some() {}
+            let elapsed = start.elapsed();
+            println!(
+                "normalize={}: {:?} ({:.1} MB/s)",
+                normalize,
+                elapsed,
+                lines.len() as f64 / elapsed.as_secs_f64() / 1e6
+            );
+        }
+    }
+
+    // Peak resident memory of loading SQuAD, compared with also materializing every pair upfront
+    // like an eager loader would. Reads and resets the peak through `/proc`, so it only runs on
+    // Linux.
+    #[test]
+    #[ignore]
+    fn squad_memory_benchmark() {
+        let reset_peak = || fs::write("/proc/self/clear_refs", "5").unwrap();
+        let peak = || -> usize {
+            let status = fs::read_to_string("/proc/self/status").unwrap();
+            let kb = status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmHWM:"))
+                .unwrap();
+            kb.trim().trim_end_matches(" kB").parse::<usize>().unwrap() * 1024
+        };
+        let context = "it was written by alice in paris . ".repeat(20);
+        let paragraphs: Vec<String> = (0..5_000)
+            .map(|i| {
+                let qas: Vec<String> = (0..5)
+                    .map(|j| {
+                        format!(
+                            r#"{{"question": "who wrote book {} {} ?", "answers": [{{"answer_start": 18, "text": "alice"}}], "is_impossible": false}}"#,
+                            i, j
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"{{"context": "{} {}", "qas": [{}]}}"#,
+                    context,
+                    i,
+                    qas.join(", ")
+                )
+            })
+            .collect();
+        let squad = format!(
+            r#"{{"data": [{{"title": "t", "paragraphs": [{}]}}]}}"#,
+            paragraphs.join(", ")
+        );
+        let file = fixture("memory-squad.json", squad.as_bytes());
+        drop((squad, paragraphs));
+        let load = || SQuADLoader::new(&file).unwrap();
+        reset_peak();
+        let before = peak();
+        let loader = load();
+        // The samples are only built once they are first needed.
+        let len = loader.len().unwrap();
+        let lazy = peak() - before;
+        drop(loader);
+
+        reset_peak();
+        let before = peak();
+        let loader = load();
+        let pairs: Vec<_> = (0..len).map(|i| loader.get(i).unwrap()).collect();
+        let eager = peak() - before;
+        assert_eq!(pairs.len(), len);
+        println!(
+            "{} samples, peak {:.1} MB on demand, {:.1} MB materialized",
+            len,
+            lazy as f64 / 1e6,
+            eager as f64 / 1e6
+        );
+    }
+
+    // Batches per second of converting classification batches to the dict of model inputs and
+    // the labels, copying every array like the getters (the conversion before `to_dict`) and with
+    // `to_object_consume` and `to_dict`. Needs numpy, run with `RUSTFLAGS="--cfg python_tests -l
+    // python3.11" cargo test --release python_conversion_benchmark -- --ignored --nocapture`.
+    #[cfg(all(feature = "python", python_tests))]
+    #[test]
+    #[ignore]
+    fn python_conversion_benchmark() {
+        use crate::python::ToPyObjectConsume;
+        use numpy::ToPyArray;
+        use pyo3::types::PyDict;
+        use pyo3::{Python, ToPyObject};
+        pyo3::prepare_freethreaded_python();
+        let line = "alice wrote the book in paris . it was written by bob ".repeat(10);
+        let labelled: Vec<Text<ClassLabel>> = (0..6_400)
+            .map(|i| Text {
+                text: line.clone(),
+                label: ClassLabel(i % 2),
+                metadata: None,
+            })
+            .collect();
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(labelled), test_tokenizer(), None).unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 32, 128).unwrap();
+        let batches = || -> Vec<_> {
+            (0..batcher.len().unwrap())
+                .map_while(|index| batcher.get(index))
+                .collect()
+        };
+        Python::with_gil(|py| {
+            let copied = batches();
+            let count = copied.len();
+            let start = Instant::now();
+            for batch in copied {
+                let inputs = PyDict::new(py);
+                for (name, array) in [
+                    ("input_ids", &batch.encoding.input_ids),
+                    ("attention_mask", &batch.encoding.attention_mask),
+                    ("token_type_ids", &batch.encoding.token_type_ids),
+                ] {
+                    inputs.set_item(name, array.to_pyarray(py)).unwrap();
+                }
+                drop((inputs, batch.labels.0.to_pyarray(py).to_object(py)));
+            }
+            let elapsed = start.elapsed();
+            println!(
+                "copied: {:.0} batches/s",
+                count as f64 / elapsed.as_secs_f64()
+            );
+
+            let consumed = batches();
+            let start = Instant::now();
+            for batch in consumed {
+                let batch = batch.to_object_consume(py);
+                let (encoding, labels): (pyo3::PyObject, pyo3::PyObject) =
+                    batch.extract(py).unwrap();
+                drop((encoding.call_method0(py, "to_dict").unwrap(), labels));
+            }
+            let elapsed = start.elapsed();
+            println!(
+                "consumed: {:.0} batches/s",
+                count as f64 / elapsed.as_secs_f64()
+            );
+        });
+    }
+
+    // Serves one request on a local port with `head` and then `body`, closing the connection
+    // afterwards. Returns the URL.
+    #[cfg(feature = "http")]
+    fn serve_once(head: String, body: &'static [u8]) -> String {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/corpus.txt", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while request.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn http_loader() {
+        let body = b"who wrote it\nit was bob\n";
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        let mut loader = TxtLoader::new(serve_once(head, body), InvalidUtf8::Error).unwrap();
+        let lines: Vec<String> = std::iter::from_fn(|| loader.next())
+            .map(|sample| sample.text)
+            .collect();
+        assert_eq!(lines, ["who wrote it", "it was bob"]);
+
+        let head = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string();
+        let err = TxtLoader::new(serve_once(head, b""), InvalidUtf8::Error)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn http_loader_connection_lost() {
+        // The connection closes long before the announced length.
+        let head =
+            "HTTP/1.1 200 OK\r\nContent-Length: 100000\r\nConnection: close\r\n\r\n".to_string();
+        let mut loader = TxtLoader::new(
+            serve_once(head, b"who wrote it\nit was"),
+            InvalidUtf8::Error,
+        )
+        .unwrap();
+        assert_eq!(loader.next().unwrap().text, "who wrote it");
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| loader.next()))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            payload.downcast_ref::<PreprocessingError>(),
+            Some(PreprocessingError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn size_hint() {
+        // Streaming loaders don't know a lower bound, only an estimate.
+        let small = fixture("size_hint_small.txt", b"who wrote it\nit was bob\nparis");
+        let loader = TxtLoader::new(&small, InvalidUtf8::Error).unwrap();
+        assert_eq!(loader.size_hint(), (0, None));
+        assert_eq!(loader.estimated_len(), Some(3));
+
+        // Estimated from the first buffer of a file that doesn't fit into it.
+        let lines: String = (0..10_000).map(|i| format!("line {}\n", i % 100)).collect();
+        let large = fixture("size_hint_large.txt", lines.as_bytes());
+        let mut loader = TxtLoader::new(&large, InvalidUtf8::Error).unwrap();
+        let estimate = loader.estimated_len().unwrap();
+        assert!((9_000..11_000).contains(&estimate), "{}", estimate);
+        let tokenizer = Tokenizer::from_tokenizer(
+            TxtLoader::new(&large, InvalidUtf8::Error).unwrap(),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batcher = Peekable::new(StaticBatcher::new(tokenizer, 2, 4).unwrap());
+        assert_eq!(batcher.size_hint(), (0, None));
+        assert_eq!(batcher.estimated_len(), Some(estimate.div_ceil(2)));
+        while loader.next().is_some() {}
+        assert_eq!(loader.estimated_len(), Some(estimate.max(10_000)));
+
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["a"; 5])), test_tokenizer(), None)
+                .unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 2, 4).unwrap();
+        assert_eq!(batcher.size_hint(), (3, Some(3)));
+        assert_eq!(batcher.estimated_len(), Some(3));
+        let pairs =
+            NextSentencePrediction::new(VecNode::new(texts(&["a"; 5])), 0.5, 4, None).unwrap();
+        assert_eq!(pairs.size_hint(), (4, Some(4)));
+    }
+
+    #[test]
+    fn paired_files_loader() {
+        let texts = fixture("paired_texts.txt", b"it was bob\nparis\n");
+        let ids = fixture("paired_ids.txt", b"1\n0\n");
+        let mut loader = PairedFilesLoader::new(&texts, &ids, None, InvalidUtf8::Error).unwrap();
+        let mut samples = Vec::new();
+        while let Some(sample) = loader.next() {
+            samples.push((sample.text, sample.label.0));
+        }
+        assert_eq!(
+            samples,
+            vec![("it was bob".to_string(), 1), ("paris".to_string(), 0)]
+        );
+
+        let names = fixture("paired_names.txt", b"person\r\ncity\r\n");
+        let mapping: HashMap<String, u32> = [("city", 0), ("person", 1)]
+            .into_iter()
+            .map(|(name, id)| (name.to_string(), id))
+            .collect();
+        let mut loader =
+            PairedFilesLoader::new(&texts, &names, Some(mapping.clone()), InvalidUtf8::Error)
+                .unwrap();
+        assert_eq!(loader.next().unwrap().label.0, 1);
+        assert_eq!(loader.next().unwrap().label.0, 0);
+        assert!(loader.next().is_none());
+        assert_eq!(loader.label_mapping(), Some(mapping));
+    }
+
+    #[test]
+    fn paired_files_loader_line_count_mismatch() {
+        let texts = fixture("paired_mismatch_texts.txt", b"it was bob\nparis\n");
+        let ids = fixture("paired_mismatch_ids.txt", b"1\n");
+        let mut loader = PairedFilesLoader::new(&texts, &ids, None, InvalidUtf8::Error).unwrap();
+        match raised(|| while loader.next().is_some() {}) {
+            PreprocessingError::Parse(message) => assert!(message.contains("more lines")),
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn truncation_side() {
+        // who 0-2, ..., by 34-35, alice 37-41, in 43-44, paris 46-50
+        let line = "who wrote the book it was written by alice in paris";
+        let tokenize = |side: TruncationSide| {
+            let samples = [(0, 2), (37, 41)]
+                .iter()
+                .map(|&span| Text {
+                    text: line.to_string(),
+                    label: Span::new(Some(span)),
+                    metadata: None,
+                })
+                .collect();
+            let mut tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None)
+                    .unwrap()
+                    .with_truncation_side(side)
+                    .unwrap()
+                    .with_truncation(4, 0, false)
+                    .unwrap();
+            std::iter::from_fn(|| tokenizer.next())
+                .map(|sample| (sample.encoding.input_ids.to_vec(), sample.label.0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tokenize(TruncationSide::Right),
+            vec![(vec![5, 6, 7, 8], Some((0, 0))), (vec![5, 6, 7, 8], None)]
+        );
+        // The answer is located relative to the kept tokens, a cut off answer is dropped.
+        assert_eq!(
+            tokenize(TruncationSide::Left),
+            vec![
+                (vec![13, 14, 16, 17], None),
+                (vec![13, 14, 16, 17], Some((1, 1)))
+            ]
+        );
+
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&[line])), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(4, 0, true)
+                .unwrap();
+        assert!(tokenizer
+            .with_truncation_side(TruncationSide::Left)
+            .is_err());
+    }
+
+    #[test]
+    fn pair_batcher() {
+        let pairs = [
+            ("who wrote it", "it was bob"),
+            ("where is paris", "paris is a city"),
+        ]
+        .iter()
+        .map(|(anchor, positive)| TextPair {
+            text: (anchor.to_string(), positive.to_string()),
+            label: NoLabel(),
+            metadata: None,
+        })
+        .collect();
+        let batcher =
+            PairBatcher::from_tokenizer(VecNode::new(pairs), test_tokenizer(), 2, 4, None).unwrap();
+        assert_eq!(batcher.len(), Some(1));
+        let batch = batcher.get(0).unwrap();
+        assert_eq!(
+            batch.anchors.input_ids,
+            array![[5, 6, 10, 0], [18, 19, 17, 0]]
+        );
+        assert_eq!(
+            batch.positives.input_ids,
+            array![[10, 11, 15, 0], [17, 19, 20, 21]]
+        );
+        assert_eq!(batch.labels, array![[1, 0], [0, 1]]);
+        assert!(batcher.get(1).is_none());
+    }
+
+    #[test]
+    fn sentence_split() {
+        let lines = [
+            "Who wrote the book? It was Bob!  Paris is a city.",
+            "",
+            "He said \"it was bob.\" Version 1.2 is out...",
+            "no punctuation",
+        ];
+        let mut node = SentenceSplit::new(VecNode::new(texts(&lines))).unwrap();
+        let sentences: Vec<String> = std::iter::from_fn(|| node.next())
+            .map(|sample| sample.text)
+            .collect();
+        assert_eq!(
+            sentences,
+            vec![
+                "Who wrote the book?",
+                "It was Bob!",
+                "Paris is a city.",
+                "He said \"it was bob.\"",
+                "Version 1.2 is out...",
+                "no punctuation",
+            ]
+        );
+    }
+
+    #[test]
+    fn next_sentence_prediction() {
+        let sentences: Vec<String> = (0..200).map(|i| format!("sentence {}", i)).collect();
+        let sentences: Vec<&str> = sentences.iter().map(String::as_str).collect();
+        let pairs = |seed| {
+            let node = VecNode::new(texts(&sentences));
+            let mut nsp = NextSentencePrediction::new(node, 0.5, 16, Some(seed)).unwrap();
+            std::iter::from_fn(|| nsp.next())
+                .map(|pair| (pair.text, pair.label.0))
+                .collect::<Vec<_>>()
+        };
+        let first = pairs(3);
+        assert_eq!(first.len(), 199);
+        assert_eq!(first, pairs(3));
+        let index = |sentence: &str| sentence[9..].parse::<usize>().unwrap();
+        for (i, ((a, b), is_next)) in first.iter().enumerate() {
+            assert_eq!(index(a), i);
+            match is_next {
+                1 => assert_eq!(index(b), i + 1),
+                _ => assert!(index(b) < i),
+            }
+        }
+        let negatives = first.iter().filter(|(_, is_next)| *is_next == 0).count();
+        assert!((70..130).contains(&negatives), "{}", negatives);
+        assert_eq!(pairs(3)[0].1, 1);
+
+        let node = VecNode::new(texts(&sentences));
+        assert!(NextSentencePrediction::new(node, 1.5, 16, None).is_err());
+    }
+
+    #[test]
+    fn get_many() {
+        let lines = ["who wrote it", "it was bob", "paris", "a city", "the book"];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 2, 3).unwrap();
+        let many: Vec<_> = batcher
+            .get_many(1, 5)
+            .into_iter()
+            .map(|batch| batch.encoding.input_ids)
+            .collect();
+        let single: Vec<_> = (1..3)
+            .map(|index| batcher.get(index).unwrap().encoding.input_ids)
+            .collect();
+        assert_eq!(many, single);
+        assert!(batcher.get_many(5, 2).is_empty());
+        assert_eq!(batcher.get_many(1, usize::MAX).len(), 2);
+    }
+
+    #[test]
+    fn squad_normalized_answers() {
+        // The answers differ from the context in case and accents, "é" is two bytes long.
+        let squad = fixture(
+            "normalized-squad.json",
+            r#"{"data": [{"title": "t", "paragraphs": [{"context": "Élan and élan, the Cafe", "qas": [
+                {"question": "q", "answers": [{"answer_start": 10, "text": "ELAN"}], "is_impossible": false},
+                {"question": "q", "answers": [{"answer_start": 20, "text": "café"}], "is_impossible": false}
+            ]}]}]}"#
+                .as_bytes(),
+        );
+        let spans = |normalize_answers| -> Vec<_> {
+            let loader = SQuADLoader::new(&squad)
+                .unwrap()
+                .with_normalize_answers(normalize_answers);
+            (0..2)
+                .map(|index| loader.get(index).unwrap().label.span)
+                .collect()
+        };
+        // Without normalization the answers aren't found.
+        assert_eq!(spans(false), vec![None, None]);
+        // The closest occurrence is taken and the offsets are characters of the original context.
+        assert_eq!(spans(true), vec![Some((9, 12)), Some((19, 22))]);
+    }
+
+    #[test]
+    #[cfg(feature = "python")]
+    fn arrow_export() {
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batch = StaticBatcher::new(tokenizer, 2, 3).unwrap().get(0).unwrap();
+        let record_batch = batch.encoding.to_arrow().unwrap();
+        let names: Vec<_> = record_batch
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "input_ids",
+                "attention_mask",
+                "token_type_ids",
+                "owned_tokens",
+                "lengths"
+            ]
+        );
+
+        let mut exported = record_batch.export();
+        let columns = import_arrow(&mut exported);
+        drop(exported);
+        let column = |name: &str| columns.iter().find(|column| column.0 == name).unwrap();
+        assert_eq!(column("input_ids").1, "+w:3");
+        assert_eq!(column("input_ids").2, "I");
+        assert_eq!(column("input_ids").3, vec![5, 6, 10, 17, 0, 0]);
+        assert_eq!(column("attention_mask").3, vec![1, 1, 1, 1, 0, 0]);
+        assert_eq!(column("lengths").1, "L");
+        assert_eq!(column("lengths").3, vec![3, 1]);
+
+        // Columns that don't fit the batch are an error.
+        let columns = vec![crate::arrow::Column::from_array1(
+            "lengths",
+            crate::arrow::Values::UInt64(vec![1, 2, 3]),
+        )];
+        assert!(crate::arrow::RecordBatch::new(2, columns).is_err());
+    }
+
+    // Imports an exported record batch the way an Arrow consumer does: the structs are moved out
+    // and marked as released, the columns are read from them and their release callbacks are
+    // called. Returns the name, the format and the format of the values of every column, and the
+    // values.
+    #[cfg(feature = "python")]
+    fn import_arrow(
+        exported: &mut crate::arrow::ExportedRecordBatch,
+    ) -> Vec<(String, String, String, Vec<u64>)> {
+        use crate::arrow::{FfiArray, FfiSchema};
+        use std::ffi::CStr;
+        let text = |ptr| unsafe { CStr::from_ptr(ptr).to_str().unwrap().to_string() };
+        unsafe {
+            let array_ptr = exported.array_address() as *mut FfiArray;
+            let schema_ptr = exported.schema_address() as *mut FfiSchema;
+            let array = std::ptr::read(array_ptr);
+            (*array_ptr).release = None;
+            let schema = std::ptr::read(schema_ptr);
+            (*schema_ptr).release = None;
+            assert_eq!(text(schema.format), "+s");
+            assert_eq!(schema.n_children, array.n_children);
+            let mut columns = Vec::new();
+            for i in 0..schema.n_children as usize {
+                let field = &**schema.children.add(i);
+                let column = &**array.children.add(i);
+                assert_eq!(column.length, array.length);
+                let format = text(field.format);
+                let (item, values) = match format.strip_prefix("+w:") {
+                    Some(_) => (&**field.children, &**column.children),
+                    None => (field, column),
+                };
+                assert_eq!(
+                    (values.null_count, values.offset, values.n_buffers),
+                    (0, 0, 2)
+                );
+                assert!((*values.buffers).is_null());
+                let data = *values.buffers.add(1);
+                let len = values.length as usize;
+                let item_format = text(item.format);
+                let data: Vec<u64> = match item_format.as_str() {
+                    "I" => std::slice::from_raw_parts(data as *const u32, len)
+                        .iter()
+                        .map(|&value| value as u64)
+                        .collect(),
+                    "L" => std::slice::from_raw_parts(data as *const u64, len).to_vec(),
+                    "l" => std::slice::from_raw_parts(data as *const i64, len)
+                        .iter()
+                        .map(|&value| value as u64)
+                        .collect(),
+                    other => panic!("Unexpected format {}", other),
+                };
+                columns.push((text(field.name), format, item_format, data));
+            }
+            // Dropping the moved structs calls their release callbacks.
+            drop(array);
+            drop(schema);
+            columns
+        }
+    }
+
+    #[test]
+    fn null_sink() {
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "it was bob", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let mut sink = NullSink::new(StaticBatcher::new(tokenizer, 2, 4).unwrap()).unwrap();
+        assert_eq!(sink.drain().count, 2);
+        assert_eq!(sink.drain().count, 0);
+    }
+
+    #[test]
+    fn truncation_stats() {
+        // 3, 3 and 1 tokens.
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "it was bob", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 2).unwrap();
+        while batcher.next().is_some() {}
+        let stats = batcher.truncation_stats().unwrap();
+        assert_eq!(stats.truncated_sequences(), 2);
+        assert_eq!(stats.truncated_tokens(), 2);
+    }
+
+    #[test]
+    fn truncation_stats_unique_samples() {
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris", "it was bob"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 2)
+            .unwrap()
+            .with_pad_batch(true);
+        // Neither the samples built by `get` nor the repeated rows of the last batch count.
+        batcher.get(0).unwrap();
+        while batcher.next().is_some() {}
+        let stats = batcher.truncation_stats().unwrap();
+        assert_eq!(stats.truncated_sequences(), 2);
+        assert_eq!(stats.truncated_tokens(), 2);
+    }
+
+    #[test]
+    fn pair_template() {
+        let pair = TextPair {
+            text: ("who wrote it".to_string(), "it was bob".to_string()),
+            label: Span::new(Some((7, 9))),
+            metadata: None,
+        };
+        let mut tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(vec![pair]), test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("[SEP] [MASK]", Some("[CLS]"), Some("[SEP]"))
+                .unwrap();
+        let sample = tokenizer.get(0).unwrap();
+        assert_eq!(
+            sample.encoding.input_ids.to_vec(),
+            vec![2, 5, 6, 10, 3, 4, 10, 11, 15, 3]
+        );
+        assert_eq!(
+            sample.encoding.token_type_ids.to_vec(),
+            vec![0, 0, 0, 0, 0, 0, 1, 1, 1, 1]
+        );
+        // "bob" moved from token 5 to token 8.
+        assert_eq!(sample.label.0, Some((8, 8)));
+        assert_eq!(
+            tokenizer.next().unwrap().encoding.input_ids,
+            sample.encoding.input_ids
+        );
+        assert!(matches!(
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["who"])), test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("</s>", None, None),
+            Err(PreprocessingError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn window() {
+        let lines = ["a", "b", "c", "d", "e"];
+        let joined = |window: Vec<Text<NoLabel>>| -> String {
+            window.into_iter().map(|sample| sample.text).collect()
+        };
+        let mut window = Window::new(VecNode::new(texts(&lines)), 3, 1).unwrap();
+        assert_eq!(window.len(), Some(3));
+        assert_eq!(window.get(2).map(joined), Some("cde".to_string()));
+        assert!(window.get(3).is_none());
+        let windows: Vec<_> = std::iter::from_fn(|| window.next()).map(joined).collect();
+        assert_eq!(windows, vec!["abc", "bcd", "cde"]);
+
+        let mut window = Window::new(VecNode::new(texts(&lines)), 2, 3).unwrap();
+        assert_eq!(window.len(), Some(2));
+        let windows: Vec<_> = std::iter::from_fn(|| window.next()).map(joined).collect();
+        assert_eq!(windows, vec!["ab", "de"]);
+    }
+
+    #[test]
+    fn squad_max_questions_per_context() {
+        let squad = fixture(
+            "limited-squad.json",
+            r#"{"data": [{"title": "t", "paragraphs": [
+                {"context": "paris", "qas": [
+                    {"question": "a", "answers": [], "is_impossible": true},
+                    {"question": "b", "answers": [], "is_impossible": true},
+                    {"question": "c", "answers": [], "is_impossible": true}
+                ]},
+                {"context": "bob", "qas": [
+                    {"question": "d", "answers": [], "is_impossible": true}
+                ]}
+            ]}]}"#
+                .as_bytes(),
+        );
+        let questions = |max_questions: Option<usize>| -> Vec<String> {
+            let mut loader = SQuADLoader::new(&squad).unwrap();
+            if let Some(max_questions) = max_questions {
+                loader = loader.with_max_questions_per_context(max_questions);
+            }
+            let len = loader.len();
+            let questions: Vec<_> = std::iter::from_fn(|| loader.next())
+                .map(|sample| sample.text.0)
+                .collect();
+            assert_eq!(len, Some(questions.len()));
+            questions
+        };
+        assert_eq!(questions(None), vec!["a", "b", "c", "d"]);
+        assert_eq!(questions(Some(2)), vec!["a", "b", "d"]);
+    }
+
+    #[test]
+    fn txt_loader_chunks() {
+        let file = fixture(
+            "chunks.txt",
+            b"who wrote it\r\n\nit was \xff bob\na considerably longer line\nparis",
+        );
+        let read = |loader: &mut TxtLoader| -> Vec<String> {
+            std::iter::from_fn(|| loader.next())
+                .map(|sample| sample.text)
+                .collect()
+        };
+        let expected = read(&mut TxtLoader::new(&file, InvalidUtf8::Skip).unwrap());
+        assert_eq!(
+            expected,
+            vec!["who wrote it", "", "a considerably longer line", "paris"]
+        );
+        // Lines spanning several chunks and newlines at chunk boundaries.
+        for buffer_size in 1..8 {
+            let mut loader =
+                TxtLoader::with_buffer_size(&file, InvalidUtf8::Skip, buffer_size).unwrap();
+            assert_eq!(read(&mut loader), expected, "{}", buffer_size);
+        }
+    }
+
+    #[test]
+    fn try_clone() {
+        let squad = fixture(
+            "clone-squad.json",
+            br#"{"data": [{"title": "t", "paragraphs": [{"context": "paris", "qas": [
+                {"question": "a", "answers": [], "is_impossible": true},
+                {"question": "b", "answers": [], "is_impossible": true}
+            ]}]}]}"#,
+        );
+        let mut loader = Peekable::new(SQuADLoader::new(&squad).unwrap());
+        assert_eq!(loader.next().unwrap().text.0, "a");
+        assert!(loader.peek().is_some());
+        // The clone starts from the beginning and doesn't affect the original.
+        let mut clone = loader.try_clone().unwrap();
+        assert_eq!(clone.len(), Some(2));
+        assert_eq!(clone.next().unwrap().text.0, "a");
+        assert_eq!(clone.next().unwrap().text.0, "b");
+        assert!(clone.next().is_none());
+        assert_eq!(loader.next().unwrap().text.0, "b");
+
+        let lines = fixture("clone.txt", b"who wrote it\n");
+        assert!(TxtLoader::new(&lines, InvalidUtf8::Error)
+            .unwrap()
+            .try_clone()
+            .is_none());
+    }
+
+    #[test]
+    fn squad_misaligned_answer_start() {
+        // "é" is two bytes long, so character and byte offsets differ after it.
+        let squad = fixture(
+            "misaligned-squad.json",
+            r#"{"data": [{"title": "t", "paragraphs": [{"context": "café paris and paris", "qas": [
+                {"question": "exact", "answers": [{"answer_start": 16, "text": "paris"}], "is_impossible": false},
+                {"question": "chars", "answers": [{"answer_start": 5, "text": "paris"}], "is_impossible": false},
+                {"question": "off", "answers": [{"answer_start": 13, "text": "paris"}], "is_impossible": false},
+                {"question": "missing", "answers": [{"answer_start": 0, "text": "london"}], "is_impossible": false}
+            ]}]}]}"#
+                .as_bytes(),
+        );
+        let loader = SQuADLoader::new(&squad).unwrap();
+        let spans: Vec<_> = (0..4)
+            .map(|index| loader.get(index).unwrap().label.span)
+            .collect();
+        // The second occurrence of "paris" disambiguated by `answer_start`, the first one given
+        // as a character offset, the occurrence closest to a wrong offset and no span at all. The
+        // spans are characters either way.
+        assert_eq!(
+            spans,
+            vec![Some((15, 19)), Some((5, 9)), Some((15, 19)), None]
+        );
+
+        // Character 13 starts the second "paris", byte 13 the first one. The character offset wins.
+        let squad = fixture(
+            "multibyte-squad.json",
+            r#"{"data": [{"title": "t", "paragraphs": [{"context": "éééééé paris paris", "qas": [
+                {"question": "second", "answers": [{"answer_start": 13, "text": "paris"}], "is_impossible": false}
+            ]}]}]}"#
+                .as_bytes(),
+        );
+        let loader = SQuADLoader::new(&squad).unwrap();
+        assert_eq!(loader.get(0).unwrap().label.span, Some((13, 17)));
+    }
+
+    // The options of the SQuAD loaders are keyword-only in Python.
+    #[cfg(all(feature = "python", python_tests))]
+    #[test]
+    fn python_squad_keyword_arguments() {
+        use pyo3::types::{PyDict, PyModule};
+        use pyo3::Python;
+        pyo3::prepare_freethreaded_python();
+        let squad = fixture(
+            "python-keyword-squad.json",
+            br#"{"data": [{"title": "t", "paragraphs": [{"context": "it was bob", "qas": [
+                {"question": "who", "answers": [{"answer_start": 6, "text": " bob"}], "is_impossible": false}
+            ]}]}]}"#,
+        );
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "ayp").unwrap();
+            crate::python::add_to_module(module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("ayp", module).unwrap();
+            globals.set_item("path", squad.to_str().unwrap()).unwrap();
+            py.run(
+                r#"
+loader = ayp.SQuADLoader(path, end_inclusive=False, trim_answers=False, split_context=100)
+assert next(loader) == ("who", "it was bob")
+for constructor, paths in [(ayp.SQuADLoader, path), (ayp.SQuADLoaderMulti, [path])]:
+    try:
+        constructor(paths, False)
+    except TypeError:
+        pass
+    else:
+        raise AssertionError("The options are keyword-only")
+try:
+    ayp.SQuADLoader(path, split_context=0)
+except ValueError:
+    pass
+else:
+    raise AssertionError("split_context=0 is rejected")
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn prompt_pair() {
+        let text = Text {
+            text: "it was bob".to_string(),
+            label: Span::new(Some((7, 9))),
+            metadata: None,
+        };
+        let mut pairs = PromptPair::new(VecNode::new(vec![text]), "who wrote the book ?").unwrap();
+        assert_eq!(pairs.len(), Some(1));
+        let pair = pairs.next().unwrap();
+        assert_eq!(
+            pair.text,
+            ("who wrote the book ?".to_string(), "it was bob".to_string())
+        );
+        assert!(pairs.next().is_none());
+        // The span still points to "bob" in the text.
+        assert_eq!(pair.tokenize(&test_tokenizer()).label.0, Some((7, 7)));
+    }
+
+    #[test]
+    fn augment_epochs() {
+        let augment = |seed| {
+            let lines = ["who wrote the book it was written by alice in paris"; 4];
+            Augment::new(
+                VecNode::new(texts(&lines)),
+                WordDropout::new(0.5).unwrap(),
+                1,
+                Some(seed),
+            )
+            .unwrap()
+        };
+        let collect = |loader: &mut DataLoader<_>| -> Vec<String> {
+            std::iter::from_fn(|| loader.next())
+                .map(|sample: Text<NoLabel>| sample.text)
+                .collect()
+        };
+        let run = |epoch| {
+            let node = augment(1);
+            node.set_epoch(epoch);
+            collect(&mut DataLoader::new(node, 2, 1).unwrap())
+        };
+        // Both workers get the same text but draw different dropouts.
+        let first = run(0);
+        assert_ne!(first[0], first[1]);
+        assert_ne!(first[2], first[3]);
+        // Every worker is reproducible, and samples don't depend on which worker built them.
+        assert_eq!(first, run(0));
+        let single: Vec<_> = (0..4)
+            .map(|index| augment(1).get(index).unwrap().text)
+            .collect();
+        assert_eq!(first, single);
+        // A new epoch draws new dropouts, going back to an epoch reproduces it.
+        let second = run(1);
+        assert_ne!(first, second);
+        let mut loader = DataLoader::new(augment(1), 2, 1).unwrap();
+        loader.set_epoch(1);
+        assert_eq!(collect(&mut loader), second);
+    }
+
+    #[test]
+    fn token_span() {
+        let tokenizer = test_tokenizer();
+        // The indices are tokens, as characters they would point into "wrote".
+        let text = Text {
+            text: "who wrote the book".to_string(),
+            label: TokenSpan::new(Some((2, 3))),
+            metadata: None,
+        };
+        assert_eq!(text.tokenize(&tokenizer).label.0, Some((2, 3)));
+        // Spans past the end of the encoding are dropped.
+        let text = Text {
+            text: "who wrote".to_string(),
+            label: TokenSpan::new(Some((1, 2))),
+            metadata: None,
+        };
+        assert_eq!(text.tokenize(&tokenizer).label.0, None);
+
+        let file = fixture("token-spans.txt", b"2\t2\tit was bob\n\t\tparis\n");
+        let loader = TokenSpanTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        let batch = StaticBatcher::new(tokenizer, 2, 4).unwrap().next().unwrap();
+        assert_eq!(batch.labels.start.to_vec(), vec![2, 0]);
+        assert_eq!(batch.labels.end.to_vec(), vec![2, 0]);
+
+        let file = fixture("token-spans-invalid.txt", b"2\tb\tit was bob\n");
+        let mut loader =
+            TokenSpanTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        match raised(|| loader.next()) {
+            PreprocessingError::Parse(message) => assert!(message.contains("token indices")),
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn pre_tokenized() {
+        let file = fixture("ids.txt", b"2 5 6 10 3\n2 17\n");
+        let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 0, Some(4)).unwrap();
+        let batch = StaticBatcher::new(node, 2, 4).unwrap().next().unwrap();
+        // The ids are taken as they are, only cut to `max_length` and padded.
+        assert_eq!(
+            batch.encoding.input_ids,
+            array![[2, 5, 6, 10], [2, 17, 0, 0]]
+        );
+        assert_eq!(
+            batch.encoding.attention_mask,
+            array![[1, 1, 1, 1], [1, 1, 0, 0]]
+        );
+
+        let file = fixture("ids-invalid.txt", b"2 five 3\n");
+        let mut loader = IdsTxtLoader::new(&file).unwrap();
+        match raised(|| loader.next()) {
+            PreprocessingError::Parse(message) => assert!(message.contains("Invalid token id")),
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "http"))]
+    fn url_without_http_feature() {
+        let err = TxtLoader::new("https://example.com/corpus.txt", InvalidUtf8::Error)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn class_balance() {
+        let labelled = |labels: &[u32]| -> Vec<Text<ClassLabel>> {
+            labels
+                .iter()
+                .map(|&label| Text {
+                    text: format!("text {}", label),
+                    label: ClassLabel(label),
+                    metadata: None,
+                })
+                .collect()
+        };
+        let balanced = |seed| -> Vec<u32> {
+            let node = VecNode::new(labelled(&[0, 0, 0, 0, 0, 0, 0, 1, 2]));
+            let mut balance = ClassBalance::new(node, Some(3000), Some(seed)).unwrap();
+            assert_eq!(balance.size_hint(), (3000, Some(3000)));
+            std::iter::from_fn(|| balance.next())
+                .map(|sample| sample.label.0)
+                .collect()
+        };
+        let labels = balanced(1);
+        assert_eq!(labels.len(), 3000);
+        // Every class makes up about a third of the stream.
+        for class in 0..3 {
+            let count = labels.iter().filter(|&&label| label == class).count();
+            assert!((900..1100).contains(&count), "{} of class {}", count, class);
+        }
+        assert_eq!(labels, balanced(1));
+
+        // The label mapping of the encoder is forwarded.
+        let samples = ["positive", "negative"]
+            .iter()
+            .map(|label| Text {
+                text: "bob".to_string(),
+                label: crate::StringLabel(label.to_string()),
+                metadata: None,
+            })
+            .collect();
+        let encoder = LabelEncoder::new(VecNode::new(samples), None).unwrap();
+        let balance = ClassBalance::new(encoder, None, None).unwrap();
+        assert_eq!(balance.label_mapping().unwrap().len(), 2);
+
+        // Streaming loaders can't be grouped by class.
+        let file = fixture("balance.txt", b"positive\tbob\n");
+        let loader = LabelledTxtLoader::new(&file, "\t".to_string(), InvalidUtf8::Error).unwrap();
+        let streaming = LabelEncoder::new(loader, None).unwrap();
+        assert!(ClassBalance::new(streaming, None, None).is_err());
+    }
+
+    #[test]
+    fn length_histogram() {
+        let lines = [
+            "who",
+            "who wrote",
+            "who wrote it",
+            "it was written by alice in paris",
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        // Lengths 1, 2, 3 and 7 in buckets of width 2.
+        let (edges, counts) = tokenizer.length_histogram(4).unwrap();
+        assert_eq!(edges, array![0, 2, 4, 6, 8]);
+        assert_eq!(counts, array![1, 2, 0, 1]);
+        assert!(tokenizer.length_histogram(0).is_err());
+
+        let file = fixture("histogram.txt", b"who wrote it\n");
+        let streaming = Tokenizer::from_tokenizer(
+            TxtLoader::new(&file, InvalidUtf8::Error).unwrap(),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        assert!(streaming.length_histogram(4).is_err());
+    }
+
+    #[test]
+    fn named_arrays() {
+        let names = |arrays: NamedArrays| -> Vec<&str> {
+            arrays.into_iter().map(|(name, _)| name).collect()
+        };
+        let inputs = ["input_ids", "attention_mask", "token_type_ids"];
+        let samples = vec![
+            Text {
+                text: "bob".to_string(),
+                label: ClassLabel(0),
+                metadata: None,
+            },
+            Text {
+                text: "paris".to_string(),
+                label: ClassLabel(1),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let mut batches = AsDict::new(StaticBatcher::new(tokenizer, 2, 4).unwrap()).unwrap();
+        let batch = batches.next().unwrap();
+        assert_eq!(names(batch.0.clone()), [&inputs[..], &["labels"]].concat());
+        assert_eq!(batch.0[3].1, array![0, 1].into_dyn());
+        // The hooks looking into the pipeline reach through to the batcher.
+        assert!(batches.truncation_stats().is_some());
+        assert!(batches.total_tokens().is_some());
+
+        let text = Text {
+            text: "it was bob".to_string(),
+            label: Span::new(Some((7, 9))),
+            metadata: None,
+        };
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(vec![text]), test_tokenizer(), None).unwrap();
+        let batch = StaticBatcher::new(tokenizer, 1, 4).unwrap().next().unwrap();
+        let arrays = batch.into_named_arrays();
+        assert_eq!(
+            names(arrays.clone()),
+            [&inputs[..], &["start_positions", "end_positions"]].concat()
+        );
+        assert_eq!(arrays[0].1, array![[10, 11, 15, 0]].into_dyn());
+        assert_eq!(arrays[3].1, array![2].into_dyn());
+        assert_eq!(arrays[4].1, array![2].into_dyn());
+
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["bob"])), test_tokenizer(), None)
+                .unwrap();
+        let batch = StaticBatcher::new(tokenizer, 1, 4).unwrap().next().unwrap();
+        assert_eq!(names(batch.into_named_arrays()), inputs);
+    }
+
+    #[test]
+    fn buffered_shuffle() {
+        let lines: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let shuffled = |buffer_size, seed| -> Vec<usize> {
+            let node = VecNode::new(texts(&lines));
+            let mut shuffle = BufferedShuffle::new(node, buffer_size, Some(seed)).unwrap();
+            assert_eq!(shuffle.size_hint(), (100, Some(100)));
+            assert!(shuffle.get(0).is_none());
+            std::iter::from_fn(|| shuffle.next())
+                .map(|sample| sample.text.parse().unwrap())
+                .collect()
+        };
+        let order = shuffled(10, 1);
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        assert_ne!(order, sorted);
+        assert_eq!(order, shuffled(10, 1));
+        assert_ne!(order, shuffled(10, 2));
+        // Outputs move up by at most the buffer size minus one, they have to be read first.
+        for (position, &value) in order.iter().enumerate() {
+            assert!(value < position + 10, "{} at position {}", value, position);
+        }
+        assert_eq!(shuffled(1, 1), sorted);
+
+        assert!(BufferedShuffle::new(VecNode::new(texts(&["bob"])), 0, None).is_err());
+
+        // The hooks of the pipeline are forwarded.
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["bob"])), test_tokenizer(), None)
+                .unwrap();
+        let shuffle = BufferedShuffle::new(tokenizer, 2, None).unwrap();
+        assert!(shuffle.tokenizer().is_some());
+        let mapping: HashMap<String, u32> = [("positive".to_string(), 0)].into_iter().collect();
+        let labelled = vec![Text {
+            text: "bob".to_string(),
+            label: crate::StringLabel("positive".to_string()),
+            metadata: None,
+        }];
+        let encoder = LabelEncoder::new(VecNode::new(labelled), Some(mapping.clone())).unwrap();
+        let shuffle = BufferedShuffle::new(encoder, 2, None).unwrap();
+        assert_eq!(shuffle.label_mapping(), Some(mapping));
+    }
+
+    #[test]
+    fn txt_loader_trim() {
+        let file = fixture(
+            "crlf.txt",
+            b"who wrote\r\n  the book \r\nit was bob \r\r\nin paris\r",
+        );
+        let read = |trim| -> Vec<String> {
+            let mut loader = TxtLoader::new(&file, InvalidUtf8::Error)
+                .unwrap()
+                .with_trim(trim);
+            std::iter::from_fn(|| loader.next())
+                .map(|sample| sample.text)
+                .collect()
+        };
+        let lines = read(LineTrim::LineEnding);
+        assert_eq!(
+            lines,
+            ["who wrote", "  the book ", "it was bob ", "in paris"]
+        );
+        assert!(lines.iter().all(|line| !line.contains('\r')));
+        assert_eq!(
+            read(LineTrim::None),
+            [
+                "who wrote\r",
+                "  the book \r",
+                "it was bob \r\r",
+                "in paris\r"
+            ]
+        );
+        assert_eq!(
+            read(LineTrim::End),
+            ["who wrote", "  the book", "it was bob", "in paris"]
+        );
+        assert_eq!(
+            read(LineTrim::Both),
+            ["who wrote", "the book", "it was bob", "in paris"]
+        );
+        assert!("trim".parse::<LineTrim>().is_err());
+    }
+
+    #[test]
+    fn ignore_index() {
+        let classes = || {
+            let samples = ["who", "wrote", "bob"]
+                .iter()
+                .enumerate()
+                .map(|(i, text)| Text {
+                    text: text.to_string(),
+                    label: ClassLabel(i as u32),
+                    metadata: None,
+                })
+                .collect();
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap()
+        };
+        // The rows repeated to fill up the last batch don't count.
+        let mut batcher = StaticBatcher::new(classes(), 2, 4)
+            .unwrap()
+            .with_pad_batch(true);
+        assert_eq!(batcher.next().unwrap().labels.0, array![0, 1]);
+        assert_eq!(batcher.next().unwrap().labels.0, array![2, -100]);
+        let mut batcher = StaticBatcher::new(classes(), 2, 4)
+            .unwrap()
+            .with_pad_batch(true)
+            .with_ignore_index(-1);
+        batcher.next();
+        assert_eq!(batcher.next().unwrap().labels.0, array![2, -1]);
+
+        let spans = vec![
+            Text {
+                text: "it was bob".to_string(),
+                label: Span::new(Some((7, 9))),
+                metadata: None,
+            },
+            Text {
+                text: "who wrote".to_string(),
+                label: Span::new(None),
+                metadata: None,
+            },
+            Text {
+                text: "in paris".to_string(),
+                label: Span::new(Some((3, 7))),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(spans), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 4)
+            .unwrap()
+            .with_pad_batch(true);
+        // Answers that aren't in the text point at the first token, only padding is ignored.
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.labels.start, array![2, 0]);
+        assert_eq!(batch.labels.end, array![2, 0]);
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.labels.start, array![1, -100]);
+        assert_eq!(batch.labels.end, array![1, -100]);
+
+        let spans = vec![
+            Text {
+                text: "it was bob".to_string(),
+                label: Spans::new(vec![(0, 1), (7, 9)]),
+                metadata: None,
+            },
+            Text {
+                text: "who wrote".to_string(),
+                label: Spans::new(vec![]),
+                metadata: None,
+            },
+            Text {
+                text: "in paris".to_string(),
+                label: Spans::new(vec![(3, 7)]),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(spans), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 4)
+            .unwrap()
+            .with_pad_batch(true)
+            .with_ignore_index(-1);
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.labels.start, array![[0, 2], [-1, -1]]);
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.labels.start, array![[1], [-1]]);
+    }
+
+    #[test]
+    fn multi_span_loader() {
+        let file = fixture(
+            "multi_span.jsonl",
+            br#"{"question": "who wrote it", "context": "it was bob in paris", "spans": [[7, 9], [14, 18]]}
+{"question": "where", "context": "paris", "spans": []}
+{"context": "it was bob", "spans": [[7, 9]]}
+{"question": "who", "context": "bob", "spans": [[0, 5]]}
+not json
+"#,
+        );
+        let loader = MultiSpanLoader::<TextPair<Spans>>::new(&file, InvalidUtf8::Error)
+            .unwrap()
+            .with_on_malformed(MalformedPolicy::Skip);
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 12).unwrap();
+        let batch = batcher.next().unwrap();
+        // "bob" and "paris" in the context after the question, the second row has no answer.
+        assert_eq!(batch.labels.start, array![[5, 7], [-100, -100]]);
+        assert_eq!(batch.labels.end, array![[5, 7], [-100, -100]]);
+        // The line without question and the last two lines.
+        assert!(batcher.next().is_none());
+
+        // Texts don't need the question, the span outside of the context is an error.
+        let mut loader = MultiSpanLoader::<Text<Spans>>::new(&file, InvalidUtf8::Error).unwrap();
+        let texts: Vec<String> = (0..3).map(|_| loader.next().unwrap().text).collect();
+        assert_eq!(texts, vec!["it was bob in paris", "paris", "it was bob"]);
+        match raised(|| loader.next()) {
+            PreprocessingError::Parse(message) => {
+                assert!(message.contains("line 4"), "{}", message)
+            }
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn squad_split_context() {
+        // The paragraphs are 27, 30 and 22 characters long, "by bob" starts at byte 51.
+        let context =
+            "Alice wrote the first book.\nThe second one. It was by bob.\nBoth are set in Paris.";
+        let squad = fixture(
+            "split-squad.json",
+            format!(
+                r#"{{"data": [{{"title": "t", "paragraphs": [{{"context": "{}", "qas": [
+                    {{"question": "who wrote it?", "answers": [{{"answer_start": 51, "text": "by bob"}}], "is_impossible": false}},
+                    {{"question": "where?", "answers": [], "is_impossible": true}}
+                ]}}]}}]}}"#,
+                context.replace('\n', "\\n")
+            )
+            .as_bytes(),
+        );
+        let samples = |split_context: Option<usize>| -> Vec<_> {
+            let mut loader = SQuADLoader::new(&squad).unwrap();
+            if let Some(max_chars) = split_context {
+                loader = loader.with_split_context(max_chars).unwrap();
+            }
+            std::iter::from_fn(|| loader.next())
+                .map(|pair| (pair.text.0, pair.text.1, pair.label.span))
+                .collect()
+        };
+        let unsplit = samples(None);
+        assert_eq!(unsplit.len(), 2);
+        assert_eq!(unsplit[0].2, Some((51, 56)));
+        assert_eq!(samples(Some(1000)).len(), 2);
+
+        let split = samples(Some(35));
+        let chunks = [
+            "Alice wrote the first book.",
+            "The second one. It was by bob.",
+            "Both are set in Paris.",
+        ];
+        assert_eq!(split.len(), 6);
+        for (i, (question, chunk, span)) in split.iter().enumerate() {
+            assert_eq!(question, if i < 3 { "who wrote it?" } else { "where?" });
+            assert_eq!(chunk, chunks[i % 3]);
+            // The answer is only in the second chunk, the other chunks are impossible.
+            let expected = if i == 1 { Some((23, 28)) } else { None };
+            assert_eq!(*span, expected);
+        }
+        assert_eq!(&split[1].1[23..29], "by bob");
+
+        // Without a paragraph break in reach the chunk ends at a sentence end or a space, but
+        // not within the answer.
+        let split = samples(Some(20));
+        let chunks: Vec<&str> = split[..split.len() / 2]
+            .iter()
+            .map(|(_, chunk, _)| chunk.as_str())
+            .collect();
+        assert_eq!(
+            chunks,
+            [
+                "Alice wrote the",
+                "first book.",
+                "The second one.",
+                "It was by bob.",
+                "Both are set in",
+                "Paris."
+            ]
+        );
+        let answers: Vec<_> = split
+            .iter()
+            .filter_map(|(_, chunk, span)| span.map(|(start, end)| &chunk[start..=end]))
+            .collect();
+        assert_eq!(answers, ["by bob"]);
+        let split = samples(Some(12));
+        assert!(split.iter().any(|(_, chunk, _)| chunk == "It was"));
+        assert!(split.iter().any(|(_, chunk, _)| chunk == "by bob."));
+        // A chunk that would end within the answer is extended to its end.
+        let split = samples(Some(2));
+        assert!(split
+            .iter()
+            .any(|(_, chunk, span)| chunk == "by bob" && *span == Some((0, 5))));
+
+        assert!(SQuADLoader::new(&squad)
+            .unwrap()
+            .with_split_context(0)
+            .is_err());
+    }
+
+    #[test]
+    fn squad_answers() {
+        let squad = fixture(
+            "answers-squad.json",
+            r#"{"data": [{"title": "t", "paragraphs": [{"context": "it was written by bob", "qas": [
+                {"question": "who wrote the book ?", "answers": [{"answer_start": 18, "text": "bob"}], "is_impossible": false},
+                {"question": "who wrote it ?", "answers": [], "is_impossible": true},
+                {"question": "who was it ?", "answers": [{"answer_start": 15, "text": "by bob"}], "is_impossible": false}
+            ]}]}]}"#
+                .as_bytes(),
+        );
+        let batches = |keep_answers| {
+            let loader = SQuADLoader::new(&squad)
+                .unwrap()
+                .with_keep_answers(keep_answers);
+            let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+            let mut batcher = StaticBatcher::new(tokenizer, 2, 16)
+                .unwrap()
+                .with_pad_batch(true);
+            [batcher.next().unwrap(), batcher.next().unwrap()]
+        };
+        let [first, second] = batches(true);
+        assert_eq!(first.labels.start, array![9, 0]);
+        assert_eq!(
+            first.labels.answers.unwrap(),
+            ["bob".to_string(), String::new()]
+        );
+        // The padding row has no answer either.
+        assert_eq!(
+            second.labels.answers.unwrap(),
+            ["by bob".to_string(), String::new()]
+        );
+        let [first, second] = batches(false);
+        assert!(first.labels.answers.is_none() && second.labels.answers.is_none());
+    }
+
+    #[test]
+    fn pack() {
+        let file = fixture("pack-ids.txt", b"2 5 3\n2 10 11 3\n2 5 6 7 8 3\n2 17 3\n");
+        let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 0, None).unwrap();
+        let node = Pack::new(node, 8, Some(3)).unwrap();
+        let batch = StaticBatcher::new(node, 4, 8).unwrap().next().unwrap();
+        // The third sample doesn't fit after the first two anymore and the fourth not after it.
+        assert_eq!(
+            batch.encoding.input_ids,
+            array![
+                [2, 5, 3, 3, 2, 10, 11, 3],
+                [2, 5, 6, 7, 8, 3, 0, 0],
+                [2, 17, 3, 0, 0, 0, 0, 0]
+            ]
+        );
+        // The separator sits between the two packed samples and belongs to the first, the segment
+        // ids count the samples of every row from 1.
+        let sequence_ids: &Array2<i64> = batch.encoding.sequence_ids.as_ref().unwrap();
+        assert_eq!(batch.encoding.input_ids[[0, 3]], 3);
+        assert_eq!(
+            *sequence_ids,
+            array![
+                [1, 1, 1, 1, 2, 2, 2, 2],
+                [1, 1, 1, 1, 1, 1, 0, 0],
+                [1, 1, 1, 0, 0, 0, 0, 0]
+            ]
+        );
+        // All 16 tokens are kept, plus the one separator.
+        assert_eq!(batch.encoding.attention_mask.sum(), 17);
+        assert_eq!(batch.encoding.owned_tokens.sum(), 16);
+
+        let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 0, None).unwrap();
+        let mut node = Pack::new(node, 4, None).unwrap();
+        // No two samples fit into 4 tokens together, the longer one is passed on as it is.
+        let lengths: Vec<usize> = std::iter::from_fn(|| node.next())
+            .map(|sample| sample.encoding.input_ids.len())
+            .collect();
+        assert_eq!(lengths, vec![3, 4, 6, 3]);
+        let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 0, None).unwrap();
+        assert!(Pack::new(node, 0, None).is_err());
+    }
+
+    // The getter hands out the packed segment ids as int64. Needs numpy, skipped without it.
+    #[cfg(all(feature = "python", python_tests))]
+    #[test]
+    fn python_sequence_ids() {
+        use crate::python::ToPyObjectConsume;
+        use pyo3::Python;
+        pyo3::prepare_freethreaded_python();
+        let file = fixture("python-pack-ids.txt", b"2 5 3\n2 10 11 3\n");
+        let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 0, None).unwrap();
+        let node = Pack::new(node, 8, Some(3)).unwrap();
+        let batch = StaticBatcher::new(node, 1, 8).unwrap().next().unwrap();
+        Python::with_gil(|py| {
+            if py.import("numpy").is_err() {
+                println!("python_sequence_ids skipped, numpy isn't installed");
+                return;
+            }
+            let encoding = batch.encoding.to_object_consume(py);
+            let ids = encoding.getattr(py, "sequence_ids").unwrap();
+            let dtype: String = ids
+                .getattr(py, "dtype")
+                .and_then(|dtype| dtype.getattr(py, "name"))
+                .and_then(|name| name.extract(py))
+                .unwrap();
+            assert_eq!(dtype, "int64");
+            let ids: Vec<Vec<i64>> = ids.call_method0(py, "tolist").unwrap().extract(py).unwrap();
+            assert_eq!(ids, vec![vec![1, 1, 1, 1, 2, 2, 2, 2]]);
+        });
+    }
+
+    #[test]
+    fn finalize() {
+        let lines: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        assert!(!VecNode::new(texts(&lines)).finalize());
+        let mut shuffle = BufferedShuffle::new(VecNode::new(texts(&lines)), 5, Some(1)).unwrap();
+        let first = shuffle.next().unwrap();
+        assert!(shuffle.finalize());
+        // The rest of the buffer is emitted, but nothing is read from upstream anymore.
+        let mut flushed: Vec<usize> = std::iter::once(first)
+            .chain(std::iter::from_fn(|| shuffle.next()))
+            .map(|sample| sample.text.parse().unwrap())
+            .collect();
+        flushed.sort();
+        assert_eq!(flushed, (0..5).collect::<Vec<_>>());
+
+        // A finalized `Pack` drains the buffer of the shuffle upstream of it before it ends.
+        let ids: String = (100..110).map(|id| format!("2 {} 3\n", id)).collect();
+        let file = fixture("finalize-ids.txt", ids.as_bytes());
+        let shuffle = BufferedShuffle::new(IdsTxtLoader::new(&file).unwrap(), 4, Some(1)).unwrap();
+        let node = PreTokenized::new(shuffle, 0, None).unwrap();
+        let mut pack = Pack::new(node, 7, Some(3)).unwrap();
+        let mut packed = vec![pack.next().unwrap()];
+        assert!(pack.finalize());
+        packed.extend(std::iter::from_fn(|| pack.next()));
+        // Six samples were read, two per packed sequence.
+        assert_eq!(packed.len(), 3);
+        let mut middle: Vec<u32> = packed
+            .iter()
+            .flat_map(|sample| sample.encoding.input_ids.to_vec())
+            .filter(|&id| id >= 100)
+            .collect();
+        middle.sort();
+        assert_eq!(middle, (100..106).collect::<Vec<_>>());
+
+        let lines = ["paris", "it was bob", "paris", "paris"];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let mut batcher = TokenBudgetBatcher::new(tokenizer, 6, Some(2)).unwrap();
+        assert_eq!(batcher.next().unwrap().encoding.input_ids.dim(), (2, 3));
+        assert!(batcher.finalize());
+        // Only the sample read to check whether it still fits is left.
+        assert_eq!(batcher.next().unwrap().encoding.input_ids.dim(), (1, 1));
+        assert!(batcher.next().is_none());
+    }
+
+    #[test]
+    fn answer_retention() {
+        let samples = vec![
+            Text {
+                text: "it was written by bob".to_string(),
+                label: Span::new(Some((18, 20))),
+                metadata: None,
+            },
+            Text {
+                text: "bob wrote the book".to_string(),
+                label: Span::new(Some((0, 2))),
+                metadata: None,
+            },
+            // Unanswerable questions aren't counted.
+            Text {
+                text: "who wrote the book".to_string(),
+                label: Span::new(None),
+                metadata: None,
+            },
+        ];
+        let retention = |overflow| {
+            let mut tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples.clone()), test_tokenizer(), None)
+                    .unwrap()
+                    .with_truncation(3, 1, overflow)
+                    .unwrap();
+            while tokenizer.next().is_some() {}
+            tokenizer.answer_retention().unwrap()
+        };
+        // "bob" is cut off the first text.
+        let truncated = retention(false);
+        assert_eq!((truncated.answers(), truncated.retained()), (2, 1));
+        assert_eq!(truncated.rate(), 0.5);
+        // The last window of the first text contains it.
+        assert_eq!(retention(true).rate(), 1.0);
+
+        assert!(VecNode::new(texts(&["bob"])).answer_retention().is_none());
+    }
+
+    #[test]
+    fn global_attention_mask() {
+        let pair = TextPair {
+            text: ("who wrote it".to_string(), "it was bob".to_string()),
+            label: Span::new(Some((7, 9))),
+            metadata: None,
+        };
+        let batch = |global_attention| {
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(vec![pair.clone()]), test_tokenizer(), None)
+                    .unwrap()
+                    .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                    .unwrap();
+            StaticBatcher::new(tokenizer, 1, 10)
+                .unwrap()
+                .with_global_attention(global_attention)
+                .next()
+                .unwrap()
+        };
+        let with_mask = batch(true);
+        assert_eq!(
+            with_mask.encoding.input_ids,
+            array![[2, 5, 6, 10, 3, 10, 11, 15, 3, 0]]
+        );
+        // `[CLS]` and the question, but neither separators, the context nor padding.
+        assert_eq!(
+            with_mask.encoding.global_attention_mask.clone().unwrap(),
+            array![[1, 1, 1, 1, 0, 0, 0, 0, 0, 0]]
+        );
+        let names: Vec<&str> = with_mask
+            .into_named_arrays()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert!(names.contains(&"global_attention_mask"));
+        assert!(batch(false).encoding.global_attention_mask.is_none());
+    }
+
+    #[test]
+    fn retry() {
+        // Fails the first `failures` calls.
+        let attempts = |retry: Retry, failures: u32| {
+            let mut calls = 0;
+            let result = retry.run(
+                || {
+                    calls += 1;
+                    if calls <= failures {
+                        Err("connection reset")
+                    } else {
+                        Ok(calls)
+                    }
+                },
+                |_| true,
+            );
+            (result.map_err(|err| err.attempts), calls)
+        };
+        assert_eq!(attempts(Retry::new(3, Duration::ZERO), 2), (Ok(3), 3));
+        assert_eq!(attempts(Retry::new(3, Duration::ZERO), 5), (Err(4), 4));
+        assert_eq!(attempts(Retry::default(), 1), (Err(1), 1));
+        // Waits 10ms, then 20ms.
+        let start = Instant::now();
+        assert_eq!(
+            attempts(Retry::new(2, Duration::from_millis(10)), 5),
+            (Err(3), 3)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        // Errors that aren't transient fail right away.
+        let mut calls = 0;
+        let result = Retry::new(3, Duration::from_secs(10)).run(
+            || {
+                calls += 1;
+                Err::<(), _>("not found")
+            },
+            |error| *error != "not found",
+        );
+        assert_eq!((result.unwrap_err().attempts, calls), (1, 1));
+        // A mistyped model id isn't retried, an overloaded Hub is.
+        use cached_path::Error;
+        assert!(!crate::retry::is_transient(&Error::HttpStatusError(404)));
+        assert!(crate::retry::is_transient(&Error::HttpStatusError(503)));
+        assert!(crate::retry::is_transient(&Error::HttpTimeoutError));
+    }
+
+    #[test]
+    fn shard() {
+        let lines: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let read = |mut node: Box<dyn Node<Output = Text<NoLabel>>>| -> Vec<usize> {
+            std::iter::from_fn(|| node.next())
+                .map(|sample| sample.text.parse().unwrap())
+                .collect()
+        };
+        let shard = Shard::new(VecNode::new(texts(&lines)), 1, 3).unwrap();
+        assert_eq!(shard.len(), Some(3));
+        assert_eq!(shard.get(2).unwrap().text, "7");
+        assert_eq!(read(Box::new(shard)), vec![1, 4, 7]);
+        // Streams are read in order and skipped, the shards together cover every output once.
+        let file = fixture("shard.txt", (lines.join("\n") + "\n").as_bytes());
+        let mut covered: Vec<usize> = (0..3)
+            .flat_map(|index| {
+                let loader = TxtLoader::new(&file, InvalidUtf8::Error).unwrap();
+                let node = Shard::new(loader, index, 3).unwrap();
+                assert!(node.len().is_none());
+                read(Box::new(node))
+            })
+            .collect();
+        assert_eq!(covered[..4], [0, 3, 6, 9]);
+        covered.sort();
+        assert_eq!(covered, (0..10).collect::<Vec<_>>());
+        assert!(Shard::new(VecNode::new(texts(&lines)), 3, 3).is_err());
+    }
+
+    #[test]
+    fn batch_buffers() {
+        let file = fixture(
+            "buffer-ids.txt",
+            b"2 5 6 7 3\n2 10 11 3\n2 12 3\n2 3\n2 17 18 3\n",
+        );
+        let node = PreTokenized::new(IdsTxtLoader::new(&file).unwrap(), 1, None).unwrap();
+        let mut batcher = StaticBatcher::new(node, 2, 4).unwrap();
+        let matrices = |batch: &Batch<NoBatchLabel>| {
+            let encoding = &batch.encoding;
+            let mut pointers: Vec<*const u32> = [
+                &encoding.input_ids,
+                &encoding.attention_mask,
+                &encoding.token_type_ids,
+                &encoding.owned_tokens,
+            ]
+            .iter()
+            .map(|matrix| matrix.as_ptr())
+            .collect();
+            pointers.sort();
+            pointers
+        };
+        let mut first = batcher.next().unwrap();
+        let pointers = matrices(&first);
+        first.encoding.recycle();
+        assert_eq!(batcher.buffers.len(), 4);
+        assert!(first.encoding.input_ids.is_empty());
+
+        // A batch of the same shape reuses the buffers, refilled with the padding of their field.
+        let mut second = batcher.next().unwrap();
+        assert_eq!(matrices(&second), pointers);
+        assert_eq!(batcher.buffers.len(), 0);
+        assert_eq!(
+            second.encoding.input_ids,
+            array![[2, 12, 3, 1], [2, 3, 1, 1]]
+        );
+        assert_eq!(
+            second.encoding.attention_mask,
+            array![[1, 1, 1, 0], [1, 1, 0, 0]]
+        );
+        assert_eq!(second.encoding.owned_tokens, second.encoding.attention_mask);
+
+        // The short last batch has another shape and leaves the buffers in the pool.
+        second.encoding.recycle();
+        let last = batcher.next().unwrap();
+        assert_eq!(last.encoding.input_ids, array![[2, 17, 18, 3]]);
+        assert_eq!(batcher.buffers.len(), 4);
+    }
+
+    #[test]
+    fn random_crop() {
+        let samples: Vec<TokenizedText<NoTokenizedLabel>> = [
+            vec![2, 5, 6, 7, 8, 9, 10, 11, 3],
+            vec![2, 17, 3],
+            vec![2, 5, 6, 7, 8, 9, 3],
+        ]
+        .into_iter()
+        .map(|ids| TokenizedText {
+            encoding: Encoding::from_ids(ids, 0),
+            label: NoTokenizedLabel,
+        })
+        .collect();
+        let crops = |seed, epoch| {
+            let mut node = RandomCrop::new(VecNode::new(samples.clone()), 4, Some(seed)).unwrap();
+            node.set_epoch(epoch);
+            let indexed: Vec<Vec<u32>> = (0..3)
+                .map(|i| node.get(i).unwrap().encoding.input_ids.to_vec())
+                .collect();
+            let crops: Vec<Vec<u32>> = std::iter::from_fn(|| node.next())
+                .map(|sample| sample.encoding.input_ids.to_vec())
+                .collect();
+            assert_eq!(crops, indexed);
+            crops
+        };
+        let first = crops(7, 0);
+        assert_eq!(first.iter().map(Vec::len).collect::<Vec<_>>(), [4, 3, 4]);
+        // The short sample is passed on, the crops are consecutive tokens of the sample.
+        assert_eq!(first[1], [2, 17, 3]);
+        assert!(first[0]
+            .windows(2)
+            .all(|pair| pair[1] == pair[0] + 1 || pair[1] == 3));
+        assert_eq!(crops(7, 0), first);
+        assert!((1..10).any(|epoch| crops(7, epoch) != first));
+        assert!(RandomCrop::new(VecNode::new(samples.clone()), 0, None).is_err());
+    }
+
+    #[test]
+    fn total_tokens() {
+        // 3, 3 and 1 tokens.
+        let tokenizer = || {
+            Tokenizer::from_tokenizer(
+                VecNode::new(texts(&["who wrote it", "it was bob", "paris"])),
+                test_tokenizer(),
+                None,
+            )
+            .unwrap()
+        };
+        let mut node = tokenizer();
+        assert_eq!(node.total_tokens(), Some(0));
+        // Computing the lengths doesn't produce samples.
+        node.lengths().unwrap();
+        assert_eq!(node.total_tokens(), Some(0));
+        node.next().unwrap();
+        node.get(2).unwrap();
+        assert_eq!(node.total_tokens(), Some(4));
+
+        // The batcher counts what's left after truncation, without the repeated rows of the
+        // padded last batch, and hides the count of the tokenizer.
+        let batcher = StaticBatcher::new(tokenizer(), 2, 2)
+            .unwrap()
+            .with_pad_batch(true);
+        let mut node = Stats::new(batcher).unwrap();
+        while node.next().is_some() {}
+        assert_eq!(node.total_tokens(), Some(5));
+        assert_eq!(
+            Pack::new(tokenizer(), 4, None).unwrap().total_tokens(),
+            None
+        );
+    }
+
+    // While set, encoding a `BlockingText` that reads "block" waits, so it times out no matter
+    // how fast the machine is.
+    static ENCODE_BLOCKED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+    #[derive(Clone)]
+    struct BlockingText(Text<NoLabel>);
+
+    #[cfg(feature = "python")]
+    impl crate::python::ToPyObjectConsume for BlockingText {
+        fn to_object_consume(self, py: pyo3::Python<'_>) -> pyo3::PyObject {
+            self.0.to_object_consume(py)
+        }
+    }
+
+    impl Sample for BlockingText {
+        type Label = NoLabel;
+        const TRUNCATION_STRATEGY: tokenizer::TruncationStrategy =
+            <Text<NoLabel> as Sample>::TRUNCATION_STRATEGY;
+        fn tokenize(self, tokenizer: &tokenizer::Tokenizer) -> TokenizedText<NoTokenizedLabel> {
+            self.0.tokenize(tokenizer)
+        }
+        fn tokenize_overflowing(
+            self,
+            tokenizer: &tokenizer::Tokenizer,
+        ) -> Vec<TokenizedText<NoTokenizedLabel>> {
+            self.0.tokenize_overflowing(tokenizer)
+        }
+        fn input(&self) -> tokenizer::EncodeInput<'_> {
+            self.0.input()
+        }
+        fn encode(
+            &self,
+            tokenizer: &tokenizer::Tokenizer,
+            add_special_tokens: bool,
+        ) -> tokenizer::Encoding {
+            while self.0.text == "block"
+                && ENCODE_BLOCKED.load(std::sync::atomic::Ordering::Relaxed)
+            {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            self.0.encode(tokenizer, add_special_tokens)
+        }
+        fn sequence(&self, index: usize) -> Option<&str> {
+            self.0.sequence(index)
+        }
+        fn into_label(self) -> NoLabel {
+            self.0.label
+        }
+        fn take_metadata(&mut self) -> Option<crate::Metadata> {
+            self.0.take_metadata()
+        }
+        fn set_metadata(&mut self, metadata: crate::Metadata) {
+            self.0.set_metadata(metadata)
+        }
+    }
+
+    #[test]
+    fn tokenizer_timeout() {
+        let tokenizer = |policy| {
+            let samples = texts(&["who wrote it", "block", "paris", "bob"])
+                .into_iter()
+                .map(BlockingText)
+                .collect();
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None)
+                .unwrap()
+                .with_timeout(Duration::from_millis(500), policy)
+        };
+        let mut node = tokenizer(TimeoutPolicy::Skip);
+        assert_eq!(node.len(), None);
+        assert_eq!(node.get(0).map(|_| ()), None);
+        let ids: Vec<Vec<u32>> = std::iter::from_fn(|| node.next())
+            .map(|sample| sample.encoding.input_ids.to_vec())
+            .collect();
+        assert_eq!(ids, [vec![5, 6, 10], vec![17], vec![15]]);
+
+        let mut node = tokenizer(TimeoutPolicy::Raise);
+        assert_eq!(node.len(), Some(4));
+        assert!(node.get(2).is_some());
+        node.next().unwrap();
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| node.next()))
+            .err()
+            .unwrap();
+        match payload.downcast_ref::<PreprocessingError>() {
+            Some(PreprocessingError::Io(err)) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::TimedOut)
+            }
+            _ => panic!("Expected a timeout error"),
+        }
+        // A new thread takes over from the one that timed out.
+        assert_eq!(node.next().unwrap().encoding.input_ids.to_vec(), vec![17]);
+        ENCODE_BLOCKED.store(false, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    #[test]
+    fn list_loader() {
+        let row = |text: &str, label: Option<RowLabel>| (vec![text.to_string()], label);
+        let name = |name: &str| Some(RowLabel::Name(name.to_string()));
+        let rows = vec![
+            row("a", name("pos")),
+            row("b", name("neg")),
+            row("c", name("pos")),
+        ];
+        let mut loader = ListLoader::<Text<ClassLabel>>::new(rows.clone(), None).unwrap();
+        assert_eq!(loader.len(), Some(3));
+        assert_eq!(loader.get(2).unwrap().label.0, 0);
+        let labels: Vec<u32> = std::iter::from_fn(|| loader.next())
+            .map(|sample| sample.label.0)
+            .collect();
+        assert_eq!(labels, [0, 1, 0]);
+        let mapping = loader.label_mapping().unwrap();
+        assert_eq!(mapping["neg"], 1);
+
+        let mapping = HashMap::from([("neg".to_string(), 0), ("pos".to_string(), 1)]);
+        let loader = ListLoader::<Text<ClassLabel>>::new(rows, Some(mapping)).unwrap();
+        assert_eq!(loader.get(0).unwrap().label.0, 1);
+
+        let ids = vec![row("a", Some(RowLabel::Id(3))), row("b", name("pos"))];
+        assert!(ListLoader::<Text<ClassLabel>>::new(ids, None).is_err());
+        let unlabelled = vec![row("a", None)];
+        assert!(ListLoader::<Text<ClassLabel>>::new(unlabelled.clone(), None).is_err());
+        assert!(ListLoader::<TextPair<NoLabel>>::new(unlabelled.clone(), None).is_err());
+        let loader = ListLoader::<Text<NoLabel>>::new(unlabelled, None).unwrap();
+        assert_eq!(loader.get(0).unwrap().text, "a");
+        assert_eq!(loader.label_mapping(), None);
+    }
+
+    #[test]
+    fn strip_html() {
+        let html = "<html><head><style>p { color: red; }</style></head><body>\n\
+            <h1>Title</h1><p>Some <b>bo</b>ld &amp; <a href=\"/x\">linked</a> text.</p>\n\
+            <!-- a comment --><script type=\"text/javascript\">if (a < b) {}</script>\n\
+            <p>1 &lt; 2&nbsp;&#8212; &#x263A;<br/>AT&T</p></body></html>";
+        let mut node = StripHtml::new(VecNode::new(texts(&[html, "a < b"]))).unwrap();
+        assert_eq!(
+            node.next().unwrap().text,
+            "Title Some bold & linked text. 1 < 2 \u{2014} \u{263A} AT&T"
+        );
+        assert_eq!(node.next().unwrap().text, "a < b");
+
+        let pair = TextPair {
+            text: ("<i>who</i>".to_string(), "<p>bob</p>".to_string()),
+            label: ClassLabel(1),
+            metadata: None,
+        };
+        let sample = StripHtml::new(VecNode::new(vec![pair]))
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(sample.text, ("who".to_string(), "bob".to_string()));
+        assert_eq!(sample.label.0, 1);
+    }
+
+    #[test]
+    fn static_batcher_max_length() {
+        let tokenizer = || {
+            Tokenizer::from_tokenizer(
+                VecNode::new(texts(&["who wrote it"])),
+                test_tokenizer(),
+                None,
+            )
+            .unwrap()
+            .with_truncation(8, 0, false)
+            .unwrap()
+        };
+        assert_eq!(tokenizer().max_length(), Some(8));
+        let err = StaticBatcher::new_strict(Peekable::new(tokenizer()), 2, 16)
+            .err()
+            .unwrap();
+        assert!(matches!(err, PreprocessingError::InvalidArgument(_)));
+        // Without strict checking, it's only a warning.
+        assert!(StaticBatcher::new(tokenizer(), 2, 16).is_ok());
+        assert!(StaticBatcher::new_strict(tokenizer(), 2, 8).is_ok());
+        // Without truncation, the tokenizer doesn't declare a maximum length.
+        let untruncated =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["paris"])), test_tokenizer(), None)
+                .unwrap();
+        assert_eq!(untruncated.max_length(), None);
+        assert!(StaticBatcher::new_strict(untruncated, 2, 512).is_ok());
+    }
+
+    #[test]
+    fn min_hash_dedup() {
+        let samples = texts(&[
+            "the quick brown fox jumps over the lazy dog near the river",
+            "a completely different sentence about tokenizers and batches",
+            "the quick brown fox jumps over the lazy dog near the river",
+            "the quick brown fox jumps over the lazy dog near the lake",
+            "",
+            "",
+        ]);
+        let mut node = MinHashDedup::new(VecNode::new(samples), 64, 2, 0.5).unwrap();
+        assert!(node.get(0).is_none());
+        let kept: Vec<String> = std::iter::from_fn(|| node.next())
+            .map(|sample| sample.text)
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                "the quick brown fox jumps over the lazy dog near the river",
+                "a completely different sentence about tokenizers and batches",
+                "",
+            ]
+        );
+        // A strict threshold only drops exact duplicates.
+        let samples = texts(&["a b c d", "a b c d", "a b c e"]);
+        let mut node = MinHashDedup::new(VecNode::new(samples), 64, 2, 1.0).unwrap();
+        assert_eq!(std::iter::from_fn(|| node.next()).count(), 2);
+        assert!(MinHashDedup::new(VecNode::new(texts(&[])), 64, 2, 0.0).is_err());
+    }
+
+    #[test]
+    fn squad_trimmed_answers() {
+        let squad = fixture(
+            "padded-squad.json",
+            br#"{"data": [{"title": "t", "paragraphs": [{"context": "it was written by bob in paris", "qas": [
+                {"question": "who", "answers": [{"answer_start": 17, "text": " bob "}], "is_impossible": false},
+                {"question": "where", "answers": [{"answer_start": 24, "text": " "}], "is_impossible": false}
+            ]}]}]}"#,
+        );
+        let loader = |trim_answers| {
+            SQuADLoader::new(&squad)
+                .unwrap()
+                .with_keep_answers(true)
+                .with_trim_answers(trim_answers)
+        };
+        // Untrimmed, the span includes the spaces around the answer.
+        assert_eq!(loader(false).get(0).unwrap().label.span, Some((17, 21)));
+        let trimmed = loader(true);
+        let sample = trimmed.get(0).unwrap();
+        assert_eq!(sample.label.span, Some((18, 20)));
+        assert_eq!(sample.label.answer.as_deref(), Some("bob"));
+        // An answer of only whitespace makes the question impossible.
+        assert_eq!(trimmed.get(1).unwrap().label.span, None);
+        // "who it was written by bob in paris" without special tokens, the span lands on "bob".
+        let tokenizer = Tokenizer::from_tokenizer(trimmed, test_tokenizer(), None).unwrap();
+        let sample = tokenizer.get(0).unwrap();
+        assert_eq!(sample.encoding.input_ids[5], 15);
+        assert_eq!(sample.label.0, Some((5, 5)));
+    }
+
+    #[test]
+    fn with_text() {
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let mut node = WithText::new(Peekable::new(tokenizer)).unwrap();
+        let (text, sample) = node.get(1).unwrap();
+        assert_eq!(text, "paris");
+        assert_eq!(sample.encoding.input_ids.to_vec(), vec![17]);
+        let (text, sample) = node.next().unwrap();
+        assert_eq!(text, "who wrote it");
+        assert_eq!(sample.encoding.input_ids.to_vec(), vec![5, 6, 10]);
+        // Every overflowing window carries the text of its example.
+        let pairs = vec![TextPair {
+            text: ("who".to_string(), "it was written by bob".to_string()),
+            label: NoLabel(),
+            metadata: None,
+        }];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(4, 1, true)
+            .unwrap();
+        let mut node = WithText::new(tokenizer).unwrap();
+        let windows: Vec<String> = std::iter::from_fn(|| node.next())
+            .map(|(text, _)| text)
+            .collect();
+        assert!(windows.len() > 1);
+        assert!(windows
+            .iter()
+            .all(|text| text == "who\nit was written by bob"));
+        assert!(WithText::new(
+            PreTokenized::new(VecNode::new(Vec::<crate::Ids>::new()), 0, None).unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn weighted_sampler() {
+        let samples = || VecNode::new(texts(&["who", "wrote", "it"]));
+        let draw = |mut node: WeightedSampler<VecNode<Text<NoLabel>>>| -> Vec<String> {
+            std::iter::from_fn(|| node.next())
+                .map(|sample| sample.text)
+                .collect()
+        };
+        let weights = Array1::from_vec(vec![3.0, 0.0, 1.0]);
+        let sampler = WeightedSampler::new(samples(), weights.clone(), 400, Some(0)).unwrap();
+        assert_eq!(sampler.size_hint(), (400, Some(400)));
+        assert!(sampler.len().is_none() && sampler.get(0).is_none());
+        let drawn = draw(sampler);
+        assert_eq!(drawn.len(), 400);
+        // Samples without weight are never drawn, the others about as often as weighted.
+        assert!(!drawn.iter().any(|text| text == "wrote"));
+        let who = drawn.iter().filter(|text| *text == "who").count();
+        assert!((250..350).contains(&who), "{}", who);
+        // The same seed gives the same stream.
+        let again = WeightedSampler::new(samples(), weights, 400, Some(0)).unwrap();
+        assert_eq!(draw(again), drawn);
+        // Weights computed from the samples.
+        let by_length = WeightedSampler::with_weight_fn(
+            samples(),
+            |sample| (sample.text.len() == 2) as u8 as f64,
+            5,
+            None,
+        )
+        .unwrap();
+        assert_eq!(draw(by_length), vec!["it"; 5]);
+        assert!(WeightedSampler::new(samples(), Array1::from_vec(vec![1.0]), 5, None).is_err());
+        assert!(WeightedSampler::new(samples(), Array1::zeros(3), 5, None).is_err());
+
+        // The label mapping of the encoder is forwarded.
+        let labelled = ["positive", "negative"]
+            .iter()
+            .map(|label| Text {
+                text: "bob".to_string(),
+                label: crate::StringLabel(label.to_string()),
+                metadata: None,
+            })
+            .collect();
+        let mapping: HashMap<String, u32> =
+            [("negative".to_string(), 0), ("positive".to_string(), 1)]
+                .into_iter()
+                .collect();
+        let encoder = LabelEncoder::new(VecNode::new(labelled), Some(mapping.clone())).unwrap();
+        let weights = Array1::from_vec(vec![1.0, 1.0]);
+        let sampler = WeightedSampler::new(encoder, weights, 5, None).unwrap();
+        assert_eq!(sampler.label_mapping(), Some(mapping));
+    }
+
+    #[test]
+    fn both_labels() {
+        // A SQuAD v2 paragraph with an answerable and an unanswerable question.
+        let squad = fixture(
+            "both-squad.json",
+            br#"{"data": [{"title": "t", "paragraphs": [{"context": "it was written by bob in paris", "qas": [
+                {"question": "who wrote it", "answers": [{"answer_start": 18, "text": "bob"}], "is_impossible": false},
+                {"question": "where is the book", "answers": [], "is_impossible": true}
+            ]}]}]}"#,
+        );
+        let loader = SQuADLoader::new(&squad).unwrap();
+        // Answer span and answerability as two heads.
+        let samples: Vec<TextPair<Both<Span, ClassLabel>>> = (0..2)
+            .map(|index| {
+                let TextPair { text, label, .. } = loader.get(index).unwrap();
+                let answerable = ClassLabel(label.span.is_some() as u32);
+                TextPair {
+                    text,
+                    label: Both::new(label, answerable),
+                    metadata: None,
+                }
+            })
+            .collect();
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let sample = tokenizer.get(0).unwrap();
+        assert_eq!(sample.label.0 .0, Some((7, 7)));
+        assert_eq!(sample.label.1 .0, 1);
+        assert_eq!(tokenizer.answer_retention().unwrap().rate(), 1.0);
+        let batch = StaticBatcher::new(tokenizer, 2, 12)
+            .unwrap()
+            .get(0)
+            .unwrap();
+        let arrays = batch.into_named_arrays();
+        let labels: Vec<(&str, Vec<i64>)> = arrays[3..]
+            .iter()
+            .map(|(name, array)| (*name, array.iter().copied().collect()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("start_positions", vec![7, 0]),
+                ("end_positions", vec![7, 0]),
+                ("labels", vec![1, 0]),
+            ]
+        );
+
+        // The same labels from the `Span`s of the loader.
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        let batcher = StaticBatcher::new(SpanWithAnswerable::new(tokenizer).unwrap(), 2, 12);
+        let arrays = batcher.unwrap().get(0).unwrap().into_named_arrays();
+        let same: Vec<(&str, Vec<i64>)> = arrays[3..]
+            .iter()
+            .map(|(name, array)| (*name, array.iter().copied().collect()))
+            .collect();
+        assert_eq!(same, labels);
+    }
+
+    #[test]
+    fn timed() {
+        let throttled = Throttle::new(VecNode::new(texts(&["who", "wrote", "it"])), 5).unwrap();
+        let mut node = Timed::new(throttled, "throttle".to_string()).unwrap();
+        let timer = node.timer().unwrap();
+        assert_eq!(timer.name(), "throttle");
+        assert_eq!(timer.elapsed(), Duration::ZERO);
+        assert_eq!(std::iter::from_fn(|| node.next()).count(), 3);
+        node.get(0).unwrap();
+        assert!(timer.elapsed() >= Duration::from_millis(20));
+        // Wrapping nodes don't pass the timer on.
+        assert!(Peekable::new(node).timer().is_none());
+    }
+
+    #[test]
+    fn max_chars() {
+        let mut node = MaxChars::new(
+            VecNode::new(texts(&["who", "who wrote it", "über", "bob"])),
+            4,
+        )
+        .unwrap();
+        // The upstream length is kept as an estimate for progress bars.
+        assert_eq!(node.estimated_len(), Some(4));
+        let kept: Vec<String> = std::iter::from_fn(|| node.next()).map(|s| s.text).collect();
+        assert_eq!(kept, vec!["who", "über", "bob"]);
+        assert_eq!(node.len(), None);
+
+        let pair = |a: &str, b: &str| TextPair {
+            text: (a.to_string(), b.to_string()),
+            label: ClassLabel(0),
+            metadata: None,
+        };
+        let mut node = MaxChars::new(
+            VecNode::new(vec![pair("who", "bob"), pair("who", "wrote")]),
+            6,
+        )
+        .unwrap();
+        assert_eq!(node.next().unwrap().text.1, "bob");
+        assert!(node.next().is_none());
+    }
+
+    #[test]
+    fn batch_span_positions() {
+        let spans = vec![
+            Text {
+                text: "who wrote it".to_string(),
+                label: Span::new(Some((4, 8))),
+                metadata: None,
+            },
+            Text {
+                text: "in paris".to_string(),
+                label: Span::new(Some((3, 7))),
+                metadata: None,
+            },
+            Text {
+                text: "bob".to_string(),
+                label: Span::new(None),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(spans), test_tokenizer(), None).unwrap();
+        let batch = StaticBatcher::new(tokenizer, 3, 4).unwrap().next().unwrap();
+        let positions = batch.labels.positions();
+        assert_eq!(positions, array![[1, 1], [1, 1], [0, 0]]);
+        assert_eq!(positions.column(0), batch.labels.start);
+        assert_eq!(positions.column(1), batch.labels.end);
+    }
+
+    #[test]
+    fn one_hot() {
+        let one_hot = |vocab_size, sparse| {
+            let tokenizer = Tokenizer::from_tokenizer(
+                VecNode::new(texts(&["who wrote", "bob"])),
+                test_tokenizer(),
+                None,
+            )
+            .unwrap();
+            let batcher = StaticBatcher::new(tokenizer, 2, 3).unwrap();
+            OneHot::new(batcher, vocab_size, sparse).unwrap()
+        };
+        let batch = one_hot(20, false).next().unwrap();
+        let input_ids = batch.batch.encoding.input_ids.clone();
+        let dense = match batch.inputs {
+            OneHotInputs::Dense(dense) => dense,
+            _ => panic!("Expected dense inputs"),
+        };
+        assert_eq!(dense.dim(), (2, 3, 20));
+        for ((row, position), &id) in input_ids.indexed_iter() {
+            let token = dense.slice(s![row, position, ..]);
+            assert_eq!(token.sum(), 1.0);
+            assert_eq!(token[id as usize], 1.0);
+        }
+        assert_eq!(dense[[0, 0, 5]], 1.0);
+        assert_eq!(dense[[1, 0, 15]], 1.0);
+
+        let (indices, shape) = match one_hot(20, true).next().unwrap().inputs {
+            OneHotInputs::Sparse { indices, shape } => (indices, shape),
+            _ => panic!("Expected sparse inputs"),
+        };
+        assert_eq!(shape, (2, 3, 20));
+        let ones: Vec<Vec<i64>> = dense
+            .indexed_iter()
+            .filter(|(_, &value)| value == 1.0)
+            .map(|((row, position, id), _)| vec![row as i64, position as i64, id as i64])
+            .collect();
+        assert_eq!(
+            indices
+                .outer_iter()
+                .map(|index| index.to_vec())
+                .collect::<Vec<_>>(),
+            ones
+        );
+
+        // Bob is outside a vocabulary of 10, which warns. Other tests may warn at the same time.
+        let warnings = crate::warnings::count();
+        let indices = match one_hot(10, true).next().unwrap().inputs {
+            OneHotInputs::Sparse { indices, .. } => indices,
+            _ => panic!("Expected sparse inputs"),
+        };
+        assert_eq!(indices.nrows(), 5);
+        assert!(indices.column(2).iter().all(|&id| id != 15));
+        assert!(crate::warnings::count() > warnings);
+    }
+
+    #[test]
+    fn special_token_ids() {
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["who"])), test_tokenizer(), None)
+                .unwrap();
+        let node = MinLength::new(Peekable::new(tokenizer), 1).unwrap();
+        let tokenizer = node.tokenizer().unwrap();
+        assert_eq!(tokenizer.get_vocab_size(true), VOCAB.len());
+        assert_eq!(
+            SpecialTokenIds::of(&tokenizer),
+            SpecialTokenIds {
+                pad: Some(0),
+                cls: Some(2),
+                sep: Some(3),
+                mask: Some(4),
+            }
+        );
+        assert!(VecNode::new(texts(&["who"])).tokenizer().is_none());
+    }
+
+    // Downloads `bert-base-uncased`, run with `cargo test bert_special_token_ids -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bert_special_token_ids() {
+        let node = Tokenizer::new(
+            VecNode::new(texts(&["who"])),
+            "bert-base-uncased",
+            None,
+            None,
+            &Retry::default(),
+        )
+        .unwrap();
+        let tokenizer = node.tokenizer().unwrap();
+        assert_eq!(tokenizer.get_vocab_size(true), 30522);
+        assert_eq!(
+            SpecialTokenIds::of(&tokenizer),
+            SpecialTokenIds {
+                pad: Some(0),
+                cls: Some(101),
+                sep: Some(102),
+                mask: Some(103),
+            }
+        );
+    }
+
+    // Downloads `bert-base-uncased` at a pinned revision, run with
+    // `cargo test tokenizer_revision -- --ignored`.
+    #[test]
+    #[ignore]
+    fn tokenizer_revision() {
+        let load = |revision| {
+            Tokenizer::new(
+                VecNode::new(texts(&["who"])),
+                "bert-base-uncased",
+                revision,
+                None,
+                &Retry::default(),
+            )
+        };
+        let pinned = load(Some("main")).unwrap().tokenizer().unwrap();
+        let default = load(None).unwrap().tokenizer().unwrap();
+        assert_eq!(pinned.get_vocab(true), default.get_vocab(true));
+        assert!(load(Some("no-such-revision")).is_err());
+    }
+
+    #[test]
+    fn span_snap_window() {
+        let encoding = test_tokenizer().encode("who wrote it", false).unwrap();
+        let token_span = |span: Span| crate::Label::tokenize(span, &encoding, 0).0;
+        // The answer starts and ends on the spaces around "wrote", which have no tokens.
+        assert_eq!(token_span(Span::new(Some((3, 9)))), None);
+        assert_eq!(
+            token_span(Span::new(Some((3, 9))).with_snap_window(1)),
+            Some((1, 1))
+        );
+        assert_eq!(
+            token_span(Span::new(Some((3, 10))).with_snap_window(1)),
+            Some((1, 2))
+        );
+
+        // Characters inside the span are searched first, then those before it.
+        let encoding = test_tokenizer().encode("who   wrote", false).unwrap();
+        let token_span = |span: Span| crate::Label::tokenize(span, &encoding, 0).0;
+        let span = Span::new(Some((3, 10)));
+        assert_eq!(token_span(span.clone().with_snap_window(2)), Some((0, 1)));
+        assert_eq!(token_span(span.with_snap_window(3)), Some((1, 1)));
+    }
+
+    #[test]
+    fn retrieval_batcher() {
+        let path = fixture(
+            "retrieval.jsonl",
+            b"{\"query\": \"who\", \"passages\": [{\"text\": \"bob wrote it\", \"label\": 1}, {\"text\": \"in paris\", \"label\": 0}]}\n\
+            {\"query\": \"where\", \"passages\": [{\"text\": \"paris\", \"label\": 1}]}\n\
+            {\"query\": \"who wrote it\", \"passages\": []}\n",
+        );
+        let loader = RetrievalLoader::new(&path, InvalidUtf8::Error).unwrap();
+        let mut batcher =
+            RetrievalBatcher::from_tokenizer(loader, test_tokenizer(), 2, 4, None).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.labels, array![[1, 0], [1, -100]]);
+        assert_eq!(
+            batch.encoding.input_ids,
+            array![[5, 15, 6, 10], [5, 16, 17, 0], [18, 17, 0, 0]]
+        );
+        assert_eq!(
+            batch.encoding.overflow_to_sample_mapping.unwrap(),
+            array![0, 0, 1]
+        );
+        // The query without passages is skipped.
+        assert!(batcher.next().is_none());
+        // Other nodes can't pass queries without passages.
+        let queries = VecNode::new(vec![crate::Retrieval::new("who".to_string(), Vec::new())]);
+        let mut batcher =
+            RetrievalBatcher::from_tokenizer(queries, test_tokenizer(), 2, 4, None).unwrap();
+        assert!(matches!(
+            raised(|| batcher.next()),
+            PreprocessingError::InvalidArgument(_)
+        ));
+
+        let path = fixture("retrieval-invalid.jsonl", b"{\"query\": \"who\"}\n");
+        let mut loader = RetrievalLoader::new(&path, InvalidUtf8::Error).unwrap();
+        match raised(|| loader.next()) {
+            PreprocessingError::Parse(message) => assert!(message.contains("line 1")),
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn causal_lm_batcher() {
+        let batcher = |seq_length| {
+            let tokenizer = Tokenizer::from_tokenizer(
+                VecNode::new(texts(&["who wrote it", "bob"])),
+                test_tokenizer(),
+                None,
+            )
+            .unwrap();
+            CausalLMBatcher::new(tokenizer, 2, seq_length, Some(2), Some(3)).unwrap()
+        };
+        let batch = batcher(6).next().unwrap();
+        assert_eq!(
+            batch.encoding.input_ids,
+            array![[2, 5, 6, 10, 3, 0], [2, 15, 3, 0, 0, 0]]
+        );
+        assert_eq!(
+            batch.labels.0,
+            array![[2, 5, 6, 10, 3, -100], [2, 15, 3, -100, -100, -100]]
+        );
+        // Truncation cuts the text, not the EOS.
+        let batch = batcher(4).next().unwrap();
+        assert_eq!(
+            batch.encoding.input_ids,
+            array![[2, 5, 6, 3], [2, 15, 3, 0]]
+        );
+        assert_eq!(batch.labels.0, array![[2, 5, 6, 3], [2, 15, 3, -100]]);
+        assert!(CausalLMBatcher::new(VecNode::new(Vec::new()), 2, 2, Some(2), Some(3)).is_err());
+    }
+
+    #[test]
+    fn overflow_repeats_question() {
+        let question = "where is paris ?";
+        let context = "it was written by alice in paris . it was a long story by bob";
+        let pair = TextPair {
+            text: (question.to_string(), context.to_string()),
+            label: NoLabel(),
+            metadata: None,
+        };
+        let context_ids = test_tokenizer()
+            .encode(context, false)
+            .unwrap()
+            .get_ids()
+            .to_vec();
+        let mut tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(vec![pair]), test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                .unwrap()
+                .with_truncation(12, 2, true)
+                .unwrap();
+        let mut windows = Vec::new();
+        while let Some(window) = tokenizer.next() {
+            windows.push(window.encoding);
+        }
+        assert_eq!(windows.len(), 5);
+        let mut owned_context = Vec::new();
+        for window in &windows {
+            // "[CLS] where is paris ? [SEP]" starts every window, only the context is windowed.
+            let ids = window.input_ids.to_vec();
+            assert_eq!(ids[..6], [2, 18, 19, 17, 9, 3]);
+            assert!(window
+                .token_type_ids
+                .iter()
+                .take(6)
+                .all(|&type_id| type_id == 0));
+            assert!(window
+                .token_type_ids
+                .iter()
+                .skip(6)
+                .all(|&type_id| type_id == 1));
+            assert_eq!(ids.last(), Some(&3));
+            owned_context.extend(
+                ids.iter()
+                    .zip(&window.owned_tokens)
+                    .filter(|(_, &owned)| owned == 1)
+                    .map(|(&id, _)| id),
+            );
+        }
+        assert_eq!(owned_context, context_ids);
+    }
+
+    #[test]
+    fn data_loader_shuffled_drain() {
+        let lines: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let run = |drain_order, seed| -> Vec<usize> {
+            let mut loader = DataLoader::new(VecNode::new(texts(&lines)), 3, 2)
+                .unwrap()
+                .with_drain_order(drain_order, Some(seed));
+            std::iter::from_fn(|| loader.next())
+                .map(|sample| sample.text.parse().unwrap())
+                .collect()
+        };
+        assert_eq!(run(DrainOrder::Fifo, 0), (0..50).collect::<Vec<_>>());
+        let shuffled = run(DrainOrder::Shuffled, 0);
+        assert_ne!(shuffled, (0..50).collect::<Vec<_>>());
+        assert_eq!(shuffled, run(DrainOrder::Shuffled, 0));
+        assert_ne!(shuffled, run(DrainOrder::Shuffled, 1));
+        // Only the next 6 outputs are buffered, so none is emitted earlier than that.
+        for (position, &index) in shuffled.iter().enumerate() {
+            assert!(index < position + 6);
+        }
+        let mut sorted = shuffled;
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..50).collect::<Vec<_>>());
+        assert!("lifo".parse::<DrainOrder>().is_err());
+    }
+
+    #[test]
+    fn dual_tokenizer() {
+        // A student with the same words under different ids.
+        let vocab: HashMap<String, u32> = VOCAB
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32 + 100))
+            .collect();
+        let model = WordPiece::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut student = tokenizer::Tokenizer::new(model);
+        student.with_normalizer(BertNormalizer::default());
+        student.with_pre_tokenizer(BertPreTokenizer);
+
+        let lines = ["who wrote the book", "it was bob", "in paris"];
+        let mut node = DualTokenizer::from_tokenizers(
+            VecNode::new(texts(&lines)),
+            test_tokenizer(),
+            student,
+            2,
+            5,
+        )
+        .unwrap();
+        assert_eq!(node.len(), Some(2));
+        let first = node.next().unwrap();
+        assert_eq!(
+            first.teacher.input_ids,
+            array![[5, 6, 7, 8, 0], [10, 11, 15, 0, 0]]
+        );
+        assert_eq!(
+            first.student.input_ids,
+            array![[105, 106, 107, 108, 100], [110, 111, 115, 100, 100]]
+        );
+        assert_eq!(first.teacher.attention_mask, first.student.attention_mask);
+        let second = node.next().unwrap();
+        assert_eq!(second.teacher.input_ids, array![[16, 17, 0, 0, 0]]);
+        assert_eq!(second.student.input_ids, array![[116, 117, 100, 100, 100]]);
+        assert!(node.next().is_none());
+    }
+
+    #[test]
+    fn span_multibyte_chars() {
+        // Every word of random, partly multibyte characters is one token.
+        let whitespace_tokenizer = || {
+            let vocab = HashMap::from([("[PAD]".to_string(), 0), ("[UNK]".to_string(), 1)]);
+            let model = WordLevel::builder()
+                .vocab(vocab)
+                .unk_token("[UNK]".to_string())
+                .build()
+                .unwrap();
+            let mut tokenizer = tokenizer::Tokenizer::new(model);
+            tokenizer.with_pre_tokenizer(WhitespaceSplit);
+            tokenizer
+        };
+        let alphabet = ['a', 'b', 'é', 'ß', 'ü', '日', '😀'];
+        let mut rng = seed::node_rng(Some(0));
+        for _ in 0..200 {
+            let words: Vec<String> = (0..rng.gen_range(1..8))
+                .map(|_| {
+                    (0..rng.gen_range(1..5))
+                        .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                        .collect()
+                })
+                .collect();
+            let text = words.join(" ");
+            let word = rng.gen_range(0..words.len());
+            let start: usize = words[..word]
+                .iter()
+                .map(|word| word.chars().count() + 1)
+                .sum();
+            let end = start + words[word].chars().count() - 1;
+            let sample = |span| Text {
+                text: text.clone(),
+                label: Span::new(Some(span)),
+                metadata: None,
+            };
+
+            let mut tokenizer = Tokenizer::from_tokenizer(
+                VecNode::new(vec![sample((start, end))]),
+                whitespace_tokenizer(),
+                None,
+            )
+            .unwrap();
+            assert_eq!(tokenizer.next().unwrap().label.0, Some((word, word)));
+            assert_eq!(
+                tokenizer.reconstruct(0, 0, word, word),
+                Some(Some(words[word].clone()))
+            );
+            let mut bytes = ByteTokenizer::new(
+                VecNode::new(vec![sample((start, end))]),
+                None,
+                None,
+                0,
+                None,
+            )
+            .unwrap();
+            let (first, last) = bytes.next().unwrap().label.0.unwrap();
+            assert_eq!(&text.as_bytes()[first..=last], words[word].as_bytes());
+
+            // Arbitrary spans, e.g. byte offsets past the last character, don't panic.
+            let len = text.len() + 2;
+            let (start, end) = (rng.gen_range(0..len), rng.gen_range(0..len));
+            sample((start, end)).tokenize(&whitespace_tokenizer());
+            ByteTokenizer::new(
+                VecNode::new(vec![sample((start, end))]),
+                None,
+                None,
+                0,
+                None,
+            )
+            .unwrap()
+            .next();
+        }
+    }
+
+    #[test]
+    fn standardize() {
+        let batches: Vec<NamedBatch> = (0..3i64)
+            .map(|batch| {
+                let features = Array1::from_iter((0..8).map(|i| (batch * 8 + i) * (i % 2 * 9 + 1)));
+                let features = features.into_shape((4, 2)).unwrap().into_dyn();
+                NamedBatch(vec![
+                    (
+                        "input_ids",
+                        array![[1, 2], [3, 4], [5, 6], [7, 8]].into_dyn(),
+                    ),
+                    ("features", features),
+                ])
+            })
+            .collect();
+        // Offline statistics of every column over all batches.
+        let values: Vec<Vec<f64>> = (0..2)
+            .map(|column| {
+                batches
+                    .iter()
+                    .flat_map(|batch| batch.0[1].1.index_axis(ndarray::Axis(1), column).to_owned())
+                    .map(|value| value as f64)
+                    .collect()
+            })
+            .collect();
+        let mean: Vec<f64> = values
+            .iter()
+            .map(|values| values.iter().sum::<f64>() / values.len() as f64)
+            .collect();
+        let std: Vec<f64> = values
+            .iter()
+            .zip(&mean)
+            .map(|(values, mean)| {
+                let variance = values
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f64>();
+                (variance / values.len() as f64).sqrt()
+            })
+            .collect();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-4;
+
+        // With a warmup over all batches, every batch uses the statistics of the whole stream.
+        let mut node =
+            Standardize::new(VecNode::new(batches.clone()), "features".to_string(), 3).unwrap();
+        assert!(node.get(0).is_none());
+        let outputs: Vec<_> = std::iter::from_fn(|| node.next()).collect();
+        assert_eq!(outputs.len(), 3);
+        let (learned_mean, learned_std) = node.feature_stats().unwrap();
+        for column in 0..2 {
+            assert!(close(learned_mean[column], mean[column]));
+            assert!(close(learned_std[column], std[column]));
+        }
+        for (output, batch) in outputs.iter().zip(&batches) {
+            assert_eq!(output.name, "features");
+            assert_eq!(output.arrays.len(), 1);
+            assert_eq!(output.arrays[0].0, "input_ids");
+            assert_eq!(output.feature.shape(), &[4, 2]);
+            for row in 0..4 {
+                for column in 0..2 {
+                    let value = batch.0[1].1[[row, column]] as f64;
+                    let expected = (value - mean[column]) / std[column];
+                    assert!(close(output.feature[[row, column]] as f64, expected));
+                }
+            }
+        }
+
+        // Given statistics are used as they are, also with `get`.
+        let node = Standardize::new(VecNode::new(batches.clone()), "features".to_string(), 0)
+            .unwrap()
+            .with_stats(Array1::from(mean.clone()), Array1::from(std.clone()))
+            .unwrap();
+        assert_eq!(node.get(2).unwrap().feature, outputs[2].feature);
+        assert!(
+            Standardize::new(VecNode::new(batches.clone()), "features".to_string(), 0)
+                .unwrap()
+                .with_stats(array![0.0], array![1.0, 1.0])
+                .is_err()
+        );
+        let mut node = Standardize::new(VecNode::new(batches), "missing".to_string(), 0).unwrap();
+        match raised(|| node.next()) {
+            PreprocessingError::InvalidArgument(message) => assert!(message.contains("missing")),
+            err => panic!("Expected an invalid argument error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn context_start() {
+        let pair = |question: &str, context: &str| TextPair {
+            text: (question.to_string(), context.to_string()),
+            label: NoLabel(),
+            metadata: None,
+        };
+        let pairs = vec![
+            pair("who wrote the book ?", "it was bob"),
+            pair("where is paris ?", "in paris"),
+        ];
+        let templated =
+            Tokenizer::from_tokenizer(VecNode::new(pairs.clone()), test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                .unwrap();
+        let batch = StaticBatcher::new(templated, 2, 12)
+            .unwrap()
+            .next()
+            .unwrap();
+        // "[CLS] who wrote the book ? [SEP] it ..." and "[CLS] where is paris ? [SEP] in ...".
+        assert_eq!(batch.encoding.prefix_special_tokens, array![1, 1]);
+        assert_eq!(batch.encoding.context_start, array![7, 6]);
+        assert_eq!(batch.encoding.input_ids[[0, 7]], 10);
+        assert_eq!(batch.encoding.input_ids[[1, 6]], 16);
+
+        // Without special tokens the context directly follows the question.
+        let plain = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None).unwrap();
+        let batch = StaticBatcher::new(plain, 2, 12).unwrap().next().unwrap();
+        assert_eq!(batch.encoding.prefix_special_tokens, array![0, 0]);
+        assert_eq!(batch.encoding.context_start, array![5, 4]);
+    }
+
+    #[test]
+    fn optional_labels() {
+        let row =
+            |text: &str, label: Option<u32>| (vec![text.to_string()], label.map(RowLabel::Id));
+        let rows = vec![
+            row("who wrote the book", Some(2)),
+            row("it was bob", None),
+            row("where is paris", Some(0)),
+        ];
+        assert!(ListLoader::<Text<ClassLabel>>::new(rows.clone(), None).is_err());
+        let loader = ListLoader::<Text<OptionalLabel<ClassLabel>>>::new(rows, None).unwrap();
+        assert!(loader.get(1).unwrap().label.0.is_none());
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        // The padded last row counts as unlabelled too.
+        let mut batcher = StaticBatcher::new(tokenizer, 4, 8)
+            .unwrap()
+            .with_pad_batch(true);
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.labels.labels.0.to_vec(), vec![2, -100, 0, -100]);
+        assert_eq!(batch.labels.present.to_vec(), vec![1, 0, 1, 0]);
+        let arrays = batch.labels.into_named_arrays();
+        let names: Vec<&str> = arrays.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["labels", "label_mask"]);
+        assert!(batcher.next().is_none());
+    }
+
+    #[test]
+    fn longer_second() {
+        fn pair<T: crate::Label>(label: T) -> TextPair<T> {
+            TextPair {
+                text: ("it was written by bob".to_string(), "who".to_string()),
+                label,
+                metadata: None,
+            }
+        }
+        let classes = vec![pair(ClassLabel(1)), pair(ClassLabel(0))];
+        // Only the second sequence is truncated, which is now the longer one.
+        let mut tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(classes), test_tokenizer(), None)
+                .unwrap()
+                .with_truncation(4, 0, false)
+                .unwrap()
+                .with_longer_second(true);
+        assert_eq!(
+            tokenizer.get(0).unwrap().encoding.input_ids.to_vec(),
+            vec![5, 10, 11, 12]
+        );
+        assert_eq!(
+            tokenizer.next().unwrap().encoding.input_ids.to_vec(),
+            vec![5, 10, 11, 12]
+        );
+        let unlabelled = vec![pair(NoLabel())];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(unlabelled), test_tokenizer(), None)
+            .unwrap()
+            .with_longer_second(true);
+        assert_eq!(
+            tokenizer.get(0).unwrap().encoding.input_ids.to_vec(),
+            vec![5, 10, 11, 12, 13, 15]
+        );
+
+        let spans = vec![pair(Span::new(Some((0, 2))))];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(spans), test_tokenizer(), None)
+            .unwrap()
+            .with_longer_second(true);
+        let sample = tokenizer.get(0).unwrap();
+        assert_eq!(
+            sample.encoding.input_ids.to_vec(),
+            vec![10, 11, 12, 13, 15, 5]
+        );
+        assert_eq!(sample.label.0, Some((5, 5)));
+    }
+
+    #[test]
+    fn multi_labels() {
+        let samples = vec![
+            Text {
+                text: "who wrote the book".to_string(),
+                label: MultiLabel::new(vec![1, 3]),
+                metadata: None,
+            },
+            Text {
+                text: "it was bob".to_string(),
+                label: MultiLabel::new(vec![2]),
+                metadata: None,
+            },
+        ];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 3, 8)
+            .unwrap()
+            .with_pad_batch(true);
+        let batch = batcher.next().unwrap();
+        // Padded to the most labels of a row, the padded last row has none.
+        assert_eq!(
+            batch.labels.0,
+            ndarray::arr2(&[[1, 3], [2, -100], [-100, -100]])
+        );
+        assert!(batcher.next().is_none());
+    }
+
+    #[test]
+    fn mmap_round_trip() {
+        // Reads back the shape in the header and the input ids of a written file.
+        fn read(path: &PathBuf) -> (String, Vec<u32>) {
+            let bytes = fs::read(path).unwrap();
+            assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+            let header = String::from_utf8(bytes[10..128].to_vec()).unwrap();
+            let ids = bytes[128..]
+                .chunks(4)
+                .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+                .collect();
+            (header, ids)
+        }
+        let lines = ["who wrote it", "it was bob", "paris"];
+        let expected = vec![5, 6, 10, 0, 10, 11, 15, 0, 17, 0, 0, 0];
+        // Preallocated for two full batches, the short last one truncates the file.
+        let path = std::env::temp_dir().join("preprocessing-known.npy");
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let mut known = StaticBatcher::new(tokenizer, 2, 4).unwrap();
+        assert_eq!(write_mmap(&mut known, &path).unwrap(), (3, 4));
+        let (header, ids) = read(&path);
+        assert!(header.starts_with("{'descr': '<u4', 'fortran_order': False, 'shape': (3, 4), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(ids, expected);
+        // Without a length the file grows with every batch.
+        let file = fixture("mmap.txt", lines.join("\n").as_bytes());
+        let path = std::env::temp_dir().join("preprocessing-streamed.npy");
+        let loader = TxtLoader::new(&file, InvalidUtf8::Error).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        let mut streamed = StaticBatcher::new(tokenizer, 2, 4).unwrap();
+        assert_eq!(write_mmap(&mut streamed, &path).unwrap(), (3, 4));
+        assert_eq!(read(&path).1, expected);
+    }
+
+    #[test]
+    fn answer_policy() {
+        let squad = r#"{"data": [{"title": "t", "paragraphs": [{"context": "it was written by alice in paris", "qas": [{"question": "who?", "answers": [{"answer_start": 18, "text": "alice"}, {"answer_start": 7, "text": "written by alice"}, {"answer_start": 24, "text": "in"}, {"answer_start": 18, "text": "alice in paris"}, {"answer_start": 0, "text": "bob"}], "is_impossible": false}]}]}]}"#;
+        let squad = fixture("policy-squad.json", squad.as_bytes());
+        let answer = |policy| {
+            let loader = SQuADLoader::new(&squad)
+                .unwrap()
+                .with_keep_answers(true)
+                .with_answer_policy(policy);
+            let label = loader.get(0).unwrap().label;
+            (label.span.unwrap(), label.answer.unwrap())
+        };
+        // The answer that isn't in the context doesn't count as the last one.
+        assert_eq!(answer(AnswerPolicy::First), ((18, 22), "alice".to_string()));
+        assert_eq!(
+            answer(AnswerPolicy::Last),
+            ((18, 31), "alice in paris".to_string())
+        );
+        assert_eq!(answer(AnswerPolicy::Shortest), ((24, 25), "in".to_string()));
+        assert_eq!(
+            answer(AnswerPolicy::Longest),
+            ((7, 22), "written by alice".to_string())
+        );
+        assert!("random".parse::<AnswerPolicy>().is_err());
+    }
+
+    #[test]
+    fn word_spans() {
+        let text = "  who wrote\tthe  café?\n";
+        let spans = crate::word_spans(text);
+        assert_eq!(spans, [(2, 5), (6, 11), (12, 15), (17, 22)]);
+        let chars: Vec<char> = text.chars().collect();
+        let words: Vec<String> = spans
+            .iter()
+            .map(|&(start, end)| chars[start..end].iter().collect())
+            .collect();
+        assert_eq!(words, text.split_whitespace().collect::<Vec<_>>());
+        assert!(crate::word_spans(" \t ").is_empty());
+        let pair = TextPair {
+            text: ("who".to_string(), "it was bob".to_string()),
+            label: NoLabel(),
+            metadata: None,
+        };
+        assert_eq!(pair.word_spans(0), Some(vec![(0, 3)]));
+        assert_eq!(pair.word_spans(1), Some(vec![(0, 2), (3, 6), (7, 10)]));
+        assert!(pair.word_spans(2).is_none());
+    }
+
+    #[test]
+    fn char_offsets_required() {
+        // Like the encoding of a tokenizer without offsets, every token covers an empty range.
+        let probe = tokenizer::Encoding::new(
+            vec![5, 6],
+            vec![0, 0],
+            vec!["who".to_string(), "wrote".to_string()],
+            vec![Some(0), Some(1)],
+            vec![(0, 0), (0, 0)],
+            vec![0, 0],
+            vec![1, 1],
+            Vec::new(),
+            HashMap::new(),
+        );
+        let err = crate::check_char_offsets(&probe).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "The tokenizer doesn't provide character offsets, which span labels need to be mapped to tokens"
+        );
+        let empty = tokenizer::Encoding::default();
+        assert!(crate::check_char_offsets(&empty).is_ok());
+        let spans = VecNode::new(Vec::<TextPair<Span>>::new());
+        assert!(Tokenizer::from_tokenizer(spans, test_tokenizer(), None).is_ok());
+    }
+
+    #[test]
+    fn label_smooth() {
+        let samples = vec![
+            Text {
+                text: "who wrote the book".to_string(),
+                label: ClassLabel(2),
+                metadata: None,
+            },
+            Text {
+                text: "it was bob".to_string(),
+                label: ClassLabel(0),
+                metadata: None,
+            },
+        ];
+        let batcher = || {
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples.clone()), test_tokenizer(), None)
+                    .unwrap();
+            StaticBatcher::new(tokenizer, 3, 8)
+                .unwrap()
+                .with_pad_batch(true)
+        };
+        assert!(LabelSmooth::new(VecNode::new(Vec::new()), 1, 0.1).is_err());
+        assert!(LabelSmooth::new(VecNode::new(Vec::new()), 3, 1.0).is_err());
+        let mut smooth = LabelSmooth::new(batcher(), 3, 0.1).unwrap();
+        let targets = smooth.next().unwrap().targets;
+        assert_eq!(targets.dim(), (3, 3));
+        for (row, label) in [(0, 2), (1, 0)] {
+            assert!((targets.row(row).sum() - 1.0).abs() < 1e-6);
+            assert!((targets[[row, label]] - 0.9).abs() < 1e-6);
+            for other in (0..3).filter(|&class| class != label) {
+                assert!((targets[[row, other]] - 0.05).abs() < 1e-6);
+            }
+        }
+        // The padded row has no label.
+        assert_eq!(targets.row(2).sum(), 0.0);
+        assert!(smooth.next().is_none());
+
+        // Classes beyond `num_classes` are an error.
+        let mut smooth = LabelSmooth::new(batcher(), 2, 0.1).unwrap();
+        assert!(matches!(
+            raised(|| smooth.next()),
+            PreprocessingError::InvalidArgument(_)
+        ));
+    }
+
+    #[test]
+    fn multiple_choice_batcher() {
+        let path = fixture(
+            "multiple-choice.jsonl",
+            b"{\"context\": \"who wrote it\", \"options\": [\"bob\", \"alice\", \"paris\"], \"label\": 0}\n\
+            {\"context\": \"where\", \"options\": [\"bob\", \"in paris\", \"it\"], \"label\": 1}\n\
+            {\"context\": \"who\", \"options\": [\"bob\", \"alice\", \"it\"], \"label\": 2}\n",
+        );
+        let loader = MultipleChoiceLoader::new(&path, InvalidUtf8::Error).unwrap();
+        let mut batcher =
+            MultipleChoiceBatcher::from_tokenizer(loader, test_tokenizer(), 2, 3, 4, None).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.input_ids.dim(), (2, 3, 4));
+        assert_eq!(batch.attention_mask.dim(), (2, 3, 4));
+        assert_eq!(batch.token_type_ids.dim(), (2, 3, 4));
+        assert_eq!(
+            batch.input_ids,
+            array![
+                [[5, 6, 10, 15], [5, 6, 10, 14], [5, 6, 10, 17]],
+                [[18, 15, 0, 0], [18, 16, 17, 0], [18, 10, 0, 0]]
+            ]
+        );
+        assert_eq!(batch.labels, array![0, 1]);
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.input_ids.dim(), (1, 3, 4));
+        assert_eq!(batch.labels, array![2]);
+        assert!(batcher.next().is_none());
+        let loader = MultipleChoiceLoader::new(&path, InvalidUtf8::Error).unwrap();
+        assert!(
+            MultipleChoiceBatcher::from_tokenizer(loader, test_tokenizer(), 2, 0, 4, None).is_err()
+        );
+        // Examples with a different number of options are an error.
+        let loader = MultipleChoiceLoader::new(&path, InvalidUtf8::Error).unwrap();
+        let mut batcher =
+            MultipleChoiceBatcher::from_tokenizer(loader, test_tokenizer(), 2, 2, 4, None).unwrap();
+        assert!(matches!(
+            raised(|| batcher.next()),
+            PreprocessingError::InvalidArgument(_)
+        ));
+
+        let path = fixture(
+            "multiple-choice-invalid.jsonl",
+            b"{\"context\": \"who\", \"options\": [\"bob\"], \"label\": 1}\n",
+        );
+        let mut loader = MultipleChoiceLoader::new(&path, InvalidUtf8::Error).unwrap();
+        match raised(|| loader.next()) {
+            PreprocessingError::Parse(message) => assert!(message.contains("out of range")),
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn repeat_reshuffles() {
+        let lines: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let repeated = |seed| -> Vec<usize> {
+            let shuffle = Shuffle::new(VecNode::new(texts(&lines)), Some(seed)).unwrap();
+            let mut repeat = Repeat::new(shuffle, Some(2)).unwrap();
+            assert_eq!(repeat.size_hint(), (40, Some(40)));
+            std::iter::from_fn(|| repeat.next())
+                .map(|sample| sample.text.parse().unwrap())
+                .collect()
+        };
+        let order = repeated(1);
+        let (first, second) = order.split_at(20);
+        let mut sorted = first.to_vec();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+        assert_ne!(first, sorted.as_slice());
+        assert_ne!(first, second);
+        assert_eq!(order, repeated(1));
+        assert_ne!(order, repeated(2));
+
+        // The epochs of a `Shuffle` are reproducible on their own.
+        let shuffle = Shuffle::new(VecNode::new(texts(&lines)), Some(1)).unwrap();
+        let epoch = |epoch| -> Vec<usize> {
+            shuffle.set_epoch(epoch);
+            (0..20)
+                .map(|i| shuffle.get(i).unwrap().text.parse().unwrap())
+                .collect()
+        };
+        assert_eq!(epoch(1), second);
+        assert_eq!(epoch(0), first);
+
+        let path = fixture("repeat_reshuffles.txt", b"alice\nbob\n");
+        let stream = TxtLoader::new(&path, InvalidUtf8::Error).unwrap();
+        assert!(Shuffle::new(stream, None).is_err());
+        let stream = TxtLoader::new(&path, InvalidUtf8::Error).unwrap();
+        assert!(Repeat::new(stream, None).is_err());
+
+        // Too many outputs to count.
+        let repeat = Repeat::new(VecNode::new(texts(&["alice", "bob"])), Some(usize::MAX)).unwrap();
+        assert_eq!(repeat.size_hint(), (usize::MAX, None));
+        assert!(repeat.len().is_none());
+
+        // The hooks of the replayed pipeline are passed through.
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["alice", "bob"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batcher = Stats::new(StaticBatcher::new(tokenizer, 1, 4).unwrap()).unwrap();
+        let mut repeat = Repeat::new(batcher, Some(2)).unwrap();
+        while repeat.next().is_some() {}
+        assert!(repeat.total_tokens().is_some());
+        assert!(repeat.stats().unwrap().max_length_seen() > 0);
+        assert!(repeat.truncation_stats().is_some());
+    }
+
+    #[test]
+    fn word_start_mask() {
+        let pairs = vec![TextPair {
+            text: ("who wrote the books".to_string(), "alice".to_string()),
+            label: NoLabel(),
+            metadata: None,
+        }];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 1, 8).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(
+            batch.encoding.input_ids.row(0).to_vec(),
+            vec![5, 6, 7, 8, 23, 14, 0, 0]
+        );
+        // `##s` continues `book`, the second sequence starts a new word and padding is 0.
+        assert_eq!(
+            batch.encoding.word_start_mask.row(0).to_vec(),
+            vec![1, 1, 1, 1, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let batches = || {
+            let samples = texts(&["who wrote the book", "alice", "bob"]);
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+            StaticBatcher::new(tokenizer, 2, 8).unwrap()
+        };
+        let mut validate = Validate::new(batches(), None, Some(8)).unwrap();
+        // The hooks of the batcher are forwarded.
+        assert!(validate.truncation_stats().is_some());
+        assert!(validate.total_tokens().is_some());
+        assert_eq!(std::iter::from_fn(|| validate.next()).count(), 2);
+
+        let validate = Validate::new(batches(), Some(2), Some(8)).unwrap();
+        assert!(validate.get(0).is_some());
+        match raised(|| validate.get(1)) {
+            PreprocessingError::InvalidArgument(message) => assert!(
+                message.starts_with("Batch 1 has 1 rows, expected 2"),
+                "{}",
+                message
+            ),
+            err => panic!("Expected an invalid argument error, got {:?}", err),
+        }
+
+        let mut validate = Validate::new(batches(), None, Some(4)).unwrap();
+        assert!(matches!(
+            raised(|| validate.next()),
+            PreprocessingError::InvalidArgument(_)
+        ));
+
+        assert!(Validate::new(batches(), Some(0), None).is_err());
+    }
+
+    #[test]
+    fn pipelined() {
+        let lines = ["who wrote the book", "alice", "bob", "where is paris"];
+        let path = fixture("pipelined.txt", lines.join("\n").as_bytes());
+        let loader = Pipelined::new(TxtLoader::new(&path, InvalidUtf8::Error).unwrap(), 2).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None).unwrap();
+        let mut tokenizer = Pipelined::new(tokenizer, 1).unwrap();
+        assert!(tokenizer.tokenizer().is_some());
+        let ids: Vec<Vec<u32>> = std::iter::from_fn(|| tokenizer.next())
+            .map(|sample| sample.encoding.input_ids.to_vec())
+            .collect();
+        assert_eq!(
+            ids,
+            vec![vec![5, 6, 7, 8], vec![14], vec![15], vec![18, 19, 17]]
+        );
+        assert!(tokenizer.next().is_none());
+
+        // A panic of the upstream node is raised by `next`.
+        let path = fixture("pipelined-invalid.txt", b"alice\n\xff\n");
+        let mut loader =
+            Pipelined::new(TxtLoader::new(&path, InvalidUtf8::Error).unwrap(), 2).unwrap();
+        assert_eq!(loader.next().unwrap().text, "alice");
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            loader.next();
+        }));
+        assert!(result.is_err());
+
+        assert!(Pipelined::new(VecNode::new(texts(&["bob"])), 0).is_err());
+    }
+
+    #[test]
+    fn truncated_rows() {
+        let samples = texts(&["who wrote the book", "alice", "where is paris", "bob"]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 4, 3).unwrap();
+        let batch = batcher.next().unwrap();
+        // A row of exactly `seq_length` tokens isn't truncated.
+        assert_eq!(batch.encoding.truncated.to_vec(), vec![1, 0, 0, 0]);
+        assert_eq!(batch.encoding.lengths.to_vec(), vec![4, 1, 3, 1]);
+    }
+
+    #[test]
+    fn bucket_batcher_workers() {
+        let words = ["who", "wrote", "the", "book", "in", "paris", "?"];
+        let lines: Vec<String> = [3, 1, 7, 5, 2, 6, 4]
+            .iter()
+            .map(|&len| words[..len].join(" "))
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let worker = |num_workers, worker_id, even_batches| -> Vec<Vec<Vec<u32>>> {
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None)
+                    .unwrap();
+            let mut batcher = BucketBatcher::new(tokenizer, 2)
+                .unwrap()
+                .with_worker(num_workers, worker_id)
+                .unwrap()
+                .with_even_batches(even_batches);
+            let len = batcher.len().unwrap();
+            let batches: Vec<Vec<Vec<u32>>> = std::iter::from_fn(|| batcher.next())
+                .map(|batch| {
+                    let encoding = batch.encoding;
+                    encoding
+                        .input_ids
+                        .outer_iter()
+                        .zip(encoding.attention_mask.outer_iter())
+                        .map(|(ids, mask)| {
+                            ids.iter()
+                                .zip(mask)
+                                .filter(|(_, &mask)| mask == 1)
+                                .map(|(&id, _)| id)
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect();
+            assert_eq!(batches.len(), len);
+            batches
+        };
+        // Sorted by length the batches hold the samples of length 1-2, 3-4, 5-6 and 7.
+        let lengths = |batches: &[Vec<Vec<u32>>]| -> Vec<Vec<usize>> {
+            batches
+                .iter()
+                .map(|batch| batch.iter().map(Vec::len).collect())
+                .collect()
+        };
+        assert_eq!(
+            lengths(&worker(1, 0, false)),
+            vec![vec![1, 2], vec![3, 4], vec![5, 6], vec![7]]
+        );
+        // By default the workers split the batches, the first ones get one more.
+        assert_eq!(lengths(&worker(3, 0, false)), vec![vec![1, 2], vec![7]]);
+        assert_eq!(lengths(&worker(3, 1, false)), vec![vec![3, 4]]);
+        assert_eq!(lengths(&worker(3, 2, false)), vec![vec![5, 6]]);
+        let mut expected: Vec<Vec<u32>> = worker(1, 0, false).into_iter().flatten().collect();
+        expected.sort();
+        for num_workers in 1..=5 {
+            // Together the workers see every sample exactly once.
+            let mut all: Vec<Vec<u32>> = (0..num_workers)
+                .flat_map(|id| worker(num_workers, id, false))
+                .flatten()
+                .collect();
+            all.sort();
+            assert_eq!(all, expected);
+        }
+        // With even batches the workers that come up short start over, so every worker has the
+        // same number of steps.
+        assert_eq!(lengths(&worker(3, 0, true)), vec![vec![1, 2], vec![7]]);
+        assert_eq!(lengths(&worker(3, 1, true)), vec![vec![3, 4], vec![1, 2]]);
+        assert_eq!(lengths(&worker(3, 2, true)), vec![vec![5, 6], vec![3, 4]]);
+        for num_workers in 1..=5 {
+            let lens: Vec<usize> = (0..num_workers)
+                .map(|id| worker(num_workers, id, true).len())
+                .collect();
+            assert!(lens.iter().all(|&len| len == lens[0]), "{:?}", lens);
+        }
+        // Every batch is padded to its own longest sample.
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let batcher = BucketBatcher::new(tokenizer, 2).unwrap();
+        assert_eq!(batcher.get(1).unwrap().encoding.input_ids.dim(), (2, 4));
+
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        assert!(BucketBatcher::new(tokenizer, 2)
+            .unwrap()
+            .with_worker(2, 2)
+            .is_err());
+    }
+
+    #[test]
+    fn sentence_order_prediction() {
+        let sentences: Vec<String> = (0..101).map(|i| format!("sentence {}", i)).collect();
+        let sentences: Vec<&str> = sentences.iter().map(String::as_str).collect();
+        let pairs = |seed| {
+            let node = VecNode::new(texts(&sentences));
+            let mut sop = SentenceOrderPrediction::new(node, 0.5, Some(seed)).unwrap();
+            assert_eq!(sop.size_hint(), (50, Some(50)));
+            std::iter::from_fn(|| sop.next())
+                .map(|pair| (pair.text, pair.label.0))
+                .collect::<Vec<_>>()
+        };
+        let first = pairs(3);
+        assert_eq!(first.len(), 50);
+        assert_eq!(first, pairs(3));
+        assert_ne!(first, pairs(4));
+        let index = |sentence: &str| sentence[9..].parse::<usize>().unwrap();
+        for (i, ((a, b), swapped)) in first.iter().enumerate() {
+            match swapped {
+                0 => assert_eq!((index(a), index(b)), (2 * i, 2 * i + 1)),
+                1 => assert_eq!((index(a), index(b)), (2 * i + 1, 2 * i)),
+                _ => panic!("Unexpected class {}", swapped),
+            }
+        }
+        let swapped = first.iter().filter(|(_, swapped)| *swapped == 1).count();
+        assert!((10..40).contains(&swapped), "{}", swapped);
+
+        let node = VecNode::new(texts(&sentences));
+        let mut ordered = SentenceOrderPrediction::new(node, 0.0, None).unwrap();
+        assert!(std::iter::from_fn(|| ordered.next()).all(|pair| pair.label.0 == 0));
+        assert!(SentenceOrderPrediction::new(VecNode::new(texts(&[])), 1.5, None).is_err());
+    }
+
+    #[test]
+    fn stacked_fields() {
+        let pairs = vec![
+            TextPair {
+                text: ("who wrote it".to_string(), "alice".to_string()),
+                label: NoLabel(),
+                metadata: None,
+            },
+            TextPair {
+                text: ("bob".to_string(), "paris".to_string()),
+                label: NoLabel(),
+                metadata: None,
+            },
+        ];
+        let mut tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), Some(1)).unwrap();
+        let samples: Vec<_> = std::iter::from_fn(|| tokenizer.next()).collect();
+        let batch = crate::stack_samples(samples, 3, None, -100, None);
+        let encoding = &batch.encoding;
+        let rows = |matrix: &ndarray::Array2<u32>| -> Vec<Vec<u32>> {
+            matrix.outer_iter().map(|row| row.to_vec()).collect()
+        };
+        // The first pair is cut off, the second one padded with the pad token.
+        assert_eq!(
+            rows(&encoding.input_ids),
+            vec![vec![5, 6, 10], vec![15, 17, 1]]
+        );
+        assert_eq!(
+            rows(&encoding.attention_mask),
+            vec![vec![1, 1, 1], vec![1, 1, 0]]
+        );
+        assert_eq!(
+            rows(&encoding.token_type_ids),
+            vec![vec![0, 0, 0], vec![0, 1, 0]]
+        );
+        assert_eq!(
+            rows(&encoding.owned_tokens),
+            vec![vec![0, 0, 0], vec![0, 1, 0]]
+        );
+        assert_eq!(encoding.truncated.to_vec(), vec![1, 0]);
+
+        let empty: Vec<TokenizedText<NoTokenizedLabel>> = Vec::new();
+        let batch = crate::stack_samples(empty, 3, None, -100, None);
+        assert_eq!(batch.encoding.input_ids.dim(), (0, 3));
+    }
+
+    #[test]
+    fn first_subword_indices() {
+        let samples = texts(&["who wrote the books", "alice"]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let sample = tokenizer.get(0).unwrap();
+        assert_eq!(
+            sample.encoding.first_subword_indices().to_vec(),
+            vec![0, 1, 2, 3]
+        );
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 8).unwrap();
+        let batch = batcher.next().unwrap();
+        let indices = batch.encoding.first_subword_indices();
+        assert_eq!(indices.row(0).to_vec(), vec![0, 1, 2, 3]);
+        assert_eq!(indices.row(1).to_vec(), vec![0, -1, -1, -1]);
+        // Every index points at a word-initial token, `##s` at 4 is skipped.
+        for (row, indices) in indices.outer_iter().enumerate() {
+            for &index in indices.iter().filter(|&&index| index >= 0) {
+                assert_eq!(batch.encoding.word_start_mask[[row, index as usize]], 1);
+            }
+        }
+    }
+
+    #[test]
+    fn squad_from_paths() {
+        let first = fixture(
+            "squad-1.json",
+            r#"{"data": [{"title": "t", "paragraphs": [
+                {"context": "it was written by alice", "qas": [
+                    {"question": "who wrote it", "answers": [{"text": "alice", "answer_start": 18}], "is_impossible": false},
+                    {"question": "was it written", "answers": [], "is_impossible": true}
+                ]}
+            ]}]}"#
+                .as_bytes(),
+        );
+        let second = fixture(
+            "squad-2.json",
+            r#"{"data": [{"title": "t", "paragraphs": [
+                {"context": "bob is in paris", "qas": [
+                    {"question": "where is bob", "answers": [{"text": "paris", "answer_start": 10}], "is_impossible": false}
+                ]}
+            ]}]}"#
+                .as_bytes(),
+        );
+        let load = |paths: Vec<&PathBuf>| SQuADLoader::from_paths(paths);
+        let mut loader = load(vec![&first, &second]).unwrap();
+        assert_eq!(loader.len(), Some(3));
+        let third = loader.get(2).unwrap();
+        assert_eq!(
+            third.text,
+            ("where is bob".to_string(), "bob is in paris".to_string())
+        );
+        assert_eq!(third.label.span, Some((10, 14)));
+        let questions: Vec<_> = std::iter::from_fn(|| loader.next())
+            .map(|sample| sample.text.0)
+            .collect();
+        assert_eq!(
+            questions,
+            vec!["who wrote it", "was it written", "where is bob"]
+        );
+        assert!(load(vec![]).is_err());
+    }
+
+    #[test]
+    fn without_normalization() {
+        let ids = |normalize| {
+            let samples = texts(&["who wrote the book", "Who wrote the Book"]);
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None)
+                    .unwrap()
+                    .with_normalization(normalize);
+            (0..2)
+                .map(|i| tokenizer.get(i).unwrap().encoding.input_ids.to_vec())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(true), vec![vec![5, 6, 7, 8], vec![5, 6, 7, 8]]);
+        // Clean text keeps its ids, uppercase words aren't lowercased into the vocabulary.
+        assert_eq!(ids(false), vec![vec![5, 6, 7, 8], vec![1, 6, 7, 1]]);
+    }
+
+    #[test]
+    fn hash_id() {
+        let samples = texts(&["who wrote the book", "alice", "who wrote the book"]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None).unwrap();
+        let hashed = HashId::new(tokenizer).unwrap();
+        let mut batcher = StaticBatcher::new(hashed, 3, 8).unwrap();
+        let hash_ids = batcher.next().unwrap().encoding.hash_ids.unwrap().to_vec();
+        // FNV-1a of the text, the same on every run.
+        assert_eq!(hash_ids[0], 13407724841197490966);
+        assert_eq!(hash_ids[0], hash_ids[2]);
+        assert_ne!(hash_ids[0], hash_ids[1]);
+        // Without `HashId` the batch has no ids.
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["alice"])), test_tokenizer(), None)
+                .unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 1, 8).unwrap();
+        assert!(batcher.next().unwrap().encoding.hash_ids.is_none());
+        assert!(HashId::new(VecNode::new(Vec::<TokenizedText<NoTokenizedLabel>>::new())).is_err());
+    }
+
+    #[test]
+    fn span_batch_without_answers() {
+        let spans = |answers: [Option<(usize, usize)>; 2]| {
+            let samples = ["it was written by bob", "who wrote the book"]
+                .iter()
+                .zip(answers)
+                .map(|(text, answer)| Text {
+                    text: text.to_string(),
+                    label: Span::new(answer),
+                    metadata: None,
+                })
+                .collect();
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None)
+                    .unwrap()
+                    .with_truncation(3, 0, false)
+                    .unwrap();
+            StaticBatcher::new(tokenizer, 2, 4).unwrap().next().unwrap()
+        };
+        // "bob" is truncated away and the second text has no answer, so nothing is answerable.
+        let batch = spans([Some((18, 20)), None]);
+        assert_eq!(batch.labels.answerable, array![0, 0]);
+        assert_eq!(batch.labels.start, array![-100, -100]);
+        assert_eq!(batch.labels.end, array![-100, -100]);
+        // With an answer in the batch, unanswerable rows still point at the first token.
+        let batch = spans([Some((3, 5)), None]);
+        assert_eq!(batch.labels.answerable, array![1, 0]);
+        assert_eq!(batch.labels.start, array![1, 0]);
+        assert_eq!(batch.labels.end, array![1, 0]);
+    }
+
+    #[test]
+    fn window_ranges() {
+        let pairs = || {
+            VecNode::new(vec![TextPair {
+                text: (
+                    "who".to_string(),
+                    "it was written by bob in paris".to_string(),
+                ),
+                label: NoLabel(),
+                metadata: None,
+            }])
+        };
+        let tokenizer = Tokenizer::from_tokenizer(pairs(), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(5, 1, true)
+            .unwrap();
+        // The question takes one token, leaving 4 of the 7 context tokens with an overlap of 1.
+        assert_eq!(tokenizer.window_ranges(0), Some(vec![(0, 4), (3, 7)]));
+        // The overlapping token is the last of the first window and the first of the second.
+        assert_eq!(
+            tokenizer.reconstruct(0, 0, 4, 4),
+            Some(Some("by".to_string()))
+        );
+        assert_eq!(
+            tokenizer.reconstruct(0, 1, 1, 1),
+            Some(Some("by".to_string()))
+        );
+        assert_eq!(tokenizer.window_ranges(1), None);
+        let tokenizer = Tokenizer::from_tokenizer(pairs(), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(5, 1, false)
+            .unwrap();
+        assert_eq!(tokenizer.window_ranges(0), Some(vec![(0, 4)]));
+        let batcher = StaticBatcher::new(tokenizer, 1, 5).unwrap();
+        assert_eq!(batcher.window_ranges(0), Some(vec![(0, 4)]));
+    }
+
+    #[test]
+    fn batch_shape() {
+        let samples = texts(&["who wrote the book", "alice", "bob"]);
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), Some(1)).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 2, 6).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(batch.encoding.batch_size(), 2);
+        assert_eq!(batch.encoding.seq_length(), 6);
+        assert_eq!(batch.encoding.pad_token(), 1);
+        // The short last batch reports its own size.
+        assert_eq!(batcher.next().unwrap().encoding.batch_size(), 1);
+    }
+
+    #[test]
+    fn token_dropout() {
+        let line = "who wrote the book it was written by alice in paris";
+        let lines = vec![line; 200];
+        let dropout = |p| {
+            let samples = VecNode::new(texts(&lines));
+            let tokenizer = Tokenizer::from_tokenizer(samples, test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                .unwrap();
+            TokenDropout::new(tokenizer, p, Some(3)).unwrap()
+        };
+        let mut node = dropout(0.25);
+        let (mut dropped, mut total) = (0, 0);
+        for index in 0..lines.len() {
+            let sample = node.next().unwrap();
+            let ids = sample.encoding.input_ids.to_vec();
+            // `[CLS]` and `[SEP]` are kept, every other token is either kept or the pad token.
+            assert_eq!((ids[0], ids[ids.len() - 1]), (2, 3));
+            let tokens = &ids[1..ids.len() - 1];
+            total += tokens.len();
+            dropped += tokens.iter().filter(|&&id| id == 0).count();
+            assert_eq!(node.get(index).unwrap().encoding.input_ids.to_vec(), ids);
+        }
+        let fraction = dropped as f64 / total as f64;
+        assert!((0.2..0.3).contains(&fraction), "dropped {}", fraction);
+        let sample = dropout(0.0).next().unwrap();
+        assert_eq!(
+            sample.encoding.input_ids.to_vec(),
+            vec![2, 5, 6, 7, 8, 10, 11, 12, 13, 14, 16, 17, 3]
+        );
+        assert!(TokenDropout::new(dropout(0.0), 1.5, None).is_err());
+    }
+
+    #[test]
+    fn compressed_squad() {
+        let squad = br#"{"data": [{"title": "t", "paragraphs": [
+            {"context": "it was written by alice", "qas": [
+                {"question": "who wrote it", "answers": [{"text": "alice", "answer_start": 18}], "is_impossible": false}
+            ]}
+        ]}]}"#;
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(squad).unwrap();
+        let files = [
+            fixture("compressed-squad.json", squad),
+            fixture("compressed-squad.json.gz", &gzip.finish().unwrap()),
+            fixture(
+                "compressed-squad.json.zst",
+                &zstd::encode_all(&squad[..], 0).unwrap(),
+            ),
+        ];
+        for file in &files {
+            let loader = SQuADLoader::new(file).unwrap();
+            assert_eq!(loader.len(), Some(1));
+            let sample = loader.get(0).unwrap();
+            assert_eq!(sample.text.0, "who wrote it");
+            assert_eq!(sample.label.span, Some((18, 22)));
+        }
+    }
+
+    #[test]
+    fn span_corruption() {
+        let lines = vec!["it was written by alice in paris who wrote the book"; 50];
+        let corruption = || {
+            let samples = VecNode::new(texts(&lines));
+            let tokenizer = Tokenizer::from_tokenizer(samples, test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                .unwrap();
+            SpanCorruption::new(tokenizer, 100, 10, 0.3, 1.5, Some(5)).unwrap()
+        };
+        let original = vec![2, 10, 11, 12, 13, 14, 16, 17, 5, 6, 7, 8, 3];
+        let mut node = corruption();
+        let is_sentinel = |id: &u32| (91..=100).contains(id);
+        for index in 0..lines.len() {
+            let sample = node.next().unwrap();
+            let inputs = sample.encoding.input_ids.to_vec();
+            let targets = sample.label.0.clone();
+            // `[CLS]` and `[SEP]` stay in place, 3 of the 11 words are corrupted in 2 spans.
+            assert_eq!((inputs[0], inputs[inputs.len() - 1]), (2, 3));
+            assert_eq!(targets.len(), 3 + 2);
+            let sentinels: Vec<u32> = inputs.iter().copied().filter(is_sentinel).collect();
+            assert_eq!(sentinels, vec![100, 99]);
+            assert_eq!(
+                targets
+                    .iter()
+                    .copied()
+                    .filter(is_sentinel)
+                    .collect::<Vec<_>>(),
+                sentinels
+            );
+            // Putting the spans of the targets back in place of their sentinels restores the text.
+            let mut restored = Vec::new();
+            for &id in &inputs {
+                if !is_sentinel(&id) {
+                    restored.push(id);
+                    continue;
+                }
+                let span = targets.iter().position(|&target| target == id).unwrap() + 1;
+                restored.extend(targets[span..].iter().take_while(|&id| !is_sentinel(id)));
+            }
+            assert_eq!(restored, original);
+            assert_eq!(node.get(index).unwrap().encoding.input_ids.to_vec(), inputs);
+        }
+        // The targets become the `labels` of the batch.
+        let mut batcher = StaticBatcher::new(corruption(), 2, 16).unwrap();
+        assert_eq!(batcher.next().unwrap().labels.0.ncols(), 5);
+        let empty = || VecNode::new(Vec::<TokenizedText<NoTokenizedLabel>>::new());
+        assert!(SpanCorruption::new(empty(), 5, 10, 0.15, 3.0, None).is_err());
+        assert!(SpanCorruption::new(empty(), 100, 10, 1.0, 3.0, None).is_err());
+    }
+
+    #[test]
+    fn window_separators() {
+        let windows = |eos_token: Option<&str>| {
+            let pairs = vec![TextPair {
+                text: (
+                    "who".to_string(),
+                    "it was written by bob in paris".to_string(),
+                ),
+                label: Span::new(Some((25, 29))),
+                metadata: None,
+            }];
+            let tokenizer = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+                .unwrap()
+                .with_pair_template("[SEP]", Some("[CLS]"), eos_token)
+                .unwrap()
+                .with_truncation(8, 2, true)
+                .unwrap();
+            StaticBatcher::new(tokenizer, 4, 8).unwrap().next().unwrap()
+        };
+        // With a trailing `[SEP]` every window has room for one context token less, so "paris"
+        // ends up in a third window, but the span points at it either way.
+        for (eos_token, rows, answer) in [(Some("[SEP]"), 3, (2, 5)), (None, 2, (1, 6))] {
+            let batch = windows(eos_token);
+            let ids = &batch.encoding.input_ids;
+            assert_eq!(ids.nrows(), rows);
+            let (row, position) = answer;
+            assert_eq!(batch.labels.answerable[row], 1);
+            assert_eq!(batch.labels.start[row], position as i64);
+            assert_eq!(batch.labels.end[row], position as i64);
+            assert_eq!(ids[[row, position]], 17);
+            for (i, window) in ids.outer_iter().enumerate() {
+                assert_eq!(window.slice(s![..3]).to_vec(), vec![2, 5, 3]);
+                let last = batch.encoding.lengths[i].min(8) - 1;
+                assert_eq!(window[last] == 3, eos_token.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn context_mask() {
+        let pairs = vec![TextPair {
+            text: ("who wrote it".to_string(), "bob in paris".to_string()),
+            label: NoLabel(),
+            metadata: None,
+        }];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+            .unwrap()
+            .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+            .unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 1, 12).unwrap();
+        let batch = batcher.next().unwrap();
+        assert_eq!(
+            batch.encoding.input_ids.row(0).to_vec(),
+            vec![2, 5, 6, 10, 3, 15, 16, 17, 3, 0, 0, 0]
+        );
+        // Only the context is 1, not the question, the special tokens or padding.
+        assert_eq!(
+            batch.encoding.context_mask.row(0).to_vec(),
+            vec![0, 0, 0, 0, 0, 1, 1, 1, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn with_metadata() {
+        let source_indices = |n: usize| {
+            (0..n)
+                .map(|i| HashMap::from([("source_index".to_string(), i.to_string())]))
+                .collect::<Vec<_>>()
+        };
+        let loader = VecNode::new(texts(&["who wrote the book", "it was written by bob"]));
+        assert!(matches!(
+            WithMetadata::new(VecNode::new(texts(&["who"])), source_indices(2)),
+            Err(PreprocessingError::InvalidArgument(_))
+        ));
+        let loader = WithMetadata::new(loader, source_indices(2)).unwrap();
+        let tokenizer = Tokenizer::from_tokenizer(loader, test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(3, 1, true)
+            .unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 4, 3).unwrap();
+        let batch = batcher.next().unwrap();
+        // Both windows of every text carry its metadata.
+        let indices: Vec<&str> = batch
+            .encoding
+            .metadata
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|metadata| metadata["source_index"].as_str())
+            .collect();
+        assert_eq!(indices, vec!["0", "0", "1", "1"]);
+        // Without `WithMetadata` there is none.
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&["who"])), test_tokenizer(), None)
+                .unwrap();
+        let batch = StaticBatcher::new(tokenizer, 1, 3).unwrap().next().unwrap();
+        assert!(batch.encoding.metadata.is_none());
+
+        // A streaming node with more samples than metadata is only noticed while reading.
+        let path = fixture("with-metadata.txt", b"who\nbob\n");
+        let loader = TxtLoader::new(&path, InvalidUtf8::Error).unwrap();
+        let mut loader = WithMetadata::new(loader, source_indices(1)).unwrap();
+        assert!(loader.next().is_some());
+        assert!(matches!(
+            raised(|| loader.next()),
+            PreprocessingError::InvalidArgument(_)
+        ));
+    }
+
+    #[test]
+    fn unbatch() {
+        let samples: Vec<Text<ClassLabel>> =
+            ["who wrote the book", "it was written by bob", "alice"]
+                .iter()
+                .enumerate()
+                .map(|(i, text)| Text {
+                    text: text.to_string(),
+                    label: ClassLabel(i as u32),
+                    metadata: None,
+                })
+                .collect();
+        let tokenizer = || {
+            Tokenizer::from_tokenizer(VecNode::new(samples.clone()), test_tokenizer(), None)
+                .unwrap()
+        };
+        let originals: Vec<_> = (0..3).map(|i| tokenizer().get(i).unwrap()).collect();
+        // The last batch repeats "alice" to fill up the batch, which `Unbatch` leaves out.
+        let batcher = StaticBatcher::new(tokenizer(), 2, 4)
+            .unwrap()
+            .with_pad_batch(true);
+        let mut unbatch = Unbatch::new(batcher).unwrap();
+        let unbatched: Vec<_> = std::iter::from_fn(|| unbatch.next()).collect();
+        assert_eq!(unbatched.len(), 3);
+        for (sample, original) in unbatched.iter().zip(&originals) {
+            let len = original.encoding.input_ids.len().min(4);
+            assert_eq!(
+                sample.encoding.input_ids,
+                original.encoding.input_ids.slice(s![..len])
+            );
+            assert_eq!(
+                sample.encoding.word_starts,
+                original.encoding.word_starts.slice(s![..len])
+            );
+            assert_eq!(sample.label.0, original.label.0);
+        }
+        // "it was written by bob" is truncated to the sequence length.
+        assert_eq!(
+            unbatched[1].encoding.input_ids.to_vec(),
+            vec![10, 11, 12, 13]
+        );
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn incompatible_node_message() {
+        use crate::python::{incompatible_node, short_type_name};
+        assert_eq!(
+            short_type_name(std::any::type_name::<Batch<crate::BatchClassLabel>>()),
+            "Batch<BatchClassLabel>"
+        );
+        let accepted = [
+            std::any::type_name::<Text<NoLabel>>(),
+            std::any::type_name::<TextPair<ClassLabel>>(),
+        ];
+        let provided = std::any::type_name::<TokenizedText<NoTokenizedLabel>>();
+        assert_eq!(
+            incompatible_node("MaxChars", &accepted, provided).to_string(),
+            "MaxChars expects a node with outputs of one of the types Text<NoLabel>, \
+             TextPair<ClassLabel>, got one with outputs of type TokenizedText<NoTokenizedLabel>"
+        );
+        assert_eq!(
+            incompatible_node("SOP", &accepted[..1], provided).to_string(),
+            "SOP expects a node with outputs of type Text<NoLabel>, got one with outputs of type \
+             TokenizedText<NoTokenizedLabel>"
+        );
+    }
+
+    #[test]
+    fn dedup_within_batch() {
+        let lines = ["bob", "bob", "alice", "bob", "paris", "alice", "paris"];
+        let tokenizer =
+            Tokenizer::from_tokenizer(VecNode::new(texts(&lines)), test_tokenizer(), None).unwrap();
+        let mut batcher = StaticBatcher::new(tokenizer, 3, 2)
+            .unwrap()
+            .with_dedup_within_batch(true);
+        assert!(batcher.len().is_none());
+        assert!(batcher.get(0).is_none());
+        let mut batches = Vec::new();
+        while let Some(batch) = batcher.next() {
+            let rows: Vec<Vec<u32>> = batch
+                .encoding
+                .input_ids
+                .outer_iter()
+                .map(|row| row.to_vec())
+                .collect();
+            let mut unique = rows.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), rows.len());
+            batches.push(rows.into_iter().map(|row| row[0]).collect::<Vec<u32>>());
+        }
+        // The duplicates are skipped and the batch filled with the next samples instead.
+        assert_eq!(batches, vec![vec![15, 14, 17], vec![14, 17]]);
+    }
+
+    #[cfg(feature = "dlpack")]
+    #[test]
+    fn dlpack_export() {
+        use crate::dlpack::DlManagedTensor;
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batch = StaticBatcher::new(tokenizer, 2, 3).unwrap().get(0).unwrap();
+        let input_ids = std::sync::Arc::new(batch.encoding.input_ids);
+        let owner = Box::new(std::sync::Arc::clone(&input_ids));
+        let managed = unsafe { DlManagedTensor::from_array2(&input_ids, owner) };
+        let tensor = unsafe { &(*managed).dl_tensor };
+        assert_eq!((tensor.device.device_type, tensor.ndim), (1, 2));
+        assert_eq!(
+            (tensor.dtype.code, tensor.dtype.bits, tensor.dtype.lanes),
+            (1, 32, 1)
+        );
+        let shape = unsafe { std::slice::from_raw_parts(tensor.shape, 2) };
+        let strides = unsafe { std::slice::from_raw_parts(tensor.strides, 2) };
+        assert_eq!((shape, strides), (&[2, 3][..], &[3, 1][..]));
+        // The tensor shares the memory of the matrix.
+        assert_eq!(tensor.data as *const u32, input_ids.as_ptr());
+        let data = unsafe { std::slice::from_raw_parts(tensor.data as *const u32, 6) };
+        assert_eq!(data, &[5, 6, 10, 17, 0, 0]);
+        assert_eq!(std::sync::Arc::strong_count(&input_ids), 2);
+        unsafe { ((*managed).deleter.unwrap())(managed) };
+        assert_eq!(std::sync::Arc::strong_count(&input_ids), 1);
+
+        // A column-major matrix is exported with its strides.
+        let transposed = input_ids.t().to_owned();
+        let managed = unsafe { DlManagedTensor::from_array2(&transposed, Box::new(())) };
+        let tensor = unsafe { &(*managed).dl_tensor };
+        let strides = unsafe { std::slice::from_raw_parts(tensor.strides, 2) };
+        assert_eq!(strides, &[1, 3]);
+        unsafe { ((*managed).deleter.unwrap())(managed) };
+    }
+
+    // Consumes a capsule of `BatchEncoding.to_dlpack` the way `torch.from_dlpack` does: the
+    // capsule is renamed to "used_dltensor" and the tensor is deleted once the consumer is done
+    // with it. The matrices only go back to the batcher once the tensor is deleted.
+    #[cfg(all(feature = "dlpack", python_tests))]
+    #[test]
+    fn python_dlpack() {
+        use crate::dlpack::DlManagedTensor;
+        use crate::python::ToPyObjectConsume;
+        use pyo3::{ffi, AsPyPointer, Python};
+        pyo3::prepare_freethreaded_python();
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 2, 3).unwrap();
+        Python::with_gil(|py| {
+            let encoding = batcher.get(0).unwrap().encoding.to_object_consume(py);
+            let capsule = encoding
+                .call_method1(py, "to_dlpack", ("input_ids",))
+                .unwrap();
+            assert!(encoding.call_method1(py, "to_dlpack", ("labels",)).is_err());
+            let managed = unsafe {
+                let capsule = capsule.as_ptr();
+                let name = b"dltensor\0".as_ptr() as *const std::os::raw::c_char;
+                let managed = ffi::PyCapsule_GetPointer(capsule, name) as *mut DlManagedTensor;
+                let used = b"used_dltensor\0".as_ptr() as *const std::os::raw::c_char;
+                assert_eq!(ffi::PyCapsule_SetName(capsule, used), 0);
+                managed
+            };
+            drop(capsule);
+            drop(encoding);
+            // The tensor keeps the batch alive.
+            assert_eq!(batcher.buffers.len(), 0);
+            let tensor = unsafe { &(*managed).dl_tensor };
+            let data = unsafe { std::slice::from_raw_parts(tensor.data as *const u32, 6) };
+            assert_eq!(data, &[5, 6, 10, 17, 0, 0]);
+            unsafe { ((*managed).deleter.unwrap())(managed) };
+            assert_eq!(batcher.buffers.len(), 4);
+
+            // A capsule that is never consumed deletes the tensor itself.
+            let encoding = batcher.get(0).unwrap().encoding.to_object_consume(py);
+            drop(
+                encoding
+                    .call_method1(py, "to_dlpack", ("input_ids",))
+                    .unwrap(),
+            );
+            drop(encoding);
+            assert_eq!(batcher.buffers.len(), 4);
+        });
+    }
+
+    // Round-trips a capsule through `torch.from_dlpack`. Needs PyTorch 2.3 or later for uint32
+    // tensors, skipped without it.
+    #[cfg(all(feature = "dlpack", python_tests))]
+    #[test]
+    fn python_dlpack_torch() {
+        use crate::python::ToPyObjectConsume;
+        use pyo3::types::PyDict;
+        use pyo3::Python;
+        pyo3::prepare_freethreaded_python();
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 2, 3).unwrap();
+        Python::with_gil(|py| {
+            let supported = py
+                .run("import torch\nassert hasattr(torch, 'uint32')", None, None)
+                .is_ok();
+            if !supported {
+                println!("python_dlpack_torch skipped, PyTorch 2.3 or later isn't installed");
+                return;
+            }
+            let globals = PyDict::new(py);
+            let encoding = batcher.get(0).unwrap().encoding.to_object_consume(py);
+            globals.set_item("encoding", encoding).unwrap();
+            py.run(
+                r#"
+import torch
+tensor = torch.from_dlpack(encoding.to_dlpack("input_ids"))
+assert tensor.dtype == torch.uint32, tensor.dtype
+assert tensor.shape == (2, 3), tensor.shape
+assert tensor.int().tolist() == [[5, 6, 10], [17, 0, 0]], tensor
+del encoding
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            // The tensor keeps the batch alive until it is deleted.
+            assert_eq!(batcher.buffers.len(), 0);
+            globals.del_item("tensor").unwrap();
+            assert_eq!(batcher.buffers.len(), 4);
+        });
+    }
+
+    // Imports a batch into pyarrow through `to_arrow`, skipped without pyarrow.
+    #[cfg(all(feature = "python", python_tests))]
+    #[test]
+    fn python_arrow_pyarrow() {
+        use crate::python::ToPyObjectConsume;
+        use pyo3::types::PyDict;
+        use pyo3::Python;
+        pyo3::prepare_freethreaded_python();
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(texts(&["who wrote it", "paris"])),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let batcher = StaticBatcher::new(tokenizer, 2, 3).unwrap();
+        Python::with_gil(|py| {
+            if py.import("pyarrow").is_err() {
+                println!("python_arrow_pyarrow skipped, pyarrow isn't installed");
+                return;
+            }
+            let globals = PyDict::new(py);
+            let encoding = batcher.get(0).unwrap().encoding.to_object_consume(py);
+            globals.set_item("encoding", encoding).unwrap();
+            py.run(
+                r#"
+import pyarrow as pa
+record_batch = encoding.to_arrow()
+assert record_batch.num_rows == 2, record_batch.num_rows
+assert record_batch.schema.field("input_ids").type == pa.list_(pa.uint32(), 3)
+assert record_batch.column("input_ids").to_pylist() == [[5, 6, 10], [17, 0, 0]]
+assert record_batch.column("attention_mask").to_pylist() == [[1, 1, 1], [1, 0, 0]]
+assert record_batch.schema.field("lengths").type == pa.uint64()
+assert record_batch.column("lengths").to_pylist() == [3, 1]
+record_batch.validate(full=True)
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            // The record batch owns copies of the arrays, so it outlives the batch.
+            globals.del_item("encoding").unwrap();
+            let lengths: Vec<u64> = py
+                .eval(
+                    "record_batch.column('lengths').to_pylist()",
+                    Some(globals),
+                    None,
+                )
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(lengths, vec![3, 1]);
+        });
+    }
+
+    #[test]
+    fn num_global_tokens() {
+        let pair = |question: &str, context: &str| TextPair {
+            text: (question.to_string(), context.to_string()),
+            label: NoLabel(),
+            metadata: None,
+        };
+        let samples = vec![pair("who wrote", "it was bob"), pair("who", "bob")];
+        let batch = |num_global_tokens, global_attention| {
+            let tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(samples.clone()), test_tokenizer(), None)
+                    .unwrap()
+                    .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                    .unwrap();
+            StaticBatcher::new(tokenizer, 2, 10)
+                .unwrap()
+                .with_num_global_tokens(num_global_tokens)
+                .with_global_attention(global_attention)
+                .get(0)
+                .unwrap()
+                .encoding
+        };
+        assert!(batch(0, false).global_attention_mask.is_none());
+        let encoding = batch(7, false);
+        assert_eq!(
+            encoding.input_ids,
+            array![
+                [2, 5, 6, 3, 10, 11, 15, 3, 0, 0],
+                [2, 5, 3, 15, 3, 0, 0, 0, 0, 0]
+            ]
+        );
+        // Regardless of the sequence, but never padding.
+        assert_eq!(
+            encoding.global_attention_mask.unwrap(),
+            array![
+                [1, 1, 1, 1, 1, 1, 1, 0, 0, 0],
+                [1, 1, 1, 1, 1, 0, 0, 0, 0, 0]
+            ]
+        );
+        // Combined with `with_global_attention` marking the question.
+        assert_eq!(
+            batch(1, true).global_attention_mask.unwrap(),
+            array![
+                [1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+                [1, 1, 0, 0, 0, 0, 0, 0, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn take_batches() {
+        let batcher = || {
+            let tokenizer = Tokenizer::from_tokenizer(
+                VecNode::new(texts(&[
+                    "who wrote it",
+                    "it was bob",
+                    "paris",
+                    "a city",
+                    "bob",
+                    "the book",
+                ])),
+                test_tokenizer(),
+                None,
+            )
+            .unwrap();
+            StaticBatcher::new(tokenizer, 2, 3).unwrap()
+        };
+        let mut take = TakeBatches::new(batcher(), 2).unwrap();
+        assert_eq!(take.len(), Some(2));
+        assert_eq!(take.size_hint(), (2, Some(2)));
+        assert!(take.get(2).is_none());
+        let batches: Vec<_> = std::iter::from_fn(|| take.next()).collect();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[1].encoding.input_ids,
+            array![[17, 0, 0], [20, 21, 0]]
+        );
+        // Upstream has only 3 batches.
+        let mut take = TakeBatches::new(batcher(), 10).unwrap();
+        assert_eq!(take.len(), Some(3));
+        assert_eq!(std::iter::from_fn(|| take.next()).count(), 3);
+
+        // The hooks of a question answering pipeline are forwarded.
+        let pairs = vec![TextPair {
+            text: (
+                "who wrote it ?".to_string(),
+                "It was written by Alice in Paris".to_string(),
+            ),
+            label: Span::new(Some((18, 23))),
+            metadata: None,
+        }];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(pairs), test_tokenizer(), None)
+            .unwrap()
+            .with_truncation(8, 1, true)
+            .unwrap();
+        let mut take = TakeBatches::new(StaticBatcher::new(tokenizer, 1, 8).unwrap(), 1).unwrap();
+        while take.next().is_some() {}
+        assert!(take.answer_retention().is_some());
+        assert_eq!(
+            take.reconstruct(0, 1, 5, 6),
+            Some(Some("Alice in".to_string()))
+        );
+        assert!(take.window_ranges(0).is_some());
+    }
+
+    #[test]
+    fn impossible_to_cls() {
+        let labels = |label: Span, template: bool| {
+            let pair = TextPair {
+                text: ("who wrote it".to_string(), "it was bob".to_string()),
+                label,
+                metadata: None,
+            };
+            let mut tokenizer =
+                Tokenizer::from_tokenizer(VecNode::new(vec![pair]), test_tokenizer(), None)
+                    .unwrap();
+            if template {
+                tokenizer = tokenizer
+                    .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+                    .unwrap();
+            }
+            StaticBatcher::new(tokenizer, 1, 10)
+                .unwrap()
+                .get(0)
+                .unwrap()
+                .labels
+        };
+        let impossible = || Span::new(None).with_impossible_to_cls(true);
+        let batch = labels(impossible(), true);
+        assert_eq!(
+            (batch.start[0], batch.end[0], batch.answerable[0]),
+            (0, 0, 1)
+        );
+        let batch = labels(impossible().with_end_inclusive(false), true);
+        assert_eq!((batch.start[0], batch.end[0]), (0, 1));
+        // Without `[CLS]` at index 0 the question stays impossible.
+        let batch = labels(impossible(), false);
+        assert_eq!(batch.answerable[0], 0);
+        // Answers are unaffected.
+        let batch = labels(Span::new(Some((7, 9))).with_impossible_to_cls(true), true);
+        assert_eq!((batch.start[0], batch.end[0]), (7, 7));
+        let batch = labels(Span::new(None), true);
+        assert_eq!((batch.start[0], batch.answerable[0]), (-100, 0));
+    }
+
+    #[test]
+    fn passage_chunk() {
+        let documents = || {
+            let mut documents = texts(&["abcdefg", "hi"]);
+            documents[0].metadata = Some(HashMap::from([("url".to_string(), "a".to_string())]));
+            VecNode::new(documents)
+        };
+        let passages = |mut chunk: PassageChunk<_>| {
+            std::iter::from_fn(|| chunk.next())
+                .map(|text| {
+                    let mut metadata = text.metadata.unwrap();
+                    let id = metadata.remove("passage_id").unwrap();
+                    (text.text, id, metadata.remove("url"))
+                })
+                .collect::<Vec<_>>()
+        };
+        let a = || Some("a".to_string());
+        assert_eq!(
+            passages(PassageChunk::new(documents(), 4, 2, "{document}-{chunk}").unwrap()),
+            vec![
+                ("abcd".to_string(), "0-0".to_string(), a()),
+                ("cdef".to_string(), "0-1".to_string(), a()),
+                ("efg".to_string(), "0-2".to_string(), a()),
+                ("hi".to_string(), "1-0".to_string(), None),
+            ]
+        );
+
+        let documents = VecNode::new(texts(&["who wrote the book ?", "paris"]));
+        let chunk = PassageChunk::new(documents, 3, 2, "doc{document}#{chunk}")
+            .unwrap()
+            .with_tokenizer(test_tokenizer());
+        assert_eq!(
+            passages(chunk),
+            vec![
+                ("who wrote the".to_string(), "doc0#0".to_string(), None),
+                ("the book ?".to_string(), "doc0#1".to_string(), None),
+                ("paris".to_string(), "doc1#0".to_string(), None),
+            ]
+        );
+        assert!(PassageChunk::new(VecNode::new(texts(&["a"])), 4, 2, "{document}").is_err());
+    }
+
+    #[test]
+    fn answer_char_spans() {
+        let context = "it was written by alice in paris";
+        let pair = |answer: Option<&str>, end_inclusive| {
+            let span = answer.map(|answer| {
+                let start = context.find(answer).unwrap();
+                (start, start + answer.len() - 1)
+            });
+            TextPair {
+                text: ("who wrote it".to_string(), context.to_string()),
+                label: Span::new(span)
+                    .with_end_inclusive(end_inclusive)
+                    .with_char_span(true),
+                metadata: None,
+            }
+        };
+        let samples = vec![
+            pair(Some("alice"), true),
+            pair(Some("in paris"), false),
+            pair(None, true),
+        ];
+        let tokenizer = Tokenizer::from_tokenizer(VecNode::new(samples), test_tokenizer(), None)
+            .unwrap()
+            .with_pair_template("[SEP]", Some("[CLS]"), Some("[SEP]"))
+            .unwrap();
+        let labels = StaticBatcher::new(tokenizer, 3, 16)
+            .unwrap()
+            .get(0)
+            .unwrap()
+            .labels;
+        let start = labels.answer_char_start.unwrap();
+        let end = labels.answer_char_end.unwrap();
+        let answer = |row: usize| &context[start[row] as usize..end[row] as usize];
+        assert_eq!((answer(0), answer(1)), ("alice", "in paris"));
+        assert_eq!((start[2], end[2]), (-100, -100));
+        // Not kept by default.
+        let tokenizer = Tokenizer::from_tokenizer(
+            VecNode::new(vec![TextPair {
+                text: ("who wrote it".to_string(), context.to_string()),
+                label: Span::new(Some((18, 22))),
+                metadata: None,
+            }]),
+            test_tokenizer(),
+            None,
+        )
+        .unwrap();
+        let labels = StaticBatcher::new(tokenizer, 1, 16)
+            .unwrap()
+            .get(0)
+            .unwrap()
+            .labels;
+        assert!(labels.answer_char_start.is_none());
+    }
 }
@@ -0,0 +1,31 @@
+// Warnings of the pipeline about data it skips or settings that won't work as expected, e.g. an
+// answer that isn't found in its context.
+//
+// They go to stderr unless a hook is set with `set_hook`. The Python bindings set one that queues
+// them and raise them as Python warnings (`UserWarning`) on the thread holding the GIL, since the
+// nodes also warn on worker threads. `count` is the number of warnings emitted so far, whatever
+// handled them.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+pub type Hook = Box<dyn Fn(&str) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+static COUNT: AtomicU64 = AtomicU64::new(0);
+
+// `None` restores printing to stderr.
+pub fn set_hook(hook: Option<Hook>) {
+    *HOOK.write().unwrap_or_else(PoisonError::into_inner) = hook;
+}
+
+pub fn count() -> u64 {
+    COUNT.load(Ordering::Relaxed)
+}
+
+pub(crate) fn warn(message: &str) {
+    COUNT.fetch_add(1, Ordering::Relaxed);
+    match &*HOOK.read().unwrap_or_else(PoisonError::into_inner) {
+        Some(hook) => hook(message),
+        None => eprintln!("Warning: {}", message),
+    }
+}